@@ -0,0 +1,13 @@
+// Generates the gRPC bindings for src/grpc.rs from proto/lnst.proto when the
+// `grpc` feature is on, using a vendored protoc so no system install is needed.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        let mut config = prost_build::Config::new();
+        config.protoc_executable(protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+        tonic_prost_build::configure()
+            .compile_with_config(config, &["proto/lnst.proto"], &["proto"])
+            .expect("compiling proto/lnst.proto");
+    }
+}
@@ -0,0 +1,35 @@
+/*
+ * lnst.h — C ABI of llm_nature_semantic_transformer (cargo feature `ffi`).
+ *
+ * Build the shared library with
+ *   cargo rustc --lib --release --crate-type cdylib --features ffi
+ *
+ * Every function takes and returns NUL-terminated UTF-8. The returned string
+ * is a JSON document owned by the caller; release it with lnst_string_free.
+ * Failures come back as {"error": "..."}, never as NULL. Nothing touches the
+ * filesystem. Calls may be made from any thread.
+ */
+#ifndef LNST_H
+#define LNST_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Execute a JSON trace (an array of op lines, or a semtrace object with
+ * "ops") in memory. Returns {"records": [...], "result": {...}}: the
+ * trace.ndjson records and a summary of the run. */
+char *lnst_execute_json(const char *trace);
+
+/* Replay trace.ndjson contents. Returns the verify report: "valid",
+ * "chain_hash", and on failure "failed_step" and "reason". */
+char *lnst_verify_ndjson(const char *ndjson);
+
+/* Free a string returned by either function above. NULL is ignored. */
+void lnst_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LNST_H */
@@ -0,0 +1,609 @@
+//! ApiProposer — hosted or local LLM backend for the query `Proposer` trait.
+//!
+//! Sends the op grammar plus the user query to an OpenAI- or Anthropic-style
+//! chat endpoint and keeps the reply lines that look like ops. `Provider::Local`
+//! targets a llama.cpp `llama-server` (or any OpenAI-compatible server) running a
+//! GGUF model, so quantized Llama/Mistral models work without an API key.
+//! The system prompt is the grammar in `prompts/grammar.txt` plus the template for
+//! the query's universe (`prompts/{qe,ge,boolfun}.txt`), unless overridden, followed
+//! by the solved examples from the few-shot bank most similar to the query.
+//! Anything the model returns is still executed and replayed, so a bad reply
+//! can only fail verification, never forge it.
+//!
+//! Every reply is checked op by op against the executor's grammar
+//! (`exec::validate_op`); when some lines do not parse, the model is re-prompted
+//! with the parse errors, up to `max_repairs` times. OpenAI-style backends are asked
+//! for token log-probabilities; a grammatical script whose confidence is below
+//! `min_confidence` is re-prompted the same way.
+//!
+//! The HTTP call needs the `api` cargo feature. Without it, or when the call
+//! fails or no valid script comes back, the proposer falls back to the offline
+//! `rules` backend, unless `strict` is set, in which case it is an error carrying
+//! the raw model output. Every exchange (requests, responses, parse errors, fallback)
+//! is kept in the proposal's `log` so the caller can store it with the run artifacts.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::intent::{classify, Intent};
+use crate::query_proposer::{ProposedTrace, Proposer, Provenance, RulesProposer};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Provider {
+    OpenAi,
+    Anthropic,
+    /// OpenAI-compatible local server, e.g. `llama-server -m model.gguf`.
+    Local,
+}
+
+impl Provider {
+    /// `claude-*` models go to Anthropic, everything else to an OpenAI-compatible endpoint.
+    pub fn for_model(model: &str) -> Self {
+        if model.starts_with("claude") {
+            Provider::Anthropic
+        } else {
+            Provider::OpenAi
+        }
+    }
+
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            Provider::OpenAi => "https://api.openai.com/v1/chat/completions",
+            Provider::Anthropic => "https://api.anthropic.com/v1/messages",
+            Provider::Local => "http://127.0.0.1:8080/v1/chat/completions",
+        }
+    }
+
+    /// Environment variable holding the API key; local servers need none.
+    pub fn key_env(self) -> Option<&'static str> {
+        match self {
+            Provider::OpenAi => Some("OPENAI_API_KEY"),
+            Provider::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Provider::Local => None,
+        }
+    }
+}
+
+/// Sampling parameters sent with every request.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Sampling {
+    pub temperature: f64,
+    pub top_p: f64,
+    pub max_tokens: u32,
+    /// Sampling seed; sent to OpenAI-compatible endpoints (Anthropic has no seed parameter).
+    pub seed: Option<u64>,
+}
+
+impl Default for Sampling {
+    /// Greedy decoding: the same query should propose the same ops.
+    fn default() -> Self {
+        Sampling { temperature: 0.0, top_p: 1.0, max_tokens: 512, seed: None }
+    }
+}
+
+/// The op grammar the executor accepts; shared by every prompt template.
+pub const OP_GRAMMAR_PROMPT: &str = include_str!("../prompts/grammar.txt");
+
+/// Per-universe prompt templates (examples and conventions), appended to the grammar.
+pub const PROMPT_TEMPLATES: [(&str, &str); 3] = [
+    ("qe", include_str!("../prompts/qe.txt")),
+    ("ge", include_str!("../prompts/ge.txt")),
+    ("boolfun", include_str!("../prompts/boolfun.txt")),
+];
+
+/// System prompt for `intent`: the grammar followed by that universe's template, if any.
+pub fn template_for(intent: Intent) -> String {
+    let key = intent.as_str().to_lowercase();
+    let body = PROMPT_TEMPLATES.iter().find(|(k, _)| *k == key).map_or("", |(_, t)| t);
+    format!("{}\n{}", OP_GRAMMAR_PROMPT.trim_end(), body.trim_end())
+}
+
+/// Request body for a conversation of alternating user/assistant `turns`, starting with the query.
+pub fn build_request(
+    provider: Provider,
+    model: &str,
+    sampling: &Sampling,
+    system: &str,
+    turns: &[JsonValue],
+) -> JsonValue {
+    match provider {
+        Provider::OpenAi | Provider::Local => {
+            let mut messages = vec![json!({ "role": "system", "content": system })];
+            messages.extend(turns.iter().cloned());
+            let mut body = json!({
+                "model": model,
+                "temperature": sampling.temperature,
+                "top_p": sampling.top_p,
+                "max_tokens": sampling.max_tokens,
+                "messages": messages,
+            });
+            if let Some(seed) = sampling.seed {
+                body["seed"] = json!(seed);
+            }
+            body["logprobs"] = json!(true);
+            body
+        }
+        Provider::Anthropic => json!({
+            "model": model,
+            "temperature": sampling.temperature,
+            "top_p": sampling.top_p,
+            "max_tokens": sampling.max_tokens,
+            "system": system,
+            "messages": turns,
+        }),
+    }
+}
+
+fn turn(role: &str, content: &str) -> JsonValue {
+    json!({ "role": role, "content": content })
+}
+
+/// Parse errors for each op line that does not fit the grammar.
+pub fn grammar_errors(ops: &[String]) -> Vec<String> {
+    ops.iter()
+        .filter_map(|op| crate::exec::validate_op(op).err().map(|e| format!("{}: {}", op, e)))
+        .collect()
+}
+
+/// Reply text from a provider response body.
+pub fn extract_text(provider: Provider, resp: &JsonValue) -> Option<String> {
+    match provider {
+        Provider::OpenAi | Provider::Local => {
+            resp["choices"][0]["message"]["content"].as_str().map(str::to_string)
+        }
+        Provider::Anthropic => {
+            let parts: Vec<&str> = resp["content"]
+                .as_array()?
+                .iter()
+                .filter_map(|b| b["text"].as_str())
+                .collect();
+            (!parts.is_empty()).then(|| parts.join("\n"))
+        }
+    }
+}
+
+/// Total tokens a provider response reports using.
+pub fn usage_tokens(provider: Provider, resp: &JsonValue) -> Option<u64> {
+    let usage = &resp["usage"];
+    match provider {
+        Provider::OpenAi | Provider::Local => usage["total_tokens"].as_u64(),
+        Provider::Anthropic => Some(usage["input_tokens"].as_u64()? + usage["output_tokens"].as_u64()?),
+    }
+}
+
+/// Per-op and whole-script confidence from token log-probabilities.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Confidence {
+    /// exp(mean token logprob) over all op lines.
+    pub trace: f64,
+    /// exp(mean token logprob) of each op line, in script order.
+    pub ops: Vec<f64>,
+}
+
+/// Confidence of the ops in an OpenAI-style response with `logprobs` enabled.
+/// Tokens are grouped into reply lines; lines that are not ops are ignored.
+/// None if the provider returned no log-probabilities.
+pub fn op_confidence(provider: Provider, resp: &JsonValue) -> Option<Confidence> {
+    if provider == Provider::Anthropic {
+        return None;
+    }
+    let tokens = resp["choices"][0]["logprobs"]["content"].as_array()?;
+    // (line text, logprob sum, token count), split on newlines inside tokens
+    let mut lines: Vec<(String, f64, usize)> = vec![(String::new(), 0.0, 0)];
+    for t in tokens {
+        let (text, lp) = (t["token"].as_str()?, t["logprob"].as_f64()?);
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push((String::new(), 0.0, 0));
+            }
+            let cur = lines.last_mut().expect("nonempty");
+            cur.0.push_str(part);
+            if !part.trim().is_empty() {
+                cur.1 += lp;
+                cur.2 += 1;
+            }
+        }
+    }
+    let op_lines: Vec<&(String, f64, usize)> =
+        lines.iter().filter(|(l, _, n)| *n > 0 && !parse_ops(l).is_empty()).collect();
+    if op_lines.is_empty() {
+        return None;
+    }
+    let (sum, n) = op_lines.iter().fold((0.0, 0), |(s, c), (_, lp, k)| (s + lp, c + k));
+    Some(Confidence {
+        trace: (sum / n as f64).exp(),
+        ops: op_lines.iter().map(|(_, lp, k)| (lp / *k as f64).exp()).collect(),
+    })
+}
+
+/// Keep reply lines whose first token is an UPPER_SNAKE op name, after stripping
+/// list markers and code fences.
+pub fn parse_ops(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| {
+            l.trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')' | '`'))
+                .trim()
+                .trim_end_matches('`')
+                .trim()
+        })
+        .filter(|l| {
+            let head = l.split_whitespace().next().unwrap_or("");
+            head.len() > 1 && head.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+pub struct ApiProposer {
+    pub provider: Provider,
+    pub model: String,
+    pub sampling: Sampling,
+    /// Overrides `provider.endpoint()`.
+    pub endpoint: Option<String>,
+    /// Re-prompts allowed after a reply with ungrammatical ops.
+    pub max_repairs: usize,
+    /// Replaces the per-universe template as the system prompt (`--prompt-file`).
+    pub prompt: Option<String>,
+    /// Fail with the raw model output instead of falling back to `rules`.
+    pub strict: bool,
+    /// Solved examples retrieved from the few-shot bank into the prompt.
+    pub few_shots: usize,
+    /// Re-prompt (then fall back) when the script's token confidence is lower.
+    pub min_confidence: Option<f64>,
+}
+
+impl ApiProposer {
+    pub fn new(model: &str) -> Self {
+        ApiProposer {
+            provider: Provider::for_model(model),
+            model: model.to_string(),
+            sampling: Sampling::default(),
+            endpoint: None,
+            max_repairs: 2,
+            prompt: None,
+            strict: false,
+            few_shots: 3,
+            min_confidence: None,
+        }
+    }
+
+    /// A local OpenAI-compatible server running `model` (typically a .gguf path).
+    pub fn local(model: &str) -> Self {
+        ApiProposer { provider: Provider::Local, ..ApiProposer::new(model) }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        self.endpoint.as_deref().unwrap_or(self.provider.endpoint())
+    }
+
+    /// System prompt for `query`: the override if set, else the template for its intent,
+    /// plus the `few_shots` most similar solved examples.
+    pub fn system_prompt(&self, query: &str) -> String {
+        let prompt = self.prompt.clone().unwrap_or_else(|| template_for(classify(query).intent));
+        if self.few_shots == 0 {
+            return prompt;
+        }
+        let bank = crate::fewshot::bank();
+        let shots = crate::fewshot::retrieve(&bank, query, self.few_shots);
+        format!("{}\n\nSolved examples:\n{}", prompt.trim_end(), crate::fewshot::render(&shots))
+    }
+
+    #[cfg(feature = "api")]
+    fn send(&self, body: &JsonValue) -> Result<JsonValue> {
+        let key = match self.provider.key_env() {
+            Some(var) => std::env::var(var).map_err(|_| anyhow!("{} is not set", var))?,
+            None => String::new(),
+        };
+        let req = ureq::post(self.endpoint()).set("content-type", "application/json");
+        let req = match self.provider {
+            Provider::OpenAi => req.set("authorization", &format!("Bearer {}", key)),
+            Provider::Anthropic => req.set("x-api-key", &key).set("anthropic-version", "2023-06-01"),
+            Provider::Local => req,
+        };
+        let resp = req.send_json(body.clone()).map_err(|e| anyhow!("request failed: {}", e))?;
+        resp.into_json().map_err(|e| anyhow!("bad response body: {}", e))
+    }
+
+    #[cfg(not(feature = "api"))]
+    fn send(&self, _body: &JsonValue) -> Result<JsonValue> {
+        Err(anyhow!("built without the `api` feature"))
+    }
+
+    /// Ask, validate, and re-prompt with parse errors until the script is grammatical
+    /// or repairs run out. Each attempt is appended to `log["attempts"]`.
+    fn converse(
+        &self,
+        query: &str,
+        log: &mut JsonValue,
+        send: &dyn Fn(&JsonValue) -> Result<JsonValue>,
+    ) -> Result<Vec<String>> {
+        self.converse_from(query, vec![turn("user", query)], log, send)
+    }
+
+    /// `converse`, continuing an existing conversation whose last turn is the user's.
+    fn converse_from(
+        &self,
+        query: &str,
+        mut turns: Vec<JsonValue>,
+        log: &mut JsonValue,
+        send: &dyn Fn(&JsonValue) -> Result<JsonValue>,
+    ) -> Result<Vec<String>> {
+        log["endpoint"] = json!(self.endpoint());
+        log["attempts"] = json!([]);
+        let system = self.system_prompt(query);
+        for attempt in 0..=self.max_repairs {
+            let body = build_request(self.provider, &self.model, &self.sampling, &system, &turns);
+            let mut entry = json!({ "attempt": attempt, "request": body });
+            let resp = send(&body);
+            let result = resp.and_then(|resp| {
+                entry["response"] = resp.clone();
+                extract_text(self.provider, &resp).ok_or_else(|| anyhow!("no text in response"))
+            });
+            let text = match result {
+                Ok(t) => t,
+                Err(e) => {
+                    entry["error"] = json!(e.to_string());
+                    log["attempts"].as_array_mut().unwrap().push(entry);
+                    return Err(e);
+                }
+            };
+            let ops = parse_ops(&text);
+            let mut errors = grammar_errors(&ops);
+            if ops.is_empty() {
+                errors.push("reply contained no op lines".to_string());
+            }
+            entry["errors"] = json!(errors);
+            let confidence = entry.get("response").and_then(|r| op_confidence(self.provider, r)).map(|c| c.trace);
+            entry["confidence"] = json!(confidence);
+            let low = match (confidence, self.min_confidence) {
+                (Some(c), Some(min)) if errors.is_empty() && c < min => Some((c, min)),
+                _ => None,
+            };
+            log["attempts"].as_array_mut().unwrap().push(entry);
+            if errors.is_empty() && low.is_none() {
+                return Ok(ops);
+            }
+            turns.push(turn("assistant", &text));
+            let retry = match low {
+                Some((c, min)) => format!(
+                    "That script's confidence is {:.3}, below the required {:.3}. Re-check each op against the grammar \
+                     and reply with the op script only.",
+                    c, min
+                ),
+                None => format!(
+                    "These lines are not valid ops:\n{}\nReply with the corrected op script only.",
+                    errors.join("\n")
+                ),
+            };
+            turns.push(turn("user", &retry));
+        }
+        Err(anyhow!("no grammatical, confident op script after {} repair(s)", self.max_repairs))
+    }
+}
+
+impl Proposer for ApiProposer {
+    fn name(&self) -> &str {
+        match self.provider {
+            Provider::Local => "local",
+            _ => "api",
+        }
+    }
+
+    fn propose(&self, query: &str) -> Result<ProposedTrace> {
+        self.propose_with(query, &|body| self.send(body))
+    }
+
+    fn refine(&self, query: &str, previous: &ProposedTrace, emptied: usize, feedback: &str) -> Result<ProposedTrace> {
+        self.refine_with(query, previous, emptied, feedback, &|body| self.send(body))
+    }
+}
+
+impl ApiProposer {
+    /// Provenance from the exchange in `log`: the prompt, the last reply, and token usage.
+    fn provenance(&self, query: &str, log: &JsonValue, fallback_used: bool) -> Provenance {
+        let responses: Vec<&JsonValue> = log["attempts"]
+            .as_array()
+            .map(|a| a.iter().filter_map(|e| e.get("response")).collect())
+            .unwrap_or_default();
+        let tokens: Vec<u64> = responses.iter().filter_map(|r| usage_tokens(self.provider, r)).collect();
+        let confidence = responses.last().and_then(|r| op_confidence(self.provider, r));
+        Provenance {
+            backend: self.name().to_string(),
+            model: Some(self.model.clone()),
+            prompt: Some(self.system_prompt(query)),
+            raw_text: responses.last().map(|r| extract_text(self.provider, r).unwrap_or_else(|| r.to_string())),
+            tokens: (!tokens.is_empty()).then(|| tokens.iter().sum()),
+            device: Some(self.endpoint().to_string()),
+            confidence: confidence.as_ref().map(|c| c.trace),
+            op_confidence: confidence.map(|c| c.ops),
+            fallback_used,
+        }
+    }
+
+    /// Ask the model to revise `previous` given the empty-set `feedback`; without a
+    /// usable reply (and not `strict`), drop the step that emptied the set instead.
+    fn refine_with(
+        &self,
+        query: &str,
+        previous: &ProposedTrace,
+        emptied: usize,
+        feedback: &str,
+        send: &dyn Fn(&JsonValue) -> Result<JsonValue>,
+    ) -> Result<ProposedTrace> {
+        let turns = vec![turn("user", query), turn("assistant", &previous.ops.join("\n")), turn("user", feedback)];
+        let mut log = json!({ "proposer": self.name(), "model": self.model, "refine": feedback });
+        match self.converse_from(query, turns, &mut log, send) {
+            Ok(ops) => {
+                log["ops"] = json!(ops);
+                Ok(ProposedTrace {
+                    ops,
+                    rationale: format!("{}: {} (refined)", self.name(), self.model),
+                    trace: None,
+                    provenance: self.provenance(query, &log, false),
+                    log: Some(log),
+                })
+            }
+            Err(e) if self.strict => Err(e),
+            Err(_) => crate::query_proposer::relax(previous, emptied),
+        }
+    }
+
+    fn propose_with(&self, query: &str, send: &dyn Fn(&JsonValue) -> Result<JsonValue>) -> Result<ProposedTrace> {
+        let mut log = json!({ "proposer": self.name(), "model": self.model });
+        match self.converse(query, &mut log, send) {
+            Ok(ops) => {
+                log["ops"] = json!(ops);
+                Ok(ProposedTrace {
+                    ops,
+                    rationale: format!("{}: {}", self.name(), self.model),
+                    trace: None,
+                    provenance: self.provenance(query, &log, false),
+                    log: Some(log),
+                })
+            }
+            Err(e) if self.strict => {
+                let raw = self.provenance(query, &log, false).raw_text.unwrap_or_else(|| "<no response>".to_string());
+                Err(anyhow!("{} proposer failed (strict, no fallback): {}\nraw model output:\n{}", self.name(), e, raw))
+            }
+            Err(e) => {
+                let mut p = RulesProposer.propose(query).map_err(|r| anyhow!("api: {}; fallback: {}", e, r))?;
+                log["error"] = json!(e.to_string());
+                log["fallback"] = json!("rules");
+                log["ops"] = json!(p.ops);
+                p.rationale = format!("{} unavailable ({}), fell back to {}", self.name(), e, p.rationale);
+                p.provenance = self.provenance(query, &log, true);
+                p.log = Some(log);
+                Ok(p)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_and_reply_round_trip() {
+        let req = build_request(Provider::Anthropic, "claude-x", &Sampling::default(), "sys", &[turn("user", "q")]);
+        assert_eq!(req["system"], json!("sys"));
+        assert_eq!(Provider::for_model("gpt-4o"), Provider::OpenAi);
+        let local = ApiProposer {
+            sampling: Sampling { temperature: 0.7, top_p: 0.9, max_tokens: 64, seed: Some(7) },
+            ..ApiProposer::local("models/mistral-7b.Q4_K_M.gguf")
+        };
+        assert_eq!(local.name(), "local");
+        assert_eq!(local.endpoint(), "http://127.0.0.1:8080/v1/chat/completions");
+        let req = build_request(local.provider, &local.model, &local.sampling, "sys", &[turn("user", "q")]);
+        assert_eq!(req["top_p"], json!(0.9));
+        assert_eq!(req["max_tokens"], json!(64));
+        assert_eq!(req["seed"], json!(7));
+        let usage = json!({ "usage": { "input_tokens": 30, "output_tokens": 12 } });
+        assert_eq!(usage_tokens(Provider::Anthropic, &usage), Some(42));
+
+        let resp = json!({ "choices": [{ "message": { "content":
+            "Here you go:\n```\n1. LOAD 13/37\n- MASK_BIT bit=2 val=1\nRETURN_SET max_items=5 include_witness=1\n```" } }] });
+        let text = extract_text(Provider::OpenAi, &resp).unwrap();
+        assert_eq!(
+            parse_ops(&text),
+            vec!["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=5 include_witness=1"]
+        );
+    }
+
+    #[test]
+    fn prompt_template_follows_query_intent() {
+        assert!(template_for(Intent::Boolfun).contains("Universe: BOOLFUN"));
+        assert_eq!(template_for(Intent::Lattice), OP_GRAMMAR_PROMPT.trim_end().to_string() + "\n");
+        let p = ApiProposer::new("gpt-4o");
+        let sys = p.system_prompt("triangles similar to 3,4,5");
+        assert!(sys.starts_with(OP_GRAMMAR_PROMPT.trim_end()));
+        assert!(sys.contains("Universe: GE"));
+        assert!(!sys.contains("Universe: QE"));
+        assert!(sys.contains("Solved examples:\nQuery: triangles similar to 3,4,5"));
+        let p = ApiProposer { prompt: Some("custom".into()), few_shots: 0, ..p };
+        assert_eq!(p.system_prompt("triangles similar to 3,4,5"), "custom");
+    }
+
+    #[cfg(not(feature = "api"))]
+    #[test]
+    fn falls_back_to_rules_and_logs_why() {
+        let p = ApiProposer::new("gpt-4o").propose("fractions near 1/3 with den<=6").unwrap();
+        assert_eq!(p.ops.first().map(String::as_str), Some("LOAD 1/3"));
+        let log = p.log.unwrap();
+        assert_eq!(log["fallback"], json!("rules"));
+        assert!(log["attempts"][0]["request"]["messages"].is_array());
+    }
+
+    #[test]
+    fn low_confidence_scripts_are_repaired() {
+        let reply = |lp: f64| {
+            let toks = ["LOAD", " 1/3", "\n", "RETURN_SET", " max_items=5 include_witness=1"];
+            json!({ "choices": [{
+                "message": { "content": toks.concat() },
+                "logprobs": { "content": toks.iter().map(|t| json!({ "token": t, "logprob": lp })).collect::<Vec<_>>() },
+            }] })
+        };
+        let c = op_confidence(Provider::OpenAi, &reply(-0.1)).unwrap();
+        assert_eq!(c.ops.len(), 2);
+        assert!((c.trace - (-0.1f64).exp()).abs() < 1e-9);
+        assert_eq!(op_confidence(Provider::Anthropic, &reply(-0.1)), None);
+
+        let replies = std::cell::RefCell::new(vec![reply(-2.0), reply(-0.01)]);
+        let send = |_: &JsonValue| -> Result<JsonValue> { Ok(replies.borrow_mut().remove(0)) };
+        let p = ApiProposer { min_confidence: Some(0.5), ..ApiProposer::new("gpt-4o") };
+        let mut log = json!({});
+        p.converse("q", &mut log, &send).unwrap();
+        let attempts = log["attempts"].as_array().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts[0]["confidence"].as_f64().unwrap() < 0.5);
+        assert!(p.provenance("q", &log, false).confidence.unwrap() > 0.9);
+    }
+
+    #[test]
+    fn repair_loop_reprompts_with_parse_errors() {
+        let replies = std::cell::RefCell::new(vec![
+            "LOAD 13/37\nMASK_BIT bit=2\nRETURN_SET max_items=5 include_witness=1",
+            "LOAD 13/37\nMASK_BIT bit=2 val=1\nRETURN_SET max_items=5 include_witness=1",
+        ]);
+        let send = |_: &JsonValue| -> Result<JsonValue> {
+            let text = replies.borrow_mut().remove(0);
+            Ok(json!({ "choices": [{ "message": { "content": text } }] }))
+        };
+        let p = ApiProposer::new("gpt-4o");
+        let mut log = json!({});
+        let ops = p.converse("q", &mut log, &send).unwrap();
+        assert_eq!(ops[1], "MASK_BIT bit=2 val=1");
+        let prov = p.provenance("q", &log, false);
+        assert!(prov.raw_text.unwrap().contains("MASK_BIT bit=2 val=1"));
+        assert_eq!(prov.device.as_deref(), Some(Provider::OpenAi.endpoint()));
+        let attempts = log["attempts"].as_array().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0]["errors"].as_array().unwrap().len(), 1);
+        // the second request carries the bad reply and the parse errors
+        let msgs = attempts[1]["request"]["messages"].as_array().unwrap();
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs[3]["content"].as_str().unwrap().contains("MASK_BIT bit=2:"));
+
+        let p = ApiProposer { max_repairs: 0, ..ApiProposer::new("gpt-4o") };
+        let bad = |_: &JsonValue| -> Result<JsonValue> { Ok(json!({ "choices": [{ "message": { "content": "FOO" } }] })) };
+        assert!(p.converse("q", &mut json!({}), &bad).is_err());
+
+        let lenient = p.propose_with("fractions near 1/3", &bad).unwrap();
+        let send_ok = |body: &JsonValue| -> Result<JsonValue> {
+            // the revision request carries the previous script and the feedback
+            let msgs = body["messages"].as_array().unwrap();
+            assert_eq!(msgs.last().unwrap()["content"], json!("step 1 emptied the set"));
+            Ok(json!({ "choices": [{ "message": { "content": "FOO" } }] }))
+        };
+        assert!(lenient.provenance.fallback_used);
+        assert_eq!(lenient.provenance.raw_text.as_deref(), Some("FOO"));
+        assert_eq!(lenient.log.as_ref().unwrap()["fallback"], json!("rules"));
+        let refined = p.refine_with("fractions near 1/3", &lenient, 1, "step 1 emptied the set", &send_ok).unwrap();
+        assert_eq!(refined.ops.len(), lenient.ops.len() - 1);
+        let strict = ApiProposer { strict: true, ..p };
+        let err = strict.propose_with("fractions near 1/3", &bad).unwrap_err().to_string();
+        assert!(err.contains("raw model output:\nFOO"), "{}", err);
+    }
+}
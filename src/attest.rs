@@ -0,0 +1,173 @@
+//! Batch attestations: one Merkle root over many runs' chain hashes.
+//!
+//! `attest` replays each run (or, with `replay` off, takes the chain hash its
+//! result.json records) and builds a SHA-256 Merkle tree whose leaves are, in
+//! run-id order,
+//!
+//! ```text
+//! SHA-256("lnst/attest/v1" 0x00 <run id> 0x00 <32-byte chain hash>)
+//! ```
+//!
+//! Publishing the root commits to the whole batch. Each run's entry carries
+//! its index and sibling path, the same shape as `RETURN_SET include_proofs`,
+//! so one run can be shown to be in the batch without the others.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::digest::{merkle_path, merkle_root_from_path, merkle_root_with, sha256_bytes, HashAlg};
+
+/// `format` of an attestation written by this version.
+pub const FORMAT: &str = "lnst-attest/1";
+const DOMAIN: &str = "lnst/attest/v1";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AttestedRun {
+    pub run_id: String,
+    pub chain_hash: String,
+    pub index: usize,
+    /// Sibling hashes from the leaf up to the root.
+    pub path: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Attestation {
+    pub format: String,
+    pub root: String,
+    /// Whether every run was replayed before it was included.
+    pub replayed: bool,
+    pub runs: Vec<AttestedRun>,
+}
+
+/// The tree leaf for one run.
+pub fn attest_leaf(run_id: &str, chain_hash: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(DOMAIN.len() + run_id.len() + 34);
+    bytes.extend_from_slice(DOMAIN.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(run_id.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(chain_hash);
+    sha256_bytes(&bytes)
+}
+
+fn parse_hex32(s: &str) -> Option<[u8; 32]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+/// Run directories named by `paths`: each is a run (it holds result.json) or
+/// a directory of runs, e.g. `runs/`.
+fn run_dirs(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for p in paths {
+        if p.join("result.json").is_file() {
+            dirs.push(p.clone());
+            continue;
+        }
+        let entries = fs::read_dir(p).map_err(|e| anyhow!("reading {}: {}", p.display(), e))?;
+        let before = dirs.len();
+        for entry in entries {
+            let dir = entry?.path();
+            if dir.join("result.json").is_file() {
+                dirs.push(dir);
+            }
+        }
+        if dirs.len() == before {
+            return Err(anyhow!("{} is neither a run directory nor holds any", p.display()));
+        }
+    }
+    Ok(dirs)
+}
+
+/// The chain hash `run_dir` attests to: replayed, or as result.json records it.
+fn chain_hash(run_dir: &Path, replay: bool) -> Result<[u8; 32]> {
+    let hash = if replay {
+        let report = crate::verify::verify_trace_report(&run_dir.join("trace.ndjson"));
+        if !report.valid {
+            return Err(anyhow!("{} does not verify: {}", run_dir.display(), report.reason.unwrap_or_default()));
+        }
+        report.chain_hash
+    } else {
+        let path = run_dir.join("result.json");
+        let txt = fs::read_to_string(&path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+        let result: serde_json::Value = serde_json::from_str(&txt)?;
+        result["chain_hash"].as_str().map(str::to_string)
+    };
+    hash.as_deref().and_then(parse_hex32).ok_or_else(|| anyhow!("{} has no chain hash", run_dir.display()))
+}
+
+/// Attest to the runs under `paths`, replaying each one first when `replay`.
+pub fn attest(paths: &[PathBuf], replay: bool) -> Result<Attestation> {
+    use rayon::prelude::*;
+    let dirs = run_dirs(paths)?;
+    let mut runs: Vec<(String, [u8; 32])> = dirs
+        .par_iter()
+        .map(|dir| {
+            let id = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            Ok((id, chain_hash(dir, replay)?))
+        })
+        .collect::<Result<_>>()?;
+    runs.sort();
+    if let Some(w) = runs.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(anyhow!("run id {} appears twice", w[0].0));
+    }
+
+    let leaves: Vec<[u8; 32]> = runs.iter().map(|(id, h)| attest_leaf(id, h)).collect();
+    let root = merkle_root_with(&HashAlg::Sha256, &leaves);
+    let runs = runs
+        .into_iter()
+        .enumerate()
+        .map(|(index, (run_id, h))| AttestedRun {
+            run_id,
+            chain_hash: hex::encode(h),
+            index,
+            path: merkle_path(&HashAlg::Sha256, &leaves, index).unwrap_or_default().into_iter().map(hex::encode).collect(),
+        })
+        .collect();
+    Ok(Attestation { format: FORMAT.to_string(), root: hex::encode(root), replayed: replay, runs })
+}
+
+/// Whether `run`'s inclusion proof leads to `root`.
+pub fn check_inclusion(root: &str, run: &AttestedRun) -> bool {
+    let Some(chain) = parse_hex32(&run.chain_hash) else { return false };
+    let Some(path) = run.path.iter().map(|h| parse_hex32(h)).collect::<Option<Vec<_>>>() else { return false };
+    let leaf = attest_leaf(&run.run_id, &chain);
+    hex::encode(merkle_root_from_path(&HashAlg::Sha256, &leaf, run.index, &path)) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_run_proves_its_inclusion() {
+        let dirs: Vec<PathBuf> = ["1/3", "2/5", "13/37"]
+            .iter()
+            .map(|e| {
+                let ops = vec![format!("LOAD {}", e), "MASK_BIT bit=2 val=1".to_string()];
+                crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap()
+            })
+            .collect();
+        let att = attest(&dirs, true).unwrap();
+        assert_eq!(att.runs.len(), 3);
+        assert!(att.runs.windows(2).all(|w| w[0].run_id < w[1].run_id));
+        assert!(att.runs.iter().all(|r| check_inclusion(&att.root, r)));
+        assert_eq!(attest(&dirs, false).unwrap().root, att.root);
+
+        let mut forged = att.runs[1].clone();
+        forged.chain_hash = att.runs[0].chain_hash.clone();
+        assert!(!check_inclusion(&att.root, &forged));
+
+        // a run whose result.json disagrees with its trace is not attested after replay
+        let result = dirs[0].join("result.json");
+        let txt = fs::read_to_string(&result).unwrap();
+        let chain = att.runs.iter().find(|r| dirs[0].ends_with(&r.run_id)).unwrap().chain_hash.clone();
+        fs::write(&result, txt.replace(&chain, &"0".repeat(64))).unwrap();
+        assert!(attest(&dirs, true).unwrap_err().to_string().contains("does not verify"));
+        assert_ne!(attest(&dirs, false).unwrap().root, att.root);
+        for dir in dirs {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
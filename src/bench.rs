@@ -0,0 +1,213 @@
+//! Built-in micro-benchmarks for the executor's hot paths.
+//!
+//! Each suite runs one fixed workload and times its phases separately:
+//! building the universe, filtering it on a signature predicate, digesting the
+//! universe into a Merkle root, searching the filtered set for a witness, and
+//! replay-verifying an executed trace of the same query. Every phase is run
+//! `iters` times; the report keeps min / mean / max in microseconds.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::digest::{merkle_root, sha256_bytes};
+
+/// Suite names accepted by `lnst bench --suite`.
+pub const SUITES: &[&str] = &["qe", "boolfun", "geom"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PhaseStats {
+    pub min_us: u128,
+    pub mean_us: u128,
+    pub max_us: u128,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Phases {
+    pub construct: PhaseStats,
+    pub filter: PhaseStats,
+    pub digest: PhaseStats,
+    pub witness: PhaseStats,
+    pub verify: PhaseStats,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SuiteReport {
+    pub suite: String,
+    pub universe_size: usize,
+    pub filtered_size: usize,
+    pub witness: Option<String>,
+    /// Witness the executor chose for the same query, from the verify phase
+    /// (triangles are reported there as the ratio a/c).
+    pub executor_witness: Option<String>,
+    pub phases: Phases,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub iters: usize,
+    pub suites: Vec<SuiteReport>,
+}
+
+/// Run `f` `iters` times and return its timing plus the last result.
+fn time<T>(iters: usize, mut f: impl FnMut() -> T) -> (PhaseStats, T) {
+    let mut samples = Vec::with_capacity(iters);
+    let mut last = None;
+    for _ in 0..iters {
+        let start = Instant::now();
+        let out = black_box(f());
+        samples.push(start.elapsed().as_micros());
+        last = Some(out);
+    }
+    let stats = PhaseStats {
+        min_us: samples.iter().copied().min().unwrap_or(0),
+        mean_us: samples.iter().sum::<u128>() / samples.len().max(1) as u128,
+        max_us: samples.iter().copied().max().unwrap_or(0),
+    };
+    (stats, last.expect("iters >= 1"))
+}
+
+fn digest_of<B: AsRef<[u8]>>(items: impl Iterator<Item = B>) -> [u8; 32] {
+    let leaves: Vec<[u8; 32]> = items.map(|b| sha256_bytes(b.as_ref())).collect();
+    merkle_root(&leaves)
+}
+
+/// Execute `ops` once, then time replay verification of the resulting trace.
+/// The run directory is removed afterwards; the executor's witness is
+/// returned alongside so the suite can check it searched the same thing.
+fn time_verify(iters: usize, ops: &[&str]) -> Result<(PhaseStats, Option<String>)> {
+    let ops: Vec<String> = ops.iter().map(|s| s.to_string()).collect();
+    let run = crate::exec::run_trace_and_write(&ops, None, false)?;
+    let dir = run.artifacts_path.ok_or_else(|| anyhow!("bench run wrote no artifacts"))?;
+    let trace = dir.join("trace.ndjson");
+    let (stats, report) = time(iters, || crate::verify::verify_trace_report(&trace));
+    let _ = fs::remove_dir_all(&dir);
+    if !report.valid {
+        return Err(anyhow!("bench trace failed verification: {}", report.reason.unwrap_or_default()));
+    }
+    Ok((stats, run.witness))
+}
+
+fn bench_qe(iters: usize) -> Result<SuiteReport> {
+    use crate::exec::witness_nearest;
+    use crate::qe::{build_qe, parse_frac};
+    use crate::semtrace::sig7;
+
+    let target = parse_frac("1/4").ok_or_else(|| anyhow!("bad bench target"))?;
+    let (construct, universe) = time(iters, build_qe);
+    let (filter, filtered) =
+        time(iters, || universe.iter().filter(|f| sig7(f) & (1 << 2) != 0).cloned().collect::<Vec<_>>());
+    let (digest, _) = time(iters, || digest_of(universe.iter().map(|f| f.canonical_bytes())));
+    let (witness, w) = time(iters, || witness_nearest(&filtered, &target));
+    let (verify, executed) = time_verify(iters, &["LOAD 1/4", "MASK_BIT bit=2 val=1", "WITNESS_NEAREST target_elem=1/4 metric=ABS_DIFF"])?;
+    Ok(SuiteReport {
+        suite: "qe".to_string(),
+        universe_size: universe.len(),
+        filtered_size: filtered.len(),
+        witness: w.map(|f| format!("{}/{}", f.num, f.den)),
+        executor_witness: executed,
+        phases: Phases { construct, filter, digest, witness, verify },
+    })
+}
+
+fn bench_boolfun(iters: usize) -> Result<SuiteReport> {
+    use crate::boolfun::{build_boolfun, canonical_cmp, parse_elem};
+
+    let target = parse_elem("0xBEEF").ok_or_else(|| anyhow!("bad bench target"))?;
+    let (construct, universe) = time(iters, || build_boolfun(4));
+    let (filter, filtered) = time(iters, || universe.iter().filter(|f| f.is_monotone()).cloned().collect::<Vec<_>>());
+    let (digest, _) = time(iters, || digest_of(universe.iter().map(|f| f.canonical_bytes())));
+    let (witness, w) = time(iters, || {
+        filtered.iter().min_by(|a, b| a.hamming(&target).cmp(&b.hamming(&target)).then_with(|| canonical_cmp(a, b))).cloned()
+    });
+    let (verify, executed) = time_verify(
+        iters,
+        &["SELECT_UNIVERSE universe=BOOLFUN n=4", "FILTER_CLASS class=monotone", "TOPK target_elem=0xBEEF k=1"],
+    )?;
+    Ok(SuiteReport {
+        suite: "boolfun".to_string(),
+        universe_size: universe.len(),
+        filtered_size: filtered.len(),
+        witness: w.map(|f| format!("0x{:04X}", f.bits)),
+        executor_witness: executed,
+        phases: Phases { construct, filter, digest, witness, verify },
+    })
+}
+
+fn bench_geom(iters: usize) -> Result<SuiteReport> {
+    use crate::geom::{build_ge, canonical_cmp, similarity_ties, Tri};
+    use crate::semtrace::sig7_geom;
+
+    let target = Tri::new(6, 8, 10).ok_or_else(|| anyhow!("bad bench target"))?;
+    let (construct, universe) = time(iters, || build_ge(20));
+    let (filter, filtered) = time(iters, || {
+        let mut set: Vec<Tri> = universe.iter().filter(|t| sig7_geom(t) & (1 << 4) != 0).copied().collect();
+        set.sort_by(canonical_cmp);
+        set
+    });
+    let (digest, _) = time(iters, || digest_of(universe.iter().map(|t| t.to_bytes())));
+    let (witness, w) = time(iters, || similarity_ties(&filtered, &target).first().copied());
+    let (verify, executed) = time_verify(iters, &["LOAD 6,8,10", "MASK_BIT bit=4 val=1", "WITNESS_NEAREST target_elem=6,8,10 metric=SIMILARITY"])?;
+    Ok(SuiteReport {
+        suite: "geom".to_string(),
+        universe_size: universe.len(),
+        filtered_size: filtered.len(),
+        witness: w.map(|t| format!("{},{},{}", t.a, t.b, t.c)),
+        executor_witness: executed,
+        phases: Phases { construct, filter, digest, witness, verify },
+    })
+}
+
+/// Parse a comma-separated suite list such as `qe,boolfun,geom`.
+pub fn parse_suites(spec: &str) -> Result<Vec<String>> {
+    let suites: Vec<String> =
+        spec.split(',').map(|s| s.trim().to_ascii_lowercase()).filter(|s| !s.is_empty()).collect();
+    if suites.is_empty() {
+        return Err(anyhow!("no bench suite named (choose from {})", SUITES.join(", ")));
+    }
+    if let Some(bad) = suites.iter().find(|s| !SUITES.contains(&s.as_str())) {
+        return Err(anyhow!("unknown bench suite {:?} (choose from {})", bad, SUITES.join(", ")));
+    }
+    Ok(suites)
+}
+
+/// Run the named suites, each phase `iters` times.
+pub fn run(suites: &[String], iters: usize) -> Result<Report> {
+    if iters == 0 {
+        return Err(anyhow!("--iters must be at least 1"));
+    }
+    let mut out = Vec::new();
+    for s in suites {
+        out.push(match s.as_str() {
+            "qe" => bench_qe(iters)?,
+            "boolfun" => bench_boolfun(iters)?,
+            "geom" => bench_geom(iters)?,
+            other => return Err(anyhow!("unknown bench suite {:?}", other)),
+        });
+    }
+    Ok(Report { iters, suites: out })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suites_agree_with_the_executor() {
+        assert!(parse_suites("qe,nope").is_err());
+        let report = run(&parse_suites("qe,boolfun, GEOM").unwrap(), 1).unwrap();
+        assert_eq!(report.suites.len(), 3);
+        for s in &report.suites {
+            assert!(s.filtered_size > 0 && s.filtered_size < s.universe_size, "{:?}", s);
+            assert!(s.witness.is_some());
+            let w = s.witness.clone().unwrap();
+            let expected = match w.split(',').collect::<Vec<_>>()[..] {
+                [a, _, c] => format!("{}/{}", a, c),
+                _ => w,
+            };
+            assert_eq!(s.executor_witness.as_deref(), Some(expected.as_str()), "{}", s.suite);
+        }
+    }
+}
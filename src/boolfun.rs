@@ -77,6 +77,193 @@ impl BoolFun {
     }
 }
 
+/// Structural classes for FILTER_CLASS (truth-table universes only, n ≤ 6).
+pub const CLASSES: [&str; 5] = ["monotone", "affine", "linear", "symmetric", "self_dual"];
+
+impl BoolFun {
+    /// Output on input row x (bit x of the packed table).
+    pub fn eval(&self, x: u32) -> bool {
+        (self.bits >> x) & 1 == 1
+    }
+
+    /// Flipping any input 0→1 never flips the output 1→0.
+    pub fn is_monotone(&self) -> bool {
+        (0..self.rows()).all(|x| {
+            (0..self.n as u32)
+                .filter(|i| x & (1 << i) == 0)
+                .all(|i| !self.eval(x) || self.eval(x | (1 << i)))
+        })
+    }
+
+    /// f(x) = c ⊕ a·x: determined by f(0) and the unit-vector rows.
+    pub fn is_affine(&self) -> bool {
+        let c = self.eval(0);
+        (0..self.rows()).all(|x| {
+            let lin = (0..self.n as u32)
+                .filter(|i| x & (1 << i) != 0)
+                .fold(c, |acc, i| acc ^ self.eval(1 << i) ^ c);
+            self.eval(x) == lin
+        })
+    }
+
+    pub fn is_linear(&self) -> bool {
+        !self.eval(0) && self.is_affine()
+    }
+
+    /// Output depends only on the number of 1 inputs.
+    pub fn is_symmetric(&self) -> bool {
+        let mut by_weight: [Option<bool>; 7] = [None; 7];
+        (0..self.rows()).all(|x| {
+            let slot = &mut by_weight[x.count_ones() as usize];
+            *slot.get_or_insert(self.eval(x)) == self.eval(x)
+        })
+    }
+
+    /// f(¬x) = ¬f(x) for every input.
+    pub fn is_self_dual(&self) -> bool {
+        let all = self.rows() - 1;
+        (0..self.rows()).all(|x| self.eval(x ^ all) != self.eval(x))
+    }
+
+    /// Walsh–Hadamard spectrum W(a) = Σₓ (−1)^(f(x) ⊕ a·x), by the fast in-place butterfly.
+    pub fn walsh(&self) -> Vec<i32> {
+        let rows = self.rows() as usize;
+        let mut w: Vec<i32> = (0..rows as u32).map(|x| if self.eval(x) { -1 } else { 1 }).collect();
+        let mut h = 1;
+        while h < rows {
+            for i in (0..rows).step_by(2 * h) {
+                for j in i..i + h {
+                    let (a, b) = (w[j], w[j + h]);
+                    w[j] = a + b;
+                    w[j + h] = a - b;
+                }
+            }
+            h *= 2;
+        }
+        w
+    }
+
+    /// Hamming distance to the nearest affine function: 2^(n−1) − max|W(a)|/2.
+    pub fn nonlinearity(&self) -> u32 {
+        let max = self.walsh().iter().map(|w| w.unsigned_abs()).max().unwrap_or(0);
+        (self.rows() - max) / 2
+    }
+
+    /// Algebraic degree: the largest monomial in the algebraic normal form (Möbius transform).
+    /// The constant zero function has degree 0.
+    pub fn degree(&self) -> u32 {
+        let mut anf = self.bits & self.mask();
+        for i in 0..self.n as u32 {
+            for x in 0..self.rows() {
+                if x & (1 << i) != 0 && (anf >> (x ^ (1 << i))) & 1 == 1 {
+                    anf ^= 1 << x;
+                }
+            }
+        }
+        (0..self.rows())
+            .filter(|x| (anf >> x) & 1 == 1)
+            .map(|x| x.count_ones())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Output complement ¬f(x).
+    pub fn negate(&self) -> BoolFun {
+        BoolFun { n: self.n, bits: !self.bits & self.mask() }
+    }
+
+    /// Dual f^d(x) = ¬f(¬x).
+    pub fn dual(&self) -> BoolFun {
+        let all = self.rows() - 1;
+        let bits = (0..self.rows())
+            .filter(|&x| !self.eval(x ^ all))
+            .fold(0u64, |acc, x| acc | (1 << x));
+        BoolFun { n: self.n, bits }
+    }
+
+    /// g(x_0, …, x_{n−1}) = f(x_{perm[0]}, …, x_{perm[n−1]}); `perm` must be a permutation of 0..n.
+    pub fn permute_vars(&self, perm: &[u8]) -> BoolFun {
+        let bits = (0..self.rows())
+            .filter(|&x| {
+                let y = perm
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &p)| x & (1 << p) != 0)
+                    .fold(0u32, |acc, (i, _)| acc | (1 << i));
+                self.eval(y)
+            })
+            .fold(0u64, |acc, x| acc | (1 << x));
+        BoolFun { n: self.n, bits }
+    }
+
+    /// Cofactor f|_{x_var = value} as a function of the remaining n−1 variables (order kept).
+    pub fn restrict(&self, var: u8, value: bool) -> BoolFun {
+        let low = (1u32 << var) - 1;
+        let bits = (0..self.rows() / 2)
+            .filter(|&x| {
+                let y = (x & low) | ((x & !low) << 1) | ((value as u32) << var);
+                self.eval(y)
+            })
+            .fold(0u64, |acc, x| acc | (1 << x));
+        BoolFun { n: self.n - 1, bits }
+    }
+
+    /// NPN-canonical representative: the smallest truth table reachable by permuting inputs,
+    /// negating inputs and negating the output. Exhaustive over n!·2^n·2 transforms.
+    pub fn npn_canonical(&self) -> BoolFun {
+        let mask = self.mask();
+        let mut best = self.bits & mask;
+        for perm in permutations(self.n) {
+            let table: Vec<u32> = (0..self.rows())
+                .map(|x| {
+                    perm.iter()
+                        .enumerate()
+                        .filter(|(_, &p)| x & (1 << p) != 0)
+                        .fold(0u32, |acc, (i, _)| acc | (1 << i))
+                })
+                .collect();
+            for neg in 0..self.rows() {
+                let bits = table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &y)| self.eval(y ^ neg))
+                    .fold(0u64, |acc, (x, _)| acc | (1 << x));
+                best = best.min(bits).min(!bits & mask);
+            }
+        }
+        BoolFun { n: self.n, bits: best }
+    }
+
+    /// Membership in a named class from CLASSES.
+    pub fn in_class(&self, class: &str) -> Option<bool> {
+        Some(match class {
+            "monotone" => self.is_monotone(),
+            "affine" => self.is_affine(),
+            "linear" => self.is_linear(),
+            "symmetric" => self.is_symmetric(),
+            "self_dual" => self.is_self_dual(),
+            _ => return None,
+        })
+    }
+}
+
+/// All permutations of 0..n in lexicographic order.
+fn permutations(n: u8) -> Vec<Vec<u8>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+    let mut out = Vec::new();
+    for p in permutations(n - 1) {
+        for i in 0..=p.len() {
+            let mut q = p.clone();
+            q.insert(i, n - 1);
+            out.push(q);
+        }
+    }
+    out.sort();
+    out
+}
+
 /// Canonical total order: (n ascending, bits ascending).
 pub fn canonical_cmp(a: &BoolFun, b: &BoolFun) -> Ordering {
     let o1 = a.n.cmp(&b.n);
@@ -271,4 +458,84 @@ mod tests {
         assert!(parse_elem("u64:128").is_none());
     }
 
+    #[test]
+    fn structural_classes_n2() {
+        let all = build_boolfun(2);
+        let count = |c: &str| all.iter().filter(|f| f.in_class(c).unwrap()).count();
+        // Dedekind number M(2) = 6 monotone functions; 8 affine, 4 linear
+        assert_eq!(count("monotone"), 6);
+        assert_eq!(count("affine"), 8);
+        assert_eq!(count("linear"), 4);
+        // 2^(n+1) symmetric, 2^(2^(n-1)) self-dual
+        assert_eq!(count("symmetric"), 8);
+        assert_eq!(count("self_dual"), 4);
+        // x0 AND x1 = rows 0b1000
+        let and = BoolFun { n: 2, bits: 0b1000 };
+        assert!(and.is_monotone() && and.is_symmetric() && !and.is_affine());
+        assert_eq!(and.in_class("bent"), None);
+    }
+
+    #[test]
+    fn transforms() {
+        // f = x0 AND NOT x1 (n=2): rows 00,01,10,11 -> only x=1 (x0=1,x1=0) is true
+        let f = BoolFun { n: 2, bits: 0b0010 };
+        assert_eq!(f.negate().bits, 0b1101);
+        // dual of AND is OR
+        assert_eq!(BoolFun { n: 2, bits: 0b1000 }.dual().bits, 0b1110);
+        // swapping x0,x1 gives x1 AND NOT x0
+        assert_eq!(f.permute_vars(&[1, 0]).bits, 0b0100);
+        assert_eq!(f.permute_vars(&[0, 1]), f);
+        // f|x1=0 = x0, f|x0=0 = 0
+        assert_eq!(f.restrict(1, false), BoolFun { n: 1, bits: 0b10 });
+        assert_eq!(f.restrict(0, false), BoolFun { n: 1, bits: 0 });
+        for g in build_boolfun(3) {
+            assert_eq!(g.dual().dual(), g);
+            assert_eq!(g.is_self_dual(), g.dual() == g);
+        }
+    }
+
+    #[test]
+    fn npn_classes() {
+        // known NPN class counts: n=1 → 2, n=2 → 4, n=3 → 14
+        for (n, classes) in [(1u8, 2usize), (2, 4), (3, 14)] {
+            let mut reps: Vec<u64> = build_boolfun(n).iter().map(|f| f.npn_canonical().bits).collect();
+            reps.sort_unstable();
+            reps.dedup();
+            assert_eq!(reps.len(), classes, "n={}", n);
+        }
+        // x0 AND NOT x1 is NPN-equivalent to AND
+        let f = BoolFun { n: 2, bits: 0b0010 };
+        assert_eq!(f.npn_canonical(), BoolFun { n: 2, bits: 0b1000 }.npn_canonical());
+    }
+
+    #[test]
+    fn nonlinearity_and_degree() {
+        // x0x1 ⊕ x2x3 is bent: nonlinearity 2^(n−1) − 2^(n/2−1) = 6
+        let bent = BoolFun {
+            n: 4,
+            bits: (0..16u64)
+                .filter(|x| ((x & 1) & (x >> 1 & 1)) ^ ((x >> 2 & 1) & (x >> 3 & 1)) == 1)
+                .fold(0, |acc, x| acc | (1 << x)),
+        };
+        assert_eq!(bent.nonlinearity(), 6);
+        assert_eq!(bent.degree(), 2);
+        let all = build_boolfun(4);
+        assert_eq!(all.iter().map(|f| f.nonlinearity()).max(), Some(6));
+        assert_eq!(all.iter().filter(|f| f.nonlinearity() == 6).count(), 896);
+        assert!(all.iter().filter(|f| f.is_affine()).all(|f| f.nonlinearity() == 0 && f.degree() <= 1));
+        // x0x1x2x3 has full degree
+        assert_eq!(BoolFun { n: 4, bits: 1 << 15 }.degree(), 4);
+        assert_eq!(BoolFun { n: 4, bits: 0 }.degree(), 0);
+    }
+
+    #[test]
+    fn structural_classes_n4() {
+        let all = build_boolfun(4);
+        let count = |c: &str| all.iter().filter(|f| f.in_class(c).unwrap()).count();
+        assert_eq!(count("monotone"), 168);
+        assert_eq!(count("affine"), 32);
+        assert_eq!(count("symmetric"), 32);
+        assert_eq!(count("self_dual"), 256);
+    }
+
 }
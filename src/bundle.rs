@@ -0,0 +1,244 @@
+//! Portable proof bundles: one run directory packed into a `.tar.zst`.
+//!
+//! A bundle holds `manifest.json` followed by the run's `trace.ndjson`,
+//! `proof.json`, `result.json` (and `paragraph.txt`, `deltas.ndjson` and
+//! `proof.sig` when present), plus `proposer.json` lifted out of proof.json when the run
+//! recorded how its ops were proposed. The manifest lists every other member with its SHA-256, so
+//! `verify_bundle` needs nothing but the archive: it checks the digests,
+//! replays the trace, and compares the replayed chain hash with result.json.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::digest::sha256_bytes;
+
+/// Manifest `format` written by this version.
+pub const FORMAT: &str = "lnst-bundle/1";
+const MANIFEST: &str = "manifest.json";
+const REQUIRED: &[&str] = &["trace.ndjson", "proof.json", "result.json"];
+const OPTIONAL: &[&str] = &["paragraph.txt", "deltas.ndjson", "proof.sig"];
+const PROPOSER: &str = "proposer.json";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FileEntry {
+    pub name: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Manifest {
+    pub format: String,
+    pub run_id: String,
+    /// Chain hash recorded in result.json at export time.
+    pub chain_hash: Option<String>,
+    pub files: Vec<FileEntry>,
+}
+
+/// Outcome of `verify_bundle`.
+#[derive(Clone, Debug, Serialize)]
+pub struct BundleReport {
+    pub bundle: String,
+    pub valid: bool,
+    pub run_id: Option<String>,
+    pub files_checked: usize,
+    pub steps_checked: usize,
+    pub chain_hash: Option<String>,
+    pub reason: Option<String>,
+}
+
+fn append(tar: &mut tar::Builder<impl std::io::Write>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_cksum();
+    tar.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Pack `run_dir` into a zstd-compressed tarball at `out`.
+pub fn export(run_dir: &Path, out: &Path) -> Result<Manifest> {
+    let mut members: Vec<(String, Vec<u8>)> = Vec::new();
+    for name in REQUIRED {
+        let path = run_dir.join(name);
+        let data = fs::read(&path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+        members.push((name.to_string(), data));
+    }
+    for name in OPTIONAL {
+        if let Ok(data) = fs::read(run_dir.join(name)) {
+            members.push((name.to_string(), data));
+        }
+    }
+    let proof: JsonValue = serde_json::from_slice(&members[1].1).map_err(|e| anyhow!("proof.json: {}", e))?;
+    if let Some(p) = proof.get("proposer") {
+        members.push((PROPOSER.to_string(), serde_json::to_vec_pretty(p)?));
+    }
+    let result: JsonValue = serde_json::from_slice(&members[2].1).map_err(|e| anyhow!("result.json: {}", e))?;
+
+    let run_id = run_dir
+        .canonicalize()?
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("{} has no directory name", run_dir.display()))?;
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        run_id,
+        chain_hash: result["chain_hash"].as_str().map(str::to_string),
+        files: members
+            .iter()
+            .map(|(name, data)| FileEntry {
+                name: name.clone(),
+                sha256: hex::encode(sha256_bytes(data)),
+                bytes: data.len() as u64,
+            })
+            .collect(),
+    };
+
+    let file = fs::File::create(out).map_err(|e| anyhow!("creating {}: {}", out.display(), e))?;
+    let mut tar = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    append(&mut tar, MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+    for (name, data) in &members {
+        append(&mut tar, name, data)?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Read every member of a bundle into memory, keyed by name.
+fn unpack(path: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let file = fs::File::open(path).map_err(|e| anyhow!("opening {}: {}", path.display(), e))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut members = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name.contains('/') || !entry.header().entry_type().is_file() {
+            return Err(anyhow!("unexpected member {:?}", name));
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if members.insert(name.clone(), data).is_some() {
+            return Err(anyhow!("duplicate member {:?}", name));
+        }
+    }
+    Ok(members)
+}
+
+fn check(path: &Path, report: &mut BundleReport) -> Result<()> {
+    let mut members = unpack(path)?;
+    let manifest: Manifest = serde_json::from_slice(
+        &members.remove(MANIFEST).ok_or_else(|| anyhow!("bundle has no {}", MANIFEST))?,
+    )
+    .map_err(|e| anyhow!("{}: {}", MANIFEST, e))?;
+    if manifest.format != FORMAT {
+        return Err(anyhow!("unsupported bundle format {:?}", manifest.format));
+    }
+    report.run_id = Some(manifest.run_id.clone());
+
+    for entry in &manifest.files {
+        let data = members.get(&entry.name).ok_or_else(|| anyhow!("{} is listed but missing", entry.name))?;
+        if hex::encode(sha256_bytes(data)) != entry.sha256 || data.len() as u64 != entry.bytes {
+            return Err(anyhow!("{} does not match its manifest digest", entry.name));
+        }
+        report.files_checked += 1;
+    }
+    if let Some(extra) = members.keys().find(|k| !manifest.files.iter().any(|f| &f.name == *k)) {
+        return Err(anyhow!("{} is not listed in the manifest", extra));
+    }
+    if let Some(missing) = REQUIRED.iter().find(|n| !members.contains_key(**n)) {
+        return Err(anyhow!("bundle has no {}", missing));
+    }
+
+    let result: JsonValue = serde_json::from_slice(&members["result.json"]).map_err(|e| anyhow!("result.json: {}", e))?;
+    let recorded = result["chain_hash"].as_str().map(str::to_string);
+    if recorded != manifest.chain_hash {
+        return Err(anyhow!("result.json chain hash differs from the manifest"));
+    }
+
+    // replay works on a file; give it a private copy of the trace, and of
+    // result.json so any inclusion proofs it carries are checked too
+    let dir = std::env::temp_dir().join(format!("lnst_bundle_{}_{}", std::process::id(), manifest.run_id));
+    fs::create_dir_all(&dir)?;
+    let trace = dir.join("trace.ndjson");
+    fs::write(&trace, &members["trace.ndjson"])?;
+    fs::write(dir.join("result.json"), &members["result.json"])?;
+    let replay = crate::verify::verify_trace_report(&trace);
+    let _ = fs::remove_dir_all(&dir);
+
+    report.steps_checked = replay.steps_checked;
+    if !replay.valid {
+        return Err(anyhow!(
+            "trace does not replay at step {}: {}",
+            replay.failed_step.map_or("?".to_string(), |s| s.to_string()),
+            replay.reason.unwrap_or_default()
+        ));
+    }
+    if replay.chain_hash != recorded {
+        return Err(anyhow!("replayed chain hash differs from result.json"));
+    }
+    report.chain_hash = replay.chain_hash;
+    Ok(())
+}
+
+/// Validate a bundle from its contents alone.
+pub fn verify_bundle(path: &Path) -> BundleReport {
+    let mut report = BundleReport {
+        bundle: path.display().to_string(),
+        valid: false,
+        run_id: None,
+        files_checked: 0,
+        steps_checked: 0,
+        chain_hash: None,
+        reason: None,
+    };
+    match check(path, &mut report) {
+        Ok(()) => report.valid = true,
+        Err(e) => report.reason = Some(e.to_string()),
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_bundle_verifies_and_detects_tampering() {
+        let ops: Vec<String> = ["LOAD 1/4", "MASK_BIT bit=2 val=1", "WITNESS_NEAREST target_elem=1/4 metric=ABS_DIFF"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let run = crate::exec::run_trace_and_write(&ops, None, false).unwrap();
+        let dir = run.artifacts_path.unwrap();
+        let out = std::env::temp_dir().join(format!("lnst_bundle_test_{}.tar.zst", std::process::id()));
+
+        let manifest = export(&dir, &out).unwrap();
+        assert_eq!(manifest.run_id, run.run_id);
+        let report = verify_bundle(&out);
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.chain_hash, manifest.chain_hash);
+        assert_eq!(report.steps_checked, 3);
+
+        // re-pack with an edited result.json but the original manifest
+        let mut members = unpack(&out).unwrap();
+        let edited = String::from_utf8(members["result.json"].clone()).unwrap().replace("\"1/4\"", "\"1/3\"");
+        members.insert("result.json".to_string(), edited.into_bytes());
+        let mut tar = tar::Builder::new(zstd::Encoder::new(fs::File::create(&out).unwrap(), 0).unwrap());
+        for (name, data) in &members {
+            append(&mut tar, name, data).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        let report = verify_bundle(&out);
+        assert!(!report.valid);
+        assert!(report.reason.unwrap().contains("result.json"));
+
+        fs::remove_file(&out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Cooperative cancellation and the small async helpers behind
+//! `exec::execute_async` and `verify::verify_async`.
+//!
+//! The crate has no async runtime of its own. `execute_async` awaits
+//! `yield_now` after every step, so whatever runtime polls it gets the thread
+//! back between ops; `verify_async` replays on a thread of its own and
+//! resolves when that thread finishes. Both check a `CancelToken` before each
+//! step or record and stop with `Cancelled` once it fires — a server cancels
+//! the token when its client goes away, e.g. through `CancelToken::guard`.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+/// A flag shared by the code that may cancel a run and the run itself.
+/// Clones share the flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Err(`Cancelled`) once the token has fired.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Cancel the token when the returned guard is dropped, e.g. with the
+    /// request future of a client that disconnected.
+    pub fn guard(&self) -> CancelGuard {
+        CancelGuard(self.clone())
+    }
+}
+
+/// Cancels its token on drop; see `CancelToken::guard`.
+pub struct CancelGuard(CancelToken);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// The error a cancelled run stops with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Return Pending once, after asking to be polled again, so the runtime can
+/// run other tasks first.
+pub async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+type Slot<T> = Arc<Mutex<(Option<std::thread::Result<T>>, Option<Waker>)>>;
+
+/// Run `f` on a thread of its own; the future resolves to what it returns
+/// and resumes its panic, if it panics.
+pub(crate) fn on_thread<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> impl Future<Output = T> {
+    let slot: Slot<T> = Arc::new(Mutex::new((None, None)));
+    let theirs = slot.clone();
+    std::thread::spawn(move || {
+        let out = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        let mut s = theirs.lock().unwrap_or_else(|e| e.into_inner());
+        s.0 = Some(out);
+        if let Some(w) = s.1.take() {
+            w.wake();
+        }
+    });
+    std::future::poll_fn(move |cx| {
+        let mut s = slot.lock().unwrap_or_else(|e| e.into_inner());
+        match s.0.take() {
+            Some(Ok(out)) => Poll::Ready(out),
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => {
+                s.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+}
+
+/// Drive `fut` to completion on the current thread, for tests and callers
+/// without a runtime.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    struct Unpark(std::thread::Thread);
+    impl std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut fut = std::pin::pin!(fut);
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn async_runs_match_the_blocking_ones_and_stop_when_cancelled() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=3"].iter().map(|s| s.to_string()).collect();
+        let (records, expected) = crate::exec::execute_in_memory(&ops).unwrap();
+        let token = CancelToken::new();
+        let got = block_on(crate::exec::execute_async(&ops, &token)).unwrap().finalize().unwrap();
+        assert_eq!((got.chain_hash, got.sample), (expected.chain_hash.clone(), expected.sample));
+
+        let ndjson: Vec<String> = records.iter().map(|r| serde_json::to_string(r).unwrap()).collect();
+        let report = block_on(crate::verify::verify_async(ndjson.join("\n"), &token)).unwrap();
+        assert_eq!(report.chain_hash, Some(expected.chain_hash));
+
+        drop(token.guard());
+        assert!(token.clone().is_cancelled());
+        let err = block_on(crate::exec::execute_async(&ops, &token)).err().unwrap();
+        assert_eq!(err.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        let err = block_on(crate::verify::verify_async(ndjson.join("\n"), &token)).unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+}
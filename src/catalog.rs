@@ -0,0 +1,218 @@
+//! Catalog of ops and universes — what `lnst ops` and `lnst universes` print.
+//!
+//! `OPS` mirrors the grammar accepted by `exec::validate_op`; the tests parse
+//! every example through it and scan the parser for op keywords missing here,
+//! so the table cannot drift from the executor. Universe sizes and bit legends
+//! are computed from the same builders and legend functions the executor uses.
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct OpSpec {
+    pub name: &'static str,
+    /// Arguments with their defaults, e.g. `metric=ABS_DIFF`.
+    pub syntax: &'static str,
+    /// Universes the op applies to ("any" when not restricted).
+    pub universes: &'static str,
+    pub summary: &'static str,
+    /// A concrete line that `exec::validate_op` accepts.
+    pub example: &'static str,
+}
+
+const fn op(
+    name: &'static str,
+    syntax: &'static str,
+    universes: &'static str,
+    summary: &'static str,
+    example: &'static str,
+) -> OpSpec {
+    OpSpec { name, syntax, universes, summary, example }
+}
+
+pub const OPS: &[OpSpec] = &[
+    op("LOAD", "LOAD <elem>", "QE, GE", "start at an element; a/b selects QE, a,b,c selects GE", "LOAD 13/37"),
+    op(
+        "SELECT_UNIVERSE",
+        "SELECT_UNIVERSE universe=<name> n=0 [group=<S4|D6>] [items=<i,j,..>] [hash=<sha256|blake3>]",
+        "any",
+        "switch to a universe; n sizes it, 0 means its default; hash= (first op only) picks the set-digest hash",
+        "SELECT_UNIVERSE universe=BOOLFUN n=4",
+    ),
+    op("MASK_BIT", "MASK_BIT bit=<0..6> val=<0|1>", "QE, GE, LATTICE, GROUP, SUBSETS, QUAD, TETRA", "keep elements whose legend bit equals val", "MASK_BIT bit=2 val=1"),
+    op("DEFINE_PRED", "DEFINE_PRED name=<id> expr=<expr over num, den, abs_num>", "QE", "define a named predicate for FILTER_PRED", "DEFINE_PRED name=small expr=\"den<=10 && num>0\""),
+    op("FILTER_PRED", "FILTER_PRED name=<legend label or DEFINE_PRED name> val=<0|1>", "QE, GE, LATTICE, GROUP, SUBSETS, QUAD, TETRA", "keep elements where the named predicate equals val", "FILTER_PRED name=den_le_6 val=1"),
+    op("FILTER_RANGE", "FILTER_RANGE min=<bound> max=<bound>", "QE, GE", "value range (QE) or perimeter range (GE)", "FILTER_RANGE min=1/4 max=1/2"),
+    op("FILTER_AREA", "FILTER_AREA min=<a> max=<b>", "GE, QUAD", "bounds on the exact area", "FILTER_AREA min=6 max=30"),
+    op("FILTER_MAX_ANGLE", "FILTER_MAX_ANGLE max=<degrees>", "GE", "bound the largest angle, whole degrees", "FILTER_MAX_ANGLE max=75"),
+    op("FILTER_SUM", "FILTER_SUM min=<i> max=<i>", "SUBSETS", "signed bounds on the subset sum", "FILTER_SUM min=10 max=20"),
+    op("FILTER_WEIGHT", "FILTER_WEIGHT min=<w> max=<w>", "BOOLFUN", "truth-table weight bounds", "FILTER_WEIGHT min=8 max=8"),
+    op("FILTER_NONLINEARITY", "FILTER_NONLINEARITY min=<n> max=<n>", "BOOLFUN", "nonlinearity bounds", "FILTER_NONLINEARITY min=4 max=6"),
+    op("FILTER_CLASS", "FILTER_CLASS class=<monotone|affine|linear|symmetric|self_dual>", "BOOLFUN", "keep functions in a structural class", "FILTER_CLASS class=monotone"),
+    op("FILTER_CONJ", "FILTER_CONJ elem=<perm>", "GROUP", "keep the conjugacy class of elem", "FILTER_CONJ elem=[1,0,2,3]"),
+    op("NPN_CLASS", "NPN_CLASS [elem=<0x..>]", "BOOLFUN (n<=6)", "keep elem's NPN class, or one representative per class", "NPN_CLASS elem=0x8000"),
+    op("PERMUTE_VARS", "PERMUTE_VARS perm=<i,j,..>", "BOOLFUN (n<=6)", "permute the input variables of every function", "PERMUTE_VARS perm=1,0,2,3"),
+    op("RESTRICT", "RESTRICT var=<i> value=<0|1>", "BOOLFUN (n<=6)", "fix one input, leaving functions of n-1 inputs", "RESTRICT var=2 value=1"),
+    op("DUAL", "DUAL", "BOOLFUN (n<=6)", "map every function to its dual", "DUAL"),
+    op("NEGATE", "NEGATE", "QE, BOOLFUN (n<=6)", "negate every element", "NEGATE"),
+    op("RECIPROCAL", "RECIPROCAL", "QE", "map every fraction to its reciprocal", "RECIPROCAL"),
+    op("MEDIANT_WITH", "MEDIANT_WITH elem=<a/b>", "QE", "map every fraction to its mediant with elem", "MEDIANT_WITH elem=1/1"),
+    op("PROJECT_SIGNATURE", "PROJECT_SIGNATURE elem=<a/b>", "QE", "move to the 7-bit signature universe at elem's signature", "PROJECT_SIGNATURE elem=7/200"),
+    op("COMPLEMENT", "COMPLEMENT", "any", "replace the set with the rest of the universe", "COMPLEMENT"),
+    op("SAVE_SET", "SAVE_SET name=<id>", "any", "remember the current set under a name", "SAVE_SET name=A"),
+    op("INTERSECT", "INTERSECT name=<id>", "any", "intersect with a saved set", "INTERSECT name=A"),
+    op("UNION", "UNION name=<id>", "any", "union with a saved set", "UNION name=A"),
+    op("PUSH_STATE", "PUSH_STATE", "non-linguistic", "save the set, constraint and witness", "PUSH_STATE"),
+    op("POP_STATE", "POP_STATE", "non-linguistic", "restore the last pushed state", "POP_STATE"),
+    op("SAMPLE", "SAMPLE seed=<s> k=<k>", "any", "keep a seeded sample of k elements", "SAMPLE seed=42 k=100"),
+    op("TOPK", "TOPK target_elem=<elem> k=<k> | TOPK metric=NONLINEARITY k=<k>", "any", "keep the k elements nearest the target (or best by metric)", "TOPK target_elem=0xBEEF k=5"),
+    op(
+        "WITNESS_NEAREST",
+        "WITNESS_NEAREST target_elem=<elem> metric=ABS_DIFF | WITNESS_NEAREST targets=<e,e,..> mode=minimax",
+        "any",
+        "pick the element nearest the target(s)",
+        "WITNESS_NEAREST target_elem=13/37 metric=ABS_DIFF",
+    ),
+    op("WITNESS_ALL_TIES", "WITNESS_ALL_TIES target_elem=<elem> metric=ABS_DIFF", "any", "pick the nearest element and record every tie", "WITNESS_ALL_TIES target_elem=13/37"),
+    op(
+        "JOIN_NEAREST",
+        "JOIN_NEAREST left_universe=<u> right_universe=<u> left_elem=<elem> right_elem=<elem> metric=ABS_DIFF",
+        "any pair",
+        "nearest pair across two universes",
+        "JOIN_NEAREST left_universe=QE right_universe=BOOLFUN left_elem=7/200 right_elem=0xBEEF",
+    ),
+    op("GROUP_BY", "GROUP_BY [by=<sig|predicate name>]", "any", "histogram of the set by signature or predicate", "GROUP_BY by=sig"),
+    op("AGGREGATE", "AGGREGATE", "any", "count, min and max of the set", "AGGREGATE"),
+    op("ASSERT_COUNT", "ASSERT_COUNT eq=<n>", "any", "fail the run unless the count is n", "ASSERT_COUNT eq=151"),
+    op("ASSERT_WITNESS", "ASSERT_WITNESS elem=<elem>", "any", "fail the run unless the witness is elem", "ASSERT_WITNESS elem=1/3"),
+    op(
+        "RETURN_SET",
+        "RETURN_SET max_items=20 include_witness=0 [offset=<n>] [sort_by=<value|distance>] [include_proofs=1]",
+        "any",
+        "return a page of the set, optionally with Merkle inclusion proofs",
+        "RETURN_SET max_items=10 include_witness=1",
+    ),
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UniverseInfo {
+    pub name: &'static str,
+    /// How a script enters the universe.
+    pub select: &'static str,
+    /// Element syntax, as accepted by LOAD / target_elem.
+    pub element: &'static str,
+    /// Element count under `select`.
+    pub size: usize,
+    /// Signature bit labels, bit 0 first; empty where elements are their own bits.
+    pub bit_legend: Vec<&'static str>,
+}
+
+fn info(name: &'static str, select: &'static str, element: &'static str, size: usize, legend: &[&'static str]) -> UniverseInfo {
+    UniverseInfo { name, select, element, size, bit_legend: legend.to_vec() }
+}
+
+/// Universe names, in `universes()` order, for places that need them without
+/// building every universe (shell completion).
+pub const UNIVERSE_NAMES: &[&str] = &[
+    "QE", "GE", "BOOLFUN", "LATTICE", "GROUP", "SUBSETS", "QUAD", "TETRA", "WORD", "SYLLABLE", "MORPHEME", "PHRASE",
+    "SEMANTIC", "DISCOURSE",
+];
+
+/// Every universe the executor can select, with sizes from its builders.
+pub fn universes() -> Vec<UniverseInfo> {
+    use crate::geom::{build_ge, build_quad, build_tetra, DEFAULT_QUAD_PERIMETER, DEFAULT_TETRA_EDGE};
+    use crate::semtrace::{bit_legend, bit_legend_geom, bit_legend_quad, bit_legend_tetra};
+    let s4 = crate::group::parse_group_name("S4").expect("S4 is a supported group");
+    vec![
+        info("QE", "LOAD <a/b>  or  SELECT_UNIVERSE universe=QE n=0", "a/b, e.g. 13/37", crate::qe::build_qe().len(), &bit_legend()),
+        info("GE", "LOAD <a,b,c>", "side lengths a,b,c, e.g. 3,4,5", build_ge(20).len(), &bit_legend_geom()),
+        info("BOOLFUN", "SELECT_UNIVERSE universe=BOOLFUN n=4", "hex truth table, e.g. 0xBEEF", crate::boolfun::build_boolfun(4).len(), &[]),
+        info("LATTICE", "SELECT_UNIVERSE universe=LATTICE n=0", "(x,y) or x,y", crate::lattice::build_lattice(crate::lattice::DEFAULT_RADIUS).len(), &crate::lattice::bit_legend()),
+        info("GROUP", "SELECT_UNIVERSE universe=GROUP n=0 group=S4", "one-line permutation, e.g. [1,0,2,3]", crate::group::GroupUniverse::build(s4).elems.len(), &crate::group::bit_legend()),
+        info("SUBSETS", "SELECT_UNIVERSE universe=SUBSETS n=0 items=3,5,7,11", "subset of items, e.g. {3,7}", crate::subsets::build_subsets(&[3, 5, 7, 11]).len(), &crate::subsets::bit_legend()),
+        info("QUAD", "SELECT_UNIVERSE universe=QUAD n=0", "side lengths a,b,c,d", build_quad(DEFAULT_QUAD_PERIMETER).len(), &bit_legend_quad()),
+        info("TETRA", "SELECT_UNIVERSE universe=TETRA n=0", "edges AB,AC,AD,BC,BD,CD", build_tetra(DEFAULT_TETRA_EDGE).len(), &bit_legend_tetra()),
+        info("WORD", "SELECT_UNIVERSE universe=WORD n=0", "a lowercase word, e.g. abandon", crate::word::build_word_universe().len(), &crate::word::Word::bit_legend()),
+        info("SYLLABLE", "SELECT_UNIVERSE universe=SYLLABLE n=0", "syllable index", crate::syllable::build_syllable_universe().len(), &crate::syllable::bit_legend()),
+        info("MORPHEME", "SELECT_UNIVERSE universe=MORPHEME n=0", "meaning id suffix", crate::morpheme::build_morpheme_universe().len(), &crate::morpheme::bit_legend()),
+        info("PHRASE", "SELECT_UNIVERSE universe=PHRASE n=0", "phrase id", crate::phrase::build_phrase_inventory().len(), &crate::phrase::bit_legend()),
+        info("SEMANTIC", "SELECT_UNIVERSE universe=SEMANTIC n=0", "graph id", crate::semantic::build_semantic_inventory().len(), &crate::semantic::bit_legend()),
+        info("DISCOURSE", "SELECT_UNIVERSE universe=DISCOURSE n=0", "discourse id", crate::discourse::build_discourse_inventory().len(), &crate::discourse::bit_legend()),
+    ]
+}
+
+/// `lnst ops` as text: one op per line with its syntax, then scope and meaning.
+pub fn render_ops() -> String {
+    OPS.iter()
+        .map(|o| format!("{}\n    {} — {}", o.syntax, o.universes, o.summary))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Long-form grammar help for `--help-ops`: the line syntax, every op with an
+/// example, and the universe names.
+pub fn render_grammar_help() -> String {
+    let mut out = vec![
+        "Op script grammar: one op per line (or a JSON array of lines), `OP key=value ...`.".to_string(),
+        "Ops run in order against the active universe; bare LOAD <elem> starts from an element's universe.".to_string(),
+        String::new(),
+    ];
+    for o in OPS {
+        out.push(o.syntax.to_string());
+        out.push(format!("    {} — {}", o.universes, o.summary));
+        out.push(format!("    e.g. {}", o.example));
+    }
+    out.push(String::new());
+    out.push(format!("Universes: {} (see `universes` for sizes and bit legends)", UNIVERSE_NAMES.join(", ")));
+    out.join("\n")
+}
+
+/// `lnst universes` as text.
+pub fn render_universes(universes: &[UniverseInfo]) -> String {
+    universes
+        .iter()
+        .map(|u| {
+            let legend: Vec<String> = u.bit_legend.iter().enumerate().map(|(i, l)| format!("{}:{}", i, l)).collect();
+            let legend = if legend.is_empty() { "(elements are their own bits)".to_string() } else { legend.join(" ") };
+            format!(
+                "{} ({} elements)\n    select:  {}\n    element: {}\n    bits:    {}",
+                u.name, u.size, u.select, u.element, legend
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn op_table_matches_the_parser() {
+        for o in OPS {
+            crate::exec::validate_op(o.example).unwrap_or_else(|e| panic!("{}: {}", o.example, e));
+            assert!(o.example.starts_with(o.name) && o.syntax.starts_with(o.name), "{}", o.name);
+        }
+        let src = include_str!("exec.rs");
+        let start = src.find("fn parse_op_to_semtrace").unwrap();
+        let end = start + src[start..].find("pub fn validate_op").unwrap();
+        let keyword = regex::Regex::new(r#"(?:starts_with|strip_prefix)\("([A-Z][A-Z_]+)|s == "([A-Z][A-Z_]+)""#).unwrap();
+        for c in keyword.captures_iter(&src[start..end]) {
+            let name = c.get(1).or(c.get(2)).unwrap().as_str();
+            assert!(OPS.iter().any(|o| o.name == name), "{} is parsed but missing from OPS", name);
+        }
+    }
+
+    #[test]
+    fn universe_names_follow_the_table() {
+        let names: Vec<&str> = universes().iter().map(|u| u.name).collect();
+        assert_eq!(names, UNIVERSE_NAMES);
+    }
+
+    #[test]
+    fn grammar_prompt_names_only_cataloged_ops() {
+        for line in crate::api_proposer::OP_GRAMMAR_PROMPT.lines().filter(|l| l.starts_with("  ")) {
+            let name = line.split_whitespace().next().unwrap();
+            assert!(OPS.iter().any(|o| o.name == name), "prompt names unknown op {}", name);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Pinned universe roots: the set digest each standard universe must have when
+//! a trace opens it.
+//!
+//! The executor and the verifier build their universes independently, so a
+//! change to either construction (a bound, the canonical order, an encoding)
+//! would otherwise only show up as a chain-hash mismatch far from its cause.
+//! Step 0 of a trace that opens one of these universes records the pinned root
+//! as `universe_root`; both sides check their own construction against it.
+//!
+//! Roots are pinned per hash backend: keys name the SHA-256 root, and
+//! `<key>@blake3` the BLAKE3 one.
+//!
+//! BOOLFUN is pinned for n ≤ 4: from n = 5 the full truth-table space
+//! (2^32 functions and up) is too large for either side to enumerate.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value as JsonValue;
+
+use crate::boolfun::is_boolfun_universe;
+use crate::digest::HashAlg;
+
+/// (universe key, hex Merkle root), keyed as `universe_key` names them.
+pub const PINNED_ROOTS: &[(&str, &str)] = &[
+    ("QE", "395ce8690eda878a8f31c46d50cbfde68fb24e84e3f018f0008930d0aa5bb754"),
+    ("GE", "0ba981089c3c8082c6bc3795027c7ce8ac3989b4fe3b81708c4c4f66d384df9d"),
+    ("BOOLFUN/n=0", "fb14152eb4d966645b5be9311cb047138ae905b128578c1a00d0054b0765ca5b"),
+    ("BOOLFUN/n=1", "8274d76a0ca2b4121f71166b834226d258bbe91d4676c0b555c50c9bafb2b15d"),
+    ("BOOLFUN/n=2", "a3088bb007d4cac990271e8047e58a58e960762bd2a7712b7cfc1f7529b7146d"),
+    ("BOOLFUN/n=3", "f3a22b32db8844a1d3f8098005d917d8ad5cade40cc617d883b49b75b1637615"),
+    ("BOOLFUN/n=4", "643580bb15731c5170bafdc38ce41e6e7ea0c6b7f1667c7df26bf4eb7272f5df"),
+    ("QE@blake3", "3ce629eaf5358366ce94c49b24adae407b148aed5ae4a7b6b88d558923635c48"),
+    ("GE@blake3", "d993e385c46809313796173fe58db4eaf9f34478f64b47d9ac7a72e57dba6c67"),
+    ("BOOLFUN/n=0@blake3", "c570b8993cc5be21854f3cd9bfce490ef93ceb481acd0da85515f821516ce5c8"),
+    ("BOOLFUN/n=1@blake3", "fe1745a5c5593c750bad1586486dcb0b11e6ec0535b5190b7fec39108817aa11"),
+    ("BOOLFUN/n=2@blake3", "770e143f7eb3eb474f13a93f405dc7a30d046e25a65eb663d5f8fc1c51e8ba13"),
+    ("BOOLFUN/n=3@blake3", "fac86a2e097826565843e35bbb2284b6c18b0d3186885c450110f8a7c0aada57"),
+    ("BOOLFUN/n=4@blake3", "cd1b83fac7134ef227a4e13be78a3978876f9dc00667b736b8b480a76e8342d1"),
+];
+
+/// The pinned universe a step opens, from its recorded op and args: `LOAD` of a
+/// fraction (QE, denominators ≤ 200) or a triangle (GE, sides ≤ 20), or
+/// `SELECT_UNIVERSE` of QE or BOOLFUN.
+pub fn universe_key(op: &str, args: &JsonValue) -> Option<String> {
+    match op {
+        "START_ELEM" => {
+            let elem = args.get("elem")?.as_str()?;
+            Some(if elem.contains(',') { "GE" } else { "QE" }.to_string())
+        }
+        "SELECT_UNIVERSE" => {
+            let u = args.get("universe")?.as_str()?.to_ascii_uppercase();
+            let n = args.get("n").and_then(|v| v.as_u64()).unwrap_or(0);
+            if u == "QE" {
+                Some("QE".to_string())
+            } else if is_boolfun_universe(&u) {
+                Some(format!("BOOLFUN/n={}", n))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+pub fn pinned_root(key: &str) -> Option<&'static str> {
+    PINNED_ROOTS.iter().find(|(k, _)| *k == key).map(|(_, r)| *r)
+}
+
+/// Check the set digest after step 0 against the pinned root of the universe
+/// the step opened under the active hash backend, returning that root (`None`
+/// when the universe is not pinned).
+pub fn check_step_zero(op: &str, args: &JsonValue, set_digest: &[u8; 32]) -> Result<Option<&'static str>> {
+    let Some(key) = universe_key(op, args) else { return Ok(None) };
+    let key = match HashAlg::active() {
+        HashAlg::Sha256 => key,
+        alg => format!("{}@{}", key, alg.name()),
+    };
+    let Some(root) = pinned_root(&key) else { return Ok(None) };
+    let got = hex::encode(set_digest);
+    if got != root {
+        return Err(anyhow!("{} universe root {} differs from the pinned root {}", key, got, root));
+    }
+    Ok(Some(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::{merkle_root_with, Hasher};
+
+    fn root_of<B: AsRef<[u8]>>(h: HashAlg, leaves: impl Iterator<Item = B>) -> String {
+        let leaves: Vec<[u8; 32]> = leaves.map(|b| h.hash(b.as_ref())).collect();
+        hex::encode(merkle_root_with(&h, &leaves))
+    }
+
+    #[test]
+    fn pinned_roots_match_the_universe_builders() {
+        for (h, suffix) in [(HashAlg::Sha256, ""), (HashAlg::Blake3, "@blake3")] {
+            let key = |k: &str| format!("{}{}", k, suffix);
+            let qe = root_of(h, crate::qe::build_qe().iter().map(|f| f.canonical_bytes()));
+            assert_eq!(pinned_root(&key("QE")), Some(qe.as_str()));
+            let mut ge: Vec<crate::qe::Frac> =
+                crate::geom::build_ge(20).iter().map(|t| crate::qe::Frac { num: t.a, den: t.c }).collect();
+            ge.sort_by(crate::qe::canonical_cmp);
+            assert_eq!(pinned_root(&key("GE")), Some(root_of(h, ge.iter().map(|f| f.canonical_bytes()))).as_deref());
+            for n in 0..=4u8 {
+                let root = root_of(h, crate::boolfun::build_boolfun(n).iter().map(|f| f.canonical_bytes()));
+                assert_eq!(pinned_root(&key(&format!("BOOLFUN/n={}", n))), Some(root.as_str()), "n={}", n);
+            }
+        }
+        assert!(pinned_root("BOOLFUN/n=5").is_none());
+    }
+
+    #[test]
+    fn step_zero_ops_name_their_universe() {
+        let key = |op: &str, args: JsonValue| universe_key(op, &args);
+        assert_eq!(key("START_ELEM", serde_json::json!({ "elem": "13/37" })).as_deref(), Some("QE"));
+        assert_eq!(key("START_ELEM", serde_json::json!({ "elem": "3,4,5" })).as_deref(), Some("GE"));
+        assert_eq!(key("SELECT_UNIVERSE", serde_json::json!({ "universe": "boolfun", "n": 3 })).as_deref(), Some("BOOLFUN/n=3"));
+        assert_eq!(key("SELECT_UNIVERSE", serde_json::json!({ "universe": "S3" })), None);
+    }
+}
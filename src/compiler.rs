@@ -49,12 +49,17 @@ pub fn compile_query_to_candidates(query: &str) -> Result<Vec<Candidate>> {
             base_ops.push(Op::SetBit { i: 2, b: 1 });
         }
         base_ops.push(Op::WitnessNearest {
-            target_elem: fr.clone(),
+            target_elem: Some(fr.clone()),
             metric: "ABS_DIFF".to_string(),
+            targets: None,
+            mode: None,
         });
         base_ops.push(Op::ReturnSet {
             max_items,
             include_witness,
+            offset: None,
+            sort_by: None,
+            include_proofs: false,
         });
 
         let base = Trace {
@@ -201,15 +206,22 @@ pub fn compile_query_to_candidates(query: &str) -> Result<Vec<Candidate>> {
         let ops: Vec<Op> = vec![
             Op::SelectUniverse {
                 universe: "BOOLFUN".to_string(),
-                n,
+                n: n.into(),
+                group: None,
+                items: None,
+                hash: None,
             },
             Op::TopK {
-                target_elem: target,
                 k,
+                target_elem: Some(target),
+                metric: None,
             },
             Op::ReturnSet {
                 max_items: k,
                 include_witness: true,
+                offset: None,
+                sort_by: None,
+                include_proofs: false,
             },
         ];
 
@@ -368,8 +380,10 @@ pub fn compile_query_to_trace(query: &str) -> Result<Trace> {
 
         // Always: nearest witness to the seed fraction
         ops.push(Op::WitnessNearest {
-            target_elem: fr,
+            target_elem: Some(fr),
             metric: "ABS_DIFF".to_string(),
+            targets: None,
+            mode: None,
         });
 
         // Return set (sample)
@@ -378,6 +392,9 @@ pub fn compile_query_to_trace(query: &str) -> Result<Trace> {
         ops.push(Op::ReturnSet {
             max_items,
             include_witness,
+            offset: None,
+            sort_by: None,
+            include_proofs: false,
         });
 
         return Ok(Trace {
@@ -456,15 +473,22 @@ pub fn compile_query_to_trace(query: &str) -> Result<Trace> {
         let ops: Vec<Op> = vec![
             Op::SelectUniverse {
                 universe: "BOOLFUN".to_string(),
-                n,
+                n: n.into(),
+                group: None,
+                items: None,
+                hash: None,
             },
             Op::TopK {
-                target_elem: target,
                 k,
+                target_elem: Some(target),
+                metric: None,
             },
             Op::ReturnSet {
                 max_items: k,
                 include_witness: true,
+                offset: None,
+                sort_by: None,
+                include_proofs: false,
             },
         ];
 
@@ -516,6 +540,7 @@ mod tests {
                 if let Op::ReturnSet {
                     max_items,
                     include_witness,
+                    ..
                 } = op
                 {
                     Some((*max_items, *include_witness))
@@ -546,11 +571,11 @@ mod tests {
         assert_eq!(t.universe, "BOOLFUN");
         assert!(t.ops.iter().any(|op| matches!(
             op,
-            Op::SelectUniverse { universe, n } if universe=="BOOLFUN" && *n==4
+            Op::SelectUniverse { universe, n, .. } if universe=="BOOLFUN" && *n==4
         )));
         assert!(t.ops.iter().any(|op| matches!(
             op,
-            Op::TopK { target_elem, k } if target_elem=="0xBEEF" && *k==5
+            Op::TopK { target_elem: Some(target_elem), k, .. } if target_elem=="0xBEEF" && *k==5
         )));
     }
 
@@ -0,0 +1,72 @@
+//! Layered configuration: `lnst.toml`, then `LNST_*` environment variables, then flags.
+//!
+//! The file only fills settings that the command line and the environment leave
+//! unset. It is read from `--config` / `LNST_CONFIG` when given (and must exist
+//! then), otherwise from `./lnst.toml` if present.
+//!
+//!   proposer = "rules"
+//!   model = "gpt-4o-mini"
+//!   output_dir = "/var/lib/lnst/runs"
+//!   verbose = false
+//!   format = "json"
+//!
+//!   [universe]
+//!   lattice_radius = 12
+//!   quad_perimeter = 30
+//!   tetra_edge = 5
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::exec::UniverseBounds;
+
+/// Config file looked up in the working directory when none is named.
+pub const DEFAULT_FILE: &str = "lnst.toml";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub proposer: Option<String>,
+    pub model: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub verbose: Option<bool>,
+    pub format: Option<String>,
+    pub universe: UniverseBounds,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Load the named file, or `./lnst.toml` when it exists; the path is None
+    /// when no file was read.
+    pub fn load(explicit: Option<&Path>) -> Result<(Option<PathBuf>, Config)> {
+        let path = match explicit {
+            Some(p) => p.to_path_buf(),
+            None if Path::new(DEFAULT_FILE).is_file() => PathBuf::from(DEFAULT_FILE),
+            None => return Ok((None, Config::default())),
+        };
+        let text = fs::read_to_string(&path).map_err(|e| anyhow!("reading config {}: {}", path.display(), e))?;
+        let cfg = Config::parse(&text).map_err(|e| anyhow!("config {}: {}", path.display(), e))?;
+        Ok((Some(path), cfg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_rejects_unknown_keys() {
+        let cfg = Config::parse("proposer = \"rules\"\nverbose = true\n[universe]\nquad_perimeter = 30\n").unwrap();
+        assert_eq!(cfg.proposer.as_deref(), Some("rules"));
+        assert_eq!(cfg.verbose, Some(true));
+        assert_eq!(cfg.universe.quad_perimeter, Some(30));
+        assert_eq!(cfg.model, None);
+        assert!(Config::parse("propser = \"rules\"").is_err());
+        assert!(Config::load(Some(Path::new("/nonexistent/lnst.toml"))).is_err());
+    }
+}
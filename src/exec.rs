@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
@@ -16,44 +18,195 @@ use crate::boolfun::{
     build_boolfun, canonical_cmp as boolfun_canonical_cmp, is_boolfun_universe,
     parse_elem as parse_boolfun, BoolFun,
 };
-use crate::digest::{merkle_root, sha256_bytes};
-use crate::qe::{build_qe, canonical_cmp, parse_frac, Frac};
-use crate::semtrace::{sig7, sig7_geom, Constraint};
+use crate::digest::{leaf_hash, merkle_path, set_root, sha256_bytes, HashAlg};
+use crate::trace_format::{TraceFormat, GROUP_BY_DOMAIN, SEMTRACE_VERSION, STEP_DOMAIN};
+use crate::geom::{
+    build_quad, build_tetra, canonical_cmp_quad, canonical_cmp_tetra, is_quad_universe,
+    is_tetra_universe, parse_quad, parse_tetra, quad_distance, quad_to_string, tetra_distance,
+    tetra_to_string, Quad, Tetra,
+};
+use crate::group::{
+    canonical_cmp as group_canonical_cmp, is_group_universe, parse_elem as parse_perm,
+    parse_group_name, perm_to_string, GroupUniverse, Perm,
+};
+use crate::lattice::{
+    build_lattice, canonical_cmp as lattice_canonical_cmp, is_lattice_universe,
+    parse_elem as parse_pt, pt_to_string, Pt,
+};
+use crate::setops::{difference_by, intersect_by, sample_seeded, union_by};
+use crate::qe::{build_qe, canonical_cmp, in_range, parse_bound, parse_frac, Frac};
+use crate::subsets::{
+    build_subsets, canonical_cmp as subset_canonical_cmp, is_subsets_universe, parse_items,
+    subset_to_string, Subset,
+};
+use crate::cancel::{yield_now, CancelToken};
+use crate::semtrace::{resolve_pred, sig7_geom, sig7_quad, Constraint, Op};
 
 #[derive(Debug)]
 pub struct ExecutionResult {
     pub valid: bool,
     pub final_count: usize,
     pub witness: Option<String>,
+    /// Absolute run directory.
     pub artifacts_path: Option<PathBuf>,
+    /// Name of the run directory under the artifacts root.
+    pub run_id: String,
     pub universe: String,
-    pub constraint_mask: u8,
-    pub constraint_value: u8,
+    pub constraint_mask: u64,
+    pub constraint_value: u64,
+    pub failed_assertion: Option<String>,
+    pub chain_hash: String,
+    /// The elements RETURN_SET returned, as result.json lists them.
+    pub sample: Vec<String>,
+    /// One entry per applied op, in order.
+    pub steps: Vec<StepMetrics>,
+    /// SHA-256 of each file written to the run directory, by file name;
+    /// empty for an in-memory run.
+    pub artifact_digests: BTreeMap<String, String>,
+    /// result.json as written; None for an in-memory run.
+    pub document: Option<RunResult>,
 }
 
-#[derive(Clone, Debug, Serialize)]
-struct StepPre {
-    set_digest: Option<String>,
-    count: usize,
-    constraint_mask: u8,
-    constraint_value: u8,
+/// What one op did to the set and how long it took.
+#[derive(Clone, Debug)]
+pub struct StepMetrics {
+    pub step: usize,
+    pub op: String,
+    pub count_before: usize,
+    pub count_after: usize,
+    pub wall: std::time::Duration,
 }
 
-#[derive(Clone, Debug, Serialize)]
-struct StepPost {
-    set_digest: Option<String>,
-    count: usize,
-    witness: Option<String>,
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct StepPre {
+    pub set_digest: Option<String>,
+    pub count: usize,
+    pub constraint_mask: u64,
+    pub constraint_value: u64,
 }
 
-#[derive(Clone, Debug, Serialize)]
-struct StepRec {
-    step: usize,
-    op: String,
-    args: JsonValue,
-    pre: StepPre,
-    post: StepPost,
-    step_digest: String,
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct StepPost {
+    pub set_digest: Option<String>,
+    pub count: usize,
+    pub witness: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_ties: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_ties_root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<BTreeMap<String, usize>>,
+}
+
+/// One line of trace.ndjson.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct StepRecord {
+    pub step: usize,
+    /// Trace format; decides how the verifier recomputes this record's digests.
+    pub semtrace_version: &'static str,
+    /// Set-digest hash backend, when not SHA-256.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<&'static str>,
+    pub op: String,
+    pub args: JsonValue,
+    /// Step 0 only: the pinned root of the universe the step opens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub universe_root: Option<String>,
+    pub pre: StepPre,
+    pub post: StepPost,
+    pub step_digest: String,
+}
+
+/// proof.json: the ops a run executed and how they came about.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunProof {
+    pub ops_in: Vec<String>,
+    pub trace_ndjson: String,
+    /// Absent from deterministic runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deterministic: bool,
+    /// How the ops were proposed (backend, sampling parameters, …).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposer: Option<JsonValue>,
+    /// Set-digest hash backend, when not SHA-256.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunVerifier {
+    pub valid: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunConstraint {
+    pub mask: u64,
+    pub value: u64,
+}
+
+/// The RETURN_SET parameters the sample was taken with.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunReturnSet {
+    pub max_items: usize,
+    pub include_witness: bool,
+    pub include_proofs: bool,
+    pub offset: usize,
+    pub sort_by: String,
+}
+
+/// Where a run's files are; relative to the run directory in deterministic runs.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunArtifacts {
+    pub run_id: String,
+    pub dir: String,
+    pub trace_ndjson: String,
+    pub proof: String,
+    pub result: String,
+    pub paragraph: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deltas: Option<String>,
+}
+
+/// result.json: the final set, its witness and a sample of it.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RunResult {
+    /// `OK`, or `EMPTY_SET` when nothing is left.
+    pub verdict: String,
+    pub verifier: RunVerifier,
+    pub chain_hash: String,
+    pub semtrace_version: String,
+    pub count: usize,
+    pub witness: Option<String>,
+    pub constraint: RunConstraint,
+    pub return_set: RunReturnSet,
+    pub sample: Vec<String>,
+    pub artifacts: RunArtifacts,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness_ties: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness_ties_root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<JsonValue>,
+    /// Inclusion proofs for the witness and sample (RETURN_SET include_proofs).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proofs: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<BTreeMap<String, usize>>,
+    /// The first ASSERT_* that did not hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_assertion: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_used: Option<JsonValue>,
+}
+
+/// Pretty JSON with keys in sorted order, as earlier runs wrote them, so
+/// replays of old runs still compare byte for byte.
+fn artifact_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&serde_json::to_value(value)?)?)
 }
 
 fn hex32(b: [u8; 32]) -> String {
@@ -63,17 +216,88 @@ fn hex32(b: [u8; 32]) -> String {
 fn canonical_set_digest(set: &[Frac]) -> [u8; 32] {
     let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
     for f in set {
-        leaves.push(sha256_bytes(&f.canonical_bytes()));
+        leaves.push(leaf_hash(&f.canonical_bytes()));
     }
-    merkle_root(&leaves)
+    set_root(&leaves)
 }
 
 fn canonical_set_digest_boolfun(set: &[BoolFun]) -> [u8; 32] {
     let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
     for f in set {
-        leaves.push(sha256_bytes(&f.canonical_bytes()));
+        leaves.push(leaf_hash(&f.canonical_bytes()));
+    }
+    set_root(&leaves)
+}
+
+fn canonical_set_digest_lattice(set: &[Pt]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for p in set {
+        leaves.push(leaf_hash(&p.canonical_bytes()));
+    }
+    set_root(&leaves)
+}
+
+fn canonical_set_digest_group(set: &[Perm]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for g in set {
+        leaves.push(leaf_hash(&g.canonical_bytes()));
+    }
+    set_root(&leaves)
+}
+
+fn canonical_set_digest_subsets(set: &[Subset]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for x in set {
+        leaves.push(leaf_hash(&x.canonical_bytes()));
+    }
+    set_root(&leaves)
+}
+
+fn canonical_set_digest_quad(set: &[Quad]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for q in set {
+        leaves.push(leaf_hash(&q.to_bytes()));
+    }
+    set_root(&leaves)
+}
+
+fn canonical_set_digest_tetra(set: &[Tetra]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for t in set {
+        leaves.push(leaf_hash(&t.to_bytes()));
+    }
+    set_root(&leaves)
+}
+
+/// Merkle audit paths from each of `elems` to `set_digest`, the final set
+/// digest over `leaves` (stored order). `encoding` names how the verifier turns
+/// a rendered element back into its leaf; see `verify::proof_leaf`.
+fn inclusion_proofs(encoding: &str, leaves: &[[u8; 32]], set_digest: &[u8; 32], elems: &[String]) -> Result<JsonValue> {
+    if set_root(leaves) != *set_digest {
+        return Err(anyhow!("include_proofs: the set digest is not a merkle root over the set"));
+    }
+    let mut items = Vec::with_capacity(elems.len());
+    for elem in elems {
+        let leaf = crate::verify::proof_leaf(encoding, elem)
+            .ok_or_else(|| anyhow!("include_proofs: cannot encode {} as {}", elem, encoding))?;
+        let index = leaves
+            .iter()
+            .position(|l| *l == leaf)
+            .ok_or_else(|| anyhow!("include_proofs: {} is not in the set", elem))?;
+        let path = merkle_path(&HashAlg::active(), leaves, index).unwrap_or_default();
+        items.push(json!({
+            "elem": elem,
+            "index": index,
+            "leaf": hex32(leaf),
+            "path": path.into_iter().map(hex32).collect::<Vec<_>>(),
+        }));
     }
-    merkle_root(&leaves)
+    Ok(json!({
+        "encoding": encoding,
+        "hash": HashAlg::active().name(),
+        "set_digest": hex32(*set_digest),
+        "elements": items,
+    }))
 }
 
 fn step_digest(pre_chain: &[u8], op: &str, args: &JsonValue, post_set: &[u8]) -> [u8; 32] {
@@ -83,14 +307,73 @@ fn step_digest(pre_chain: &[u8], op: &str, args: &JsonValue, post_set: &[u8]) ->
         "args": args,
         "post": hex::encode(post_set),
     });
-    let bytes = serde_json::to_vec(&obj).expect("json encode");
-    sha256_bytes(&bytes)
+    TraceFormat::CURRENT.digest(STEP_DOMAIN, &obj)
+}
+
+/// Post digest for a GROUP_BY step: binds the histogram to the set digest.
+fn group_by_digest(set_digest: &[u8; 32], hist: &BTreeMap<String, usize>) -> [u8; 32] {
+    let obj = json!({ "set": hex::encode(set_digest), "group_by": hist });
+    TraceFormat::CURRENT.digest(GROUP_BY_DOMAIN, &obj)
+}
+
+/// Evaluate an ASSERT_COUNT / ASSERT_WITNESS step against the post-state.
+/// Returns the failure description, or None when the assertion holds.
+fn failed_assertion(op: &str, args: &JsonValue, count: usize, witness: Option<&str>) -> Option<String> {
+    match op {
+        "ASSERT_COUNT" => {
+            let want = args.get("eq").and_then(|v| v.as_u64())?;
+            (count as u64 != want).then(|| format!("ASSERT_COUNT eq={} got={}", want, count))
+        }
+        "ASSERT_WITNESS" => {
+            let want = args.get("elem").and_then(|v| v.as_str())?;
+            // fractions compare in reduced form, everything else by its rendering
+            let norm = |s: &str| parse_frac(s).map(|f| frac_to_string(&f)).unwrap_or_else(|| s.to_string());
+            (witness.map(norm) != Some(norm(want)))
+                .then(|| format!("ASSERT_WITNESS elem={} got={}", want, witness.unwrap_or("none")))
+        }
+        _ => None,
+    }
+}
+
+/// One RETURN_SET page: `set` minus `exclude`, stably sorted by `order`, then
+/// `limit` elements starting at `offset`.
+fn page_window<'a, T: PartialEq>(
+    set: &'a [T],
+    exclude: Option<&T>,
+    order: impl Fn(&T, &T) -> std::cmp::Ordering,
+    offset: usize,
+    limit: usize,
+) -> Vec<&'a T> {
+    let mut v: Vec<&T> = set.iter().filter(|x| exclude != Some(*x)).collect();
+    v.sort_by(|x, y| order(x, y));
+    v.into_iter().skip(offset).take(limit).collect()
+}
+
+/// The 7-bit predicate legend of the selected universe; flags are
+/// (boolfun, ge, lattice, group, subsets, quad, tetra, linguistic).
+/// None where SET_BIT has no 7-bit signature to filter on.
+fn pred_legend(flags: &[bool; 8]) -> Option<[&'static str; 7]> {
+    match flags {
+        [true, ..] | [.., true] => None,
+        [_, true, ..] => Some(crate::semtrace::bit_legend_geom()),
+        [_, _, true, ..] => Some(crate::lattice::bit_legend()),
+        [_, _, _, true, ..] => Some(crate::group::bit_legend()),
+        [_, _, _, _, true, ..] => Some(crate::subsets::bit_legend()),
+        [_, _, _, _, _, true, ..] => Some(crate::semtrace::bit_legend_quad()),
+        [_, _, _, _, _, _, true, _] => Some(crate::semtrace::bit_legend_tetra()),
+        _ => Some(crate::semtrace::bit_legend()),
+    }
 }
 
 fn frac_to_string(f: &Frac) -> String {
     format!("{}/{}", f.num, f.den)
 }
 
+/// First and last element of a canonically sorted set, rendered (null when empty).
+fn set_ends<T>(set: &[T], render: impl Fn(&T) -> String) -> (Option<String>, Option<String>) {
+    (set.first().map(&render), set.last().map(&render))
+}
+
 fn boolfun_to_string(f: &BoolFun) -> String {
     if f.n == 4 {
         format!("0x{:04X}", (f.bits & 0xFFFF) as u16)
@@ -111,7 +394,7 @@ fn dist_lt(x: (i64, i64), y: (i64, i64)) -> bool {
     x.0 * y.1 < y.0 * x.1
 }
 
-fn witness_nearest(set: &[Frac], target: &Frac) -> Option<Frac> {
+pub(crate) fn witness_nearest(set: &[Frac], target: &Frac) -> Option<Frac> {
     if set.is_empty() {
         return None;
     }
@@ -132,10 +415,10 @@ fn witness_nearest(set: &[Frac], target: &Frac) -> Option<Frac> {
     Some(best)
 }
 
-fn filter_qe(qe: &[Frac], cst: Constraint) -> Vec<Frac> {
+fn filter_qe(qe: &[Frac], cst: Constraint, user_preds: &[(String, crate::pred::Expr)]) -> Vec<Frac> {
     let mut out = Vec::new();
     for f in qe {
-        if cst.matches(sig7(f)) {
+        if cst.matches(crate::pred::ext_sig(f, user_preds)) {
             out.push(*f);
         }
     }
@@ -143,6 +426,54 @@ fn filter_qe(qe: &[Frac], cst: Constraint) -> Vec<Frac> {
     out
 }
 
+/// Named snapshot of a state set (SAVE_SET), tagged by universe.
+#[derive(Clone, Debug)]
+enum SavedSet {
+    Qe(Vec<Frac>),
+    Ge(Vec<crate::geom::Tri>),
+    BoolFun(Vec<BoolFun>),
+}
+
+/// One PUSH_STATE entry: everything POP_STATE restores for the active universe.
+#[derive(Clone, Debug)]
+struct Frame {
+    key: String,
+    set_digest: [u8; 32],
+    cst: Constraint,
+    state_set: Vec<Frac>,
+    ge_set: Vec<crate::geom::Tri>,
+    boolfun_set: Vec<BoolFun>,
+    lattice_set: Vec<Pt>,
+    group_set: Vec<Perm>,
+    subset_set: Vec<Subset>,
+    quad_set: Vec<Quad>,
+    tetra_set: Vec<Tetra>,
+    witness: Option<Frac>,
+    witness_bf: Option<BoolFun>,
+    witness_pt: Option<Pt>,
+    witness_perm: Option<Perm>,
+    witness_subset: Option<Subset>,
+    witness_quad: Option<Quad>,
+    witness_tetra: Option<Tetra>,
+}
+
+/// Identity of the selected universe, so a frame is only popped back into the universe it came from.
+fn frame_key(flags: [bool; 7], group: Option<&GroupUniverse>, items: &[i64], boolfun_n: u8) -> String {
+    format!(
+        "{:?}|{}|{:?}|{}",
+        flags,
+        group.map(|g| g.kind.name()).unwrap_or_default(),
+        items,
+        boolfun_n
+    )
+}
+
+fn project_tris(tris: &[crate::geom::Tri]) -> Vec<Frac> {
+    let mut v: Vec<Frac> = tris.iter().map(|t| Frac { num: t.a, den: t.c }).collect();
+    v.sort_by(crate::qe::canonical_cmp);
+    v
+}
+
 fn parse_kv_u64(tok: &str, key: &str) -> Option<u64> {
     let prefix = format!("{key}=");
     if !tok.starts_with(&prefix) {
@@ -151,6 +482,14 @@ fn parse_kv_u64(tok: &str, key: &str) -> Option<u64> {
     tok[prefix.len()..].parse().ok()
 }
 
+fn parse_kv_i64(tok: &str, key: &str) -> Option<i64> {
+    let prefix = format!("{key}=");
+    if !tok.starts_with(&prefix) {
+        return None;
+    }
+    tok[prefix.len()..].parse().ok()
+}
+
 fn parse_kv_bool(tok: &str, key: &str) -> Option<bool> {
     let prefix = format!("{key}=");
     if !tok.starts_with(&prefix) {
@@ -175,6 +514,65 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
         return Ok(("START_ELEM".to_string(), json!({ "elem": elem })));
     }
 
+    if let Some(rest) = s.strip_prefix("DEFINE_PRED") {
+        // expected: DEFINE_PRED name=small expr="den<=10 && num>0" (expr runs to end of line)
+        let name = rest
+            .split_whitespace()
+            .find_map(|t| t.strip_prefix("name="))
+            .ok_or_else(|| anyhow!("DEFINE_PRED missing name="))?;
+        let expr = rest
+            .split_once("expr=")
+            .map(|(_, e)| e.trim().trim_matches('"'))
+            .ok_or_else(|| anyhow!("DEFINE_PRED missing expr="))?;
+        let e = crate::pred::parse_expr(expr).map_err(|m| anyhow!("DEFINE_PRED {}: {}", name, m))?;
+        return Ok((
+            "DEFINE_PRED".to_string(),
+            json!({ "name": name, "expr": crate::pred::render(&e) }),
+        ));
+    }
+
+    if s.starts_with("FILTER_NONLINEARITY") {
+        // expected: FILTER_NONLINEARITY min=4 max=6
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let min = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "min"))
+            .ok_or_else(|| anyhow!("FILTER_NONLINEARITY missing min="))?;
+        let max = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "max"))
+            .ok_or_else(|| anyhow!("FILTER_NONLINEARITY missing max="))?;
+        return Ok(("FILTER_NONLINEARITY".to_string(), json!({ "min": min, "max": max })));
+    }
+
+    if s.starts_with("FILTER_CLASS") {
+        // expected: FILTER_CLASS class=monotone|affine|linear|symmetric|self_dual
+        let class = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("class="))
+            .ok_or_else(|| anyhow!("FILTER_CLASS missing class="))?;
+        return Ok(("FILTER_CLASS".to_string(), json!({ "class": class.to_ascii_lowercase() })));
+    }
+
+    if s.starts_with("FILTER_PRED") {
+        // expected: FILTER_PRED name=den_le_6 val=1 (names from the universe's bit legend)
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let name = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("name="))
+            .ok_or_else(|| anyhow!("FILTER_PRED missing name="))?;
+        let val = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "val"))
+            .ok_or_else(|| anyhow!("FILTER_PRED missing val="))?;
+        return Ok(("FILTER_PRED".to_string(), json!({ "name": name, "val": val })));
+    }
+
     if s.starts_with("MASK_BIT") {
         // expected: MASK_BIT bit=2 val=1
         let toks: Vec<&str> = s.split_whitespace().collect();
@@ -198,11 +596,28 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
         let toks: Vec<&str> = s.split_whitespace().collect();
         let mut universe: Option<String> = None;
         let mut n: Option<u64> = None;
+        let mut group: Option<String> = None;
+        let mut items: Option<Vec<i64>> = None;
+        let mut hash: Option<HashAlg> = None;
         for (j, t) in toks.iter().enumerate().skip(1) {
+            if let Some(v) = t.strip_prefix("hash=") {
+                hash = Some(HashAlg::parse(v).ok_or_else(|| anyhow!("SELECT_UNIVERSE unknown hash={} (sha256, blake3)", v))?);
+                continue;
+            }
             if universe.is_none() && t.starts_with("universe=") {
                 universe = Some(t.trim_start_matches("universe=").trim_end_matches(|c: char| c == ';' || c == ',').to_string());
                 continue;
             }
+            if group.is_none() && t.starts_with("group=") {
+                group = Some(t.trim_start_matches("group=").to_string());
+                continue;
+            }
+            if items.is_none() {
+                if let Some(v) = t.strip_prefix("items=") {
+                    items = Some(parse_items(v).ok_or_else(|| anyhow!("SELECT_UNIVERSE bad items={}", v))?);
+                    continue;
+                }
+            }
             if n.is_none() {
                 n = parse_kv_u64(t, "n");
                 if n.is_some() {
@@ -215,12 +630,223 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
         }
         let universe = universe.ok_or_else(|| anyhow!("SELECT_UNIVERSE missing universe="))?;
         let n = n.unwrap_or(0) as u8;
+        let mut args = json!({ "universe": universe, "n": n });
+        if let Some(g) = group {
+            args["group"] = json!(g);
+        }
+        if let Some(v) = items {
+            args["items"] = json!(v);
+        }
+        if let Some(h) = hash {
+            args["hash"] = json!(h.name());
+        }
+        return Ok(("SELECT_UNIVERSE".to_string(), args));
+    }
+
+    if s.starts_with("SAMPLE") {
+        // expected: SAMPLE seed=42 k=100
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let seed = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "seed"))
+            .ok_or_else(|| anyhow!("SAMPLE missing seed="))?;
+        let k = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "k"))
+            .ok_or_else(|| anyhow!("SAMPLE missing k="))?;
+        return Ok(("SAMPLE".to_string(), json!({ "seed": seed, "k": k })));
+    }
+
+    if s.starts_with("ASSERT_COUNT") {
+        // expected: ASSERT_COUNT eq=151
+        let eq = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "eq"))
+            .ok_or_else(|| anyhow!("ASSERT_COUNT missing eq="))?;
+        return Ok(("ASSERT_COUNT".to_string(), json!({ "eq": eq })));
+    }
+
+    if s.starts_with("ASSERT_WITNESS") {
+        // expected: ASSERT_WITNESS elem=1/3
+        let elem = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("elem="))
+            .ok_or_else(|| anyhow!("ASSERT_WITNESS missing elem="))?;
+        return Ok(("ASSERT_WITNESS".to_string(), json!({ "elem": elem })));
+    }
+
+    if s.starts_with("GROUP_BY") {
+        // expected: GROUP_BY  or  GROUP_BY by=<sig|predicate name>
+        let by = s.split_whitespace().skip(1).find_map(|t| t.strip_prefix("by="));
         return Ok((
-            "SELECT_UNIVERSE".to_string(),
-            json!({ "universe": universe, "n": n }),
+            "GROUP_BY".to_string(),
+            match by {
+                Some(b) => json!({ "by": b }),
+                None => json!({}),
+            },
+        ));
+    }
+
+    if s == "PUSH_STATE" || s == "POP_STATE" {
+        return Ok((s.to_string(), json!({})));
+    }
+
+    if s.starts_with("NPN_CLASS") {
+        // expected: NPN_CLASS  or  NPN_CLASS elem=0x8000
+        return Ok((
+            "NPN_CLASS".to_string(),
+            match s.split_whitespace().skip(1).find_map(|t| t.strip_prefix("elem=")) {
+                Some(e) => json!({ "elem": e }),
+                None => json!({}),
+            },
         ));
     }
 
+    if s == "DUAL" {
+        return Ok((s.to_string(), json!({})));
+    }
+
+    if s.starts_with("PERMUTE_VARS") {
+        // expected: PERMUTE_VARS perm=1,0,2,3
+        let perm: Vec<u64> = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("perm="))
+            .ok_or_else(|| anyhow!("PERMUTE_VARS missing perm="))?
+            .split(',')
+            .map(|x| x.trim().parse::<u64>().map_err(|_| anyhow!("bad PERMUTE_VARS perm entry: {}", x)))
+            .collect::<Result<_>>()?;
+        return Ok(("PERMUTE_VARS".to_string(), json!({ "perm": perm })));
+    }
+
+    if s.starts_with("RESTRICT") {
+        // expected: RESTRICT var=2 value=1
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let var = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "var"))
+            .ok_or_else(|| anyhow!("RESTRICT missing var="))?;
+        let value = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "value"))
+            .ok_or_else(|| anyhow!("RESTRICT missing value="))?;
+        return Ok(("RESTRICT".to_string(), json!({ "var": var, "value": value })));
+    }
+
+    if s == "NEGATE" || s == "RECIPROCAL" {
+        return Ok((s.to_string(), json!({})));
+    }
+
+    if s.starts_with("MEDIANT_WITH") {
+        // expected: MEDIANT_WITH elem=1/1
+        let elem = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("elem="))
+            .ok_or_else(|| anyhow!("MEDIANT_WITH missing elem="))?;
+        let f = parse_frac(elem).ok_or_else(|| anyhow!("MEDIANT_WITH bad elem: {}", elem))?;
+        return Ok(("MEDIANT_WITH".to_string(), json!({ "elem": frac_to_string(&f) })));
+    }
+
+    if s == "AGGREGATE" {
+        return Ok(("AGGREGATE".to_string(), json!({})));
+    }
+
+    if s == "COMPLEMENT" {
+        return Ok(("COMPLEMENT".to_string(), json!({})));
+    }
+
+    if s.starts_with("SAVE_SET") || s.starts_with("INTERSECT") || s.starts_with("UNION") {
+        // expected: SAVE_SET name=A | INTERSECT name=A | UNION name=A
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let op = toks[0];
+        let name = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("name="))
+            .ok_or_else(|| anyhow!("{} missing name=", op))?;
+        return Ok((op.to_string(), json!({ "name": name })));
+    }
+
+    if s.starts_with("FILTER_CONJ") {
+        // expected: FILTER_CONJ elem=[1,0,2,3]
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let elem = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("elem="))
+            .ok_or_else(|| anyhow!("FILTER_CONJ missing elem="))?;
+        return Ok(("FILTER_CONJ".to_string(), json!({ "elem": elem })));
+    }
+
+    if s.starts_with("FILTER_SUM") {
+        // expected: FILTER_SUM min=10 max=20 (signed)
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let mut min: Option<i64> = None;
+        let mut max: Option<i64> = None;
+        for t in toks.iter().skip(1) {
+            if min.is_none() {
+                min = parse_kv_i64(t, "min");
+            }
+            if max.is_none() {
+                max = parse_kv_i64(t, "max");
+            }
+        }
+        let min = min.ok_or_else(|| anyhow!("FILTER_SUM missing min="))?;
+        let max = max.ok_or_else(|| anyhow!("FILTER_SUM missing max="))?;
+        return Ok(("FILTER_SUM".to_string(), json!({ "min": min, "max": max })));
+    }
+
+    if s.starts_with("FILTER_MAX_ANGLE") {
+        // expected: FILTER_MAX_ANGLE max=75 (largest angle, whole degrees)
+        let max = s
+            .split_whitespace()
+            .skip(1)
+            .find_map(|t| parse_kv_u64(t, "max"))
+            .ok_or_else(|| anyhow!("FILTER_MAX_ANGLE missing max="))?;
+        return Ok(("FILTER_MAX_ANGLE".to_string(), json!({ "max": max })));
+    }
+
+    if s.starts_with("FILTER_AREA") {
+        // expected: FILTER_AREA min=6 max=30 (bounds on the exact area)
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let mut min: Option<u64> = None;
+        let mut max: Option<u64> = None;
+        for t in toks.iter().skip(1) {
+            if min.is_none() {
+                min = parse_kv_u64(t, "min");
+            }
+            if max.is_none() {
+                max = parse_kv_u64(t, "max");
+            }
+        }
+        let min = min.ok_or_else(|| anyhow!("FILTER_AREA missing min="))?;
+        let max = max.ok_or_else(|| anyhow!("FILTER_AREA missing max="))?;
+        return Ok(("FILTER_AREA".to_string(), json!({ "min": min, "max": max })));
+    }
+
+    if s.starts_with("FILTER_RANGE") {
+        // expected: FILTER_RANGE min=1/4 max=1/2 (QE) or FILTER_RANGE min=10 max=20 (GE perimeter)
+        let toks: Vec<&str> = s.split_whitespace().collect();
+        let min = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("min="))
+            .ok_or_else(|| anyhow!("FILTER_RANGE missing min="))?;
+        let max = toks
+            .iter()
+            .skip(1)
+            .find_map(|t| t.strip_prefix("max="))
+            .ok_or_else(|| anyhow!("FILTER_RANGE missing max="))?;
+        return Ok(("FILTER_RANGE".to_string(), json!({ "min": min, "max": max })));
+    }
+
     if s.starts_with("FILTER_WEIGHT") {
         // expected: FILTER_WEIGHT min=1 max=3
         let toks: Vec<&str> = s.split_whitespace().collect();
@@ -243,8 +869,16 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
     }
 
     if s.starts_with("TOPK") {
-        // expected: TOPK target=0xBEEF k=5
+        // expected: TOPK target=0xBEEF k=5  or  TOPK metric=NONLINEARITY k=5 (BOOLFUN)
         let toks: Vec<&str> = s.split_whitespace().collect();
+        if let Some(metric) = toks.iter().skip(1).find_map(|t| t.strip_prefix("metric=")) {
+            let k = toks
+                .iter()
+                .skip(1)
+                .find_map(|t| parse_kv_u64(t, "k"))
+                .ok_or_else(|| anyhow!("TOPK missing k="))?;
+            return Ok(("TOPK".to_string(), json!({ "metric": metric, "k": k })));
+        }
         let mut target: Option<String> = None;
         let mut k: Option<u64> = None;
         for t in toks.iter().skip(1) {
@@ -266,9 +900,29 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
         ));
     }
 
-    if s.starts_with("WITNESS_NEAREST") {
+    if s.starts_with("WITNESS_NEAREST") || s.starts_with("WITNESS_ALL_TIES") {
         // expected: WITNESS_NEAREST target=13/37 (metric defaults ABS_DIFF)
+        //      or: WITNESS_NEAREST targets=13/37,1/3 mode=minimax|sum
         let toks: Vec<&str> = s.split_whitespace().collect();
+        if s.starts_with("WITNESS_NEAREST") {
+            if let Some(list) = toks.iter().skip(1).find_map(|t| t.strip_prefix("targets=")) {
+                let targets: Vec<&str> = list.split(',').filter(|t| !t.is_empty()).collect();
+                let mode = toks
+                    .iter()
+                    .skip(1)
+                    .find_map(|t| t.strip_prefix("mode="))
+                    .unwrap_or("minimax");
+                let metric = toks
+                    .iter()
+                    .skip(1)
+                    .find_map(|t| t.strip_prefix("metric="))
+                    .unwrap_or("ABS_DIFF");
+                return Ok((
+                    "WITNESS_NEAREST".to_string(),
+                    json!({ "targets": targets, "mode": mode, "metric": metric }),
+                ));
+            }
+        }
         let mut target: Option<String> = None;
         let mut metric: Option<String> = None;
         for t in toks.iter().skip(1) {
@@ -286,10 +940,11 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
                 );
             }
         }
-        let target_elem = target.ok_or_else(|| anyhow!("WITNESS_NEAREST missing target="))?;
+        let name = if s.starts_with("WITNESS_ALL_TIES") { "WITNESS_ALL_TIES" } else { "WITNESS_NEAREST" };
+        let target_elem = target.ok_or_else(|| anyhow!("{} missing target=", name))?;
         let metric = metric.unwrap_or_else(|| "ABS_DIFF".to_string());
         return Ok((
-            "WITNESS_NEAREST".to_string(),
+            name.to_string(),
             json!({ "target_elem": target_elem, "metric": metric }),
         ));
     }
@@ -351,22 +1006,46 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
     }
 
     if s.starts_with("RETURN_SET") {
-        // expected: RETURN_SET max_items=10 include_witness=true
+        // expected: RETURN_SET max_items=10 include_witness=true [offset=20] [sort_by=value|distance]
+        //          [include_proofs=true]
+        // limit= is an alias for max_items=
         let toks: Vec<&str> = s.split_whitespace().collect();
         let mut max_items: usize = 20;
         let mut include_witness: bool = false;
+        let mut include_proofs: bool = false;
+        let mut offset: Option<u64> = None;
+        let mut sort_by: Option<&str> = None;
         for t in toks.iter().skip(1) {
-            if let Some(v) = parse_kv_u64(t, "max_items") {
+            if let Some(v) = parse_kv_u64(t, "max_items").or_else(|| parse_kv_u64(t, "limit")) {
                 max_items = v as usize;
             }
             if let Some(v) = parse_kv_bool(t, "include_witness") {
                 include_witness = v;
             }
+            if let Some(v) = parse_kv_bool(t, "include_proofs") {
+                include_proofs = v;
+            }
+            if let Some(v) = parse_kv_u64(t, "offset") {
+                offset = Some(v);
+            }
+            if let Some(v) = t.strip_prefix("sort_by=") {
+                if v != "value" && v != "distance" {
+                    return Err(anyhow!("RETURN_SET sort_by must be value or distance, got {}", v));
+                }
+                sort_by = Some(v);
+            }
         }
-        return Ok((
-            "RETURN_SET".to_string(),
-            json!({ "max_items": max_items, "include_witness": include_witness }),
-        ));
+        let mut args = json!({ "max_items": max_items, "include_witness": include_witness });
+        if let Some(o) = offset {
+            args["offset"] = json!(o);
+        }
+        if let Some(b) = sort_by {
+            args["sort_by"] = json!(b);
+        }
+        if include_proofs {
+            args["include_proofs"] = json!(true);
+        }
+        return Ok(("RETURN_SET".to_string(), args));
     }
     if s.starts_with("PROJECT_SIGNATURE") {
         let toks: Vec<&str> = s.split_whitespace().collect();
@@ -382,198 +1061,1251 @@ fn parse_op_to_semtrace(op: &str) -> Result<(String, JsonValue)> {
     Err(anyhow!("unknown op: {}", s))
 }
 
-pub fn run_trace_and_write(
-    ops: &[String],
-    _trace_path: Option<&Path>,
-    verbose: bool,
-) -> Result<ExecutionResult> {
-    let start = Instant::now();
+/// Check one op line against the op grammar without executing it.
+pub fn validate_op(op: &str) -> Result<()> {
+    op.parse::<Op>().map(|_| ())
+}
 
-    // Artifacts dir
-    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S%.6fZ").to_string();
-    let thread_id = format!("{:?}", std::thread::current().id()).replace("ThreadId(", "").replace(")", "");
-    let artifacts_dir = PathBuf::from("runs").join(format!("{}_{}", timestamp, thread_id));
-    fs::create_dir_all(&artifacts_dir)?;
+/// The trace record op name and args an op line parses to.
+pub(crate) fn parse_op(op: &str) -> Result<(String, JsonValue)> {
+    parse_op_to_semtrace(op)
+}
 
-    let trace_ndjson_path = artifacts_dir.join("trace.ndjson");
-    let proof_path = artifacts_dir.join("proof.json");
-    let result_path = artifacts_dir.join("result.json");
-    let paragraph_path = artifacts_dir.join("paragraph.txt");
+/// Where runs write their artifacts: `<root>/<run id>/`. Unset fields fall back to
+/// the `LNST_OUTPUT_DIR` / `LNST_RUN_ID` environment variables, then to `runs/` and
+/// `<utc timestamp>_<thread>`.
+#[derive(Clone, Debug, Default)]
+pub struct OutputConfig {
+    pub root: Option<PathBuf>,
+    pub run_id: Option<String>,
+    /// Also write deltas.ndjson, the per-step element deltas `verify --succinct` reads.
+    pub deltas: bool,
+    /// Sign each run with this Ed25519 PKCS#8 PEM key, writing proof.sig.
+    pub sign_key: Option<PathBuf>,
+    /// Set-digest hash backend for runs whose first op does not pick one with
+    /// `SELECT_UNIVERSE ... hash=`.
+    pub hash: HashAlg,
+    /// Reproducible artifacts: the run id comes from the ops (`ops_run_id`),
+    /// proof.json carries no timestamp and artifact paths are relative to the
+    /// run directory, so re-running the same ops rewrites every file byte for byte.
+    pub deterministic: bool,
+}
 
-    // Universe state
-    let qe = build_qe();
-    let ge_state = crate::geom::build_ge(20);
-
-    let mut boolfun_all: Vec<BoolFun> = Vec::new();
-    let mut boolfun_set: Vec<BoolFun> = Vec::new();
-    let mut boolfun_n: u8 = 0;
-    let mut is_boolfun: bool = false;
-
-    let mut state_set: Vec<Frac> = Vec::new();
-    let mut cst = Constraint::empty();
-    let mut active_universe: String = "QE".to_string();
-    let mut set_digest: [u8; 32] = sha256_bytes(b"");
-    let mut witness: Option<Frac> = None;
-    let mut witness_bf: Option<BoolFun> = None;
-    let mut is_ge: bool = false;
-
-    let mut word_all: Vec<Word> = Vec::new();
-    let mut word_set: Vec<Word> = Vec::new();
-    let mut is_word: bool = false;
-    let mut witness_word: Option<Word> = None;
-    let _ = &witness_word; // read via is_word branches below
-    let mut syllable_all: Vec<Syllable> = Vec::new();
-    let mut syllable_set: Vec<Syllable> = Vec::new();
-    let mut witness_syllable: Option<Syllable> = None;
-    let _ = &witness_syllable;
-    let mut morpheme_all: Vec<Morpheme> = Vec::new();
-    let mut morpheme_set: Vec<Morpheme> = Vec::new();
-    let mut witness_morpheme: Option<Morpheme> = None;
-    let _ = &witness_morpheme;
-    let mut phrase_all: Vec<Phrase> = Vec::new();
-    let mut phrase_set: Vec<Phrase> = Vec::new();
-    let mut witness_phrase: Option<Phrase> = None;
-    let _ = &witness_phrase;
-    let mut semantic_all: Vec<SemanticGraph> = Vec::new();
-    let mut semantic_set: Vec<SemanticGraph> = Vec::new();
-    let mut witness_semantic: Option<SemanticGraph> = None;
-    let _ = &witness_semantic;
-    let mut discourse_all: Vec<DiscourseGraph> = Vec::new();
-    let mut discourse_set: Vec<DiscourseGraph> = Vec::new();
-    let mut witness_discourse: Option<DiscourseGraph> = None;
-    let _ = &witness_discourse;
-    let mut is_syllable = false;
-    let mut is_morpheme = false;
-    let mut is_phrase = false;
-    let mut is_semantic = false;
-    let mut is_discourse = false;
-
-    let mut chain: [u8; 32] = sha256_bytes(b"");
+static OUTPUT: std::sync::Mutex<OutputConfig> = std::sync::Mutex::new(OutputConfig {
+    root: None,
+    run_id: None,
+    deltas: false,
+    sign_key: None,
+    hash: HashAlg::Sha256,
+    deterministic: false,
+});
 
-    // RETURN_SET params for result output
-    let mut want_max_items: usize = 20;
-    let mut want_include_witness: bool = false;
+/// Set the artifacts root and run id for every later run in this process.
+pub fn set_output_config(cfg: OutputConfig) {
+    *OUTPUT.lock().unwrap_or_else(|e| e.into_inner()) = cfg;
+}
 
-    let mut out_lines: Vec<String> = Vec::with_capacity(ops.len());
+/// Change only the default hash backend (e.g. from a trace file's `hash`
+/// header), returning the previous one.
+pub fn set_default_hash(hash: HashAlg) -> HashAlg {
+    std::mem::replace(&mut OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).hash, hash)
+}
 
-    for (step_idx, raw_op) in ops.iter().enumerate() {
-        let (op, args) = parse_op_to_semtrace(raw_op)?;
+/// The artifacts root runs are written under, made absolute.
+pub fn artifacts_root() -> Result<PathBuf> {
+    let cfg = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let root = cfg
+        .root
+        .or_else(|| std::env::var_os("LNST_OUTPUT_DIR").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("runs"));
+    Ok(std::path::absolute(root)?)
+}
 
-        let pre = StepPre {
-            set_digest: if step_idx == 0
-                && ((is_boolfun && boolfun_set.is_empty()) || (!is_boolfun && state_set.is_empty()))
-            {
-                None
-            } else {
-                Some(hex32(set_digest))
-            },
-            count: if is_boolfun {
-                boolfun_set.len()
-            } else {
-                state_set.len()
-            },
-            constraint_mask: cst.mask,
-            constraint_value: cst.value,
-        };
+/// Sizes used for `SELECT_UNIVERSE ... n=0` instead of each universe's built-in
+/// default. The chosen n is written into the trace, so replay does not depend on it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UniverseBounds {
+    pub lattice_radius: Option<u64>,
+    pub quad_perimeter: Option<u64>,
+    pub tetra_edge: Option<u64>,
+}
 
-        match op.as_str() {
-            "SELECT_UNIVERSE" => {
-                let u = args
-                    .get("universe")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("bad args for SELECT_UNIVERSE"))?;
-                let n = args.get("n").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
+static BOUNDS: std::sync::Mutex<UniverseBounds> =
+    std::sync::Mutex::new(UniverseBounds { lattice_radius: None, quad_perimeter: None, tetra_edge: None });
 
-                let u_norm = u.to_ascii_uppercase();
-                active_universe = u_norm.clone();
+/// Set the universe bounds for every later run in this process.
+pub fn set_universe_bounds(bounds: UniverseBounds) {
+    *BOUNDS.lock().unwrap_or_else(|e| e.into_inner()) = bounds;
+}
 
-                // BOOLFUN
-                if is_boolfun_universe(u_norm.as_str()) {
-                    is_boolfun = true;
-                    is_ge = false;
-                    cst = Constraint::empty();
-                    state_set.clear();
-                    boolfun_n = n;
-                    boolfun_all = build_boolfun(n);
-                    boolfun_set = boolfun_all.clone();
-                    boolfun_set.sort_by(boolfun_canonical_cmp);
-                    set_digest = canonical_set_digest_boolfun(&boolfun_set);
-                    witness = None;
-                    witness_bf = None;
-                } else if u_norm == "QE" {
+/// Replace `n=0` in SELECT_UNIVERSE args with the configured bound, if any.
+fn apply_universe_bounds(args: &mut JsonValue) {
+    if args.get("n").and_then(|v| v.as_u64()) != Some(0) {
+        return;
+    }
+    let b = *BOUNDS.lock().unwrap_or_else(|e| e.into_inner());
+    let u = args.get("universe").and_then(|v| v.as_str()).unwrap_or("");
+    let bound = if is_lattice_universe(u) {
+        b.lattice_radius
+    } else if is_quad_universe(u) {
+        b.quad_perimeter
+    } else if is_tetra_universe(u) {
+        b.tetra_edge
+    } else {
+        None
+    };
+    if let Some(n) = bound {
+        args["n"] = json!(n);
+    }
+}
+
+/// Where the CLI writes compiled input traces: `traces/` under the CWD, or next to
+/// the runs when an artifacts root is configured.
+pub fn traces_dir() -> Result<PathBuf> {
+    let configured = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).root.is_some()
+        || std::env::var_os("LNST_OUTPUT_DIR").is_some();
+    if configured {
+        Ok(artifacts_root()?.join("traces"))
+    } else {
+        Ok(PathBuf::from("traces"))
+    }
+}
+
+/// Run id of a deterministic run: `ops_` and the first 16 hex digits of the
+/// SHA-256 of the ops as canonical JSON.
+pub fn ops_run_id(ops: &[String]) -> String {
+    format!("ops_{}", &hex32(sha256_bytes(&crate::canonical::to_jcs(&json!(ops))))[..16])
+}
+
+/// Create a fresh run directory. A configured run id that is already taken (e.g.
+/// by an earlier beam candidate) gets a `-2`, `-3`, … suffix.
+fn create_run_dir(ops: &[String], deterministic: bool) -> Result<(String, PathBuf)> {
+    let root = artifacts_root()?;
+    fs::create_dir_all(&root)?;
+    let fixed = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).run_id.clone();
+    let base = match fixed.or_else(|| std::env::var("LNST_RUN_ID").ok()) {
+        Some(id) => id,
+        None if deterministic => ops_run_id(ops),
+        None => {
+            let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S%.6fZ").to_string();
+            let thread_id = format!("{:?}", std::thread::current().id()).replace("ThreadId(", "").replace(")", "");
+            format!("{}_{}", timestamp, thread_id)
+        }
+    };
+    claim_run_dir(&root, &base)
+}
+
+fn claim_run_dir(root: &Path, base: &str) -> Result<(String, PathBuf)> {
+    for n in 1.. {
+        let id = if n == 1 { base.to_string() } else { format!("{}-{}", base, n) };
+        match fs::create_dir(root.join(&id)) {
+            Ok(()) => return Ok((id.clone(), root.join(id))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(anyhow!("creating run directory {}: {}", root.join(&id).display(), e)),
+        }
+    }
+    unreachable!("run id suffixes are unbounded")
+}
+
+/// Outcome of re-executing a saved run.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReplayReport {
+    pub run: PathBuf,
+    pub replay: Option<PathBuf>,
+    pub ops: Vec<String>,
+    pub stored_chain_hash: Option<String>,
+    pub replay_chain_hash: Option<String>,
+    /// result.json keys whose values differ (artifact paths excluded), or the replay error.
+    pub divergences: Vec<String>,
+    /// For a deterministic run: whether every stored artifact was rewritten byte for byte.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_identical: Option<bool>,
+    pub reproduced: bool,
+}
+
+/// Files a run directory may hold; a deterministic replay must rewrite each stored one exactly.
+const ARTIFACT_FILES: &[&str] = &["trace.ndjson", "deltas.ndjson", "proof.json", "result.json", "paragraph.txt", "proof.sig"];
+
+/// Re-execute the `ops_in` recorded in `run_dir/proof.json` (with the same proposer
+/// provenance) and compare the new result.json, chain hash included, to the stored one.
+/// A deterministic run is replayed deterministically and each of its artifacts
+/// compared byte for byte; pass the same `--deltas` / `--sign-key` it was written with.
+pub fn replay_run(run_dir: &Path) -> Result<ReplayReport> {
+    let read = |name: &str| -> Result<JsonValue> {
+        let path = run_dir.join(name);
+        let txt = fs::read_to_string(&path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+        serde_json::from_str(&txt).map_err(|e| anyhow!("parsing {}: {}", path.display(), e))
+    };
+    let (proof, stored) = (read("proof.json")?, read("result.json")?);
+    let ops: Vec<String> = serde_json::from_value(proof["ops_in"].clone())
+        .map_err(|e| anyhow!("proof.json ops_in: {}", e))?;
+    let hash = match proof.get("hash").and_then(|h| h.as_str()) {
+        Some(h) => HashAlg::parse(h).ok_or_else(|| anyhow!("proof.json: unknown hash {}", h))?,
+        None => HashAlg::Sha256,
+    };
+    let mut report = ReplayReport {
+        run: run_dir.to_path_buf(),
+        replay: None,
+        ops: ops.clone(),
+        stored_chain_hash: stored["chain_hash"].as_str().map(str::to_string),
+        replay_chain_hash: None,
+        divergences: Vec::new(),
+        byte_identical: None,
+        reproduced: false,
+    };
+    // replay as the run was written: same backend, mode and deltas
+    let deterministic = proof["deterministic"] == json!(true);
+    let out = OutputConfig {
+        hash,
+        deterministic,
+        deltas: run_dir.join("deltas.ndjson").exists(),
+        ..OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    };
+    let r = match run_trace(&ops, proof.get("proposer"), &out) {
+        Ok(r) => r,
+        Err(e) => {
+            report.divergences.push(format!("replay failed: {}", e));
+            return Ok(report);
+        }
+    };
+    let new_dir = r.artifacts_path.clone().ok_or_else(|| anyhow!("replay wrote no artifacts"))?;
+    let fresh: JsonValue = serde_json::from_str(&fs::read_to_string(new_dir.join("result.json"))?)?;
+    report.replay = Some(new_dir.clone());
+    report.replay_chain_hash = fresh["chain_hash"].as_str().map(str::to_string);
+    let empty = serde_json::Map::new();
+    let (a, b) = (stored.as_object().unwrap_or(&empty), fresh.as_object().unwrap_or(&empty));
+    let keys: std::collections::BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for k in keys {
+        if k != "artifacts" && a.get(k) != b.get(k) {
+            report.divergences.push(format!(
+                "{}: stored {} != replay {}",
+                k,
+                a.get(k).map_or("<missing>".to_string(), JsonValue::to_string),
+                b.get(k).map_or("<missing>".to_string(), JsonValue::to_string)
+            ));
+        }
+    }
+    if deterministic {
+        let before = report.divergences.len();
+        for name in ARTIFACT_FILES {
+            let Ok(stored) = fs::read(run_dir.join(name)) else { continue };
+            match fs::read(new_dir.join(name)) {
+                Ok(fresh) if fresh == stored => {}
+                Ok(_) => report.divergences.push(format!("{}: not byte-identical", name)),
+                Err(_) => report.divergences.push(format!("{}: missing from the replay", name)),
+            }
+        }
+        report.byte_identical = Some(report.divergences.len() == before);
+    }
+    report.reproduced = report.divergences.is_empty();
+    Ok(report)
+}
+
+/// Execute `ops` and write the run directory. Progress is logged through
+/// `tracing` (the `execute` and `artifacts` spans), not printed, so `verbose`
+/// no longer changes anything; a subscriber's filter decides what is shown.
+pub fn run_trace_and_write(
+    ops: &[String],
+    trace_path: Option<&Path>,
+    verbose: bool,
+) -> Result<ExecutionResult> {
+    run_trace_and_write_with(ops, trace_path, verbose, None)
+}
+
+/// As `run_trace_and_write`, also recording how the ops were proposed
+/// (backend, sampling parameters, …) under `proposer` in proof.json.
+pub fn run_trace_and_write_with(
+    ops: &[String],
+    _trace_path: Option<&Path>,
+    _verbose: bool,
+    provenance: Option<&JsonValue>,
+) -> Result<ExecutionResult> {
+    let out = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    run_trace(ops, provenance, &out)
+}
+
+/// Snapshot of an `Executor` between steps.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExecutorState {
+    /// Steps applied so far.
+    pub steps: usize,
+    pub universe: String,
+    pub count: usize,
+    pub set_digest: String,
+    pub witness: Option<String>,
+    pub constraint_mask: u64,
+    pub constraint_value: u64,
+    /// Step digest of the last step applied.
+    pub chain_hash: String,
+}
+
+/// Step events from an `Executor`, for logs, progress displays or metrics.
+pub trait ExecObserver {
+    /// After each step, with the record it adds to trace.ndjson.
+    fn on_step(&mut self, _rec: &StepRecord) {}
+    /// Once, when the run is finalized or its artifacts are written.
+    fn on_finish(&mut self, _result: &ExecutionResult) {}
+}
+
+/// Logs each step at debug level and the finish at info, for `--verbose`
+/// and `RUST_LOG`.
+struct StepLog {
+    start: Option<Instant>,
+}
+
+/// Now, for step timings. std has no clock on wasm32-unknown-unknown, so
+/// there every step takes no time.
+fn clock() -> Option<Instant> {
+    (!cfg!(target_arch = "wasm32")).then(Instant::now)
+}
+
+impl ExecObserver for StepLog {
+    fn on_step(&mut self, rec: &StepRecord) {
+        tracing::debug!(step = rec.step, op = %rec.op, count = rec.post.count, "step");
+    }
+
+    fn on_finish(&mut self, result: &ExecutionResult) {
+        let dir = result.artifacts_path.as_ref().map(|d| d.display().to_string());
+        tracing::info!(count = result.final_count, valid = result.valid, elapsed = ?self.start.map(|t| t.elapsed()), dir, "execution finished");
+    }
+}
+
+/// Executes ops one at a time, keeping the set each step leaves behind.
+///
+/// `run_trace_and_write` drives one over a whole trace and hands it to
+/// `write_artifacts`; a library caller can drive it directly, look at the set
+/// between steps and stop whenever it likes, without touching the filesystem.
+pub struct Executor {
+    hash: HashAlg,
+    // op lines applied so far
+    ops: Vec<String>,
+    observers: Vec<Box<dyn ExecObserver + Send>>,
+
+    // Universe state
+    qe: Vec<Frac>,
+    ge_state: Vec<crate::geom::Tri>,
+
+    boolfun_all: Vec<BoolFun>,
+    boolfun_set: Vec<BoolFun>,
+    boolfun_n: u8,
+    is_boolfun: bool,
+
+    state_set: Vec<Frac>,
+    cst: Constraint,
+    active_universe: String,
+    set_digest: [u8; 32],
+    witness: Option<Frac>,
+    witness_bf: Option<BoolFun>,
+    is_ge: bool,
+    // GE triangles behind the (num=a, den=c) projection in state_set
+    ge_set: Vec<crate::geom::Tri>,
+    saved_sets: BTreeMap<String, SavedSet>,
+    // PUSH_STATE / POP_STATE stack, replayed identically by the verifier
+    state_stack: Vec<Frame>,
+    // DEFINE_PRED registry: user signature bits 7, 8, … in definition order
+    user_preds: Vec<(String, crate::pred::Expr)>,
+
+    lattice_all: Vec<Pt>,
+    lattice_set: Vec<Pt>,
+    witness_pt: Option<Pt>,
+    is_lattice: bool,
+
+    group_univ: Option<GroupUniverse>,
+    group_set: Vec<Perm>,
+    witness_perm: Option<Perm>,
+    is_group: bool,
+
+    subset_items: Vec<i64>,
+    subset_all: Vec<Subset>,
+    subset_set: Vec<Subset>,
+    witness_subset: Option<Subset>,
+    is_subsets: bool,
+
+    quad_all: Vec<Quad>,
+    quad_set: Vec<Quad>,
+    witness_quad: Option<Quad>,
+    is_quad: bool,
+
+    tetra_all: Vec<Tetra>,
+    tetra_set: Vec<Tetra>,
+    witness_tetra: Option<Tetra>,
+    is_tetra: bool,
+
+    word_all: Vec<Word>,
+    word_set: Vec<Word>,
+    is_word: bool,
+    witness_word: Option<Word>,
+    syllable_all: Vec<Syllable>,
+    syllable_set: Vec<Syllable>,
+    witness_syllable: Option<Syllable>,
+    morpheme_all: Vec<Morpheme>,
+    morpheme_set: Vec<Morpheme>,
+    witness_morpheme: Option<Morpheme>,
+    phrase_all: Vec<Phrase>,
+    phrase_set: Vec<Phrase>,
+    witness_phrase: Option<Phrase>,
+    semantic_all: Vec<SemanticGraph>,
+    semantic_set: Vec<SemanticGraph>,
+    witness_semantic: Option<SemanticGraph>,
+    discourse_all: Vec<DiscourseGraph>,
+    discourse_set: Vec<DiscourseGraph>,
+    witness_discourse: Option<DiscourseGraph>,
+    is_syllable: bool,
+    is_morpheme: bool,
+    is_phrase: bool,
+    is_semantic: bool,
+    is_discourse: bool,
+
+    chain: [u8; 32],
+
+    // RETURN_SET params for result output
+    want_max_items: usize,
+    want_offset: usize,
+    want_sort_by: String,
+    want_include_witness: bool,
+    want_include_proofs: bool,
+
+    // Last WITNESS_ALL_TIES result: tied elements and their merkle sub-root
+    witness_ties: Option<(Vec<String>, [u8; 32])>,
+    // Last AGGREGATE statistics
+    aggregate: Option<JsonValue>,
+    // Last GROUP_BY histogram
+    group_by: Option<BTreeMap<String, usize>>,
+    // First ASSERT_* step that did not hold; the verifier rejects the trace on it
+    first_failed_assertion: Option<String>,
+
+    // trace.ndjson lines so far
+    out_lines: Vec<String>,
+    // Element deltas, kept while every step's set digest is a plain merkle root
+    // over its leaves; None once a step is not (e.g. GE after MASK_BIT).
+    deltas: Option<Vec<String>>,
+    prev_leaves: Vec<[u8; 32]>,
+    metrics: Vec<StepMetrics>,
+}
+
+impl Executor {
+    fn start(hash: HashAlg, deltas: bool) -> Executor {
+        let _hash = crate::digest::use_hash(hash);
+        Executor {
+            hash,
+            ops: Vec::new(),
+            observers: vec![Box::new(StepLog { start: clock() })],
+            qe: build_qe(),
+            ge_state: crate::geom::build_ge(20),
+            boolfun_all: Vec::new(),
+            boolfun_set: Vec::new(),
+            boolfun_n: 0,
+            is_boolfun: false,
+            state_set: Vec::new(),
+            cst: Constraint::empty(),
+            active_universe: "QE".to_string(),
+            set_digest: set_root(&[]),
+            witness: None,
+            witness_bf: None,
+            is_ge: false,
+            ge_set: Vec::new(),
+            saved_sets: BTreeMap::new(),
+            state_stack: Vec::new(),
+            user_preds: Vec::new(),
+            lattice_all: Vec::new(),
+            lattice_set: Vec::new(),
+            witness_pt: None,
+            is_lattice: false,
+            group_univ: None,
+            group_set: Vec::new(),
+            witness_perm: None,
+            is_group: false,
+            subset_items: Vec::new(),
+            subset_all: Vec::new(),
+            subset_set: Vec::new(),
+            witness_subset: None,
+            is_subsets: false,
+            quad_all: Vec::new(),
+            quad_set: Vec::new(),
+            witness_quad: None,
+            is_quad: false,
+            tetra_all: Vec::new(),
+            tetra_set: Vec::new(),
+            witness_tetra: None,
+            is_tetra: false,
+            word_all: Vec::new(),
+            word_set: Vec::new(),
+            is_word: false,
+            witness_word: None,
+            syllable_all: Vec::new(),
+            syllable_set: Vec::new(),
+            witness_syllable: None,
+            morpheme_all: Vec::new(),
+            morpheme_set: Vec::new(),
+            witness_morpheme: None,
+            phrase_all: Vec::new(),
+            phrase_set: Vec::new(),
+            witness_phrase: None,
+            semantic_all: Vec::new(),
+            semantic_set: Vec::new(),
+            witness_semantic: None,
+            discourse_all: Vec::new(),
+            discourse_set: Vec::new(),
+            witness_discourse: None,
+            is_syllable: false,
+            is_morpheme: false,
+            is_phrase: false,
+            is_semantic: false,
+            is_discourse: false,
+            chain: sha256_bytes(b""),
+            want_max_items: 20,
+            want_offset: 0,
+            want_sort_by: "value".to_string(),
+            want_include_witness: false,
+            want_include_proofs: false,
+            witness_ties: None,
+            aggregate: None,
+            group_by: None,
+            first_failed_assertion: None,
+            out_lines: Vec::new(),
+            deltas: deltas.then(Vec::new),
+            prev_leaves: Vec::new(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Apply one op and return its trace.ndjson record.
+    pub fn apply(&mut self, op: Op) -> Result<StepRecord> {
+        let started = clock();
+        let _hash = crate::digest::use_hash(self.hash);
+        let (step_idx, line) = (self.out_lines.len(), op.to_string());
+        let (op, mut args) = (op.name().to_string(), op.args());
+        if op == "SELECT_UNIVERSE" {
+            apply_universe_bounds(&mut args);
+            if args.get("hash").is_some_and(|h| h.as_str() != Some(self.hash.name())) {
+                return Err(anyhow!("step {}: hash= can only be chosen by the first op, and this run uses {}", step_idx, self.hash.name()));
+            }
+        }
+        let mut step_ties: Option<(Vec<String>, [u8; 32])> = None;
+        let mut step_hist: Option<BTreeMap<String, usize>> = None;
+        let mut step_agg: Option<JsonValue> = None;
+
+        let pre = StepPre {
+            set_digest: if step_idx == 0
+                && ((self.is_boolfun && self.boolfun_set.is_empty()) || (!self.is_boolfun && self.state_set.is_empty()))
+            {
+                None
+            } else {
+                Some(hex32(self.set_digest))
+            },
+            count: if self.is_boolfun {
+                self.boolfun_set.len()
+            } else if self.is_lattice {
+                self.lattice_set.len()
+            } else if self.is_group {
+                self.group_set.len()
+            } else if self.is_subsets {
+                self.subset_set.len()
+            } else if self.is_quad {
+                self.quad_set.len()
+            } else if self.is_tetra {
+                self.tetra_set.len()
+            } else {
+                self.state_set.len()
+            },
+            constraint_mask: self.cst.mask,
+            constraint_value: self.cst.value,
+        };
+
+        match op.as_str() {
+            "SELECT_UNIVERSE" => {
+                let u = args
+                    .get("universe")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for SELECT_UNIVERSE"))?;
+                let n = args.get("n").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
+
+                let u_norm = u.to_ascii_uppercase();
+                self.active_universe = u_norm.clone();
+                self.is_lattice = false;
+                self.is_group = false;
+                self.is_subsets = false;
+                self.is_quad = false;
+                self.is_tetra = false;
+
+                // BOOLFUN
+                if is_boolfun_universe(u_norm.as_str()) {
+                    self.is_boolfun = true;
+                    self.is_ge = false;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    self.boolfun_n = n;
+                    self.boolfun_all = build_boolfun(n);
+                    self.boolfun_set = self.boolfun_all.clone();
+                    self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                    self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                } else if u_norm == "QE" {
                     // QE (fractions)
-                    is_boolfun = false;
-                    is_ge = false;
-                    cst = Constraint::empty();
-                    state_set = qe.clone();
-                    set_digest = canonical_set_digest(&state_set);
-                    witness = None;
-                    witness_bf = None;
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.cst = Constraint::empty();
+                    self.state_set = self.qe.clone();
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                    self.witness = None;
+                    self.witness_bf = None;
                 } else if is_word_universe(u_norm.as_str()) {
-                    is_boolfun = false;
-                    is_ge = false;
-                    is_word = true;
-                    cst = Constraint::empty();
-                    state_set.clear();
-                    if word_all.is_empty() {
-                        word_all = build_word_universe();
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_word = true;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    if self.word_all.is_empty() {
+                        self.word_all = build_word_universe();
                     }
-                    word_set = word_all.clone();
-                    set_digest = {
-                        let leaves: Vec<[u8; 32]> = word_set.iter()
-                            .map(|w| sha256_bytes(&w.canonical_bytes()))
+                    self.word_set = self.word_all.clone();
+                    self.set_digest = {
+                        let leaves: Vec<[u8; 32]> = self.word_set.iter()
+                            .map(|w| leaf_hash(&w.canonical_bytes()))
                             .collect();
-                        crate::digest::merkle_root(&leaves)
+                        set_root(&leaves)
                     };
-                    witness = None;
-                    witness_bf = None;
-                    witness_word = None;
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_word = None;
                 } else if is_syllable_universe(u_norm.as_str()) {
-                    is_boolfun=false; is_ge=false; is_word=false; is_syllable=true;
-                    is_morpheme=false; is_phrase=false; is_semantic=false; is_discourse=false;
-                    cst=Constraint::empty(); state_set.clear();
-                    if syllable_all.is_empty() { syllable_all=build_syllable_universe(); }
-                    syllable_set=syllable_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=syllable_set.iter().map(|s|sha256_bytes(&s.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
-                    witness=None; witness_bf=None; witness_syllable=None;
+                    self.is_boolfun=false; self.is_ge=false; self.is_word=false; self.is_syllable=true;
+                    self.is_morpheme=false; self.is_phrase=false; self.is_semantic=false; self.is_discourse=false;
+                    self.cst=Constraint::empty(); self.state_set.clear();
+                    if self.syllable_all.is_empty() { self.syllable_all=build_syllable_universe(); }
+                    self.syllable_set=self.syllable_all.clone();
+                    self.set_digest={let mut l:Vec<[u8;32]>=self.syllable_set.iter().map(|s|leaf_hash(&s.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
+                    self.witness=None; self.witness_bf=None; self.witness_syllable=None;
                 } else if is_morpheme_universe(u_norm.as_str()) {
-                    is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
-                    is_morpheme=true; is_phrase=false; is_semantic=false; is_discourse=false;
-                    cst=Constraint::empty(); state_set.clear();
-                    if morpheme_all.is_empty() { morpheme_all=build_morpheme_universe(); }
-                    morpheme_set=morpheme_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=morpheme_set.iter().map(|m|sha256_bytes(&m.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
-                    witness=None; witness_bf=None; witness_morpheme=None;
+                    self.is_boolfun=false; self.is_ge=false; self.is_word=false; self.is_syllable=false;
+                    self.is_morpheme=true; self.is_phrase=false; self.is_semantic=false; self.is_discourse=false;
+                    self.cst=Constraint::empty(); self.state_set.clear();
+                    if self.morpheme_all.is_empty() { self.morpheme_all=build_morpheme_universe(); }
+                    self.morpheme_set=self.morpheme_all.clone();
+                    self.set_digest={let mut l:Vec<[u8;32]>=self.morpheme_set.iter().map(|m|leaf_hash(&m.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
+                    self.witness=None; self.witness_bf=None; self.witness_morpheme=None;
                 } else if is_phrase_universe(u_norm.as_str()) {
-                    is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
-                    is_morpheme=false; is_phrase=true; is_semantic=false; is_discourse=false;
-                    cst=Constraint::empty(); state_set.clear();
-                    if phrase_all.is_empty() { phrase_all=build_phrase_inventory(); }
-                    phrase_set=phrase_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=phrase_set.iter().map(|p|sha256_bytes(&p.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
-                    witness=None; witness_bf=None; witness_phrase=None;
+                    self.is_boolfun=false; self.is_ge=false; self.is_word=false; self.is_syllable=false;
+                    self.is_morpheme=false; self.is_phrase=true; self.is_semantic=false; self.is_discourse=false;
+                    self.cst=Constraint::empty(); self.state_set.clear();
+                    if self.phrase_all.is_empty() { self.phrase_all=build_phrase_inventory(); }
+                    self.phrase_set=self.phrase_all.clone();
+                    self.set_digest={let mut l:Vec<[u8;32]>=self.phrase_set.iter().map(|p|leaf_hash(&p.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
+                    self.witness=None; self.witness_bf=None; self.witness_phrase=None;
                 } else if is_semantic_universe(u_norm.as_str()) {
-                    is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
-                    is_morpheme=false; is_phrase=false; is_semantic=true; is_discourse=false;
-                    cst=Constraint::empty(); state_set.clear();
-                    if semantic_all.is_empty() { semantic_all=build_semantic_inventory(); }
-                    semantic_set=semantic_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=semantic_set.iter().map(|g|sha256_bytes(&g.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
-                    witness=None; witness_bf=None; witness_semantic=None;
+                    self.is_boolfun=false; self.is_ge=false; self.is_word=false; self.is_syllable=false;
+                    self.is_morpheme=false; self.is_phrase=false; self.is_semantic=true; self.is_discourse=false;
+                    self.cst=Constraint::empty(); self.state_set.clear();
+                    if self.semantic_all.is_empty() { self.semantic_all=build_semantic_inventory(); }
+                    self.semantic_set=self.semantic_all.clone();
+                    self.set_digest={let mut l:Vec<[u8;32]>=self.semantic_set.iter().map(|g|leaf_hash(&g.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
+                    self.witness=None; self.witness_bf=None; self.witness_semantic=None;
                 } else if is_discourse_universe(u_norm.as_str()) {
-                    is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
-                    is_morpheme=false; is_phrase=false; is_semantic=false; is_discourse=true;
-                    cst=Constraint::empty(); state_set.clear();
-                    if discourse_all.is_empty() { discourse_all=build_discourse_inventory(); }
-                    discourse_set=discourse_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=discourse_set.iter().map(|g|sha256_bytes(&g.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
-                    witness=None; witness_bf=None; witness_discourse=None;
+                    self.is_boolfun=false; self.is_ge=false; self.is_word=false; self.is_syllable=false;
+                    self.is_morpheme=false; self.is_phrase=false; self.is_semantic=false; self.is_discourse=true;
+                    self.cst=Constraint::empty(); self.state_set.clear();
+                    if self.discourse_all.is_empty() { self.discourse_all=build_discourse_inventory(); }
+                    self.discourse_set=self.discourse_all.clone();
+                    self.set_digest={let mut l:Vec<[u8;32]>=self.discourse_set.iter().map(|g|leaf_hash(&g.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
+                    self.witness=None; self.witness_bf=None; self.witness_discourse=None;
+                } else if is_lattice_universe(u_norm.as_str()) {
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_lattice = true;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    let r = if n == 0 { crate::lattice::DEFAULT_RADIUS } else { n as i32 };
+                    self.lattice_all = build_lattice(r);
+                    self.lattice_set = self.lattice_all.clone();
+                    self.set_digest = canonical_set_digest_lattice(&self.lattice_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_pt = None;
+                } else if is_group_universe(u_norm.as_str()) {
+                    let name = args
+                        .get("group")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("SELECT_UNIVERSE GROUP missing group="))?;
+                    let kind = parse_group_name(name)
+                        .ok_or_else(|| anyhow!("unsupported group: {}", name))?;
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_group = true;
+                    self.active_universe = kind.name();
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    let g = GroupUniverse::build(kind);
+                    self.group_set = g.elems.clone();
+                    self.group_univ = Some(g);
+                    self.set_digest = canonical_set_digest_group(&self.group_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_perm = None;
+                } else if is_subsets_universe(u_norm.as_str()) {
+                    let items: Vec<i64> = args
+                        .get("items")
+                        .and_then(|v| serde_json::from_value(v.clone()).ok())
+                        .ok_or_else(|| anyhow!("SELECT_UNIVERSE SUBSETS missing items="))?;
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_subsets = true;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    self.subset_all = build_subsets(&items);
+                    self.subset_items = items;
+                    self.subset_set = self.subset_all.clone();
+                    self.set_digest = canonical_set_digest_subsets(&self.subset_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_subset = None;
+                } else if is_quad_universe(u_norm.as_str()) {
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_quad = true;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    let max_p = if n == 0 { crate::geom::DEFAULT_QUAD_PERIMETER } else { n as i32 };
+                    self.quad_all = build_quad(max_p);
+                    self.quad_set = self.quad_all.clone();
+                    self.set_digest = canonical_set_digest_quad(&self.quad_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_quad = None;
+                } else if is_tetra_universe(u_norm.as_str()) {
+                    let max_e = if n == 0 { crate::geom::DEFAULT_TETRA_EDGE } else { n as i32 };
+                    if max_e > crate::geom::MAX_TETRA_EDGE {
+                        return Err(anyhow!("TETRA max edge is {}", crate::geom::MAX_TETRA_EDGE));
+                    }
+                    self.is_boolfun = false;
+                    self.is_ge = false;
+                    self.is_tetra = true;
+                    self.cst = Constraint::empty();
+                    self.state_set.clear();
+                    self.tetra_all = build_tetra(max_e);
+                    self.tetra_set = self.tetra_all.clone();
+                    self.set_digest = canonical_set_digest_tetra(&self.tetra_set);
+                    self.witness = None;
+                    self.witness_bf = None;
+                    self.witness_tetra = None;
                 } else {
                     return Err(anyhow!("unsupported universe: {}", u));
                 }
             }
+            "FILTER_SUM" => {
+                if !self.is_subsets {
+                    return Err(anyhow!("FILTER_SUM requires SUBSETS universe"));
+                }
+                let min = args
+                    .get("min")
+                    .and_then(|v| v.as_i64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_SUM"))?;
+                let max = args
+                    .get("max")
+                    .and_then(|v| v.as_i64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_SUM"))?;
+                self.subset_set.retain(|x| x.sum >= min && x.sum <= max);
+                self.set_digest = canonical_set_digest_subsets(&self.subset_set);
+            }
+            "FILTER_MAX_ANGLE" => {
+                let max = match args.get("max").and_then(|v| v.as_u64()) {
+                    Some(d) if self.is_ge => d as u32,
+                    _ => return Err(anyhow!("FILTER_MAX_ANGLE requires GE universe and max=<degrees>")),
+                };
+                self.ge_set.retain(|t| t.max_angle_at_most(max));
+                self.state_set = project_tris(&self.ge_set);
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "FILTER_AREA" => {
+                if !self.is_quad && !self.is_ge {
+                    return Err(anyhow!("FILTER_AREA requires QUAD or GE universe"));
+                }
+                let min = args
+                    .get("min")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_AREA"))? as i64;
+                let max = args
+                    .get("max")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_AREA"))? as i64;
+                // min ≤ K ≤ max  ⇔  16·min² ≤ 16K² ≤ 16·max²
+                let in_range = |k16: i64| {
+                    let k16 = k16 as i128;
+                    k16 >= 16 * (min as i128).pow(2) && k16 <= 16 * (max as i128).pow(2)
+                };
+                if self.is_ge {
+                    self.ge_set.retain(|t| in_range(t.area_sq16()));
+                    self.state_set = project_tris(&self.ge_set);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                } else {
+                    self.quad_set.retain(|q| in_range(q.area_sq16()));
+                    self.set_digest = canonical_set_digest_quad(&self.quad_set);
+                }
+            }
+            "SAMPLE" => {
+                let seed = args
+                    .get("seed")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for SAMPLE"))?;
+                let k = args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for SAMPLE"))? as usize;
+                if self.is_boolfun {
+                    self.boolfun_set = sample_seeded(&self.boolfun_set, seed, k);
+                    self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                } else if self.is_ge {
+                    self.ge_set = sample_seeded(&self.ge_set, seed, k);
+                    self.state_set = project_tris(&self.ge_set);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                } else if self.is_lattice {
+                    self.lattice_set = sample_seeded(&self.lattice_set, seed, k);
+                    self.set_digest = canonical_set_digest_lattice(&self.lattice_set);
+                } else if self.is_group {
+                    self.group_set = sample_seeded(&self.group_set, seed, k);
+                    self.set_digest = canonical_set_digest_group(&self.group_set);
+                } else if self.is_subsets {
+                    self.subset_set = sample_seeded(&self.subset_set, seed, k);
+                    self.set_digest = canonical_set_digest_subsets(&self.subset_set);
+                } else if self.is_quad {
+                    self.quad_set = sample_seeded(&self.quad_set, seed, k);
+                    self.set_digest = canonical_set_digest_quad(&self.quad_set);
+                } else if self.is_tetra {
+                    self.tetra_set = sample_seeded(&self.tetra_set, seed, k);
+                    self.set_digest = canonical_set_digest_tetra(&self.tetra_set);
+                } else if self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                    return Err(anyhow!("SAMPLE is not supported for universe {}", self.active_universe));
+                } else {
+                    self.state_set = sample_seeded(&self.state_set, seed, k);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                }
+            }
+            "AGGREGATE" => {
+                // min/max follow each universe's canonical order (exact value order for QE)
+                let mut stats = if self.is_boolfun {
+                    let mut hist = vec![0usize; (1usize << self.boolfun_n) + 1];
+                    for f in &self.boolfun_set {
+                        hist[f.weight() as usize] += 1;
+                    }
+                    let (min, max) = set_ends(&self.boolfun_set, boolfun_to_string);
+                    json!({ "min": min, "max": max, "weight_hist": hist })
+                } else if self.is_ge {
+                    let per_sum: i64 = self.ge_set.iter().map(|t| t.perimeter() as i64).sum();
+                    let (min, max) = set_ends(&self.ge_set, |t| format!("{},{},{}", t.a, t.b, t.c));
+                    json!({ "min": min, "max": max, "perimeter_sum": per_sum })
+                } else if self.is_lattice {
+                    let (min, max) = set_ends(&self.lattice_set, pt_to_string);
+                    json!({ "min": min, "max": max })
+                } else if self.is_group {
+                    let (min, max) = set_ends(&self.group_set, perm_to_string);
+                    json!({ "min": min, "max": max })
+                } else if self.is_subsets {
+                    let (min, max) = set_ends(&self.subset_set, |x| subset_to_string(&self.subset_items, x));
+                    json!({ "min": min, "max": max })
+                } else if self.is_quad {
+                    let (min, max) = set_ends(&self.quad_set, quad_to_string);
+                    json!({ "min": min, "max": max })
+                } else if self.is_tetra {
+                    let (min, max) = set_ends(&self.tetra_set, tetra_to_string);
+                    json!({ "min": min, "max": max })
+                } else if self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                    return Err(anyhow!("AGGREGATE is not supported for universe {}", self.active_universe));
+                } else {
+                    let (min, max) = set_ends(&self.state_set, frac_to_string);
+                    json!({ "min": min, "max": max, "mean": crate::qe::exact_mean(&self.state_set) })
+                };
+                stats["count"] = json!(if self.is_boolfun {
+                    self.boolfun_set.len()
+                } else if self.is_ge {
+                    self.ge_set.len()
+                } else if self.is_lattice {
+                    self.lattice_set.len()
+                } else if self.is_group {
+                    self.group_set.len()
+                } else if self.is_subsets {
+                    self.subset_set.len()
+                } else if self.is_quad {
+                    self.quad_set.len()
+                } else if self.is_tetra {
+                    self.tetra_set.len()
+                } else {
+                    self.state_set.len()
+                });
+                step_agg = Some(stats);
+            }
+            "GROUP_BY" => {
+                let by = args.get("by").and_then(|v| v.as_str()).unwrap_or("sig");
+                let (sigs, legend): (Vec<u8>, [&str; 7]) = if self.is_ge {
+                    (
+                        self.ge_set.iter().map(crate::semtrace::sig7_geom).collect(),
+                        crate::semtrace::bit_legend_geom(),
+                    )
+                } else if self.is_lattice {
+                    (
+                        self.lattice_set.iter().map(crate::lattice::sig7).collect(),
+                        crate::lattice::bit_legend(),
+                    )
+                } else if self.is_group {
+                    let g = self.group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                    (self.group_set.iter().map(|h| g.sig7(h)).collect(), crate::group::bit_legend())
+                } else if self.is_subsets {
+                    let n_items = self.subset_items.len();
+                    (
+                        self.subset_set.iter().map(|x| crate::subsets::sig7(x, n_items)).collect(),
+                        crate::subsets::bit_legend(),
+                    )
+                } else if self.is_quad {
+                    (
+                        self.quad_set.iter().map(crate::semtrace::sig7_quad).collect(),
+                        crate::semtrace::bit_legend_quad(),
+                    )
+                } else if self.is_tetra {
+                    (
+                        self.tetra_set.iter().map(crate::semtrace::sig7_tetra).collect(),
+                        crate::semtrace::bit_legend_tetra(),
+                    )
+                } else if self.is_boolfun || self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                    return Err(anyhow!("GROUP_BY is not supported for universe {}", self.active_universe));
+                } else {
+                    (
+                        self.state_set.iter().map(crate::semtrace::sig7).collect(),
+                        crate::semtrace::bit_legend(),
+                    )
+                };
+                // by=sig buckets on the full signature (bit6..bit0); a legend name buckets on one bit
+                let bit = if by == "sig" {
+                    None
+                } else {
+                    match legend.iter().position(|p| *p == by) {
+                        Some(i) => Some(i),
+                        None => return Err(anyhow!("GROUP_BY unknown predicate {} for universe {}", by, self.active_universe)),
+                    }
+                };
+                let mut hist: BTreeMap<String, usize> = BTreeMap::new();
+                for s in sigs {
+                    let key = match bit {
+                        Some(i) => ((s >> i) & 1).to_string(),
+                        None => format!("{:07b}", s),
+                    };
+                    *hist.entry(key).or_insert(0) += 1;
+                }
+                step_hist = Some(hist);
+            }
+            "ASSERT_COUNT" | "ASSERT_WITNESS" => {
+                // checked against the post-state below; no state change
+            }
+            // NEGATE on BOOLFUN complements outputs; on QE it is handled by the element-map arm below
+            "NEGATE" | "DUAL" | "PERMUTE_VARS" | "RESTRICT" if self.is_boolfun || op != "NEGATE" => {
+                if !self.is_boolfun || self.boolfun_n > 6 {
+                    return Err(anyhow!("{} requires a BOOLFUN truth-table universe (n<=6)", op));
+                }
+                let perm: Vec<u8> = match op.as_str() {
+                    "PERMUTE_VARS" => {
+                        let perm: Option<Vec<u8>> = args
+                            .get("perm")
+                            .and_then(|v| v.as_array())
+                            .and_then(|a| a.iter().map(|x| x.as_u64().map(|x| x as u8)).collect());
+                        let mut sorted = perm.clone().unwrap_or_default();
+                        sorted.sort_unstable();
+                        if sorted != (0..self.boolfun_n).collect::<Vec<u8>>() {
+                            return Err(anyhow!("PERMUTE_VARS perm must be a permutation of 0..{}", self.boolfun_n));
+                        }
+                        perm.unwrap_or_default()
+                    }
+                    _ => Vec::new(),
+                };
+                let (var, value) = match op.as_str() {
+                    "RESTRICT" => match (args.get("var").and_then(|v| v.as_u64()), args.get("value").and_then(|v| v.as_u64())) {
+                        (Some(i), Some(b)) if i < self.boolfun_n as u64 && b <= 1 => (i as u8, b == 1),
+                        _ => return Err(anyhow!("bad args for RESTRICT (var<{}, value=0|1)", self.boolfun_n)),
+                    },
+                    _ => (0, false),
+                };
+                let t = |f: &BoolFun| match op.as_str() {
+                    "NEGATE" => f.negate(),
+                    "DUAL" => f.dual(),
+                    "PERMUTE_VARS" => f.permute_vars(&perm),
+                    _ => f.restrict(var, value),
+                };
+                self.boolfun_set = self.boolfun_set.iter().map(t).collect();
+                self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                self.boolfun_set.dedup();
+                self.witness_bf = self.witness_bf.as_ref().map(t);
+                if op == "RESTRICT" {
+                    self.boolfun_n -= 1;
+                    self.boolfun_all = build_boolfun(self.boolfun_n);
+                }
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+            }
+            "NEGATE" | "RECIPROCAL" | "MEDIANT_WITH" => {
+                if self.is_boolfun || self.is_ge || self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra
+                    || self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse
+                {
+                    return Err(anyhow!("{} is QE-only, got {}", op, self.active_universe));
+                }
+                self.state_set = match op.as_str() {
+                    "NEGATE" => crate::qe::map_set(&self.state_set, crate::qe::negate),
+                    "RECIPROCAL" => crate::qe::map_set(&self.state_set, crate::qe::reciprocal),
+                    _ => {
+                        let g = match args.get("elem").and_then(|v| v.as_str()).and_then(parse_frac) {
+                            Some(g) => g,
+                            None => return Err(anyhow!("bad args for MEDIANT_WITH")),
+                        };
+                        crate::qe::map_set(&self.state_set, |f| Some(crate::qe::mediant(f, &g)))
+                    }
+                };
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "PUSH_STATE" | "POP_STATE" => {
+                if self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                    return Err(anyhow!("{} is not supported for universe {}", op, self.active_universe));
+                }
+                let key = frame_key(
+                    [self.is_boolfun, self.is_ge, self.is_lattice, self.is_group, self.is_subsets, self.is_quad, self.is_tetra],
+                    self.group_univ.as_ref(),
+                    &self.subset_items,
+                    self.boolfun_n,
+                );
+                if op == "PUSH_STATE" {
+                    self.state_stack.push(Frame {
+                        key,
+                        set_digest: self.set_digest,
+                        cst: self.cst,
+                        state_set: self.state_set.clone(),
+                        ge_set: self.ge_set.clone(),
+                        boolfun_set: self.boolfun_set.clone(),
+                        lattice_set: self.lattice_set.clone(),
+                        group_set: self.group_set.clone(),
+                        subset_set: self.subset_set.clone(),
+                        quad_set: self.quad_set.clone(),
+                        tetra_set: self.tetra_set.clone(),
+                        witness: self.witness,
+                        witness_bf: self.witness_bf,
+                        witness_pt: self.witness_pt,
+                        witness_perm: self.witness_perm.clone(),
+                        witness_subset: self.witness_subset,
+                        witness_quad: self.witness_quad,
+                        witness_tetra: self.witness_tetra,
+                    });
+                } else {
+                    let fr = match self.state_stack.pop() {
+                        Some(fr) => fr,
+                        None => return Err(anyhow!("POP_STATE on an empty state stack")),
+                    };
+                    if fr.key != key {
+                        return Err(anyhow!("POP_STATE into a different universe than PUSH_STATE"));
+                    }
+                    self.set_digest = fr.set_digest;
+                    self.cst = fr.cst;
+                    self.state_set = fr.state_set;
+                    self.ge_set = fr.ge_set;
+                    self.boolfun_set = fr.boolfun_set;
+                    self.lattice_set = fr.lattice_set;
+                    self.group_set = fr.group_set;
+                    self.subset_set = fr.subset_set;
+                    self.quad_set = fr.quad_set;
+                    self.tetra_set = fr.tetra_set;
+                    self.witness = fr.witness;
+                    self.witness_bf = fr.witness_bf;
+                    self.witness_pt = fr.witness_pt;
+                    self.witness_perm = fr.witness_perm;
+                    self.witness_subset = fr.witness_subset;
+                    self.witness_quad = fr.witness_quad;
+                    self.witness_tetra = fr.witness_tetra;
+                }
+            }
+            "DEFINE_PRED" => {
+                let name = args
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for DEFINE_PRED"))?;
+                let expr = args
+                    .get("expr")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for DEFINE_PRED"))?;
+                let e = crate::pred::parse_expr(expr).map_err(|m| anyhow!("DEFINE_PRED {}: {}", name, m))?;
+                if self.user_preds.iter().any(|(n, _)| n == name)
+                    || resolve_pred(&crate::semtrace::bit_legend(), name).is_some()
+                {
+                    return Err(anyhow!("DEFINE_PRED {} is already defined", name));
+                }
+                if self.user_preds.len() >= crate::pred::MAX_USER_PREDS {
+                    return Err(anyhow!("DEFINE_PRED: at most {} user predicates", crate::pred::MAX_USER_PREDS));
+                }
+                self.user_preds.push((name.to_string(), e));
+            }
+            "COMPLEMENT" => {
+                if self.is_boolfun {
+                    self.boolfun_set = difference_by(&self.boolfun_all, &self.boolfun_set, boolfun_canonical_cmp);
+                    self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                } else if self.is_ge {
+                    self.ge_set = difference_by(&self.ge_state, &self.ge_set, crate::geom::canonical_cmp);
+                    self.state_set = project_tris(&self.ge_set);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                } else if self.is_lattice {
+                    self.lattice_set = difference_by(&self.lattice_all, &self.lattice_set, lattice_canonical_cmp);
+                    self.set_digest = canonical_set_digest_lattice(&self.lattice_set);
+                } else if self.is_group {
+                    let g = match self.group_univ.as_ref() {
+                        Some(g) => g,
+                        None => return Err(anyhow!("GROUP not selected")),
+                    };
+                    self.group_set = difference_by(&g.elems, &self.group_set, group_canonical_cmp);
+                    self.set_digest = canonical_set_digest_group(&self.group_set);
+                } else if self.is_subsets {
+                    self.subset_set = difference_by(&self.subset_all, &self.subset_set, subset_canonical_cmp);
+                    self.set_digest = canonical_set_digest_subsets(&self.subset_set);
+                } else if self.is_quad {
+                    self.quad_set = difference_by(&self.quad_all, &self.quad_set, canonical_cmp_quad);
+                    self.set_digest = canonical_set_digest_quad(&self.quad_set);
+                } else if self.is_tetra {
+                    self.tetra_set = difference_by(&self.tetra_all, &self.tetra_set, canonical_cmp_tetra);
+                    self.set_digest = canonical_set_digest_tetra(&self.tetra_set);
+                } else if self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                    return Err(anyhow!("COMPLEMENT is not supported for universe {}", self.active_universe));
+                } else {
+                    self.state_set = difference_by(&self.qe, &self.state_set, canonical_cmp);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                }
+            }
+            "SAVE_SET" => {
+                let name = args
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for SAVE_SET"))?;
+                let snap = if self.is_boolfun {
+                    SavedSet::BoolFun(self.boolfun_set.clone())
+                } else if self.is_ge {
+                    SavedSet::Ge(self.ge_set.clone())
+                } else if self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra {
+                    return Err(anyhow!("SAVE_SET requires QE, GE or BOOLFUN universe"));
+                } else {
+                    SavedSet::Qe(self.state_set.clone())
+                };
+                self.saved_sets.insert(name.to_string(), snap);
+            }
+            "INTERSECT" | "UNION" => {
+                let name = args
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for {}", op))?;
+                let union = op.as_str() == "UNION";
+                let is_qe = !self.is_boolfun && !self.is_ge && !self.is_lattice && !self.is_group && !self.is_subsets && !self.is_quad && !self.is_tetra;
+                match self.saved_sets
+                    .get(name)
+                    .ok_or_else(|| anyhow!("{} unknown set name: {}", op, name))? {
+                    SavedSet::BoolFun(v) if self.is_boolfun => {
+                        self.boolfun_set = if union {
+                            union_by(&self.boolfun_set, v, boolfun_canonical_cmp)
+                        } else {
+                            intersect_by(&self.boolfun_set, v, boolfun_canonical_cmp)
+                        };
+                        self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                    }
+                    SavedSet::Ge(v) if self.is_ge => {
+                        self.ge_set = if union {
+                            union_by(&self.ge_set, v, crate::geom::canonical_cmp)
+                        } else {
+                            intersect_by(&self.ge_set, v, crate::geom::canonical_cmp)
+                        };
+                        self.state_set = project_tris(&self.ge_set);
+                        self.set_digest = canonical_set_digest(&self.state_set);
+                    }
+                    SavedSet::Qe(v) if is_qe => {
+                        self.state_set = if union {
+                            union_by(&self.state_set, v, canonical_cmp)
+                        } else {
+                            intersect_by(&self.state_set, v, canonical_cmp)
+                        };
+                        self.set_digest = canonical_set_digest(&self.state_set);
+                    }
+                    _ => {
+                        return Err(anyhow!("{} {}: saved set is from a different universe", op, name));
+                    }
+                }
+            }
+            "FILTER_RANGE" => {
+                let min_s = args
+                    .get("min")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_RANGE"))?;
+                let max_s = args
+                    .get("max")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_RANGE"))?;
+                if self.is_ge {
+                    let min: i32 = min_s.parse().map_err(|_| anyhow!("bad perimeter min: {}", min_s))?;
+                    let max: i32 = max_s.parse().map_err(|_| anyhow!("bad perimeter max: {}", max_s))?;
+                    self.ge_set.retain(|t| t.perimeter() >= min && t.perimeter() <= max);
+                    self.state_set = project_tris(&self.ge_set);
+                } else if self.is_boolfun || self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra {
+                    return Err(anyhow!("FILTER_RANGE requires QE or GE universe"));
+                } else {
+                    let min = parse_bound(min_s).ok_or_else(|| anyhow!("bad range min: {}", min_s))?;
+                    let max = parse_bound(max_s).ok_or_else(|| anyhow!("bad range max: {}", max_s))?;
+                    self.state_set.retain(|f| in_range(f, &min, &max));
+                }
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "NPN_CLASS" => {
+                if !self.is_boolfun || self.boolfun_n > 6 {
+                    return Err(anyhow!("NPN_CLASS requires a BOOLFUN truth-table universe (n<=6)"));
+                }
+                match args.get("elem").and_then(|v| v.as_str()) {
+                    // filter to the class of elem
+                    Some(e) => {
+                        let target = match parse_boolfun(e) {
+                            Some(t) if t.n == self.boolfun_n => t.npn_canonical(),
+                            _ => return Err(anyhow!("bad NPN_CLASS elem for BOOLFUN n={}: {}", self.boolfun_n, e)),
+                        };
+                        self.boolfun_set.retain(|f| f.npn_canonical() == target);
+                    }
+                    // dedup to class representatives
+                    None => {
+                        self.boolfun_set = self.boolfun_set.iter().map(|f| f.npn_canonical()).collect();
+                        self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                        self.boolfun_set.dedup();
+                        self.witness_bf = self.witness_bf.map(|f| f.npn_canonical());
+                    }
+                }
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+            }
+            "FILTER_CLASS" => {
+                let class = args
+                    .get("class")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_CLASS"))?;
+                if !self.is_boolfun || self.boolfun_n > 6 {
+                    return Err(anyhow!("FILTER_CLASS requires a BOOLFUN truth-table universe (n<=6)"));
+                }
+                if !crate::boolfun::CLASSES.contains(&class) {
+                    return Err(anyhow!(
+                        "FILTER_CLASS unknown class {} (known: {})",
+                        class,
+                        crate::boolfun::CLASSES.join(", ")
+                    ));
+                }
+                self.boolfun_set.retain(|f| f.in_class(class) == Some(true));
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+            }
+            "FILTER_NONLINEARITY" => {
+                if !self.is_boolfun || self.boolfun_n > 6 {
+                    return Err(anyhow!("FILTER_NONLINEARITY requires a BOOLFUN truth-table universe (n<=6)"));
+                }
+                let min = args
+                    .get("min")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_NONLINEARITY"))? as u32;
+                let max = args
+                    .get("max")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_NONLINEARITY"))? as u32;
+                self.boolfun_set.retain(|f| (min..=max).contains(&f.nonlinearity()));
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+            }
             "FILTER_WEIGHT" => {
-                if !is_boolfun {
+                if !self.is_boolfun {
                     return Err(anyhow!("FILTER_WEIGHT requires BOOLFUN universe"));
                 }
                 let min = args
@@ -586,7 +2318,7 @@ pub fn run_trace_and_write(
                     .and_then(|v| v.as_u64())
                     .ok_or_else(|| anyhow!("bad args for FILTER_WEIGHT"))?
                     as u32;
-                let mut out: Vec<BoolFun> = boolfun_all
+                let mut out: Vec<BoolFun> = self.boolfun_all
                     .iter()
                     .copied()
                     .filter(|f| {
@@ -595,13 +2327,99 @@ pub fn run_trace_and_write(
                     })
                     .collect();
                 out.sort_by(boolfun_canonical_cmp);
-                boolfun_set = out;
-                set_digest = canonical_set_digest_boolfun(&boolfun_set);
+                self.boolfun_set = out;
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
             }
-            "TOPK" => {
-                if !is_boolfun {
-                    return Err(anyhow!("TOPK requires BOOLFUN universe"));
+            "TOPK" if args.get("metric").and_then(|v| v.as_str()) == Some("NONLINEARITY") => {
+                if !self.is_boolfun || self.boolfun_n > 6 {
+                    return Err(anyhow!("TOPK metric=NONLINEARITY requires a BOOLFUN truth-table universe (n<=6)"));
+                }
+                let k = args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))? as usize;
+                // most nonlinear first; ties by canonical order
+                let mut scored: Vec<(u32, BoolFun)> =
+                    self.boolfun_set.iter().map(|f| (f.nonlinearity(), *f)).collect();
+                scored.sort_by(|(da, fa), (db, fb)| db.cmp(da).then_with(|| boolfun_canonical_cmp(fa, fb)));
+                scored.truncate(k);
+                self.witness_bf = scored.first().map(|(_, f)| *f);
+                self.boolfun_set = scored.into_iter().map(|(_, f)| f).collect();
+                self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+            }
+            "TOPK" if self.is_group => {
+                let g = self.group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                let target_s = args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))?;
+                let k = args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))? as usize;
+                let target = parse_perm(target_s)
+                    .filter(|t| g.contains(t))
+                    .ok_or_else(|| anyhow!("bad group target: {}", target_s))?;
+                let top = g.topk(&self.group_set, &target, k);
+                self.witness_perm = top.first().cloned();
+                self.group_set = top;
+                self.group_set.sort_by(group_canonical_cmp);
+                self.set_digest = canonical_set_digest_group(&self.group_set);
+            }
+            "FILTER_CONJ" => {
+                if !self.is_group {
+                    return Err(anyhow!("FILTER_CONJ requires GROUP universe"));
+                }
+                let g = self.group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                let elem = args
+                    .get("elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for FILTER_CONJ"))?;
+                let x = parse_perm(elem)
+                    .filter(|x| g.contains(x))
+                    .ok_or_else(|| anyhow!("bad group elem: {}", elem))?;
+                let rep = g.class_rep(&x);
+                self.group_set.retain(|h| g.class_rep(h) == rep);
+                self.set_digest = canonical_set_digest_group(&self.group_set);
+            }
+            "TOPK" if self.is_ge => {
+                let target_s = args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))?;
+                let k = args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))? as usize;
+                let t = crate::geom::parse_tri(target_s).ok_or_else(|| anyhow!("bad tri target: {}", target_s))?;
+                let top = crate::geom::topk_tri(&self.ge_set, &t, k);
+                self.witness = top.first().map(|w| Frac { num: w.a, den: w.c });
+                self.ge_set = top;
+                self.ge_set.sort_by(crate::geom::canonical_cmp);
+                self.state_set = project_tris(&self.ge_set);
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "TOPK" if !self.is_boolfun => {
+                if self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra {
+                    return Err(anyhow!("TOPK is not supported for universe {}", self.active_universe));
                 }
+                let target_s = args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))?;
+                let k = args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args for TOPK"))? as usize;
+                let t = parse_frac(target_s).ok_or_else(|| anyhow!("bad frac target: {}", target_s))?;
+                let top = crate::qe::topk_nearest(&self.state_set, &t, k);
+                self.witness = top.first().copied();
+                self.state_set = top;
+                self.state_set.sort_by(canonical_cmp);
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "TOPK" => {
                 let target_s = args
                     .get("target_elem")
                     .and_then(|v| v.as_str())
@@ -612,21 +2430,21 @@ pub fn run_trace_and_write(
                     .ok_or_else(|| anyhow!("bad args for TOPK"))? as usize;
                 let target =
                     parse_boolfun(target_s).ok_or_else(|| anyhow!("bad boolfun target"))?;
-                if boolfun_n == 0 {
-                    boolfun_n = target.n;
-                    boolfun_all = build_boolfun(boolfun_n);
-                    boolfun_set = boolfun_all.clone();
-                    boolfun_set.sort_by(boolfun_canonical_cmp);
+                if self.boolfun_n == 0 {
+                    self.boolfun_n = target.n;
+                    self.boolfun_all = build_boolfun(self.boolfun_n);
+                    self.boolfun_set = self.boolfun_all.clone();
+                    self.boolfun_set.sort_by(boolfun_canonical_cmp);
                 }
-                if target.n != boolfun_n {
+                if target.n != self.boolfun_n {
                     return Err(anyhow!(
                         "boolfun target n mismatch: have={} want={}",
                         target.n,
-                        boolfun_n
+                        self.boolfun_n
                     ));
                 }
 
-                let mut scored: Vec<(u32, BoolFun)> = boolfun_set
+                let mut scored: Vec<(u32, BoolFun)> = self.boolfun_set
                     .iter()
                     .copied()
                     .map(|f| (f.hamming(&target), f))
@@ -636,10 +2454,10 @@ pub fn run_trace_and_write(
                 });
                 let take = k.min(scored.len());
                 let top: Vec<BoolFun> = scored.into_iter().take(take).map(|(_, f)| f).collect();
-                boolfun_set = top;
-                boolfun_set.sort_by(boolfun_canonical_cmp);
-                set_digest = canonical_set_digest_boolfun(&boolfun_set);
-                witness_bf = boolfun_set.get(0).copied();
+                self.boolfun_set = top;
+                self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                self.witness_bf = self.boolfun_set.get(0).copied();
             }
 
             "START_ELEM" => {
@@ -648,11 +2466,16 @@ pub fn run_trace_and_write(
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("bad args for START_ELEM"))?;
 
-                is_ge = elem.contains(',');
+                self.is_ge = elem.contains(',');
+                self.is_lattice = false;
+                self.is_group = false;
+                self.is_subsets = false;
+                self.is_quad = false;
+                self.is_tetra = false;
 
-                cst = Constraint::empty();
+                self.cst = Constraint::empty();
 
-                if is_ge {
+                if self.is_ge {
                     let parts: Vec<&str> = elem
                         .split(',')
                         .map(|s| s.trim())
@@ -666,57 +2489,159 @@ pub fn run_trace_and_write(
                     let c: i32 = parts[2].parse().map_err(|_| anyhow!("bad tri"))?;
                     crate::geom::Tri::new(a, b, c).ok_or_else(|| anyhow!("bad tri"))?;
 
-                    let mut tris = ge_state.clone();
-                    tris.sort_by(crate::geom::canonical_cmp);
-                    let mut v: Vec<Frac> = tris
-                        .into_iter()
-                        .map(|t| Frac { num: t.a, den: t.c })
-                        .collect();
-                    v.sort_by(crate::qe::canonical_cmp);
-                    state_set = v;
-                    set_digest = canonical_set_digest(&state_set);
-                    is_boolfun = false;
-                    witness_bf = None;
-                    witness = Some(Frac { num: a, den: c });
+                    self.ge_set = self.ge_state.clone();
+                    self.ge_set.sort_by(crate::geom::canonical_cmp);
+                    self.state_set = project_tris(&self.ge_set);
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                    self.is_boolfun = false;
+                    self.witness_bf = None;
+                    self.witness = Some(Frac { num: a, den: c });
                 } else {
                     let f = parse_frac(elem).ok_or_else(|| anyhow!("bad frac elem"))?;
-                    state_set = qe.clone();
-                    set_digest = canonical_set_digest(&state_set);
-                    is_boolfun = false;
-                    witness_bf = None;
-                    witness = Some(f);
+                    self.state_set = self.qe.clone();
+                    self.set_digest = canonical_set_digest(&self.state_set);
+                    self.is_boolfun = false;
+                    self.witness_bf = None;
+                    self.witness = Some(f);
                 }
             }
-            "SET_BIT" => {
-                let i = args
-                    .get("i")
-                    .and_then(|v| v.as_u64())
-                    .ok_or_else(|| anyhow!("bad args for SET_BIT"))? as u8;
-                let b = args
-                    .get("b")
+            "FILTER_PRED"
+                if self.user_preds
+                    .iter()
+                    .any(|(n, _)| Some(n.as_str()) == args.get("name").and_then(|v| v.as_str())) =>
+            {
+                let name = args.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                let val = args
+                    .get("val")
                     .and_then(|v| v.as_u64())
-                    .ok_or_else(|| anyhow!("bad args for SET_BIT"))? as u8;
+                    .filter(|v| *v <= 1)
+                    .ok_or_else(|| anyhow!("FILTER_PRED val must be 0 or 1"))?;
+                if self.is_boolfun || self.is_ge || self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra
+                    || self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse
+                {
+                    return Err(anyhow!("user predicate {} is QE-only, got {}", name, self.active_universe));
+                }
+                let k = self.user_preds.iter().position(|(n, _)| n == name).unwrap_or(0);
+                let i = crate::pred::FIRST_USER_BIT + k as u8;
+                self.cst = self.cst.set_bit(i, val as u8);
+                let bit = 1u64 << i;
+                self.state_set.retain(|f| (crate::pred::ext_sig(f, &self.user_preds) & bit != 0) == (val == 1));
+                self.set_digest = canonical_set_digest(&self.state_set);
+            }
+            "SET_BIT" | "FILTER_PRED" => {
+                let (i, b) = if op == "FILTER_PRED" {
+                    let linguistic = self.is_word || self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse;
+                    let flags = [self.is_boolfun, self.is_ge, self.is_lattice, self.is_group, self.is_subsets, self.is_quad, self.is_tetra, linguistic];
+                    let name = args
+                        .get("name")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("bad args for FILTER_PRED"))?;
+                    let val = args
+                        .get("val")
+                        .and_then(|v| v.as_u64())
+                        .filter(|v| *v <= 1)
+                        .ok_or_else(|| anyhow!("FILTER_PRED val must be 0 or 1"))?;
+                    let legend = pred_legend(&flags)
+                        .ok_or_else(|| anyhow!("FILTER_PRED is not supported for universe {}", self.active_universe))?;
+                    let i = resolve_pred(&legend, name).ok_or_else(|| {
+                        anyhow!("unknown predicate {} for universe {} (known: {})", name, self.active_universe, legend.join(", "))
+                    })?;
+                    (i, val as u8)
+                } else {
+                    let i = args
+                        .get("i")
+                        .and_then(|v| v.as_u64())
+                        .ok_or_else(|| anyhow!("bad args for SET_BIT"))? as u8;
+                    let b = args
+                        .get("b")
+                        .and_then(|v| v.as_u64())
+                        .ok_or_else(|| anyhow!("bad args for SET_BIT"))? as u8;
+                    (i, b)
+                };
 
-                cst = cst.set_bit(i, b);
+                if i >= 64 {
+                    return Err(anyhow!("SET_BIT bit {} is out of range (0..64)", i));
+                }
+                self.cst = self.cst.set_bit(i, b);
 
-                if is_ge {
-                    let mut tris: Vec<crate::geom::Tri> = ge_state
+                if self.is_tetra {
+                    self.tetra_set = self.tetra_all
                         .iter()
                         .copied()
-                        .filter(|t| cst.matches(sig7_geom(t)))
-                        .collect();
-                    tris.sort_by(crate::geom::canonical_cmp);
-                    let mut v: Vec<Frac> = tris
-                        .into_iter()
-                        .map(|t| Frac { num: t.a, den: t.c })
+                        .filter(|t| self.cst.matches(crate::semtrace::sig7_tetra(t)))
                         .collect();
-                    v.sort_by(crate::qe::canonical_cmp);
-                    state_set = v;
+                    self.tetra_set.sort_by(canonical_cmp_tetra);
+                    self.set_digest = canonical_set_digest_tetra(&self.tetra_set);
+                } else if self.is_quad {
+                    self.quad_set = self.quad_all
+                        .iter()
+                        .copied()
+                        .filter(|q| self.cst.matches(sig7_quad(q)))
+                        .collect();
+                    self.quad_set.sort_by(canonical_cmp_quad);
+                    self.set_digest = canonical_set_digest_quad(&self.quad_set);
+                } else if self.is_subsets {
+                    let n_items = self.subset_items.len();
+                    self.subset_set = self.subset_all
+                        .iter()
+                        .copied()
+                        .filter(|x| self.cst.matches(crate::subsets::sig7(x, n_items)))
+                        .collect();
+                    self.subset_set.sort_by(subset_canonical_cmp);
+                    self.set_digest = canonical_set_digest_subsets(&self.subset_set);
+                } else if self.is_group {
+                    let g = self.group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                    self.group_set = g
+                        .elems
+                        .iter()
+                        .filter(|h| self.cst.matches(g.sig7(h)))
+                        .cloned()
+                        .collect();
+                    self.set_digest = canonical_set_digest_group(&self.group_set);
+                } else if self.is_lattice {
+                    self.lattice_set = self.lattice_all
+                        .iter()
+                        .copied()
+                        .filter(|p| self.cst.matches(crate::lattice::sig7(p)))
+                        .collect();
+                    self.lattice_set.sort_by(lattice_canonical_cmp);
+                    self.set_digest = canonical_set_digest_lattice(&self.lattice_set);
+                } else if self.is_ge {
+                    self.ge_set = self.ge_state
+                        .iter()
+                        .copied()
+                        .filter(|t| self.cst.matches(sig7_geom(t)))
+                        .collect();
+                    self.ge_set.sort_by(crate::geom::canonical_cmp);
+                    self.state_set = project_tris(&self.ge_set);
                 } else {
-                    state_set = filter_qe(&qe, cst);
-                    set_digest = canonical_set_digest(&state_set);
+                    self.state_set = filter_qe(&self.qe, self.cst, &self.user_preds);
+                    self.set_digest = canonical_set_digest(&self.state_set);
                 }
             }
+            "WITNESS_NEAREST" if args.get("targets").is_some() => {
+                let targets: Vec<Frac> = args["targets"]
+                    .as_array()
+                    .and_then(|v| v.iter().map(|t| t.as_str().and_then(parse_frac)).collect())
+                    .ok_or_else(|| anyhow!("bad targets for WITNESS_NEAREST"))?;
+                let mode = args.get("mode").and_then(|v| v.as_str()).unwrap_or("minimax");
+                let metric = args.get("metric").and_then(|v| v.as_str()).unwrap_or("ABS_DIFF");
+                if self.is_boolfun || self.is_ge || self.is_lattice || self.is_group || self.is_subsets || self.is_quad || self.is_tetra {
+                    return Err(anyhow!("multi-target WITNESS_NEAREST is QE-only, got {}", self.active_universe));
+                }
+                if metric != "ABS_DIFF" {
+                    return Err(anyhow!("multi-target WITNESS_NEAREST requires metric=ABS_DIFF, got {}", metric));
+                }
+                if mode != "minimax" && mode != "sum" {
+                    return Err(anyhow!("WITNESS_NEAREST mode must be minimax or sum, got {}", mode));
+                }
+                if targets.is_empty() {
+                    return Err(anyhow!("WITNESS_NEAREST targets= is empty"));
+                }
+                let w = crate::qe::witness_nearest_multi(&self.state_set, &targets, mode == "minimax")
+                    .ok_or_else(|| anyhow!("empty set"))?;
+                self.witness = Some(w);
+            }
             "WITNESS_NEAREST" => {
                 let target = args
                     .get("target_elem")
@@ -726,45 +2651,112 @@ pub fn run_trace_and_write(
                     .get("metric")
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("bad args for WITNESS_NEAREST"))?;
-                if is_syllable && metric == "HAMMING_SIG" {
+                if self.is_tetra {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Err(anyhow!("TETRA requires metric=L1, got {}", metric));
+                    }
+                    let t = parse_tetra(target).ok_or_else(|| anyhow!("bad tetra target"))?;
+                    let w = self.tetra_set
+                        .iter()
+                        .copied()
+                        .min_by(|x, y| {
+                            tetra_distance(x, &t)
+                                .cmp(&tetra_distance(y, &t))
+                                .then_with(|| canonical_cmp_tetra(x, y))
+                        })
+                        .ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness_tetra = Some(w);
+                } else if self.is_quad {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Err(anyhow!("QUAD requires metric=L1, got {}", metric));
+                    }
+                    let t = parse_quad(target).ok_or_else(|| anyhow!("bad quad target"))?;
+                    let w = self.quad_set
+                        .iter()
+                        .copied()
+                        .min_by(|x, y| {
+                            quad_distance(x, &t)
+                                .cmp(&quad_distance(y, &t))
+                                .then_with(|| canonical_cmp_quad(x, y))
+                        })
+                        .ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness_quad = Some(w);
+                } else if self.is_subsets {
+                    if metric != "ABS_DIFF" {
+                        return Err(anyhow!("SUBSETS requires metric=ABS_DIFF, got {}", metric));
+                    }
+                    let t: i64 = target
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("bad subset-sum target: {}", target))?;
+                    let w = crate::subsets::witness_nearest(&self.subset_set, t)
+                        .ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness_subset = Some(w);
+                } else if self.is_lattice {
+                    if metric != "EUCLID_SQ" {
+                        return Err(anyhow!("LATTICE requires metric=EUCLID_SQ, got {}", metric));
+                    }
+                    let t = parse_pt(target).ok_or_else(|| anyhow!("bad lattice target"))?;
+                    let w = crate::lattice::witness_nearest(&self.lattice_set, &t)
+                        .ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness_pt = Some(w);
+                } else if self.is_syllable && metric == "HAMMING_SIG" {
                     let t_idx: usize = target.trim().parse().unwrap_or(0);
-                    if let Some(ts) = syllable_all.get(t_idx).cloned() {
-                        witness_syllable = syllable_set.iter().min_by_key(|s| syllable_sig_distance(s, &ts)).cloned();
+                    if let Some(ts) = self.syllable_all.get(t_idx).cloned() {
+                        self.witness_syllable = self.syllable_set.iter().min_by_key(|s| syllable_sig_distance(s, &ts)).cloned();
                     }
-                } else if is_word && metric == "HAMMING_SIG" {
+                } else if self.is_word && metric == "HAMMING_SIG" {
                     // Word universe: nearest by signature Hamming distance
                     let t_text = target.trim().to_ascii_lowercase();
-                    let t_word = word_all.iter().find(|w| w.text == t_text)
+                    let t_word = self.word_all.iter().find(|w| w.text == t_text)
                         .cloned()
                         .or_else(|| crate::word::parse_elem(&t_text))
                         .ok_or_else(|| anyhow!("word not found: {}", target))?;
-                    let best = word_set.iter()
+                    let best = self.word_set.iter()
                         .min_by_key(|w| sig_distance(w, &t_word))
                         .cloned()
                         .ok_or_else(|| anyhow!("empty word set"))?;
-                    witness_word = Some(best);
-                } else if is_morpheme && metric == "HAMMING_SIG" {
+                    self.witness_word = Some(best);
+                } else if self.is_morpheme && metric == "HAMMING_SIG" {
                     let t_norm = target.trim().to_ascii_lowercase();
-                    if let Some(tm) = morpheme_all.iter().find(|m| m.meaning_id.ends_with(&t_norm)).cloned() {
-                        witness_morpheme = morpheme_set.iter().min_by_key(|m| morpheme_sig_distance(m, &tm)).cloned();
+                    if let Some(tm) = self.morpheme_all.iter().find(|m| m.meaning_id.ends_with(&t_norm)).cloned() {
+                        self.witness_morpheme = self.morpheme_set.iter().min_by_key(|m| morpheme_sig_distance(m, &tm)).cloned();
                     }
-                } else if is_phrase && metric == "HAMMING_SIG" {
+                } else if self.is_phrase && metric == "HAMMING_SIG" {
                     let t_id: u32 = target.trim().parse().unwrap_or(1);
-                    if let Some(tp) = phrase_all.iter().find(|p| p.phrase_id == t_id).cloned() {
-                        witness_phrase = phrase_set.iter().min_by_key(|p| phrase_sig_distance(p, &tp)).cloned();
+                    if let Some(tp) = self.phrase_all.iter().find(|p| p.phrase_id == t_id).cloned() {
+                        self.witness_phrase = self.phrase_set.iter().min_by_key(|p| phrase_sig_distance(p, &tp)).cloned();
                     }
-                } else if is_semantic && metric == "HAMMING_SIG" {
+                } else if self.is_semantic && metric == "HAMMING_SIG" {
                     let t_id: u32 = target.trim().parse().unwrap_or(1);
-                    if let Some(tg) = semantic_all.iter().find(|g| g.graph_id == t_id).cloned() {
-                        witness_semantic = semantic_set.iter().min_by_key(|g| semantic_sig_distance(g, &tg)).cloned();
+                    if let Some(tg) = self.semantic_all.iter().find(|g| g.graph_id == t_id).cloned() {
+                        self.witness_semantic = self.semantic_set.iter().min_by_key(|g| semantic_sig_distance(g, &tg)).cloned();
                     }
-                } else if is_discourse && metric == "HAMMING_SIG" {
+                } else if self.is_discourse && metric == "HAMMING_SIG" {
                     let t_id: u32 = target.trim().parse().unwrap_or(1);
-                    if let Some(tg) = discourse_all.iter().find(|g| g.discourse_id == t_id).cloned() {
-                        witness_discourse = discourse_set.iter().min_by_key(|g| discourse_sig_distance(g, &tg)).cloned();
+                    if let Some(tg) = self.discourse_all.iter().find(|g| g.discourse_id == t_id).cloned() {
+                        self.witness_discourse = self.discourse_set.iter().min_by_key(|g| discourse_sig_distance(g, &tg)).cloned();
+                    }
+                } else if self.is_ge && metric == "SIMILARITY" {
+                    let t = crate::geom::parse_tri(target).ok_or_else(|| anyhow!("bad tri target"))?;
+                    let ties = crate::geom::similarity_ties(&self.ge_set, &t);
+                    if ties.is_empty() {
+                        return Err(anyhow!("empty set"));
                     }
+                    self.witness = ties.first().map(|w| Frac { num: w.a, den: w.c });
+                    // equally similar shapes go into the trace so the canonical pick is auditable
+                    let leaves: Vec<[u8; 32]> = ties.iter().map(|x| leaf_hash(&x.to_bytes())).collect();
+                    step_ties = Some((
+                        ties.iter().map(|x| format!("{},{},{}", x.a, x.b, x.c)).collect(),
+                        set_root(&leaves),
+                    ));
+                } else if !self.is_ge && crate::qe::EXTRA_METRICS.contains(&metric) {
+                    let t = parse_frac(target).ok_or_else(|| anyhow!("bad frac target"))?;
+                    let w = crate::qe::witness_nearest_by(&self.state_set, &t, metric)
+                        .ok_or_else(|| anyhow!("no element at finite {} distance", metric))?;
+                    self.witness = Some(w);
                 } else if metric == "ABS_DIFF" {
-                    let t: Frac = if is_ge || target.contains(',') {
+                    let t: Frac = if self.is_ge || target.contains(',') {
                         let parts: Vec<&str> = target
                             .split(',')
                             .map(|s| s.trim())
@@ -781,12 +2773,120 @@ pub fn run_trace_and_write(
                     } else {
                         parse_frac(target).ok_or_else(|| anyhow!("bad frac target"))?
                     };
-                    let w = witness_nearest(&state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
-                    witness = Some(w);
+                    let w = witness_nearest(&self.state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness = Some(w);
                 } else {
                     return Err(anyhow!("unsupported metric: {}", metric));
                 }
             }
+            "WITNESS_ALL_TIES" => {
+                let target = args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for WITNESS_ALL_TIES"))?;
+                let metric = args
+                    .get("metric")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args for WITNESS_ALL_TIES"))?;
+                // (display string, canonical bytes) of every co-minimal element, canonical order
+                let ties: Vec<(String, Vec<u8>)> = if self.is_lattice {
+                    if metric != "EUCLID_SQ" {
+                        return Err(anyhow!("LATTICE requires metric=EUCLID_SQ, got {}", metric));
+                    }
+                    let t = parse_pt(target).ok_or_else(|| anyhow!("bad lattice target"))?;
+                    let d = self.lattice_set.iter().map(|p| crate::lattice::dist_sq(p, &t)).min();
+                    let ties: Vec<Pt> = self.lattice_set
+                        .iter()
+                        .copied()
+                        .filter(|p| Some(crate::lattice::dist_sq(p, &t)) == d)
+                        .collect();
+                    self.witness_pt = ties.first().copied();
+                    ties.iter().map(|p| (pt_to_string(p), p.canonical_bytes().to_vec())).collect()
+                } else if self.is_subsets {
+                    if metric != "ABS_DIFF" {
+                        return Err(anyhow!("SUBSETS requires metric=ABS_DIFF, got {}", metric));
+                    }
+                    let t: i64 = target.trim().parse::<i64>().ok().ok_or_else(|| anyhow!("bad subset-sum target: {}", target))?;
+                    let d = self.subset_set.iter().map(|x| (x.sum - t).abs()).min();
+                    let ties: Vec<Subset> = self.subset_set
+                        .iter()
+                        .copied()
+                        .filter(|x| Some((x.sum - t).abs()) == d)
+                        .collect();
+                    self.witness_subset = ties.first().copied();
+                    ties.iter()
+                        .map(|x| (subset_to_string(&self.subset_items, x), x.canonical_bytes().to_vec()))
+                        .collect()
+                } else if self.is_quad {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Err(anyhow!("QUAD requires metric=L1, got {}", metric));
+                    }
+                    let t = parse_quad(target).ok_or_else(|| anyhow!("bad quad target"))?;
+                    let d = self.quad_set.iter().map(|q| quad_distance(q, &t)).min();
+                    let ties: Vec<Quad> = self.quad_set
+                        .iter()
+                        .copied()
+                        .filter(|q| Some(quad_distance(q, &t)) == d)
+                        .collect();
+                    self.witness_quad = ties.first().copied();
+                    ties.iter().map(|q| (quad_to_string(q), q.to_bytes().to_vec())).collect()
+                } else if self.is_tetra {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Err(anyhow!("TETRA requires metric=L1, got {}", metric));
+                    }
+                    let t = parse_tetra(target).ok_or_else(|| anyhow!("bad tetra target"))?;
+                    let d = self.tetra_set.iter().map(|x| tetra_distance(x, &t)).min();
+                    let ties: Vec<Tetra> = self.tetra_set
+                        .iter()
+                        .copied()
+                        .filter(|x| Some(tetra_distance(x, &t)) == d)
+                        .collect();
+                    self.witness_tetra = ties.first().copied();
+                    ties.iter().map(|x| (tetra_to_string(x), x.to_bytes().to_vec())).collect()
+                } else if self.is_ge {
+                    if metric != "L1" && metric != "ABS_DIFF" && metric != "SIMILARITY" {
+                        return Err(anyhow!("GE requires metric=L1 or SIMILARITY, got {}", metric));
+                    }
+                    let t = crate::geom::parse_tri(target).ok_or_else(|| anyhow!("bad tri target"))?;
+                    let ties: Vec<crate::geom::Tri> = if metric == "SIMILARITY" {
+                        crate::geom::similarity_ties(&self.ge_set, &t)
+                    } else {
+                        let d = self.ge_set.iter().map(|x| crate::geom::tri_distance(x, &t)).min();
+                        self.ge_set
+                            .iter()
+                            .copied()
+                            .filter(|x| Some(crate::geom::tri_distance(x, &t)) == d)
+                            .collect()
+                    };
+                    self.witness = ties.first().map(|w| Frac { num: w.a, den: w.c });
+                    ties.iter()
+                        .map(|x| (format!("{},{},{}", x.a, x.b, x.c), x.to_bytes().to_vec()))
+                        .collect()
+                } else if self.is_boolfun || self.is_group {
+                    return Err(anyhow!("WITNESS_ALL_TIES is not supported for universe {}", self.active_universe));
+                } else {
+                    if metric != "ABS_DIFF" {
+                        return Err(anyhow!("QE requires metric=ABS_DIFF, got {}", metric));
+                    }
+                    let t = parse_frac(target).ok_or_else(|| anyhow!("bad frac target"))?;
+                    let w = witness_nearest(&self.state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
+                    let dw = distance_num_den(&t, &w);
+                    self.witness = Some(w);
+                    let mut ties: Vec<Frac> = self.state_set
+                        .iter()
+                        .copied()
+                        .filter(|f| {
+                            let d = distance_num_den(&t, f);
+                            d.0 * dw.1 == dw.0 * d.1
+                        })
+                        .collect();
+                    ties.sort_by(canonical_cmp);
+                    ties.iter().map(|f| (frac_to_string(f), f.canonical_bytes().to_vec())).collect()
+                };
+                let leaves: Vec<[u8; 32]> = ties.iter().map(|(_, b)| leaf_hash(b)).collect();
+                let root = set_root(&leaves);
+                step_ties = Some((ties.into_iter().map(|(s, _)| s).collect(), root));
+            }
             "PROJECT_SIGNATURE" => {
                 let elem = args
                     .get("elem")
@@ -796,16 +2896,21 @@ pub fn run_trace_and_write(
                 let sig: u64 = (crate::semtrace::sig7(&f) as u64) & 0x7f;
 
                 // QE -> 7-bit signature -> BOOLFUN signature universe (n=7, bits in 0..127)
-                is_boolfun = true;
-                is_ge = false;
-                cst = Constraint::empty();
-                state_set.clear();
-                boolfun_n = 7;
-                boolfun_all = build_boolfun(7);
-                boolfun_set = boolfun_all.clone();
-                boolfun_set.sort_by(boolfun_canonical_cmp);
-                set_digest = canonical_set_digest_boolfun(&boolfun_set);
-                witness_bf = Some(BoolFun { n: 7, bits: sig });
+                self.is_boolfun = true;
+                self.is_ge = false;
+                self.is_lattice = false;
+                self.is_group = false;
+                self.is_subsets = false;
+                self.is_quad = false;
+                self.is_tetra = false;
+                self.cst = Constraint::empty();
+                self.state_set.clear();
+                self.boolfun_n = 7;
+                self.boolfun_all = build_boolfun(7);
+                self.boolfun_set = self.boolfun_all.clone();
+                self.boolfun_set.sort_by(boolfun_canonical_cmp);
+                self.set_digest = canonical_set_digest_boolfun(&self.boolfun_set);
+                self.witness_bf = Some(BoolFun { n: 7, bits: sig });
             }
             "JOIN_NEAREST" => {
                 let metric = args
@@ -838,16 +2943,21 @@ pub fn run_trace_and_write(
 
                 if lu_norm == "QE" && is_boolfun_universe(ru_norm.as_str()) {
                     let bf = parse_boolfun(re).ok_or_else(|| anyhow!("bad right_elem"))?;
-                    witness_bf = Some(bf);
-                    cst.mask = 0x7f;
-                    cst.value = (bf.bits as u8) & 0x7f;
+                    self.is_lattice = false;
+                    self.is_group = false;
+                    self.is_subsets = false;
+                self.is_quad = false;
+                self.is_tetra = false;
+                    self.witness_bf = Some(bf);
+                    self.cst.mask = 0x7f;
+                    self.cst.value = bf.bits & 0x7f;
 
-                    state_set = filter_qe(&qe, cst);
-                    set_digest = canonical_set_digest(&state_set);
+                    self.state_set = filter_qe(&self.qe, self.cst, &self.user_preds);
+                    self.set_digest = canonical_set_digest(&self.state_set);
 
                     let t = parse_frac(le).ok_or_else(|| anyhow!("bad left_elem"))?;
-                    let w = witness_nearest(&state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
-                    witness = Some(w);
+                    let w = witness_nearest(&self.state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
+                    self.witness = Some(w);
                 } else {
                     return Err(anyhow!(
                         "JOIN_NEAREST unsupported join: left_universe={} right_universe={}",
@@ -858,185 +2968,659 @@ pub fn run_trace_and_write(
             }
 
             "RETURN_SET" => {
-                want_max_items =
+                self.want_max_items =
                     args.get("max_items").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
-                want_include_witness = args
+                self.want_include_witness = args
                     .get("include_witness")
                     .and_then(|v| v.as_bool())
                     .unwrap_or(false);
+                self.want_include_proofs = args
+                    .get("include_proofs")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                self.want_offset = args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                self.want_sort_by = args
+                    .get("sort_by")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("value")
+                    .to_string();
             }
             _ => return Err(anyhow!("unknown semtrace op: {}", op)),
         }
 
         let post = StepPost {
-            set_digest: Some(hex32(set_digest)),
-            count: if is_boolfun {
-                boolfun_set.len()
+            set_digest: Some(hex32(self.set_digest)),
+            count: if self.is_boolfun {
+                self.boolfun_set.len()
+            } else if self.is_lattice {
+                self.lattice_set.len()
+            } else if self.is_group {
+                self.group_set.len()
+            } else if self.is_subsets {
+                self.subset_set.len()
+            } else if self.is_quad {
+                self.quad_set.len()
+            } else if self.is_tetra {
+                self.tetra_set.len()
             } else {
-                state_set.len()
+                self.state_set.len()
             },
-            witness: if is_boolfun {
-                witness_bf.as_ref().map(boolfun_to_string)
+            witness: if self.is_boolfun {
+                self.witness_bf.as_ref().map(boolfun_to_string)
+            } else if self.is_lattice {
+                self.witness_pt.as_ref().map(pt_to_string)
+            } else if self.is_group {
+                self.witness_perm.as_ref().map(perm_to_string)
+            } else if self.is_subsets {
+                self.witness_subset.as_ref().map(|x| subset_to_string(&self.subset_items, x))
+            } else if self.is_quad {
+                self.witness_quad.as_ref().map(quad_to_string)
+            } else if self.is_tetra {
+                self.witness_tetra.as_ref().map(tetra_to_string)
             } else {
-                witness.as_ref().map(frac_to_string)
+                self.witness.as_ref().map(frac_to_string)
             },
+            witness_ties: step_ties.as_ref().map(|(v, _)| v.clone()),
+            witness_ties_root: step_ties.as_ref().map(|(_, r)| hex32(*r)),
+            aggregate: step_agg.clone(),
+            group_by: step_hist.clone(),
         };
+        if step_ties.is_some() {
+            self.witness_ties = step_ties;
+        }
+        if step_agg.is_some() {
+            self.aggregate = step_agg;
+        }
+        if step_hist.is_some() {
+            self.group_by = step_hist.clone();
+        }
+        if self.first_failed_assertion.is_none() {
+            self.first_failed_assertion = failed_assertion(&op, &args, post.count, post.witness.as_deref())
+                .map(|m| format!("step {}: {}", step_idx, m));
+        }
 
-        let sd = step_digest(&chain, &op, &args, &set_digest);
-        chain = sd;
+        let post_digest = match step_hist.as_ref() {
+            Some(h) => group_by_digest(&self.set_digest, h),
+            None => self.set_digest,
+        };
+        let sd = step_digest(&self.chain, &op, &args, &post_digest);
+        self.chain = sd;
 
-        let rec = StepRec {
+        if let Some(lines) = self.deltas.as_mut() {
+            let leaves: Vec<[u8; 32]> = if self.is_boolfun {
+                self.boolfun_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect()
+            } else if self.is_lattice {
+                self.lattice_set.iter().map(|p| leaf_hash(&p.canonical_bytes())).collect()
+            } else if self.is_group {
+                self.group_set.iter().map(|g| leaf_hash(&g.canonical_bytes())).collect()
+            } else if self.is_subsets {
+                self.subset_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+            } else if self.is_quad {
+                self.quad_set.iter().map(|q| leaf_hash(&q.to_bytes())).collect()
+            } else if self.is_tetra {
+                self.tetra_set.iter().map(|t| leaf_hash(&t.to_bytes())).collect()
+            } else if self.is_word {
+                self.word_set.iter().map(|w| leaf_hash(&w.canonical_bytes())).collect()
+            } else if self.is_syllable || self.is_morpheme || self.is_phrase || self.is_semantic || self.is_discourse {
+                let mut l: Vec<[u8; 32]> = if self.is_syllable {
+                    self.syllable_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+                } else if self.is_morpheme {
+                    self.morpheme_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+                } else if self.is_phrase {
+                    self.phrase_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+                } else if self.is_semantic {
+                    self.semantic_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+                } else {
+                    self.discourse_set.iter().map(|x| leaf_hash(&x.canonical_bytes())).collect()
+                };
+                l.sort_unstable();
+                l
+            } else {
+                self.state_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect()
+            };
+            if set_root(&leaves) == self.set_digest {
+                lines.push(serde_json::to_string(&crate::succinct::delta(step_idx, &self.prev_leaves, &leaves))?);
+                self.prev_leaves = leaves;
+            } else {
+                tracing::info!(universe = %self.active_universe, step = step_idx, "deltas not recorded: the set digest is not a merkle root over the set");
+                self.deltas = None;
+            }
+        }
+        let universe_root = if step_idx == 0 {
+            crate::commitments::check_step_zero(&op, &args, &self.set_digest)?.map(str::to_string)
+        } else {
+            None
+        };
+        let rec = StepRecord {
             step: step_idx,
+            semtrace_version: SEMTRACE_VERSION,
+            hash: (self.hash != HashAlg::Sha256).then(|| self.hash.name()),
             op,
             args,
+            universe_root,
             pre,
             post,
             step_digest: hex32(sd),
         };
 
-        out_lines.push(serde_json::to_string(&rec)?);
+        self.out_lines.push(serde_json::to_string(&rec)?);
+        self.ops.push(line);
+        self.metrics.push(StepMetrics {
+            step: step_idx,
+            op: rec.op.clone(),
+            count_before: rec.pre.count,
+            count_after: rec.post.count,
+            wall: started.map(|t| t.elapsed()).unwrap_or_default(),
+        });
+        for o in self.observers.iter_mut() {
+            o.on_step(&rec);
+        }
+        Ok(rec)
     }
 
-    fs::write(&trace_ndjson_path, out_lines.join("\n") + "\n")?;
+    /// Report every later step, and the finish, to `observer`.
+    pub fn observe(&mut self, observer: impl ExecObserver + Send + 'static) {
+        self.observers.push(Box::new(observer));
+    }
 
-    let replay_ok = crate::verify::verify_trace_ndjson(&trace_ndjson_path)?;
+    /// An executor over `universe`, opened as `SELECT_UNIVERSE universe=<universe> n=0`
+    /// opens it, with set digests built by the configured hash backend.
+    pub fn new(universe: &str) -> Result<Executor> {
+        let hash = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).hash;
+        let mut exec = Executor::start(hash, false);
+        exec.apply(Op::SelectUniverse { universe: universe.to_string(), n: 0, group: None, items: None, hash: None })?;
+        Ok(exec)
+    }
 
-    let proof = json!({
-        "ops_in": ops,
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "trace_ndjson": trace_ndjson_path,
-    });
-    fs::write(&proof_path, serde_json::to_string_pretty(&proof)?)?;
-
-    let witness_s = if is_boolfun {
-        witness_bf.as_ref().map(boolfun_to_string)
-    } else if is_syllable {
-        witness_syllable.as_ref().map(|s| format!("syllable:{}", String::from_utf8_lossy(&s.canonical_bytes()).chars().take(40).collect::<String>()))
-    } else if is_word {
-        witness_word.as_ref().map(|w| w.text.clone())
-    } else if is_morpheme {
-        witness_morpheme.as_ref().map(|m| m.meaning_id.to_string())
-    } else if is_phrase {
-        witness_phrase.as_ref().map(|p| format!("phrase:{}", p.phrase_id))
-    } else if is_semantic {
-        witness_semantic.as_ref().map(|g| format!("graph:{}", g.graph_id))
-    } else if is_discourse {
-        witness_discourse.as_ref().map(|g| format!("discourse:{}", g.discourse_id))
-    } else {
-        witness.as_ref().map(frac_to_string)
-    };
+    /// Where the run stands after the ops applied so far.
+    pub fn state(&self) -> ExecutorState {
+        ExecutorState {
+            steps: self.out_lines.len(),
+            universe: self.active_universe.clone(),
+            count: self.count(),
+            set_digest: hex32(self.set_digest),
+            witness: self.witness_string(),
+            constraint_mask: self.cst.mask,
+            constraint_value: self.cst.value,
+            chain_hash: hex32(self.chain),
+        }
+    }
 
-    let mut sample: Vec<String> = Vec::new();
+    /// The current set, in canonical order, rendered as RETURN_SET renders elements.
+    pub fn elements(&self) -> Vec<String> {
+        if self.is_boolfun {
+            self.boolfun_set.iter().map(boolfun_to_string).collect()
+        } else if self.is_lattice {
+            self.lattice_set.iter().map(pt_to_string).collect()
+        } else if self.is_group {
+            self.group_set.iter().map(perm_to_string).collect()
+        } else if self.is_subsets {
+            self.subset_set.iter().map(|x| subset_to_string(&self.subset_items, x)).collect()
+        } else if self.is_quad {
+            self.quad_set.iter().map(quad_to_string).collect()
+        } else if self.is_tetra {
+            self.tetra_set.iter().map(tetra_to_string).collect()
+        } else if self.is_syllable {
+            self.syllable_set.iter().map(|s| format!("syllable:{}", String::from_utf8_lossy(&s.canonical_bytes()).chars().take(40).collect::<String>())).collect()
+        } else if self.is_word {
+            self.word_set.iter().map(|w| w.text.clone()).collect()
+        } else if self.is_morpheme {
+            self.morpheme_set.iter().map(|m| m.meaning_id.to_string()).collect()
+        } else if self.is_phrase {
+            self.phrase_set.iter().map(|p| format!("phrase:{}", p.phrase_id)).collect()
+        } else if self.is_semantic {
+            self.semantic_set.iter().map(|g| format!("graph:{}", g.graph_id)).collect()
+        } else if self.is_discourse {
+            self.discourse_set.iter().map(|g| format!("discourse:{}", g.discourse_id)).collect()
+        } else {
+            self.state_set.iter().map(frac_to_string).collect()
+        }
+    }
 
-    if want_include_witness {
-        if let Some(w) = witness_s.as_ref() {
-            sample.push(w.clone());
+    /// Replay the records so far and summarize the run, writing nothing:
+    /// `artifacts_path` is None and `run_id` is the deterministic `ops_…` id.
+    pub fn finalize(mut self) -> Result<ExecutionResult> {
+        let lines: Vec<&str> = self.out_lines.iter().map(String::as_str).collect();
+        let witness = self.witness_string();
+        let result = ExecutionResult {
+            valid: crate::verify::replay_lines(&lines).valid,
+            final_count: self.count(),
+            sample: self.sample(witness.as_ref())?,
+            witness,
+            artifacts_path: None,
+            run_id: ops_run_id(&self.ops),
+            universe: self.active_universe,
+            constraint_mask: self.cst.mask,
+            constraint_value: self.cst.value,
+            failed_assertion: self.first_failed_assertion,
+            chain_hash: hex32(self.chain),
+            steps: self.metrics,
+            artifact_digests: BTreeMap::new(),
+            document: None,
+        };
+        for o in self.observers.iter_mut() {
+            o.on_finish(&result);
         }
+        Ok(result)
     }
 
-    let remain = want_max_items.saturating_sub(sample.len());
+    fn count(&self) -> usize {
+        if self.is_boolfun {
+            self.boolfun_set.len()
+        } else if self.is_lattice {
+            self.lattice_set.len()
+        } else if self.is_group {
+            self.group_set.len()
+        } else if self.is_subsets {
+            self.subset_set.len()
+        } else if self.is_quad {
+            self.quad_set.len()
+        } else if self.is_tetra {
+            self.tetra_set.len()
+        } else if self.is_word {
+            self.word_set.len()
+        } else if self.is_syllable {
+            self.syllable_set.len()
+        } else if self.is_morpheme {
+            self.morpheme_set.len()
+        } else if self.is_phrase {
+            self.phrase_set.len()
+        } else if self.is_semantic {
+            self.semantic_set.len()
+        } else if self.is_discourse {
+            self.discourse_set.len()
+        } else {
+            self.state_set.len()
+        }
+    }
 
-    if is_boolfun {
-        let mut pushed = 0usize;
-        for f in boolfun_set.iter() {
-            if pushed >= remain {
-                break;
-            }
-            if let Some(w) = witness_bf.as_ref() {
-                if *f == *w {
-                    continue;
-                }
+    /// The page RETURN_SET asked for: the witness when included, then up to
+    /// `max_items` elements from `offset`, nearest the witness first under
+    /// `sort_by=distance`.
+    fn sample(&self, witness: Option<&String>) -> Result<Vec<String>> {
+        let mut sample: Vec<String> = Vec::new();
+
+        if self.want_include_witness {
+            if let Some(w) = witness {
+                sample.push(w.clone());
             }
-            sample.push(boolfun_to_string(f));
-            pushed += 1;
         }
-    } else {
-        let mut pushed = 0usize;
-        for f in state_set.iter() {
-            if pushed >= remain {
-                break;
-            }
-            if let Some(w) = witness.as_ref() {
-                if *f == *w {
-                    continue;
-                }
-            }
-            sample.push(frac_to_string(f));
-            pushed += 1;
+
+        let remain = self.want_max_items.saturating_sub(sample.len());
+        let by_distance = self.want_sort_by == "distance";
+        if by_distance && witness.is_none() {
+            return Err(anyhow!("RETURN_SET sort_by=distance requires a witness"));
+        }
+
+        // The witness itself is never repeated in the page; ties in distance keep canonical order.
+        if self.is_boolfun {
+            let page = page_window(
+                &self.boolfun_set,
+                self.witness_bf.as_ref(),
+                |x, y| match self.witness_bf.as_ref().filter(|_| by_distance) {
+                    Some(w) => x.hamming(w).cmp(&y.hamming(w)),
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(boolfun_to_string));
+        } else if self.is_lattice {
+            let page = page_window(
+                &self.lattice_set,
+                self.witness_pt.as_ref(),
+                |x, y| match self.witness_pt.as_ref().filter(|_| by_distance) {
+                    Some(w) => crate::lattice::dist_sq(x, w).cmp(&crate::lattice::dist_sq(y, w)),
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(pt_to_string));
+        } else if self.is_group {
+            let page = page_window(
+                &self.group_set,
+                self.witness_perm.as_ref(),
+                |x, y| match (self.witness_perm.as_ref().filter(|_| by_distance), self.group_univ.as_ref()) {
+                    (Some(w), Some(g)) => g.distance(x, w).cmp(&g.distance(y, w)),
+                    _ => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(perm_to_string));
+        } else if self.is_subsets {
+            let page = page_window(
+                &self.subset_set,
+                self.witness_subset.as_ref(),
+                |x, y| match self.witness_subset.as_ref().filter(|_| by_distance) {
+                    Some(w) => (x.sum - w.sum).abs().cmp(&(y.sum - w.sum).abs()),
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(|x| subset_to_string(&self.subset_items, x)));
+        } else if self.is_quad {
+            let page = page_window(
+                &self.quad_set,
+                self.witness_quad.as_ref(),
+                |x, y| match self.witness_quad.as_ref().filter(|_| by_distance) {
+                    Some(w) => quad_distance(x, w).cmp(&quad_distance(y, w)),
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(quad_to_string));
+        } else if self.is_tetra {
+            let page = page_window(
+                &self.tetra_set,
+                self.witness_tetra.as_ref(),
+                |x, y| match self.witness_tetra.as_ref().filter(|_| by_distance) {
+                    Some(w) => tetra_distance(x, w).cmp(&tetra_distance(y, w)),
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(tetra_to_string));
+        } else {
+            let page = page_window(
+                &self.state_set,
+                self.witness.as_ref(),
+                |x, y| match self.witness.as_ref().filter(|_| by_distance) {
+                    Some(w) => {
+                        let (dx, dy) = (distance_num_den(w, x), distance_num_den(w, y));
+                        (dx.0 * dy.1).cmp(&(dy.0 * dx.1))
+                    }
+                    None => std::cmp::Ordering::Equal,
+                },
+                self.want_offset,
+                remain,
+            );
+            sample.extend(page.into_iter().map(frac_to_string));
+        }
+        Ok(sample)
+    }
+
+    fn witness_string(&self) -> Option<String> {
+        if self.is_boolfun {
+            self.witness_bf.as_ref().map(boolfun_to_string)
+        } else if self.is_lattice {
+            self.witness_pt.as_ref().map(pt_to_string)
+        } else if self.is_group {
+            self.witness_perm.as_ref().map(perm_to_string)
+        } else if self.is_subsets {
+            self.witness_subset.as_ref().map(|x| subset_to_string(&self.subset_items, x))
+        } else if self.is_quad {
+            self.witness_quad.as_ref().map(quad_to_string)
+        } else if self.is_tetra {
+            self.witness_tetra.as_ref().map(tetra_to_string)
+        } else if self.is_syllable {
+            self.witness_syllable.as_ref().map(|s| format!("syllable:{}", String::from_utf8_lossy(&s.canonical_bytes()).chars().take(40).collect::<String>()))
+        } else if self.is_word {
+            self.witness_word.as_ref().map(|w| w.text.clone())
+        } else if self.is_morpheme {
+            self.witness_morpheme.as_ref().map(|m| m.meaning_id.to_string())
+        } else if self.is_phrase {
+            self.witness_phrase.as_ref().map(|p| format!("phrase:{}", p.phrase_id))
+        } else if self.is_semantic {
+            self.witness_semantic.as_ref().map(|g| format!("graph:{}", g.graph_id))
+        } else if self.is_discourse {
+            self.witness_discourse.as_ref().map(|g| format!("discourse:{}", g.discourse_id))
+        } else {
+            self.witness.as_ref().map(frac_to_string)
         }
     }
+}
 
-    let set_nonempty = if is_boolfun {
-        !boolfun_set.is_empty()
+/// Execute `ops` with set digests built by `out.hash`, unless the first op
+/// is a `SELECT_UNIVERSE` naming its own `hash=`, writing artifacts as `out` says.
+fn run_trace(ops: &[String], provenance: Option<&JsonValue>, out: &OutputConfig) -> Result<ExecutionResult> {
+    let (mut exec, _) = execute(ops, out.hash, out.deltas)?;
+    // proof.json records the ops as given
+    exec.ops = ops.to_vec();
+    write_artifacts(exec, provenance, out)
+}
+
+/// Apply `ops` to a fresh executor, hashing with `default_hash` unless the
+/// first op is a `SELECT_UNIVERSE` naming its own `hash=`.
+fn execute(ops: &[String], default_hash: HashAlg, deltas: bool) -> Result<(Executor, Vec<StepRecord>)> {
+    let _span = tracing::info_span!("execute", ops = ops.len()).entered();
+    let typed: Vec<Op> = ops.iter().map(|op| op.parse()).collect::<Result<_>>()?;
+    let mut exec = Executor::start(chosen_hash(&typed, default_hash), deltas);
+    let records = typed.into_iter().map(|op| exec.apply(op)).collect::<Result<_>>()?;
+    Ok((exec, records))
+}
+
+/// The first op's `hash=` when it is a `SELECT_UNIVERSE` naming one, else `default_hash`.
+fn chosen_hash(typed: &[Op], default_hash: HashAlg) -> HashAlg {
+    match typed.first() {
+        Some(Op::SelectUniverse { hash: Some(h), .. }) => HashAlg::parse(h).unwrap_or(default_hash),
+        _ => default_hash,
+    }
+}
+
+/// Apply `ops` as `run_trace_and_write` would, yielding to the async runtime
+/// after every step and stopping with `cancel::Cancelled` once `cancel`
+/// fires. Hand the executor to `write_artifacts` or `Executor::finalize`.
+pub async fn execute_async(ops: &[String], cancel: &CancelToken) -> Result<Executor> {
+    let out = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let typed: Vec<Op> = ops.iter().map(|op| op.parse()).collect::<Result<_>>()?;
+    let mut exec = Executor::start(chosen_hash(&typed, out.hash), out.deltas);
+    for op in typed {
+        cancel.check()?;
+        exec.apply(op)?;
+        yield_now().await;
+    }
+    Ok(exec)
+}
+
+/// `run_trace_and_write_with` over `execute_async`: the run can be cancelled
+/// between steps, and does not hold the thread for the whole trace.
+pub async fn run_trace_and_write_async(ops: &[String], provenance: Option<&JsonValue>, cancel: &CancelToken) -> Result<ExecutionResult> {
+    let mut exec = execute_async(ops, cancel).await?;
+    cancel.check()?;
+    // proof.json records the ops as given
+    exec.ops = ops.to_vec();
+    let out = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    write_artifacts(exec, provenance, &out)
+}
+
+/// Execute `ops` without touching the filesystem: the trace.ndjson records
+/// and a summary whose `artifacts_path` is None. Hashing follows the
+/// configured backend, as for `run_trace_and_write`.
+pub fn execute_in_memory(ops: &[String]) -> Result<(Vec<StepRecord>, ExecutionResult)> {
+    let hash = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).hash;
+    execute_in_memory_with(ops, hash)
+}
+
+/// `execute_in_memory` with set digests built by `hash`, unless the first op
+/// names its own.
+pub fn execute_in_memory_with(ops: &[String], hash: HashAlg) -> Result<(Vec<StepRecord>, ExecutionResult)> {
+    let (exec, records) = execute(ops, hash, false)?;
+    Ok((records, exec.finalize()?))
+}
+
+/// The artifact sink: create a run directory under the artifacts root and
+/// write `exec`'s trace.ndjson, proof.json, result.json, paragraph.txt and,
+/// when it kept them, deltas.ndjson, then replay the written trace.
+pub fn write_artifacts(mut exec: Executor, provenance: Option<&JsonValue>, out: &OutputConfig) -> Result<ExecutionResult> {
+    let _span = tracing::info_span!("artifacts", deterministic = out.deterministic).entered();
+    let mut observers = std::mem::take(&mut exec.observers);
+    let (deterministic, hash) = (out.deterministic, exec.hash);
+    let _hash = crate::digest::use_hash(hash);
+    let ops = exec.ops.as_slice();
+    let (run_id, artifacts_dir) = create_run_dir(ops, deterministic)?;
+
+    let trace_ndjson_path = artifacts_dir.join("trace.ndjson");
+    let proof_path = artifacts_dir.join("proof.json");
+    let result_path = artifacts_dir.join("result.json");
+    let paragraph_path = artifacts_dir.join("paragraph.txt");
+    let deltas_path = artifacts_dir.join("deltas.ndjson");
+
+    fs::write(&trace_ndjson_path, exec.out_lines.join("\n") + "\n")?;
+    if let Some(lines) = exec.deltas.as_ref() {
+        fs::write(&deltas_path, lines.join("\n") + "\n")?;
+    }
+
+    let replay_ok = crate::verify::verify_trace_ndjson(&trace_ndjson_path)?;
+
+    // deterministic runs name their files relative to the run directory
+    let shown = |p: &Path| match p.file_name().filter(|_| deterministic) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => p.to_string_lossy().into_owned(),
+    };
+    let proof = RunProof {
+        ops_in: ops.to_vec(),
+        trace_ndjson: shown(&trace_ndjson_path),
+        timestamp: (!deterministic).then(|| chrono::Utc::now().to_rfc3339()),
+        deterministic,
+        proposer: provenance.cloned(),
+        hash: (hash != HashAlg::Sha256).then(|| hash.name().to_string()),
+    };
+    fs::write(&proof_path, artifact_json(&proof)?)?;
+
+    let (witness_s, count) = (exec.witness_string(), exec.count());
+
+    let sample = exec.sample(witness_s.as_ref())?;
+
+    // The witness and every sampled element, each with a path to the set digest.
+    let proofs = if exec.want_include_proofs {
+        let (encoding, leaves): (String, Vec<[u8; 32]>) = if exec.is_group || exec.is_subsets || exec.is_word || exec.is_syllable
+            || exec.is_morpheme || exec.is_phrase || exec.is_semantic || exec.is_discourse
+        {
+            return Err(anyhow!("RETURN_SET include_proofs is not supported for universe {}", exec.active_universe));
+        } else if exec.is_boolfun {
+            (format!("boolfun{}", exec.boolfun_n), exec.boolfun_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect())
+        } else if exec.is_lattice {
+            ("pt".to_string(), exec.lattice_set.iter().map(|p| leaf_hash(&p.canonical_bytes())).collect())
+        } else if exec.is_quad {
+            ("quad".to_string(), exec.quad_set.iter().map(|q| leaf_hash(&q.to_bytes())).collect())
+        } else if exec.is_tetra {
+            ("tetra".to_string(), exec.tetra_set.iter().map(|t| leaf_hash(&t.to_bytes())).collect())
+        } else {
+            ("frac".to_string(), exec.state_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect())
+        };
+        let mut elems: Vec<String> = witness_s.iter().cloned().collect();
+        for e in &sample {
+            if !elems.contains(e) {
+                elems.push(e.clone());
+            }
+        }
+        Some(inclusion_proofs(&encoding, &leaves, &exec.set_digest, &elems)?)
     } else {
-        !state_set.is_empty()
+        None
+    };
+
+    let set_nonempty = if exec.is_boolfun {
+        !exec.boolfun_set.is_empty()
+    } else if exec.is_lattice {
+        !exec.lattice_set.is_empty()
+    } else if exec.is_group {
+        !exec.group_set.is_empty()
+    } else if exec.is_subsets {
+        !exec.subset_set.is_empty()
+    } else if exec.is_quad {
+        !exec.quad_set.is_empty()
+    } else if exec.is_tetra {
+        !exec.tetra_set.is_empty()
+    } else {
+        !exec.state_set.is_empty()
     };
     let verdict_ok = replay_ok;
-    let result = json!({
-        "verdict": if set_nonempty { "OK" } else { "EMPTY_SET" },
-        "verifier": { "valid": replay_ok },
-        "chain_hash": hex32(chain),
-        "count": if is_boolfun { boolfun_set.len() } else if is_word { word_set.len() } else if is_syllable { syllable_set.len() } else if is_morpheme { morpheme_set.len() } else if is_phrase { phrase_set.len() } else if is_semantic { semantic_set.len() } else if is_discourse { discourse_set.len() } else { state_set.len() },
-        "witness": witness_s,
-        "constraint": { "mask": cst.mask, "value": cst.value },
-        "return_set": { "max_items": want_max_items, "include_witness": want_include_witness },
-        "sample": sample,
-        "artifacts": {
-            "trace_ndjson": trace_ndjson_path,
-            "proof": proof_path,
-            "result": result_path,
-            "paragraph": paragraph_path,
-        }
-    });
-    fs::write(&result_path, serde_json::to_string_pretty(&result)?)?;
+    let result = RunResult {
+        verdict: if set_nonempty { "OK" } else { "EMPTY_SET" }.to_string(),
+        verifier: RunVerifier { valid: replay_ok },
+        chain_hash: hex32(exec.chain),
+        semtrace_version: SEMTRACE_VERSION.to_string(),
+        count,
+        witness: witness_s.clone(),
+        constraint: RunConstraint { mask: exec.cst.mask, value: exec.cst.value },
+        return_set: RunReturnSet {
+            max_items: exec.want_max_items,
+            include_witness: exec.want_include_witness,
+            include_proofs: exec.want_include_proofs,
+            offset: exec.want_offset,
+            sort_by: exec.want_sort_by,
+        },
+        sample,
+        artifacts: RunArtifacts {
+            run_id: if deterministic { ops_run_id(ops) } else { run_id.clone() },
+            dir: if deterministic { ".".to_string() } else { artifacts_dir.to_string_lossy().into_owned() },
+            trace_ndjson: shown(&trace_ndjson_path),
+            proof: shown(&proof_path),
+            result: shown(&result_path),
+            paragraph: shown(&paragraph_path),
+            deltas: exec.deltas.is_some().then(|| shown(&deltas_path)),
+        },
+        witness_ties_root: exec.witness_ties.as_ref().map(|(_, root)| hex32(*root)),
+        witness_ties: exec.witness_ties.map(|(ties, _)| ties),
+        aggregate: exec.aggregate,
+        proofs,
+        group_by: exec.group_by,
+        failed_assertion: exec.first_failed_assertion.clone(),
+        fallback_used: provenance.and_then(|p| p.get("fallback_used")).cloned(),
+    };
+    fs::write(&result_path, artifact_json(&result)?)?;
+    let document = result;
 
     let paragraph = format!(
         "Semantic Transformer (exec)\nchain_hash={}\ncount={}\nwitness={}\n",
-        hex32(chain),
-        state_set.len(),
-        witness
+        hex32(exec.chain),
+        exec.state_set.len(),
+        exec.witness
             .as_ref()
             .map(frac_to_string)
             .unwrap_or_else(|| "(none)".to_string()),
     );
     fs::write(&paragraph_path, paragraph)?;
-
-    let elapsed = start.elapsed();
-    if verbose {
-        println!("⏱️  Execution completed in {:.2?}", elapsed);
-        println!("📁 Artifacts written to: {}", artifacts_dir.display());
+    if let Some(key) = out.sign_key.as_ref() {
+        crate::signing::sign_run(&artifacts_dir, key)?;
+    }
+    let mut artifact_digests = BTreeMap::new();
+    for name in ARTIFACT_FILES {
+        if let Ok(bytes) = fs::read(artifacts_dir.join(name)) {
+            artifact_digests.insert(name.to_string(), hex32(sha256_bytes(&bytes)));
+        }
     }
 
-    Ok(ExecutionResult {
+    let result = ExecutionResult {
         valid: verdict_ok,
-        final_count: if is_boolfun { boolfun_set.len()
-        } else if is_word { word_set.len()
-        } else if is_syllable { syllable_set.len()
-        } else if is_morpheme { morpheme_set.len()
-        } else if is_phrase { phrase_set.len()
-        } else if is_semantic { semantic_set.len()
-        } else if is_discourse { discourse_set.len()
-        } else { state_set.len() },
+        final_count: count,
         witness: witness_s,
         artifacts_path: Some(artifacts_dir),
-        universe: active_universe.clone(),
-        constraint_mask: cst.mask,
-        constraint_value: cst.value,
-    })
+        run_id,
+        universe: exec.active_universe.clone(),
+        constraint_mask: exec.cst.mask,
+        constraint_value: exec.cst.value,
+        failed_assertion: exec.first_failed_assertion,
+        chain_hash: hex32(exec.chain),
+        sample: document.sample.clone(),
+        steps: exec.metrics,
+        artifact_digests,
+        document: Some(document),
+    };
+    for o in observers.iter_mut() {
+        o.on_finish(&result);
+    }
+    Ok(result)
 }
 
 pub fn write_trace_to_file(ops: &[String], query: &str) -> Result<PathBuf> {
     let trace_dir = PathBuf::from("traces");
     fs::create_dir_all(&trace_dir)?;
 
-    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-    let trace_path = trace_dir.join(format!("trace_{}.json", timestamp));
-
-    let trace = json!({
-        "query": query,
-        "ops": ops,
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-    });
+    let deterministic = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).deterministic;
+    let (name, mut trace) = (
+        if deterministic { ops_run_id(ops) } else { chrono::Utc::now().format("%Y%m%d_%H%M%S").to_string() },
+        json!({ "query": query, "ops": ops }),
+    );
+    if !deterministic {
+        trace["timestamp"] = json!(chrono::Utc::now().to_rfc3339());
+    }
+    let trace_path = trace_dir.join(format!("trace_{}.json", name));
 
     fs::write(&trace_path, serde_json::to_string_pretty(&trace)?)?;
 
@@ -1090,4 +3674,871 @@ mod tests {
         assert_eq!(result.witness.as_deref(), Some("abandon"));
     }
 
+    #[test]
+    fn lattice_visible_quadrant_witness() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=LATTICE n=5".to_string(),
+            "MASK_BIT bit=0 val=1".to_string(),
+            "MASK_BIT bit=4 val=1".to_string(),
+            "WITNESS_NEAREST target_elem=(4,4) metric=EUCLID_SQ".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // (4,4) and (3,3) are not visible; (3,4) and (4,3) tie at distance 1, x ascending wins
+        assert_eq!(result.witness.as_deref(), Some("(3,4)"));
+    }
+
+    #[test]
+    fn group_conjugacy_class_topk() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=GROUP group=S_4".to_string(),
+            "FILTER_CONJ elem=[1,0,2,3]".to_string(),
+            "TOPK target_elem=[0,1,2,3] k=3".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // 6 transpositions; the three adjacent ones are at word distance 1, [0,1,3,2] last canonically
+        assert_eq!(result.final_count, 3);
+        assert_eq!(result.witness.as_deref(), Some("[0,1,3,2]"));
+    }
+
+    #[test]
+    fn subsets_closest_sum() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=SUBSETS items=3,5,7,11".to_string(),
+            "MASK_BIT bit=0 val=0".to_string(),
+            "FILTER_SUM min=10 max=20".to_string(),
+            "WITNESS_NEAREST target_elem=17".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // sums in [10,20]: 10,11,12,14,15,16,18,19
+        assert_eq!(result.final_count, 8);
+        assert_eq!(result.witness.as_deref(), Some("{5,11}"));
+    }
+
+    #[test]
+    fn quad_heronian_area_witness() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QUAD n=20".to_string(),
+            "MASK_BIT bit=4 val=1".to_string(),
+            "FILTER_AREA min=10 max=20".to_string(),
+            "WITNESS_NEAREST target_elem=3,3,4,5 metric=L1".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // 3,3,4,5 has irrational area; the 3x4 and 3x5 rectangles tie at L1 distance 1
+        // and the smaller perimeter wins
+        assert_eq!(result.witness.as_deref(), Some("3,3,4,4"));
+    }
+
+    #[test]
+    fn tetra_embeddable_nearest() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=TETRA n=3".to_string(),
+            "MASK_BIT bit=5 val=1".to_string(),
+            "MASK_BIT bit=4 val=1".to_string(),
+            "WITNESS_NEAREST target_elem=3,2,2,2,2,3 metric=L1".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // 3,2,2,2,2,3 does not embed; the regular 2-tetrahedron and 2,3,3,3,3,2 tie at
+        // L1 distance 2 and the smaller edge sum wins
+        assert_eq!(result.witness.as_deref(), Some("2,2,2,2,2,2"));
+    }
+
+    #[test]
+    fn filter_range_qe_and_ge() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "MASK_BIT bit=2 val=1".to_string(),
+            "FILTER_RANGE min=1/4 max=1/2".to_string(),
+            "WITNESS_NEAREST target_elem=1/5 metric=ABS_DIFF".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // den<=6 fractions in [1/4,1/2]: 1/4, 1/3, 2/5, 1/2
+        assert_eq!(result.final_count, 4);
+        assert_eq!(result.witness.as_deref(), Some("1/4"));
+
+        let ops = vec![
+            "LOAD 3,4,5".to_string(),
+            "FILTER_RANGE min=12 max=12".to_string(),
+            "RETURN_SET max_items=5 include_witness=0".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // perimeter 12: 2,5,5 3,4,5 4,4,4
+        assert_eq!(result.final_count, 3);
+    }
+
+    #[test]
+    fn topk_qe_and_ge() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "MASK_BIT bit=2 val=1".to_string(),
+            "TOPK target_elem=13/37 k=3".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        assert_eq!(result.final_count, 3);
+        // 13/37 ≈ 0.351; nearest den<=6 fraction is 1/3
+        assert_eq!(result.witness.as_deref(), Some("1/3"));
+
+        let ops = vec![
+            "LOAD 3,4,5".to_string(),
+            "TOPK target_elem=3,4,5 k=4".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        assert_eq!(result.final_count, 4);
+        assert_eq!(result.witness.as_deref(), Some("3/5"));
+    }
+
+    #[test]
+    fn witness_all_ties_lattice() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=LATTICE n=2".to_string(),
+            "MASK_BIT bit=5 val=1".to_string(),
+            "WITNESS_ALL_TIES target_elem=(1,1) metric=EUCLID_SQ".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        assert_eq!(result.witness.as_deref(), Some("(0,1)"));
+        let dir = result.artifacts_path.unwrap();
+        let out: JsonValue =
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!(out["witness_ties"], json!(["(0,1)", "(1,0)"]));
+        assert_eq!(out["witness_ties_root"].as_str().map(|s| s.len()), Some(64));
+    }
+
+    #[test]
+    fn save_set_union_and_intersect() {
+        // (den<=6 ∪ num_even) ∩ [0,1]
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=0 max=1".to_string(),
+            "SAVE_SET name=unit".to_string(),
+            "MASK_BIT bit=2 val=1".to_string(),
+            "SAVE_SET name=small_den".to_string(),
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "MASK_BIT bit=3 val=1".to_string(),
+            "UNION name=small_den".to_string(),
+            "INTERSECT name=unit".to_string(),
+            "RETURN_SET max_items=5 include_witness=0".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        assert_eq!(result.final_count, 4085);
+    }
+
+    #[test]
+    fn complement_of_small_denominators() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "MASK_BIT bit=2 val=1".to_string(),
+            "COMPLEMENT".to_string(),
+            "FILTER_RANGE min=0 max=1".to_string(),
+            "WITNESS_NEAREST target_elem=1/2 metric=ABS_DIFF".to_string(),
+            "RETURN_SET max_items=5 include_witness=1".to_string(),
+        ];
+        let result = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(result.valid, "verifier must agree with executor");
+        // 1/2 itself is excluded; 99/199 and 100/199 tie at 1/398, smaller |num| wins
+        assert_eq!(result.witness.as_deref(), Some("99/199"));
+    }
+
+    #[test]
+    fn sample_is_replayable() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "SAMPLE seed=42 k=100".to_string(),
+            "RETURN_SET max_items=5 include_witness=0".to_string(),
+        ];
+        let a = run_trace_and_write(&ops, None, false).unwrap();
+        let b = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(a.valid && b.valid, "verifier must agree with executor");
+        assert_eq!(a.final_count, 100);
+        let sample = |r: &ExecutionResult| -> JsonValue {
+            let dir = r.artifacts_path.as_ref().unwrap();
+            let out: JsonValue =
+                serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+            out["chain_hash"].clone()
+        };
+        assert_eq!(sample(&a), sample(&b));
+    }
+
+    #[test]
+    fn aggregate_qe_mean_and_boolfun_hist() {
+        let read = |r: &ExecutionResult| -> JsonValue {
+            let dir = r.artifacts_path.as_ref().unwrap();
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap()
+        };
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=199/200 max=1".to_string(),
+            "AGGREGATE".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        let agg = &read(&r)["aggregate"];
+        assert_eq!(agg["count"], json!(2));
+        assert_eq!(agg["min"], json!("199/200"));
+        assert_eq!(agg["max"], json!("1/1"));
+        assert_eq!(agg["mean"], json!("399/400"));
+
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=2".to_string(),
+            "AGGREGATE".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        let agg = &read(&r)["aggregate"];
+        assert_eq!(agg["count"], json!(16));
+        assert_eq!(agg["weight_hist"], json!([1, 4, 6, 4, 1]));
+    }
+
+    #[test]
+    fn group_by_histogram_is_bound_to_digest() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=199/200 max=1".to_string(),
+            "GROUP_BY".to_string(),
+            "GROUP_BY by=rat_int".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        let dir = r.artifacts_path.as_ref().unwrap();
+        let out: JsonValue =
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!(out["group_by"], json!({ "0": 1, "1": 1 }));
+
+        let trace = dir.join("trace.ndjson");
+        let txt = fs::read_to_string(&trace).unwrap();
+        let step2: JsonValue = serde_json::from_str(txt.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(step2["post"]["group_by"], json!({ "0100001": 1, "1000111": 1 }));
+        let report = crate::verify::verify_trace_report(&trace);
+        assert!(report.valid);
+        assert_eq!(report.chain_hash.as_ref(), out["chain_hash"].as_str().map(str::to_string).as_ref());
+
+        // a forged histogram must not replay
+        fs::write(&trace, txt.replace("\"0100001\":1", "\"0100001\":2")).unwrap();
+        assert!(crate::verify::verify_trace_ndjson(&trace).is_err());
+        let report = crate::verify::verify_trace_report(&trace);
+        assert!(!report.valid);
+        assert_eq!((report.failed_step, report.failed_op.as_deref()), (Some(2), Some("GROUP_BY")));
+        assert!(report.reason.unwrap().contains("group_by mismatch"));
+    }
+
+    #[test]
+    fn replay_reproduces_a_saved_run() {
+        let ops = vec!["LOAD 13/37".to_string(), "MASK_BIT bit=2 val=1".to_string()];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        let dir = r.artifacts_path.unwrap();
+        let report = replay_run(&dir).unwrap();
+        assert!(report.reproduced, "{:?}", report.divergences);
+        assert_eq!(report.replay_chain_hash, report.stored_chain_hash);
+
+        let path = dir.join("result.json");
+        let mut stored: JsonValue = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        stored["count"] = json!(1);
+        fs::write(&path, stored.to_string()).unwrap();
+        let report = replay_run(&dir).unwrap();
+        assert!(!report.reproduced);
+        assert_eq!(report.divergences.len(), 1);
+        assert!(report.divergences[0].starts_with("count: stored 1"));
+    }
+
+    #[test]
+    fn run_dirs_get_distinct_ids() {
+        let r = run_trace_and_write(&["LOAD 13/37".to_string()], None, false).unwrap();
+        let dir = r.artifacts_path.unwrap();
+        assert!(dir.is_absolute());
+        assert!(dir.ends_with(&r.run_id));
+
+        let root = std::env::temp_dir().join(format!("lnst_run_ids_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let ids: Vec<String> = (0..3).map(|_| claim_run_dir(&root, "nightly").unwrap().0).collect();
+        assert_eq!(ids, ["nightly", "nightly-2", "nightly-3"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn assertions_are_rechecked_by_verifier() {
+        let mut ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=199/200 max=1".to_string(),
+            "WITNESS_NEAREST target_elem=2/3 metric=ABS_DIFF".to_string(),
+            "ASSERT_COUNT eq=2".to_string(),
+            "ASSERT_WITNESS elem=398/400".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.failed_assertion, None);
+
+        ops[3] = "ASSERT_COUNT eq=3".to_string();
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(!r.valid, "a false assertion must fail verification");
+        assert_eq!(r.failed_assertion.as_deref(), Some("step 3: ASSERT_COUNT eq=3 got=2"));
+
+        ops[3] = "ASSERT_COUNT eq=2".to_string();
+        ops[4] = "ASSERT_WITNESS elem=1/1".to_string();
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(!r.valid);
+        assert_eq!(
+            r.failed_assertion.as_deref(),
+            Some("step 4: ASSERT_WITNESS elem=1/1 got=199/200")
+        );
+    }
+
+    #[test]
+    fn return_set_pages_and_distance_order() {
+        let page = |ret: &str| -> JsonValue {
+            let ops = vec![
+                "SELECT_UNIVERSE universe=QE n=0".to_string(),
+                "FILTER_RANGE min=0 max=1".to_string(),
+                "WITNESS_NEAREST target_elem=1/3 metric=ABS_DIFF".to_string(),
+                ret.to_string(),
+            ];
+            let r = run_trace_and_write(&ops, None, false).unwrap();
+            assert!(r.valid);
+            let dir = r.artifacts_path.as_ref().unwrap();
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap()
+        };
+        let all = page("RETURN_SET max_items=6 include_witness=0");
+        let p1 = page("RETURN_SET limit=3 include_witness=0");
+        let p2 = page("RETURN_SET limit=3 offset=3 include_witness=0");
+        let joined: Vec<JsonValue> = p1["sample"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .chain(p2["sample"].as_array().unwrap())
+            .cloned()
+            .collect();
+        assert_eq!(all["sample"], json!(joined));
+        assert_eq!(p2["return_set"]["offset"], json!(3));
+
+        let near = page("RETURN_SET max_items=3 include_witness=1 sort_by=distance");
+        assert_eq!(near["sample"], json!(["1/3", "67/200", "66/199"]));
+        assert_eq!(near["return_set"]["sort_by"], json!("distance"));
+    }
+
+    #[test]
+    fn witness_nearest_extra_metrics() {
+        let nearest = |target: &str, metric: &str| -> Option<String> {
+            let ops = vec![
+                "SELECT_UNIVERSE universe=QE n=0".to_string(),
+                "FILTER_RANGE min=0 max=1".to_string(),
+                format!("WITNESS_NEAREST target_elem={} metric={}", target, metric),
+            ];
+            let r = run_trace_and_write(&ops, None, false).unwrap();
+            assert!(r.valid, "verifier must replay metric {}", metric);
+            r.witness
+        };
+        assert_eq!(nearest("1/201", "DEN_DIFF").as_deref(), Some("1/200"));
+        assert_eq!(nearest("7/3", "LOG_RATIO").as_deref(), Some("1/1"));
+        assert_eq!(nearest("5/8", "MEDIANT_DEPTH").as_deref(), Some("5/8"));
+    }
+
+    #[test]
+    fn witness_nearest_multi_target() {
+        let run = |mode: &str| -> Option<String> {
+            let ops = vec![
+                "SELECT_UNIVERSE universe=QE n=0".to_string(),
+                "FILTER_RANGE min=0 max=1".to_string(),
+                format!("WITNESS_NEAREST targets=1/5,2/5 mode={}", mode),
+            ];
+            let r = run_trace_and_write(&ops, None, false).unwrap();
+            assert!(r.valid);
+            r.witness
+        };
+        assert_eq!(run("minimax").as_deref(), Some("3/10"));
+        // every point of [1/5, 2/5] has the same sum; smallest (|num|, den) wins
+        assert_eq!(run("sum").as_deref(), Some("1/3"));
+    }
+
+    #[test]
+    fn qe_element_maps() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=199/200 max=1".to_string(),
+            "RECIPROCAL".to_string(),
+            "MEDIANT_WITH elem=0/1".to_string(),
+            "NEGATE".to_string(),
+            "RETURN_SET max_items=5 include_witness=0".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        let dir = r.artifacts_path.as_ref().unwrap();
+        let out: JsonValue =
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!(out["sample"], json!(["-1/1", "-1/2"]));
+    }
+
+    #[test]
+    fn push_pop_state_rolls_back() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=199/200 max=1".to_string(),
+            "WITNESS_NEAREST target_elem=1/1 metric=ABS_DIFF".to_string(),
+            "PUSH_STATE".to_string(),
+            "NEGATE".to_string(),
+            "WITNESS_NEAREST target_elem=-1/2 metric=ABS_DIFF".to_string(),
+            "ASSERT_WITNESS elem=-199/200".to_string(),
+            "POP_STATE".to_string(),
+            "ASSERT_WITNESS elem=1/1".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.failed_assertion, None);
+        assert_eq!(r.final_count, 2);
+
+        let unbalanced = vec!["SELECT_UNIVERSE universe=QE n=0".to_string(), "POP_STATE".to_string()];
+        assert!(run_trace_and_write(&unbalanced, None, false).is_err());
+    }
+
+    #[test]
+    fn filter_pred_matches_mask_bit() {
+        let run = |op: &str| {
+            let ops = vec!["SELECT_UNIVERSE universe=QE n=0".to_string(), op.to_string()];
+            run_trace_and_write(&ops, None, false).unwrap()
+        };
+        let by_name = run("FILTER_PRED name=den_le_6 val=1");
+        let by_bit = run("MASK_BIT bit=2 val=1");
+        assert!(by_name.valid && by_bit.valid);
+        assert_eq!(by_name.final_count, by_bit.final_count);
+        assert_eq!((by_name.constraint_mask, by_name.constraint_value), (0b100, 0b100));
+
+        let ops = vec![
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_PRED name=perim_le_20 val=1".to_string(),
+        ];
+        let err = run_trace_and_write(&ops, None, false).unwrap_err().to_string();
+        assert!(err.contains("unknown predicate perim_le_20"), "{}", err);
+    }
+
+    #[test]
+    fn define_pred_filters_and_replays() {
+        let ops = vec![
+            "DEFINE_PRED name=small expr=\"den<=10 && num>0\"".to_string(),
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_RANGE min=0 max=1".to_string(),
+            "FILTER_PRED name=small val=1".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        // reduced a/b with 0 < a ≤ b ≤ 10: Farey F10 without 0/1
+        assert_eq!(r.final_count, 32);
+
+        let dir = r.artifacts_path.as_ref().unwrap();
+        let txt = fs::read_to_string(dir.join("trace.ndjson")).unwrap();
+        let step0: JsonValue = serde_json::from_str(txt.lines().next().unwrap()).unwrap();
+        assert_eq!(step0["args"]["expr"], json!("(den<=10 && num>0)"));
+    }
+
+    #[test]
+    fn user_pred_bits_live_in_constraint() {
+        let ops = vec![
+            "DEFINE_PRED name=small expr=\"den<=10\"".to_string(),
+            "SELECT_UNIVERSE universe=QE n=0".to_string(),
+            "FILTER_PRED name=small val=1".to_string(),
+            // re-filtering from the universe keeps the user bit
+            "MASK_BIT bit=0 val=1".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.constraint_mask, 1 | (1 << 7));
+        assert_eq!(r.constraint_value, 1 | (1 << 7));
+        let dir = r.artifacts_path.as_ref().unwrap();
+        let txt = fs::read_to_string(dir.join("trace.ndjson")).unwrap();
+        let step3: JsonValue = serde_json::from_str(txt.lines().nth(3).unwrap()).unwrap();
+        assert_eq!(step3["pre"]["constraint_mask"], json!(128));
+        // positive fractions with den ≤ 10 and |num| ≤ 200
+        assert!(r.final_count > 0 && r.final_count < 2000);
+    }
+
+    #[test]
+    fn boolfun_filter_class() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=4".to_string(),
+            "FILTER_CLASS class=monotone".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.final_count, 168);
+
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=4".to_string(),
+            "FILTER_CLASS class=symmetric".to_string(),
+            "FILTER_CLASS class=self_dual".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        // n=4 is even, so no symmetric function is self-dual
+        assert_eq!(r.final_count, 0);
+    }
+
+    #[test]
+    fn boolfun_nonlinearity_ops() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=4".to_string(),
+            "FILTER_NONLINEARITY min=6 max=6".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.final_count, 896, "bent functions in 4 variables");
+
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=4".to_string(),
+            "FILTER_WEIGHT min=6 max=6".to_string(),
+            "TOPK metric=NONLINEARITY k=3".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.final_count, 3);
+        let w = crate::boolfun::parse_elem(r.witness.as_deref().unwrap()).unwrap();
+        assert_eq!(w.nonlinearity(), 6);
+    }
+
+    #[test]
+    fn boolfun_transforms() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=3".to_string(),
+            "FILTER_CLASS class=monotone".to_string(),
+            "DUAL".to_string(),
+            "PERMUTE_VARS perm=2,0,1".to_string(),
+            "NEGATE".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        // duality and variable permutation preserve monotonicity; negation maps it to antitone
+        assert_eq!(r.final_count, 20);
+
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=3".to_string(),
+            "FILTER_WEIGHT min=4 max=4".to_string(),
+            "RESTRICT var=1 value=0".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        // every 2-variable function is a cofactor of some balanced 3-variable function
+        assert_eq!(r.final_count, 16);
+
+        let bad = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=3".to_string(),
+            "PERMUTE_VARS perm=0,0,1".to_string(),
+        ];
+        assert!(run_trace_and_write(&bad, None, false).is_err());
+    }
+
+    #[test]
+    fn boolfun_npn_class() {
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=3".to_string(),
+            "NPN_CLASS".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.final_count, 14);
+
+        // weight-1 members of the class of AND (0x8000): one per minterm
+        let ops = vec![
+            "SELECT_UNIVERSE universe=BOOLFUN n=4".to_string(),
+            "FILTER_WEIGHT min=1 max=1".to_string(),
+            "NPN_CLASS elem=0x8000".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid);
+        assert_eq!(r.final_count, 16);
+    }
+
+    #[test]
+    fn ge_area_and_max_angle_filters() {
+        let ops = vec![
+            "LOAD 3,4,5".to_string(),
+            "FILTER_MAX_ANGLE max=70".to_string(),
+            "FILTER_AREA min=10 max=1000".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid, "verifier must agree with executor");
+        let expected = crate::geom::build_ge(20)
+            .into_iter()
+            .filter(|t| t.max_angle_at_most(70) && t.area_sq16() >= 1600)
+            .count();
+        assert_eq!(r.final_count, expected);
+        assert!(expected > 0);
+
+        let bad = vec!["LOAD 1/2".to_string(), "FILTER_MAX_ANGLE max=90".to_string()];
+        assert!(run_trace_and_write(&bad, None, false).is_err());
+    }
+
+    #[test]
+    fn witness_nearest_similarity_ge() {
+        let ops = vec![
+            "LOAD 3,4,5".to_string(),
+            "WITNESS_NEAREST target_elem=9,12,15 metric=SIMILARITY".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        assert!(r.valid, "verifier must agree with executor");
+        // smallest triangle of the same shape wins the tie
+        assert_eq!(r.witness.as_deref(), Some("3/5"));
+        let dir = r.artifacts_path.as_ref().unwrap();
+        let out: JsonValue = serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!(out["witness_ties"], json!(["3,4,5", "6,8,10", "9,12,15", "12,16,20"]));
+    }
+
+    #[test]
+    fn return_set_inclusion_proofs_verify() {
+        let ops = vec![
+            "LOAD 13/37".to_string(),
+            "MASK_BIT bit=2 val=1".to_string(),
+            "WITNESS_NEAREST target_elem=13/37 metric=ABS_DIFF".to_string(),
+            "RETURN_SET max_items=5 include_proofs=true".to_string(),
+        ];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        let dir = r.artifacts_path.unwrap();
+        let result_path = dir.join("result.json");
+        let out: JsonValue = serde_json::from_str(&fs::read_to_string(&result_path).unwrap()).unwrap();
+        let elements = out["proofs"]["elements"].as_array().unwrap();
+        assert_eq!(elements[0]["elem"], json!(r.witness.unwrap()));
+        assert_eq!(elements.len(), 6);
+
+        let report = crate::verify::verify_trace_report(&dir.join("trace.ndjson"));
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.proofs_checked, Some(6));
+
+        // claim a different element at the witness's position
+        let tampered = fs::read_to_string(&result_path).unwrap().replacen(elements[0]["elem"].as_str().unwrap(), "1/2", 1);
+        fs::write(&result_path, tampered).unwrap();
+        let report = crate::verify::verify_trace_report(&dir.join("trace.ndjson"));
+        assert!(!report.valid);
+        assert!(report.reason.unwrap().starts_with("inclusion proof"));
+
+        let ops = vec!["SELECT_UNIVERSE universe=S3".to_string(), "RETURN_SET include_proofs=true".to_string()];
+        assert!(run_trace_and_write(&ops, None, false).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn step_zero_records_the_pinned_universe_root() {
+        let ops = vec!["LOAD 13/37".to_string(), "MASK_BIT bit=2 val=1".to_string()];
+        let r = run_trace_and_write(&ops, None, false).unwrap();
+        let dir = r.artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        let text = fs::read_to_string(&trace).unwrap();
+        let first: JsonValue = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first["universe_root"].as_str(), crate::commitments::pinned_root("QE"));
+
+        let forged = text.replacen(crate::commitments::pinned_root("QE").unwrap(), &"0".repeat(64), 1);
+        fs::write(&trace, forged).unwrap();
+        let report = crate::verify::verify_trace_report(&trace);
+        assert!(!report.valid);
+        assert_eq!(report.failed_step, Some(0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verify_reports_the_first_mismatch_and_each_step() {
+        use crate::verify::{MismatchKind, StepStatus};
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "MASK_BIT bit=0 val=0", "ASSERT_COUNT eq=3"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let dir = run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        let text = fs::read_to_string(&trace).unwrap();
+
+        let report = crate::verify::verify_trace_report(&trace);
+        let m = report.mismatch.unwrap();
+        assert_eq!((m.step, m.kind, m.field.as_str(), m.got.as_str()), (3, MismatchKind::Assertion, "args.eq", "3"));
+        assert_eq!(report.steps.iter().filter(|s| s.status == StepStatus::Ok).count(), 3);
+        assert_eq!(report.steps[3].status, StepStatus::Failed);
+
+        // forge the count at step 1: the later steps are never replayed
+        let mut lines: Vec<JsonValue> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let real = lines[1]["post"]["count"].as_u64().unwrap();
+        lines[1]["post"]["count"] = json!(real + 1);
+        let forged: Vec<String> = lines.iter().map(JsonValue::to_string).collect();
+        fs::write(&trace, forged.join("\n")).unwrap();
+        let report = crate::verify::verify_trace_report(&trace);
+        let m = report.mismatch.unwrap();
+        assert_eq!((m.step, m.kind), (1, MismatchKind::Count));
+        assert_eq!((m.expected, m.got), (real.to_string(), (real + 1).to_string()));
+        let statuses: Vec<StepStatus> = report.steps.iter().map(|s| s.status).collect();
+        assert_eq!(statuses, [StepStatus::Ok, StepStatus::Failed, StepStatus::NotReplayed, StepStatus::NotReplayed]);
+        assert_eq!(report.steps[2].op, "SET_BIT");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deterministic_runs_rewrite_every_artifact_byte_for_byte() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=3"].iter().map(|s| s.to_string()).collect();
+        let out = OutputConfig { deterministic: true, deltas: true, ..Default::default() };
+        let (a, b) = (run_trace(&ops, None, &out).unwrap(), run_trace(&ops, None, &out).unwrap());
+        let (a, b) = (a.artifacts_path.unwrap(), b.artifacts_path.unwrap());
+        assert!(a.ends_with(ops_run_id(&ops)));
+        for name in ARTIFACT_FILES.iter().filter(|n| a.join(n).exists()) {
+            assert_eq!(fs::read(a.join(name)).unwrap(), fs::read(b.join(name)).unwrap(), "{}", name);
+        }
+        assert!(!fs::read_to_string(a.join("proof.json")).unwrap().contains("timestamp"));
+
+        let report = replay_run(&a).unwrap();
+        assert_eq!((report.reproduced, report.byte_identical), (true, Some(true)), "{:?}", report.divergences);
+        fs::write(a.join("paragraph.txt"), "edited\n").unwrap();
+        let edited = replay_run(&a).unwrap();
+        assert_eq!(edited.divergences, ["paragraph.txt: not byte-identical"]);
+        for dir in [a, b, report.replay.unwrap(), edited.replay.unwrap()] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn verify_cross_checks_result_and_proof_json() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=4"].iter().map(|s| s.to_string()).collect();
+        let dir = run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        let report = crate::verify::verify_trace_report(&trace);
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.artifacts_checked, ["result.json", "proof.json"]);
+
+        let (result_path, proof_path) = (dir.join("result.json"), dir.join("proof.json"));
+        let (result, proof) = (fs::read_to_string(&result_path).unwrap(), fs::read_to_string(&proof_path).unwrap());
+        let mut forged: JsonValue = serde_json::from_str(&result).unwrap();
+        forged["sample"][1] = json!("1/1000");
+        fs::write(&result_path, forged.to_string()).unwrap();
+        let report = crate::verify::verify_trace_report(&trace);
+        assert!(!report.valid);
+        let m = report.artifact_mismatch.unwrap();
+        assert_eq!((m.file.as_str(), m.field.as_str()), ("result.json", "sample[1]"));
+
+        fs::write(&result_path, &result).unwrap();
+        fs::write(&proof_path, proof.replace("MASK_BIT bit=2 val=1", "MASK_BIT bit=2 val=0")).unwrap();
+        let m = crate::verify::verify_trace_report(&trace).artifact_mismatch.unwrap();
+        assert_eq!((m.file.as_str(), m.field.as_str()), ("proof.json", "ops_in[1]"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blake3_runs_record_their_backend_and_replay() {
+        let ops: Vec<String> = ["SELECT_UNIVERSE universe=QE n=0 hash=blake3", "MASK_BIT bit=2 val=1", "RETURN_SET include_proofs=true"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let dir = run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let text = fs::read_to_string(dir.join("trace.ndjson")).unwrap();
+        assert!(text.lines().all(|l| l.contains("\"hash\":\"blake3\"")));
+        let first: JsonValue = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first["universe_root"].as_str(), crate::commitments::pinned_root("QE@blake3"));
+        let report = crate::verify::verify_trace_report(&dir.join("trace.ndjson"));
+        assert!(report.valid, "{:?}", report);
+        assert!(report.proofs_checked.is_some_and(|n| n > 0));
+        assert!(replay_run(&dir).unwrap().reproduced);
+
+        // the same records read as SHA-256 no longer verify
+        fs::write(dir.join("trace.ndjson"), text.replace(",\"hash\":\"blake3\"", "").replace(" hash=blake3", "")).unwrap();
+        assert!(!crate::verify::verify_trace_report(&dir.join("trace.ndjson")).valid);
+        fs::remove_dir_all(dir).unwrap();
+
+        let late = vec!["LOAD 1/3".to_string(), "SELECT_UNIVERSE universe=QE n=0 hash=blake3".to_string()];
+        assert!(run_trace_and_write(&late, None, false).unwrap_err().to_string().contains("first op"));
+    }
+
+    #[test]
+    fn executor_steps_match_a_written_run() {
+        let ops: Vec<String> = ["SELECT_UNIVERSE universe=QE n=0", "MASK_BIT bit=2 val=1", "MASK_BIT bit=0 val=0"].iter().map(|s| s.to_string()).collect();
+        let dir = run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let written: Vec<String> = fs::read_to_string(dir.join("trace.ndjson")).unwrap().lines().map(str::to_string).collect();
+        fs::remove_dir_all(dir).unwrap();
+
+        let mut exec = Executor::new("QE").unwrap();
+        let full = exec.state().count;
+        for (i, op) in ops.iter().enumerate().skip(1) {
+            let rec = exec.apply(op.parse().unwrap()).unwrap();
+            assert_eq!(serde_json::to_string(&rec).unwrap(), written[i]);
+            assert_eq!(exec.state().chain_hash, rec.step_digest);
+        }
+        let state = exec.state();
+        assert!(state.count < full && state.count == exec.elements().len());
+        assert_eq!((state.steps, state.constraint_mask), (3, 0b101));
+        assert!(exec.elements().iter().all(|e| parse_frac(e).is_some()));
+
+        // stopping early still leaves a replayable prefix
+        let mut early = Executor::new("QE").unwrap();
+        early.apply("MASK_BIT bit=2 val=1".parse().unwrap()).unwrap();
+        let res = early.finalize().unwrap();
+        assert!(res.valid && res.artifacts_path.is_none());
+        assert_eq!(res.constraint_mask, 0b100);
+    }
+
+    #[test]
+    fn observers_see_every_step_and_the_finish() {
+        use std::sync::{Arc, Mutex};
+        struct Log(Arc<Mutex<Vec<String>>>);
+        impl ExecObserver for Log {
+            fn on_step(&mut self, rec: &StepRecord) {
+                self.0.lock().unwrap().push(format!("{} {}", rec.op, rec.post.count));
+            }
+            fn on_finish(&mut self, result: &ExecutionResult) {
+                self.0.lock().unwrap().push(format!("done {}", result.final_count));
+            }
+        }
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut exec = Executor::new("QE").unwrap();
+        exec.observe(Log(log.clone()));
+        let a = exec.apply("MASK_BIT bit=2 val=1".parse().unwrap()).unwrap().post.count;
+        let b = exec.apply("MASK_BIT bit=0 val=0".parse().unwrap()).unwrap().post.count;
+        let dir = write_artifacts(exec, None, &OutputConfig::default()).unwrap().artifacts_path.unwrap();
+        let want = [format!("SET_BIT {}", a), format!("SET_BIT {}", b), format!("done {}", b)];
+        assert_eq!(*log.lock().unwrap(), want);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn in_memory_runs_write_nothing() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=3"].iter().map(|s| s.to_string()).collect();
+        let (records, res) = execute_in_memory(&ops).unwrap();
+        assert!(res.valid && res.artifacts_path.is_none());
+        assert_eq!(res.run_id, ops_run_id(&ops.iter().map(|o| o.parse::<Op>().unwrap().to_string()).collect::<Vec<_>>()));
+
+        let written = run_trace_and_write(&ops, None, false).unwrap();
+        let dir = written.artifacts_path.unwrap();
+        let lines: Vec<String> = records.iter().map(|r| serde_json::to_string(r).unwrap()).collect();
+        assert_eq!(lines.join("\n") + "\n", fs::read_to_string(dir.join("trace.ndjson")).unwrap());
+        assert_eq!((res.final_count, res.witness), (written.final_count, written.witness));
+        fs::remove_dir_all(dir).unwrap();
+        assert!(execute_in_memory(&["MASK_BIT bit=99".to_string()]).is_err());
+    }
+
+    #[test]
+    fn results_carry_steps_sample_and_artifact_digests() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=3"].iter().map(|s| s.to_string()).collect();
+        let written = run_trace_and_write(&ops, None, false).unwrap();
+        let dir = written.artifacts_path.clone().unwrap();
+        let doc: RunResult = serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!((&written.chain_hash, &written.sample), (&doc.chain_hash, &doc.sample));
+        assert_eq!(written.document.as_ref().map(|d| d.count), Some(doc.count));
+
+        let steps: Vec<(&str, usize, usize)> = written.steps.iter().map(|m| (m.op.as_str(), m.count_before, m.count_after)).collect();
+        assert_eq!(steps.len(), 3);
+        assert_eq!((steps[1].0, steps[1].1, steps[1].2), ("SET_BIT", steps[0].2, written.final_count));
+        for name in ["trace.ndjson", "proof.json", "result.json", "paragraph.txt"] {
+            assert_eq!(written.artifact_digests[name], hex32(sha256_bytes(&fs::read(dir.join(name)).unwrap())), "{}", name);
+        }
+        assert!(!written.artifact_digests.contains_key("deltas.ndjson"));
+
+        let (_, mem) = execute_in_memory(&ops).unwrap();
+        assert_eq!((mem.chain_hash, mem.sample, mem.steps.len()), (written.chain_hash, written.sample, 3));
+        assert!(mem.artifact_digests.is_empty() && mem.document.is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,179 @@
+//! Step-by-step narrative of an executed trace.
+//!
+//! Reads a run's `trace.ndjson` and renders each step as a sentence: what the
+//! op does (SET_BIT steps are spelled out with the active universe's bit
+//! legend), how the set count moved, and finally why the witness was chosen.
+//! The replay verdict from `verify` is appended, so the narrative never claims
+//! more than the trace proves.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+use crate::verify::verify_trace_report;
+
+/// The 7-bit predicate legend of a universe, if SET_BIT filters on one there.
+fn legend_for(universe: &str) -> Option<[&'static str; 7]> {
+    let u = universe.to_ascii_uppercase();
+    if u == "QE" {
+        Some(crate::semtrace::bit_legend())
+    } else if u == "GE" {
+        Some(crate::semtrace::bit_legend_geom())
+    } else if crate::geom::is_quad_universe(&u) {
+        Some(crate::semtrace::bit_legend_quad())
+    } else if crate::geom::is_tetra_universe(&u) {
+        Some(crate::semtrace::bit_legend_tetra())
+    } else if crate::lattice::is_lattice_universe(&u) {
+        Some(crate::lattice::bit_legend())
+    } else if crate::group::is_group_universe(&u) {
+        Some(crate::group::bit_legend())
+    } else if crate::subsets::is_subsets_universe(&u) {
+        Some(crate::subsets::bit_legend())
+    } else {
+        None
+    }
+}
+
+/// Argument value as bare text (strings unquoted).
+fn arg(args: &JsonValue, key: &str) -> String {
+    match args.get(key) {
+        Some(JsonValue::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => "?".to_string(),
+    }
+}
+
+/// What one op does, in words.
+fn describe(op: &str, args: &JsonValue, universe: &str) -> String {
+    match op {
+        "START_ELEM" => format!("Start from {} in the {} universe", arg(args, "elem"), universe),
+        "SELECT_UNIVERSE" => format!("Select the {} universe (n={})", arg(args, "universe"), arg(args, "n")),
+        "SET_BIT" => {
+            let i = args["i"].as_u64().unwrap_or(u64::MAX);
+            let truth = if args["b"].as_u64() == Some(1) { "true" } else { "false" };
+            match legend_for(universe).and_then(|l| l.get(i as usize).copied()) {
+                Some(label) => format!("Keep elements where bit {} ({}) is {}", i, label, truth),
+                None => format!("Keep elements whose signature bit {} is {}", i, arg(args, "b")),
+            }
+        }
+        "WITNESS_NEAREST" | "WITNESS_ALL_TIES" => {
+            format!("Pick the element nearest {} by {}", arg(args, "target_elem"), arg(args, "metric"))
+        }
+        "TOPK" => format!("Keep the {} elements nearest {}", arg(args, "k"), arg(args, "target_elem")),
+        "RETURN_SET" => format!("Return up to {} elements", arg(args, "max_items")),
+        _ => {
+            let parts: Vec<String> = args
+                .as_object()
+                .map(|m| m.keys().map(|k| format!("{}={}", k, arg(args, k))).collect())
+                .unwrap_or_default();
+            if parts.is_empty() {
+                format!("Apply {}", op)
+            } else {
+                format!("Apply {} with {}", op, parts.join(" "))
+            }
+        }
+    }
+}
+
+/// Render the narrative for a trace.ndjson (or a run directory holding one),
+/// as plain text or Markdown.
+pub fn explain_trace(path: &Path, markdown: bool) -> Result<String> {
+    let path = if path.is_dir() { path.join("trace.ndjson") } else { path.to_path_buf() };
+    let text = fs::read_to_string(&path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+    let steps: Vec<JsonValue> = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| anyhow!("trace line {}: {}", i + 1, e)))
+        .collect::<Result<_>>()?;
+    if steps.is_empty() {
+        return Err(anyhow!("{} has no steps", path.display()));
+    }
+
+    let mut out = Vec::new();
+    if markdown {
+        out.push(format!("# Explanation of `{}`", path.display()));
+        out.push(String::new());
+    }
+    let mut universe = "QE".to_string();
+    let mut nearest: Option<(String, String)> = None;
+    for step in &steps {
+        let op = step["op"].as_str().unwrap_or("?");
+        let args = &step["args"];
+        match op {
+            "SELECT_UNIVERSE" => universe = arg(args, "universe").to_ascii_uppercase(),
+            "START_ELEM" if arg(args, "elem").contains(',') => universe = "GE".to_string(),
+            "WITNESS_NEAREST" | "WITNESS_ALL_TIES" | "TOPK" => {
+                let metric = if args.get("metric").is_some() { arg(args, "metric") } else { "distance".to_string() };
+                nearest = Some((arg(args, "target_elem"), metric));
+            }
+            _ => {}
+        }
+        let (pre, post) = (&step["pre"]["count"], &step["post"]["count"]);
+        let what = describe(op, args, &universe);
+        out.push(if markdown {
+            format!("{}. **{}** — {} (count {} → {})", step["step"], op, what, pre, post)
+        } else {
+            format!("Step {}: {} [{}] (count {} -> {})", step["step"], what, op, pre, post)
+        });
+    }
+
+    let last = &steps[steps.len() - 1]["post"];
+    let count = &last["count"];
+    let answer = match (last["witness"].as_str(), nearest) {
+        (Some(w), Some((target, metric))) => {
+            let mut s = format!(
+                "The answer is {}: of the {} elements that satisfy every step, it is the nearest to {} by {}.",
+                w, count, target, metric
+            );
+            if let Some(ties) = last["witness_ties"].as_array().filter(|t| t.len() > 1) {
+                s.push_str(&format!(" It ties with {} others at the same distance.", ties.len() - 1));
+            }
+            s
+        }
+        (Some(w), None) => format!("{} elements remain; the witness is {}.", count, w),
+        (None, _) => format!("{} elements remain and no witness was selected.", count),
+    };
+    let report = verify_trace_report(&path);
+    let verdict = match (&report.chain_hash, &report.reason) {
+        (Some(h), _) => format!("Replay verification: valid over {} steps (chain {}).", report.steps_checked, h),
+        (None, Some(r)) => format!("Replay verification: FAILED — {}.", r),
+        (None, None) => "Replay verification: FAILED.".to_string(),
+    };
+    out.push(String::new());
+    if markdown {
+        out.push(format!("**Answer.** {}", answer));
+        out.push(String::new());
+        out.push(format!("_{}_", verdict));
+    } else {
+        out.push(answer);
+        out.push(verdict);
+    }
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrates_bits_counts_and_witness() {
+        let ops: Vec<String> = ["LOAD 1/4", "MASK_BIT bit=2 val=1", "WITNESS_NEAREST target_elem=1/4 metric=ABS_DIFF"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let r = crate::exec::run_trace_and_write(&ops, None, false).unwrap();
+        let dir = r.artifacts_path.unwrap();
+
+        let text = explain_trace(&dir, false).unwrap();
+        assert!(text.contains("bit 2 (den<=6)"), "{}", text);
+        assert!(text.contains(&format!("count 48927 -> {}", r.final_count)), "{}", text);
+        assert!(text.contains(&format!("The answer is {}", r.witness.unwrap())), "{}", text);
+        assert!(text.contains("Replay verification: valid over 3 steps"), "{}", text);
+
+        let md = explain_trace(&dir, true).unwrap();
+        assert!(md.starts_with("# Explanation of"));
+        assert!(md.contains("2. **WITNESS_NEAREST**"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Few-shot example bank for model-backed proposers.
+//!
+//! `examples/fewshot.jsonl` holds solved (query, ops) pairs across the QE, GE
+//! and BOOLFUN universes. For a new query, the examples with the highest
+//! character-trigram overlap are rendered into the prompt, so the model sees
+//! worked scripts of the same shape instead of only the fraction demo.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The bundled example bank, one JSON object per line.
+pub const BANK_JSONL: &str = include_str!("../examples/fewshot.jsonl");
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Example {
+    pub query: String,
+    pub ops: Vec<String>,
+}
+
+/// Parse a JSONL example bank; blank lines are skipped.
+pub fn load_bank(text: &str) -> Result<Vec<Example>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| anyhow!("example line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// The bundled bank.
+pub fn bank() -> Vec<Example> {
+    load_bank(BANK_JSONL).expect("bundled examples/fewshot.jsonl parses")
+}
+
+fn trigrams(s: &str) -> BTreeSet<Vec<char>> {
+    let words: Vec<String> = s.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return BTreeSet::new();
+    }
+    let padded: Vec<char> = format!("  {} ", words.join(" ")).chars().collect();
+    padded.windows(3).map(<[char]>::to_vec).collect()
+}
+
+/// Dice coefficient of the two strings' character trigram sets, in [0, 1].
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (ta, tb) = (trigrams(a), trigrams(b));
+    if ta.is_empty() || tb.is_empty() {
+        return 0.0;
+    }
+    2.0 * ta.intersection(&tb).count() as f64 / (ta.len() + tb.len()) as f64
+}
+
+/// The `k` examples most similar to `query`, best first; ties keep bank order.
+pub fn retrieve<'a>(bank: &'a [Example], query: &str, k: usize) -> Vec<&'a Example> {
+    let mut scored: Vec<(f64, &Example)> = bank.iter().map(|e| (similarity(query, &e.query), e)).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(k).map(|(_, e)| e).collect()
+}
+
+/// Examples as prompt text: each query followed by its op lines.
+pub fn render(examples: &[&Example]) -> String {
+    examples
+        .iter()
+        .map(|e| format!("Query: {}\n{}", e.query, e.ops.join("\n")))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_examples_are_grammatical() {
+        let bank = bank();
+        assert!(bank.len() >= 10);
+        for e in &bank {
+            for op in &e.ops {
+                crate::exec::validate_op(op).unwrap_or_else(|err| panic!("{:?}: {}: {}", e.query, op, err));
+            }
+        }
+        assert!(load_bank("{\"query\": \"q\"}").is_err());
+    }
+
+    #[test]
+    fn retrieval_matches_query_shape() {
+        let bank = bank();
+        let top = retrieve(&bank, "boolean functions of weight 6 near 0xF00F", 2);
+        assert!(top.iter().all(|e| e.ops[0].starts_with("SELECT_UNIVERSE universe=BOOLFUN")));
+        let top = retrieve(&bank, "triangles similar to 5,12,13", 1);
+        assert!(top[0].ops[0].starts_with("LOAD 3,4,5"));
+        assert!(similarity("abc", "abc") > 0.99);
+        assert_eq!(similarity("", ""), 0.0);
+        let text = render(&top);
+        assert!(text.starts_with("Query: triangles similar to 3,4,5"));
+    }
+}
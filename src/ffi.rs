@@ -0,0 +1,108 @@
+//! C ABI — execute and verify from non-Rust hosts, declared in `include/lnst.h`.
+//!
+//!   lnst_execute_json(trace)   JSON trace → {"records": [...], "result": {...}}
+//!   lnst_verify_ndjson(text)   trace.ndjson contents → verify::VerifyReport
+//!   lnst_string_free(s)        release a string either one returned
+//!
+//! Arguments and results are NUL-terminated UTF-8. Each call returns a JSON
+//! document the caller owns and must pass to `lnst_string_free`; a failure is
+//! returned as `{"error": "..."}` rather than as NULL, so a host never has to
+//! fetch the error separately. The documents are the ones the browser bindings
+//! (`wasm`) return, and nothing touches the filesystem. The exports are built
+//! under cargo feature `ffi`; build the library with
+//! `cargo rustc --lib --release --crate-type cdylib --features ffi`.
+
+#[cfg(feature = "ffi")]
+pub use exports::*;
+
+#[cfg(feature = "ffi")]
+mod exports {
+    use anyhow::{anyhow, Result};
+    use serde_json::json;
+    use std::ffi::{c_char, CStr, CString};
+
+    /// Read a C string argument.
+    ///
+    /// # Safety
+    /// `s` must be NULL or point to a NUL-terminated string that stays valid for
+    /// the call.
+    unsafe fn arg<'a>(s: *const c_char) -> Result<&'a str> {
+        if s.is_null() {
+            return Err(anyhow!("argument is NULL"));
+        }
+        CStr::from_ptr(s).to_str().map_err(|e| anyhow!("argument is not UTF-8: {}", e))
+    }
+
+    /// Hand a result to the caller as an owned C string, errors as `{"error": ...}`.
+    fn returned(out: Result<String>) -> *mut c_char {
+        let text = out.unwrap_or_else(|e| json!({ "error": e.to_string() }).to_string());
+        // JSON escapes every control character, so there is no interior NUL
+        CString::new(text).expect("JSON has no NUL bytes").into_raw()
+    }
+
+    /// Execute a JSON trace (op array or semtrace object) in memory.
+    ///
+    /// # Safety
+    /// `trace` must be NULL or a NUL-terminated string. The result must be freed
+    /// with `lnst_string_free`.
+    #[no_mangle]
+    pub unsafe extern "C" fn lnst_execute_json(trace: *const c_char) -> *mut c_char {
+        returned(arg(trace).and_then(crate::wasm::execute_json))
+    }
+
+    /// Replay trace.ndjson contents and report the first step that fails.
+    ///
+    /// # Safety
+    /// `text` must be NULL or a NUL-terminated string. The result must be freed
+    /// with `lnst_string_free`.
+    #[no_mangle]
+    pub unsafe extern "C" fn lnst_verify_ndjson(text: *const c_char) -> *mut c_char {
+        returned(arg(text).and_then(crate::wasm::verify_json))
+    }
+
+    /// Free a string returned by `lnst_execute_json` or `lnst_verify_ndjson`.
+    /// NULL is ignored.
+    ///
+    /// # Safety
+    /// `s` must be NULL or a pointer those functions returned, not yet freed.
+    #[no_mangle]
+    pub unsafe extern "C" fn lnst_string_free(s: *mut c_char) {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "ffi"))]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value as JsonValue};
+    use std::ffi::{c_char, CStr, CString};
+
+    fn call(f: unsafe extern "C" fn(*const c_char) -> *mut c_char, input: &str) -> JsonValue {
+        let input = CString::new(input).unwrap();
+        unsafe {
+            let out = f(input.as_ptr());
+            let doc = serde_json::from_str(CStr::from_ptr(out).to_str().unwrap()).unwrap();
+            lnst_string_free(out);
+            doc
+        }
+    }
+
+    #[test]
+    fn executes_and_verifies_through_the_c_abi() {
+        let out = call(lnst_execute_json, r#"["LOAD 13/37", "MASK_BIT bit=2 val=1"]"#);
+        assert_eq!(out["result"]["valid"], json!(true));
+        let ndjson: Vec<String> = out["records"].as_array().unwrap().iter().map(JsonValue::to_string).collect();
+        let report = call(lnst_verify_ndjson, &ndjson.join("\n"));
+        assert_eq!(report["chain_hash"], out["result"]["chain_hash"]);
+
+        assert!(call(lnst_execute_json, r#"["MASK_BIT bit=99"]"#)["error"].is_string());
+        unsafe {
+            let out = lnst_verify_ndjson(std::ptr::null());
+            assert!(CStr::from_ptr(out).to_str().unwrap().contains("NULL"));
+            lnst_string_free(out);
+            lnst_string_free(std::ptr::null_mut());
+        }
+    }
+}
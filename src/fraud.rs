@@ -0,0 +1,154 @@
+//! Fraud proofs: the one transition a trace gets wrong, packaged for a third party.
+//!
+//! When a trace fails to replay, `fraud_proof` states the disagreement —
+//! the pre-state digest, the disputed op and args, the post digest the trace
+//! claims and the one the replay computes — together with the records before
+//! the disputed one. Those records rebuild the pre-state, and their last
+//! step digest is `pre_chain`, so anyone holding the published chain can tell
+//! they are the trace's own. `check_fraud_proof` re-derives just the disputed
+//! transition: the prefix must replay to the stated pre-state, and the
+//! disputed record must not.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+use crate::digest::sha256_bytes;
+use crate::verify::replay_lines;
+
+/// `format` of a fraud proof written by this version.
+pub const FORMAT: &str = "lnst-fraud/1";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FraudProof {
+    pub format: String,
+    pub step: usize,
+    pub op: String,
+    pub args: JsonValue,
+    /// Set digest replayed just before the disputed step (None at step 0).
+    pub pre_set_digest: Option<String>,
+    /// Step digest of the record before the disputed one; the empty-input
+    /// SHA-256 at step 0.
+    pub pre_chain: String,
+    pub claimed_post_set_digest: Option<String>,
+    pub claimed_count: Option<u64>,
+    /// None when the op itself does not replay.
+    pub recomputed_post_set_digest: Option<String>,
+    pub recomputed_count: Option<usize>,
+    /// Why the disputed record does not replay.
+    pub reason: String,
+    /// The trace records before the disputed one, then the disputed record.
+    pub prefix: Vec<JsonValue>,
+    pub disputed: JsonValue,
+}
+
+fn lines_of(records: &[JsonValue]) -> Vec<String> {
+    records.iter().map(JsonValue::to_string).collect()
+}
+
+/// The fraud proof for `trace_path`'s first record that does not replay;
+/// None when the whole trace replays.
+pub fn fraud_proof(trace_path: &Path) -> Result<Option<FraudProof>> {
+    let txt = fs::read_to_string(trace_path).map_err(|e| anyhow!("reading {}: {}", trace_path.display(), e))?;
+    let lines: Vec<&str> = txt.lines().filter(|l| !l.trim().is_empty()).collect();
+    let replay = replay_lines(&lines);
+    if replay.valid {
+        return Ok(None);
+    }
+    let at = replay.steps.checked_sub(1).ok_or_else(|| anyhow!("the trace fails before its first record"))?;
+    let records: Vec<JsonValue> = lines[..=at].iter().map(|l| serde_json::from_str(l)).collect::<Result<_, _>>()?;
+    let (prefix, disputed) = (records[..at].to_vec(), records[at].clone());
+    let pre_chain = match prefix.last() {
+        Some(r) => r["step_digest"].as_str().unwrap_or_default().to_string(),
+        None => hex::encode(sha256_bytes(b"")),
+    };
+    let recomputed = replay.trail.get(at);
+    Ok(Some(FraudProof {
+        format: FORMAT.to_string(),
+        step: disputed["step"].as_u64().unwrap_or(at as u64) as usize,
+        op: disputed["op"].as_str().unwrap_or_default().to_string(),
+        args: disputed["args"].clone(),
+        pre_set_digest: at.checked_sub(1).and_then(|i| replay.trail.get(i)).map(|(_, d)| hex::encode(d)),
+        pre_chain,
+        claimed_post_set_digest: disputed["post"]["set_digest"].as_str().map(str::to_string),
+        claimed_count: disputed["post"]["count"].as_u64(),
+        recomputed_post_set_digest: recomputed.map(|(_, d)| hex::encode(d)),
+        recomputed_count: recomputed.map(|(c, _)| *c),
+        reason: replay.reason.unwrap_or_default(),
+        prefix,
+        disputed,
+    }))
+}
+
+/// Re-derive the disputed transition. Ok when the proof stands: its prefix
+/// replays to the stated pre-state and chain, and the disputed record, which
+/// must be the one stated, does not replay after it.
+pub fn check_fraud_proof(proof: &FraudProof) -> Result<()> {
+    if proof.format != FORMAT {
+        return Err(anyhow!("unsupported fraud proof format {:?}", proof.format));
+    }
+    let stated = (proof.disputed["op"].as_str(), &proof.disputed["args"], proof.disputed["post"]["set_digest"].as_str());
+    if stated != (Some(proof.op.as_str()), &proof.args, proof.claimed_post_set_digest.as_deref()) {
+        return Err(anyhow!("the disputed record is not the transition the proof states"));
+    }
+
+    let mut lines = lines_of(&proof.prefix);
+    let pre = replay_lines(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+    if !pre.valid {
+        return Err(anyhow!("the prefix does not replay: {}", pre.reason.unwrap_or_default()));
+    }
+    let pre_chain = proof.prefix.last().and_then(|r| r["step_digest"].as_str()).map(str::to_string);
+    if pre_chain.unwrap_or_else(|| hex::encode(sha256_bytes(b""))) != proof.pre_chain {
+        return Err(anyhow!("the prefix does not end at pre_chain"));
+    }
+    if pre.trail.last().map(|(_, d)| hex::encode(d)) != proof.pre_set_digest {
+        return Err(anyhow!("the prefix does not replay to pre_set_digest"));
+    }
+
+    lines.push(proof.disputed.to_string());
+    let all = replay_lines(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+    if all.valid {
+        return Err(anyhow!("step {} replays; there is no fraud", proof.step));
+    }
+    let recomputed = all.trail.get(proof.prefix.len());
+    if recomputed.map(|(_, d)| hex::encode(d)) != proof.recomputed_post_set_digest || recomputed.map(|(c, _)| *c) != proof.recomputed_count {
+        return Err(anyhow!("the recomputed post-state is not the one the proof states"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraud_proofs_rederive_the_disputed_step() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "MASK_BIT bit=0 val=0", "RETURN_SET"].iter().map(|s| s.to_string()).collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        assert!(fraud_proof(&trace).unwrap().is_none());
+
+        // claim a different set after step 2
+        let mut recs: Vec<JsonValue> = fs::read_to_string(&trace).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let real = recs[2]["post"]["set_digest"].clone();
+        recs[2]["post"]["set_digest"] = recs[1]["post"]["set_digest"].clone();
+        fs::write(&trace, lines_of(&recs).join("\n")).unwrap();
+        let proof = fraud_proof(&trace).unwrap().unwrap();
+        assert_eq!((proof.step, proof.prefix.len()), (2, 2));
+        assert_eq!(proof.pre_set_digest.as_deref(), recs[1]["post"]["set_digest"].as_str());
+        assert_eq!(proof.recomputed_post_set_digest.as_deref(), real.as_str());
+        check_fraud_proof(&proof).unwrap();
+
+        // a proof against a step that does replay does not stand
+        let mut honest = proof.clone();
+        honest.disputed["post"]["set_digest"] = real.clone();
+        honest.claimed_post_set_digest = real.as_str().map(str::to_string);
+        assert!(check_fraud_proof(&honest).unwrap_err().to_string().contains("no fraud"));
+        let mut moved = proof;
+        moved.pre_set_digest = Some(hex::encode([0u8; 32]));
+        assert!(check_fraud_proof(&moved).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
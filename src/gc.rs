@@ -0,0 +1,120 @@
+//! Artifact retention — prune old run directories and stale input traces.
+//!
+//! A run directory is any directory under the artifacts root holding a
+//! `trace.ndjson` or `result.json`; anything else there is left alone. Runs
+//! are ordered by modification time, newest first: the newest `keep_last` are
+//! kept, and with `keep_valid` so is every run whose verifier passed. Files in
+//! the traces directory are input copies overwritten by later runs, so one is
+//! stale once it is older than every run that remains.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    pub keep_last: usize,
+    pub keep_valid: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub kept_runs: usize,
+    pub remove_runs: Vec<PathBuf>,
+    pub remove_traces: Vec<PathBuf>,
+}
+
+fn modified(p: &Path) -> SystemTime {
+    fs::metadata(p).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+fn is_valid_run(dir: &Path) -> bool {
+    fs::read_to_string(dir.join("result.json"))
+        .ok()
+        .and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok())
+        .and_then(|v| v["verifier"]["valid"].as_bool())
+        .unwrap_or(false)
+}
+
+/// Decide what to remove without touching anything.
+pub fn plan(runs_root: &Path, traces_dir: &Path, policy: Policy) -> Result<Plan> {
+    let mut runs: Vec<(SystemTime, PathBuf)> = Vec::new();
+    if runs_root.is_dir() {
+        for entry in fs::read_dir(runs_root)? {
+            let path = entry?.path();
+            let is_run = path.join("trace.ndjson").is_file() || path.join("result.json").is_file();
+            if is_run && !fs::symlink_metadata(&path)?.file_type().is_symlink() {
+                runs.push((modified(&path), path));
+            }
+        }
+    }
+    runs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+
+    let mut plan = Plan::default();
+    let mut oldest_kept: Option<SystemTime> = None;
+    for (i, (time, dir)) in runs.into_iter().enumerate() {
+        if i < policy.keep_last || (policy.keep_valid && is_valid_run(&dir)) {
+            plan.kept_runs += 1;
+            oldest_kept = Some(oldest_kept.map_or(time, |t| t.min(time)));
+        } else {
+            plan.remove_runs.push(dir);
+        }
+    }
+    if traces_dir.is_dir() {
+        for entry in fs::read_dir(traces_dir)? {
+            let path = entry?.path();
+            if path.is_file() && oldest_kept.is_none_or(|t| modified(&path) < t) {
+                plan.remove_traces.push(path);
+            }
+        }
+        plan.remove_traces.sort();
+    }
+    Ok(plan)
+}
+
+/// Delete everything the plan lists.
+pub fn apply(plan: &Plan) -> Result<()> {
+    for dir in &plan.remove_runs {
+        fs::remove_dir_all(dir)?;
+    }
+    for file in &plan.remove_traces {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_newest_and_valid_runs() {
+        let root = std::env::temp_dir().join(format!("lnst_gc_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (runs, traces) = (root.join("runs"), root.join("traces"));
+        fs::create_dir_all(&traces).unwrap();
+        fs::write(traces.join("compiled_input.json"), "{}").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        for (name, valid) in [("a", true), ("b", false), ("c", false), ("d", false)] {
+            let dir = runs.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("result.json"), format!("{{\"verifier\": {{\"valid\": {}}}}}", valid)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        fs::create_dir_all(runs.join("notes")).unwrap();
+
+        let p = plan(&runs, &traces, Policy { keep_last: 2, keep_valid: true }).unwrap();
+        assert_eq!(p.kept_runs, 3);
+        assert_eq!(p.remove_runs, vec![runs.join("b")]);
+        assert_eq!(p.remove_traces, vec![traces.join("compiled_input.json")]);
+
+        let p = plan(&runs, &traces, Policy { keep_last: 2, keep_valid: false }).unwrap();
+        assert_eq!(p.remove_runs, vec![runs.join("b"), runs.join("a")]);
+        apply(&p).unwrap();
+        assert!(runs.join("c").is_dir() && !runs.join("a").exists() && runs.join("notes").is_dir());
+        assert!(!traces.join("compiled_input.json").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -65,6 +65,30 @@ impl Tri {
         let rhs = self.c * self.c;
         lhs.cmp(&rhs)
     }
+
+    /// 16·K² where K is the Heron area; exact in integers.
+    pub fn area_sq16(&self) -> i64 {
+        let (a, b, c) = (self.a as i64, self.b as i64, self.c as i64);
+        (a + b + c) * (-a + b + c) * (a - b + c) * (a + b - c)
+    }
+
+    /// The largest angle (opposite c) is at most `deg` degrees.
+    ///
+    /// cos C = (a²+b²−c²)/(2ab) is rational, and by Niven's theorem cos θ is rational for an
+    /// integer θ in 60..=180 only at 60, 90, 120 and 180, so those bounds are compared exactly
+    /// and every other bound can never be hit with equality.
+    pub fn max_angle_at_most(&self, deg: u32) -> bool {
+        let (a, b, c) = (self.a as i64, self.b as i64, self.c as i64);
+        let (num, den) = (a * a + b * b - c * c, 2 * a * b);
+        match deg {
+            0..=59 => false,
+            60 => 2 * num >= den,
+            90 => num >= 0,
+            120 => 2 * num >= -den,
+            180.. => true,
+            d => num as f64 / den as f64 > (d as f64).to_radians().cos(),
+        }
+    }
 }
 
 /// canonical ordering (like QE canonical_cmp)
@@ -103,12 +127,453 @@ pub fn build_ge(max_side: i32) -> Vec<Tri> {
 }
 
 /// distance for witness (L1)
-#[allow(dead_code)]
 pub fn tri_distance(a: &Tri, b: &Tri) -> i64 {
     ((a.a - b.a).abs() + (a.b - b.b).abs() + (a.c - b.c).abs()) as i64
 }
 
+/// Shape distance: L1 between side vectors normalized by perimeter, as an exact fraction
+/// Σ|x_i·P_t − t_i·P_x| / (P_x·P_t). Similar triangles are at distance 0.
+pub fn similarity_distance(x: &Tri, t: &Tri) -> (i64, i64) {
+    let (px, pt) = (x.perimeter() as i64, t.perimeter() as i64);
+    let num = [(x.a, t.a), (x.b, t.b), (x.c, t.c)]
+        .iter()
+        .map(|&(xi, ti)| (xi as i64 * pt - ti as i64 * px).abs())
+        .sum();
+    (num, px * pt)
+}
+
+/// Every element of `set` at minimal shape distance from `t`, in canonical order
+/// (so the first is the smallest triangle of the most similar shape).
+pub fn similarity_ties(set: &[Tri], t: &Tri) -> Vec<Tri> {
+    let closer = |x: (i64, i64), y: (i64, i64)| (x.0 * y.1).cmp(&(y.0 * x.1));
+    let best = set
+        .iter()
+        .map(|x| similarity_distance(x, t))
+        .min_by(|x, y| closer(*x, *y));
+    let mut ties: Vec<Tri> = set
+        .iter()
+        .copied()
+        .filter(|x| best.is_some_and(|b| closer(similarity_distance(x, t), b) == Ordering::Equal))
+        .collect();
+    ties.sort_by(canonical_cmp);
+    ties
+}
+
+/// Default perimeter bound for SELECT_UNIVERSE universe=QUAD n=0.
+pub const DEFAULT_QUAD_PERIMETER: i32 = 24;
+
+/// Cyclic quadrilateral with sides sorted a ≤ b ≤ c ≤ d.
+///
+/// A cyclic quadrilateral exists for any side multiset with d < a + b + c, and its
+/// area depends only on the multiset (Brahmagupta), so sides are stored sorted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Quad {
+    pub a: i32,
+    pub b: i32,
+    pub c: i32,
+    pub d: i32,
+}
+
+impl Quad {
+    pub fn new(a: i32, b: i32, c: i32, d: i32) -> Option<Self> {
+        let mut s = [a, b, c, d];
+        if s.iter().any(|&x| x <= 0) {
+            return None;
+        }
+        s.sort_unstable();
+
+        // polygon inequality: longest side shorter than the other three
+        if s[0] + s[1] + s[2] <= s[3] {
+            return None;
+        }
+
+        Some(Quad { a: s[0], b: s[1], c: s[2], d: s[3] })
+    }
+
+    /// canonical bytes (16 bytes, big-endian i32)
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        out[0..4].copy_from_slice(&self.a.to_be_bytes());
+        out[4..8].copy_from_slice(&self.b.to_be_bytes());
+        out[8..12].copy_from_slice(&self.c.to_be_bytes());
+        out[12..16].copy_from_slice(&self.d.to_be_bytes());
+        out
+    }
+
+    pub fn perimeter(&self) -> i32 {
+        self.a + self.b + self.c + self.d
+    }
+
+    /// 16·K² where K is the Brahmagupta area; exact in integers.
+    pub fn area_sq16(&self) -> i64 {
+        let (a, b, c, d) = (self.a as i64, self.b as i64, self.c as i64, self.d as i64);
+        (-a + b + c + d) * (a - b + c + d) * (a + b - c + d) * (a + b + c - d)
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.a == self.d
+    }
+
+    /// Two pairs of equal sides: arranged opposite, the cyclic quad is a rectangle.
+    pub fn is_rectangle_like(&self) -> bool {
+        self.a == self.b && self.c == self.d
+    }
+
+    pub fn has_equal_pair(&self) -> bool {
+        self.a == self.b || self.b == self.c || self.c == self.d
+    }
+
+    pub fn is_primitive(&self) -> bool {
+        gcd(gcd3(self.a, self.b, self.c), self.d) == 1
+    }
+
+    /// Area is rational: 16K² is a perfect square.
+    pub fn has_rational_area(&self) -> bool {
+        isqrt_exact(self.area_sq16()).is_some()
+    }
+
+    /// Area is an integer (Brahmagupta analogue of Heronian).
+    pub fn is_heronian(&self) -> bool {
+        isqrt_exact(self.area_sq16()).is_some_and(|r| r % 4 == 0)
+    }
+}
+
+/// canonical ordering for quads (same convention as Tri)
+pub fn canonical_cmp_quad(x: &Quad, y: &Quad) -> Ordering {
+    x.perimeter()
+        .cmp(&y.perimeter())
+        .then_with(|| x.a.cmp(&y.a))
+        .then_with(|| x.b.cmp(&y.b))
+        .then_with(|| x.c.cmp(&y.c))
+        .then_with(|| x.d.cmp(&y.d))
+}
+
+/// Build the QUAD universe: all cyclic quads with perimeter ≤ max_perimeter.
+pub fn build_quad(max_perimeter: i32) -> Vec<Quad> {
+    let mut out = Vec::new();
+
+    for a in 1..=max_perimeter / 4 {
+        for b in a..=(max_perimeter - a) / 3 {
+            for c in b..=(max_perimeter - a - b) / 2 {
+                for d in c..=(max_perimeter - a - b - c) {
+                    if let Some(q) = Quad::new(a, b, c, d) {
+                        out.push(q);
+                    }
+                }
+            }
+        }
+    }
+
+    out.sort_by(canonical_cmp_quad);
+    out
+}
+
+/// distance for quad witness (L1 over sorted sides)
+pub fn quad_distance(x: &Quad, y: &Quad) -> i64 {
+    ((x.a - y.a).abs() + (x.b - y.b).abs() + (x.c - y.c).abs() + (x.d - y.d).abs()) as i64
+}
+
+/// Parse "a,b,c,d" into a Quad.
+pub fn parse_quad(s: &str) -> Option<Quad> {
+    let parts: Vec<i32> = s
+        .split(',')
+        .map(|p| p.trim().parse::<i32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if parts.len() != 4 {
+        return None;
+    }
+    Quad::new(parts[0], parts[1], parts[2], parts[3])
+}
+
+pub fn quad_to_string(q: &Quad) -> String {
+    format!("{},{},{},{}", q.a, q.b, q.c, q.d)
+}
+
+pub fn is_quad_universe(u: &str) -> bool {
+    matches!(u.to_ascii_uppercase().as_str(), "QUAD" | "GQ" | "CYCLIC_QUAD")
+}
+
+/// Default max edge for SELECT_UNIVERSE universe=TETRA n=0.
+pub const DEFAULT_TETRA_EDGE: i32 = 4;
+
+/// Largest accepted max edge (6^6 edge vectors before canonical dedup).
+pub const MAX_TETRA_EDGE: i32 = 6;
+
+/// Edge index pairs for vertices 0..4, in the order AB, AC, AD, BC, BD, CD.
+const TETRA_EDGES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+/// Integer-edged tetrahedron, edges e = [AB, AC, AD, BC, BD, CD].
+///
+/// Stored in canonical form: the lexicographically least edge vector over all
+/// 24 relabelings of the vertices. Every face satisfies the strict triangle
+/// inequality; whether the six edges embed in 3D is the Cayley–Menger predicate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Tetra {
+    pub e: [i32; 6],
+}
+
+impl Tetra {
+    pub fn new(e: [i32; 6]) -> Option<Self> {
+        if e.iter().any(|&x| x <= 0) {
+            return None;
+        }
+        let t = Tetra { e };
+        if t.faces().iter().any(|f| f.is_none()) {
+            return None;
+        }
+        Some(Tetra { e: canonical_edges(&e) })
+    }
+
+    /// canonical bytes (24 bytes, big-endian i32)
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut out = [0u8; 24];
+        for (i, x) in self.e.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn edge_sum(&self) -> i32 {
+        self.e.iter().sum()
+    }
+
+    /// The four faces ABC, ABD, ACD, BCD.
+    pub fn faces(&self) -> [Option<Tri>; 4] {
+        let [ab, ac, ad, bc, bd, cd] = self.e;
+        [
+            Tri::new(ab, bc, ac),
+            Tri::new(ab, bd, ad),
+            Tri::new(ac, cd, ad),
+            Tri::new(bc, cd, bd),
+        ]
+    }
+
+    /// 288·V² via the Cayley–Menger determinant; exact in integers.
+    pub fn cayley_menger(&self) -> i128 {
+        let mut d2 = [[0i128; 4]; 4];
+        for (k, &(i, j)) in TETRA_EDGES.iter().enumerate() {
+            let x = self.e[k] as i128;
+            d2[i][j] = x * x;
+            d2[j][i] = x * x;
+        }
+        let mut m = [[0i128; 5]; 5];
+        for i in 0..5 {
+            for j in 0..5 {
+                m[i][j] = match (i, j) {
+                    (0, 0) => 0,
+                    (0, _) | (_, 0) => 1,
+                    _ => d2[i - 1][j - 1],
+                };
+            }
+        }
+        det5(&m)
+    }
+
+    /// Positive volume: the edges are realisable as a non-degenerate tetrahedron.
+    pub fn is_cm_valid(&self) -> bool {
+        self.cayley_menger() > 0
+    }
+
+    pub fn is_regular(&self) -> bool {
+        self.e.iter().all(|&x| x == self.e[0])
+    }
+
+    pub fn all_faces_isosceles(&self) -> bool {
+        self.faces().iter().flatten().all(|f| f.is_isosceles())
+    }
+
+    pub fn any_face_equilateral(&self) -> bool {
+        self.faces().iter().flatten().any(|f| f.is_equilateral())
+    }
+
+    /// Opposite edges pairwise equal (AB=CD, AC=BD, AD=BC).
+    pub fn is_disphenoid(&self) -> bool {
+        self.e[0] == self.e[5] && self.e[1] == self.e[4] && self.e[2] == self.e[3]
+    }
+
+    pub fn is_primitive(&self) -> bool {
+        self.e.iter().fold(0, |g, &x| gcd(g, x)) == 1
+    }
+}
+
+/// Apply a vertex relabeling to an edge vector.
+fn relabel(e: &[i32; 6], perm: &[usize; 4]) -> [i32; 6] {
+    let mut out = [0i32; 6];
+    for (k, &(i, j)) in TETRA_EDGES.iter().enumerate() {
+        let (pi, pj) = (perm[i].min(perm[j]), perm[i].max(perm[j]));
+        let idx = TETRA_EDGES.iter().position(|&p| p == (pi, pj)).unwrap_or(0);
+        out[idx] = e[k];
+    }
+    out
+}
+
+fn vertex_perms() -> Vec<[usize; 4]> {
+    let mut out = Vec::with_capacity(24);
+    for a in 0..4 {
+        for b in 0..4 {
+            for c in 0..4 {
+                for d in 0..4 {
+                    let p = [a, b, c, d];
+                    if (0..4).all(|v| p.contains(&v)) {
+                        out.push(p);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn canonical_edges(e: &[i32; 6]) -> [i32; 6] {
+    vertex_perms()
+        .iter()
+        .map(|p| relabel(e, p))
+        .min()
+        .unwrap_or(*e)
+}
+
+/// canonical ordering for tetrahedra: edge sum, then edge vector
+pub fn canonical_cmp_tetra(x: &Tetra, y: &Tetra) -> Ordering {
+    x.edge_sum()
+        .cmp(&y.edge_sum())
+        .then_with(|| x.e.cmp(&y.e))
+}
+
+/// Build the TETRA universe: canonical face-valid tetrahedra with edges ≤ max_edge.
+pub fn build_tetra(max_edge: i32) -> Vec<Tetra> {
+    let perms = vertex_perms();
+    let mut out = Vec::new();
+    let mut e = [1i32; 6];
+    loop {
+        let t = Tetra { e };
+        if t.faces().iter().all(|f| f.is_some()) && perms.iter().all(|p| relabel(&e, p) >= e) {
+            out.push(t);
+        }
+        // odometer increment over 1..=max_edge
+        let mut k = 5;
+        loop {
+            if e[k] < max_edge {
+                e[k] += 1;
+                break;
+            }
+            e[k] = 1;
+            if k == 0 {
+                out.sort_by(canonical_cmp_tetra);
+                return out;
+            }
+            k -= 1;
+        }
+    }
+}
+
+/// L1 edge-vector distance, minimised over vertex relabelings of y.
+pub fn tetra_distance(x: &Tetra, y: &Tetra) -> i64 {
+    vertex_perms()
+        .iter()
+        .map(|p| {
+            let ye = relabel(&y.e, p);
+            x.e.iter().zip(ye.iter()).map(|(a, b)| (a - b).abs() as i64).sum::<i64>()
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+/// Parse "AB,AC,AD,BC,BD,CD" into a Tetra.
+pub fn parse_tetra(s: &str) -> Option<Tetra> {
+    let parts: Vec<i32> = s
+        .split(',')
+        .map(|p| p.trim().parse::<i32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let e: [i32; 6] = parts.try_into().ok()?;
+    Tetra::new(e)
+}
+
+pub fn tetra_to_string(t: &Tetra) -> String {
+    let parts: Vec<String> = t.e.iter().map(|x| x.to_string()).collect();
+    parts.join(",")
+}
+
+pub fn is_tetra_universe(u: &str) -> bool {
+    matches!(u.to_ascii_uppercase().as_str(), "TETRA" | "GT" | "TETRAHEDRON")
+}
+
+fn det5(m: &[[i128; 5]; 5]) -> i128 {
+    // Laplace expansion along the first row; 5x5 is small enough to stay exact.
+    fn det(m: &[Vec<i128>]) -> i128 {
+        let n = m.len();
+        if n == 1 {
+            return m[0][0];
+        }
+        let mut acc = 0i128;
+        for col in 0..n {
+            if m[0][col] == 0 {
+                continue;
+            }
+            let minor: Vec<Vec<i128>> = m[1..]
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != col)
+                        .map(|(_, v)| *v)
+                        .collect()
+                })
+                .collect();
+            let sign = if col % 2 == 0 { 1 } else { -1 };
+            acc += sign * m[0][col] * det(&minor);
+        }
+        acc
+    }
+    let rows: Vec<Vec<i128>> = m.iter().map(|r| r.to_vec()).collect();
+    det(&rows)
+}
+
+/// k triangles nearest to target by L1 side distance; ties by canonical order.
+pub fn topk_tri(set: &[Tri], target: &Tri, k: usize) -> Vec<Tri> {
+    let mut v: Vec<Tri> = set.to_vec();
+    v.sort_by(|x, y| {
+        tri_distance(x, target)
+            .cmp(&tri_distance(y, target))
+            .then_with(|| canonical_cmp(x, y))
+    });
+    v.truncate(k);
+    v
+}
+
+/// Parse "a,b,c" into a Tri.
+pub fn parse_tri(s: &str) -> Option<Tri> {
+    let parts: Vec<i32> = s
+        .split(',')
+        .map(|p| p.trim().parse::<i32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if parts.len() != 3 {
+        return None;
+    }
+    Tri::new(parts[0], parts[1], parts[2])
+}
+
 /// helpers
+fn isqrt_exact(n: i64) -> Option<i64> {
+    if n < 0 {
+        return None;
+    }
+    let mut r = (n as f64).sqrt() as i64;
+    while r * r > n {
+        r -= 1;
+    }
+    while (r + 1) * (r + 1) <= n {
+        r += 1;
+    }
+    if r * r == n {
+        Some(r)
+    } else {
+        None
+    }
+}
+
 fn gcd(mut a: i32, mut b: i32) -> i32 {
     while b != 0 {
         let r = a % b;
@@ -125,3 +590,91 @@ fn gcd(mut a: i32, mut b: i32) -> i32 {
 fn gcd3(a: i32, b: i32, c: i32) -> i32 {
     gcd(gcd(a, b), c)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_brahmagupta_area() {
+        // 3x4 rectangle: K = 12, 16K² = 2304
+        let q = Quad::new(4, 3, 4, 3).unwrap();
+        assert_eq!((q.a, q.b, q.c, q.d), (3, 3, 4, 4));
+        assert_eq!(q.area_sq16(), 2304);
+        assert!(q.is_rectangle_like() && q.is_heronian() && q.is_primitive());
+        // unit square: K = 1
+        let sq = Quad::new(1, 1, 1, 1).unwrap();
+        assert!(sq.is_square() && sq.is_heronian());
+        // 1,1,1,2: 16K² = 27, irrational area
+        let t = Quad::new(1, 1, 1, 2).unwrap();
+        assert_eq!(t.area_sq16(), 27);
+        assert!(!t.has_rational_area());
+        assert!(Quad::new(1, 1, 1, 3).is_none());
+    }
+
+    #[test]
+    fn quad_universe_bounds() {
+        let u = build_quad(8);
+        assert_eq!(u[0], Quad::new(1, 1, 1, 1).unwrap());
+        assert!(u.iter().all(|q| q.perimeter() <= 8));
+        // perimeter 4..=8 multisets with d < a+b+c
+        assert_eq!(u.len(), 8);
+        assert_eq!(parse_quad("2,1,2,1"), Quad::new(1, 1, 2, 2));
+        assert_eq!(quad_to_string(&u[0]), "1,1,1,1");
+    }
+
+    #[test]
+    fn tetra_cayley_menger() {
+        // regular unit tetrahedron: V² = 1/72, so 288V² = 4
+        let r = Tetra::new([1; 6]).unwrap();
+        assert_eq!(r.cayley_menger(), 4);
+        assert!(r.is_regular() && r.is_disphenoid() && r.is_cm_valid());
+        // every face is 2,2,3, but C and D sit at most 2·√1.75 ≈ 2.65 apart,
+        // so CD = 3 cannot close up in 3D
+        let flat = Tetra::new([3, 2, 2, 2, 2, 3]).unwrap();
+        assert!(flat.all_faces_isosceles());
+        assert!(!flat.is_cm_valid());
+        assert!(Tetra::new([1, 1, 3, 1, 1, 1]).is_none());
+    }
+
+    #[test]
+    fn tetra_canonical_and_distance() {
+        let a = Tetra::new([3, 2, 2, 2, 2, 2]).unwrap();
+        let b = Tetra::new([2, 2, 2, 2, 2, 3]).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(tetra_to_string(&a), "2,2,2,2,2,3");
+        assert_eq!(tetra_distance(&a, &Tetra::new([2; 6]).unwrap()), 1);
+        let u = build_tetra(2);
+        assert_eq!(u[0], Tetra::new([1; 6]).unwrap());
+        assert!(u.iter().all(|t| t.e.iter().all(|&x| x <= 2)));
+        assert_eq!(parse_tetra("3,2,2,2,2,2"), Some(a));
+    }
+
+    #[test]
+    fn similarity_prefers_shape_over_size() {
+        let set = build_ge(20);
+        let t = Tri::new(30, 40, 50).unwrap();
+        let ties = similarity_ties(&set, &t);
+        assert_eq!(ties[0], Tri::new(3, 4, 5).unwrap());
+        assert!(ties.iter().all(|x| similarity_distance(x, &t).0 == 0));
+        assert_eq!(ties.len(), 4); // 3,4,5 … 12,16,20
+        // no exact copy available: nearest shape is still unique and deterministic
+        let odd = similarity_ties(&[Tri::new(2, 3, 4).unwrap(), Tri::new(4, 4, 5).unwrap()], &Tri::new(3, 3, 4).unwrap());
+        assert_eq!(odd, vec![Tri::new(4, 4, 5).unwrap()]);
+    }
+
+    #[test]
+    fn heron_area_and_max_angle() {
+        let t = Tri::new(3, 4, 5).unwrap();
+        assert_eq!(t.area_sq16(), 16 * 36);
+        assert!(t.max_angle_at_most(90));
+        assert!(!t.max_angle_at_most(89));
+        let eq = Tri::new(5, 5, 5).unwrap();
+        assert!(eq.max_angle_at_most(60));
+        assert!(!eq.max_angle_at_most(59));
+        // 3,5,7 has a 120° angle exactly
+        let obtuse = Tri::new(3, 5, 7).unwrap();
+        assert!(obtuse.max_angle_at_most(120));
+        assert!(!obtuse.max_angle_at_most(119));
+    }
+}
@@ -0,0 +1,338 @@
+//! Group universe (G) — elements of a small permutation group.
+//!
+//! Selected with `SELECT_UNIVERSE universe=GROUP group=S_4` (or `group=D_8`).
+//!   S_n  symmetric group on n points, n ≤ 6, generated by adjacent
+//!        transpositions (i i+1); word length = inversion count
+//!   D_n  dihedral group of the regular n-gon (order 2n), 3 ≤ n ≤ 32,
+//!        generated by r: i ↦ i+1, r⁻¹, and s: i ↦ −i (mod n)
+//!
+//! Element syntax: one-line notation "[1,0,2,3]" (images of 0..n-1).
+//!
+//! Signature bits:
+//!   bit0  even             permutation parity is even
+//!   bit1  identity         order 1
+//!   bit2  involution       order 2
+//!   bit3  order_3          order 3
+//!   bit4  order_4          order 4
+//!   bit5  fixed_point_free no i with g(i) = i
+//!   bit6  central          commutes with every element of the group
+//!
+//! Conjugacy classes are computed within the selected group (not within S_n),
+//! so FILTER_CONJ over D_n matches the dihedral classes.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GroupKind {
+    Symmetric(u8),
+    Dihedral(u8),
+}
+
+impl GroupKind {
+    pub fn name(&self) -> String {
+        match self {
+            GroupKind::Symmetric(n) => format!("S_{}", n),
+            GroupKind::Dihedral(n) => format!("D_{}", n),
+        }
+    }
+
+    /// Number of points acted on.
+    pub fn degree(&self) -> u8 {
+        match self {
+            GroupKind::Symmetric(n) | GroupKind::Dihedral(n) => *n,
+        }
+    }
+
+    /// Fixed generating set used for word lengths.
+    pub fn generators(&self) -> Vec<Perm> {
+        match *self {
+            GroupKind::Symmetric(n) => (0..n.saturating_sub(1))
+                .map(|i| {
+                    let mut v: Vec<u8> = (0..n).collect();
+                    v.swap(i as usize, i as usize + 1);
+                    Perm(v)
+                })
+                .collect(),
+            GroupKind::Dihedral(n) => {
+                let r = Perm((0..n).map(|i| (i + 1) % n).collect());
+                let r_inv = Perm((0..n).map(|i| (i + n - 1) % n).collect());
+                let s = Perm((0..n).map(|i| (n - i) % n).collect());
+                vec![r, r_inv, s]
+            }
+        }
+    }
+}
+
+/// Parse "S_4", "S4", "D_8", "d8".
+pub fn parse_group_name(s: &str) -> Option<GroupKind> {
+    let t = s.trim().to_ascii_uppercase();
+    let (head, rest) = t.split_at(1.min(t.len()));
+    let n: u8 = rest.trim_start_matches('_').parse().ok()?;
+    match head {
+        "S" if (1..=6).contains(&n) => Some(GroupKind::Symmetric(n)),
+        "D" if (3..=32).contains(&n) => Some(GroupKind::Dihedral(n)),
+        _ => None,
+    }
+}
+
+/// Permutation in one-line notation: self.0[i] is the image of i.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Perm(pub Vec<u8>);
+
+impl Perm {
+    pub fn identity(n: u8) -> Self {
+        Perm((0..n).collect())
+    }
+
+    pub fn degree(&self) -> u8 {
+        self.0.len() as u8
+    }
+
+    /// Canonical bytes for hashing/merkle: [n] followed by the n images.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + self.0.len());
+        out.push(self.degree());
+        out.extend_from_slice(&self.0);
+        out
+    }
+
+    /// Composition (self ∘ other)(i) = self(other(i)).
+    pub fn compose(&self, other: &Perm) -> Perm {
+        Perm(other.0.iter().map(|&i| self.0[i as usize]).collect())
+    }
+
+    pub fn inverse(&self) -> Perm {
+        let mut v = vec![0u8; self.0.len()];
+        for (i, &j) in self.0.iter().enumerate() {
+            v[j as usize] = i as u8;
+        }
+        Perm(v)
+    }
+
+    /// Cycle lengths, sorted descending (fixed points included).
+    pub fn cycle_type(&self) -> Vec<u8> {
+        let n = self.0.len();
+        let mut seen = vec![false; n];
+        let mut out = Vec::new();
+        for start in 0..n {
+            if seen[start] {
+                continue;
+            }
+            let mut len = 0u8;
+            let mut i = start;
+            while !seen[i] {
+                seen[i] = true;
+                i = self.0[i] as usize;
+                len += 1;
+            }
+            out.push(len);
+        }
+        out.sort_unstable_by(|a, b| b.cmp(a));
+        out
+    }
+
+    pub fn order(&self) -> u32 {
+        self.cycle_type()
+            .iter()
+            .fold(1u32, |acc, &l| lcm(acc, l as u32))
+    }
+
+    pub fn is_even(&self) -> bool {
+        let ct = self.cycle_type();
+        let transpositions: usize = ct.iter().map(|&l| l as usize - 1).sum();
+        transpositions.is_multiple_of(2)
+    }
+
+    pub fn is_fixed_point_free(&self) -> bool {
+        self.0.iter().enumerate().all(|(i, &j)| i as u8 != j)
+    }
+}
+
+/// Canonical total order: lexicographic on one-line notation.
+pub fn canonical_cmp(a: &Perm, b: &Perm) -> Ordering {
+    a.degree().cmp(&b.degree()).then_with(|| a.0.cmp(&b.0))
+}
+
+/// An enumerated group with word lengths over its fixed generating set.
+#[derive(Clone, Debug)]
+pub struct GroupUniverse {
+    pub kind: GroupKind,
+    /// Elements in canonical order.
+    pub elems: Vec<Perm>,
+    word_len: BTreeMap<Perm, u32>,
+}
+
+impl GroupUniverse {
+    /// Enumerate the group by breadth-first search over its Cayley graph.
+    pub fn build(kind: GroupKind) -> Self {
+        let gens = kind.generators();
+        let id = Perm::identity(kind.degree());
+        let mut word_len: BTreeMap<Perm, u32> = BTreeMap::new();
+        word_len.insert(id.clone(), 0);
+        let mut queue = VecDeque::from([id]);
+        while let Some(g) = queue.pop_front() {
+            let d = word_len[&g];
+            for s in &gens {
+                let h = g.compose(s);
+                if !word_len.contains_key(&h) {
+                    word_len.insert(h.clone(), d + 1);
+                    queue.push_back(h);
+                }
+            }
+        }
+        let mut elems: Vec<Perm> = word_len.keys().cloned().collect();
+        elems.sort_by(canonical_cmp);
+        GroupUniverse { kind, elems, word_len }
+    }
+
+    pub fn contains(&self, g: &Perm) -> bool {
+        self.word_len.contains_key(g)
+    }
+
+    /// Word length of g in the fixed generators (None if g is not in the group).
+    pub fn word_len(&self, g: &Perm) -> Option<u32> {
+        self.word_len.get(g).copied()
+    }
+
+    /// Cayley-graph distance: word length of a⁻¹b.
+    pub fn distance(&self, a: &Perm, b: &Perm) -> Option<u32> {
+        self.word_len(&a.inverse().compose(b))
+    }
+
+    pub fn is_central(&self, g: &Perm) -> bool {
+        self.elems.iter().all(|h| h.compose(g) == g.compose(h))
+    }
+
+    /// Canonical representative of g's conjugacy class: least h g h⁻¹ in canonical order.
+    pub fn class_rep(&self, g: &Perm) -> Perm {
+        self.elems
+            .iter()
+            .map(|h| h.compose(g).compose(&h.inverse()))
+            .min_by(canonical_cmp)
+            .unwrap_or_else(|| g.clone())
+    }
+
+    /// Compute signature bits for group predicates.
+    pub fn sig7(&self, g: &Perm) -> u8 {
+        let ord = g.order();
+        let preds = [
+            g.is_even(),
+            ord == 1,
+            ord == 2,
+            ord == 3,
+            ord == 4,
+            g.is_fixed_point_free(),
+            self.is_central(g),
+        ];
+        let mut bits: u8 = 0;
+        for (i, b) in preds.iter().enumerate() {
+            if *b {
+                bits |= 1u8 << i;
+            }
+        }
+        bits
+    }
+
+    /// k elements closest to target in the word metric; ties broken canonically.
+    pub fn topk(&self, set: &[Perm], target: &Perm, k: usize) -> Vec<Perm> {
+        let mut scored: Vec<(u32, &Perm)> = set
+            .iter()
+            .map(|g| (self.distance(target, g).unwrap_or(u32::MAX), g))
+            .collect();
+        scored.sort_by(|(da, a), (db, b)| da.cmp(db).then_with(|| canonical_cmp(a, b)));
+        scored.into_iter().take(k).map(|(_, g)| g.clone()).collect()
+    }
+}
+
+pub fn bit_legend() -> [&'static str; 7] {
+    [
+        "even",
+        "identity",
+        "involution",
+        "order_3",
+        "order_4",
+        "fixed_point_free",
+        "central",
+    ]
+}
+
+/// Parse one-line notation "[1,0,2,3]"; must be a permutation of 0..n-1.
+pub fn parse_elem(s: &str) -> Option<Perm> {
+    let t = s.trim();
+    let t = t.strip_prefix('[')?.strip_suffix(']')?;
+    let v: Vec<u8> = t
+        .split(',')
+        .map(|p| p.trim().parse::<u8>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let mut seen = vec![false; v.len()];
+    for &i in &v {
+        if i as usize >= v.len() || seen[i as usize] {
+            return None;
+        }
+        seen[i as usize] = true;
+    }
+    Some(Perm(v))
+}
+
+pub fn perm_to_string(g: &Perm) -> String {
+    let parts: Vec<String> = g.0.iter().map(|i| i.to_string()).collect();
+    format!("[{}]", parts.join(","))
+}
+
+pub fn is_group_universe(u: &str) -> bool {
+    matches!(u.to_ascii_uppercase().as_str(), "GROUP" | "PERM" | "PERMGROUP")
+}
+
+fn lcm(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    a / x * b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_orders() {
+        assert_eq!(GroupUniverse::build(GroupKind::Symmetric(4)).elems.len(), 24);
+        assert_eq!(GroupUniverse::build(GroupKind::Symmetric(5)).elems.len(), 120);
+        assert_eq!(GroupUniverse::build(GroupKind::Dihedral(4)).elems.len(), 8);
+        assert_eq!(GroupUniverse::build(GroupKind::Dihedral(8)).elems.len(), 16);
+        assert_eq!(parse_group_name("s_4"), Some(GroupKind::Symmetric(4)));
+        assert_eq!(parse_group_name("D8"), Some(GroupKind::Dihedral(8)));
+        assert!(parse_group_name("S_9").is_none());
+    }
+
+    #[test]
+    fn symmetric_word_length_is_inversions() {
+        let g = GroupUniverse::build(GroupKind::Symmetric(4));
+        // longest element [3,2,1,0] has 6 inversions
+        assert_eq!(g.word_len(&parse_elem("[3,2,1,0]").unwrap()), Some(6));
+        assert_eq!(g.word_len(&parse_elem("[1,0,2,3]").unwrap()), Some(1));
+    }
+
+    #[test]
+    fn predicates_and_classes() {
+        let d4 = GroupUniverse::build(GroupKind::Dihedral(4));
+        // r^2 = [2,3,0,1] is the unique non-identity central element of D_4
+        let r2 = parse_elem("[2,3,0,1]").unwrap();
+        let central: Vec<&Perm> = d4.elems.iter().filter(|g| d4.is_central(g)).collect();
+        assert_eq!(central.len(), 2);
+        assert_eq!(d4.sig7(&r2) & 0b1000100, 0b1000100);
+        // S_4 classes follow cycle type: 5 classes
+        let s4 = GroupUniverse::build(GroupKind::Symmetric(4));
+        let mut reps: Vec<Perm> = s4.elems.iter().map(|g| s4.class_rep(g)).collect();
+        reps.sort();
+        reps.dedup();
+        assert_eq!(reps.len(), 5);
+        assert_eq!(perm_to_string(&r2), "[2,3,0,1]");
+        assert!(parse_elem("[0,0,1]").is_none());
+    }
+}
@@ -0,0 +1,162 @@
+//! gRPC mode — the HTTP server's propose / execute / verify, over tonic.
+//!
+//! The service is described by `proto/lnst.proto` (package `lnst.v1`) and its
+//! bindings are generated at build time under cargo feature `grpc`.
+//! `ExecuteStream` sends one `StepRecord` per line of the run's trace.ndjson
+//! and then the `ExecutionResult`. The executor runs a trace to completion
+//! before anything is streamed, so steps arrive in one burst. Runs yield to
+//! the runtime between steps and replays run off it, and both stop when the
+//! client goes away and its request is dropped.
+
+use anyhow::Result;
+
+#[cfg(feature = "grpc")]
+pub mod pb {
+    tonic::include_proto!("lnst.v1");
+}
+
+#[cfg(feature = "grpc")]
+mod service {
+    use anyhow::{anyhow, Result};
+    use serde_json::Value as JsonValue;
+    use std::fs;
+    use tonic::{Request, Response, Status};
+
+    use super::pb::{self, execute_event::Event, lnst_server::Lnst};
+    use crate::cancel::CancelToken;
+    use crate::query_proposer::Registry;
+
+    fn status(e: anyhow::Error) -> Status {
+        Status::invalid_argument(e.to_string())
+    }
+
+    /// Run on the blocking pool; the executor and proposers are synchronous.
+    async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T, Status> {
+        tokio::task::spawn_blocking(f).await.map_err(|e| Status::internal(e.to_string()))?.map_err(status)
+    }
+
+    pub(super) fn step_record(line: &JsonValue) -> pb::StepRecord {
+        pb::StepRecord {
+            step: line["step"].as_u64().unwrap_or(0),
+            op: line["op"].as_str().unwrap_or("").to_string(),
+            args_json: line["args"].to_string(),
+            pre_count: line["pre"]["count"].as_u64().unwrap_or(0),
+            post_count: line["post"]["count"].as_u64().unwrap_or(0),
+            witness: line["post"]["witness"].as_str().map(str::to_string),
+            step_digest: line["step_digest"].as_str().unwrap_or("").to_string(),
+        }
+    }
+
+    /// Execute `ops`, returning the recorded steps and the result. The run
+    /// yields between steps and stops once `cancel` fires.
+    pub(super) async fn run(ops: &[String], cancel: &CancelToken) -> Result<(Vec<pb::StepRecord>, pb::ExecutionResult)> {
+        let r = crate::exec::run_trace_and_write_async(ops, None, cancel).await?;
+        let dir = r.artifacts_path.as_ref().ok_or_else(|| anyhow!("run wrote no artifacts"))?;
+        let steps = fs::read_to_string(dir.join("trace.ndjson"))?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Ok(step_record(&serde_json::from_str(l)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((
+            steps,
+            pb::ExecutionResult {
+                valid: r.valid,
+                final_count: r.final_count as u64,
+                witness: r.witness.clone(),
+                run_id: r.run_id.clone(),
+                universe: r.universe.clone(),
+                chain_hash: r.chain_hash.clone(),
+                failed_assertion: r.failed_assertion.clone(),
+            },
+        ))
+    }
+
+    pub(super) struct Service;
+
+    #[tonic::async_trait]
+    impl Lnst for Service {
+        async fn propose(&self, req: Request<pb::ProposeRequest>) -> Result<Response<pb::Trace>, Status> {
+            let req = req.into_inner();
+            let ops = blocking(move || {
+                let name = if req.proposer.is_empty() { "compiler" } else { req.proposer.as_str() };
+                Ok(Registry::with_defaults().get(name)?.propose(&req.query)?.ops)
+            })
+            .await?;
+            Ok(Response::new(pb::Trace { ops }))
+        }
+
+        async fn execute(&self, req: Request<pb::Trace>) -> Result<Response<pb::ExecutionResult>, Status> {
+            let ops = req.into_inner().ops;
+            // tonic drops this future when the client goes away
+            let cancel = CancelToken::new();
+            let _guard = cancel.guard();
+            let (_, result) = run(&ops, &cancel).await.map_err(status)?;
+            Ok(Response::new(result))
+        }
+
+        type ExecuteStreamStream = tonic::codegen::tokio_stream::Iter<std::vec::IntoIter<Result<pb::ExecuteEvent, Status>>>;
+
+        async fn execute_stream(&self, req: Request<pb::Trace>) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+            let ops = req.into_inner().ops;
+            let cancel = CancelToken::new();
+            let _guard = cancel.guard();
+            let (steps, result) = run(&ops, &cancel).await.map_err(status)?;
+            let events: Vec<Result<pb::ExecuteEvent, Status>> = steps
+                .into_iter()
+                .map(Event::Step)
+                .chain([Event::Result(result)])
+                .map(|e| Ok(pb::ExecuteEvent { event: Some(e) }))
+                .collect();
+            Ok(Response::new(tonic::codegen::tokio_stream::iter(events)))
+        }
+
+        async fn verify(&self, req: Request<pb::VerifyRequest>) -> Result<Response<pb::VerifyResponse>, Status> {
+            let text = req.into_inner().trace_ndjson;
+            let cancel = CancelToken::new();
+            let _guard = cancel.guard();
+            let r = crate::verify::verify_async(text, &cancel).await.map_err(status)?;
+            Ok(Response::new(pb::VerifyResponse {
+                valid: r.valid,
+                steps_checked: r.steps_checked as u64,
+                chain_hash: r.chain_hash,
+                failed_step: r.failed_step.map(|s| s as u64),
+                failed_op: r.failed_op,
+                reason: r.reason,
+            }))
+        }
+    }
+}
+
+/// Serve the gRPC service on `addr` (e.g. "127.0.0.1:50051") until the process exits.
+#[cfg(feature = "grpc")]
+pub fn serve(addr: &str) -> Result<()> {
+    let addr = addr.parse().map_err(|e| anyhow::anyhow!("bad address {}: {}", addr, e))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(
+        tonic::transport::Server::builder()
+            .add_service(pb::lnst_server::LnstServer::new(service::Service))
+            .serve(addr),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+pub fn serve(_addr: &str) -> Result<()> {
+    Err(anyhow::anyhow!("built without the `grpc` feature"))
+}
+
+#[cfg(all(test, feature = "grpc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_streams_every_step_then_the_result() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1"].iter().map(|s| s.to_string()).collect();
+        let (steps, result) = crate::cancel::block_on(service::run(&ops, &crate::cancel::CancelToken::new())).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].op, "SET_BIT");
+        assert_eq!(steps[1].post_count, result.final_count);
+        assert!(result.valid && result.chain_hash.len() == 64);
+        let _ = std::fs::remove_dir_all(crate::exec::artifacts_root().unwrap().join(&result.run_id));
+    }
+}
@@ -0,0 +1,128 @@
+//! Query intent — which universe a free-text query is about.
+//!
+//! Keyword and element-shape rules decide first (`0xBEEF` → BOOLFUN, `3,4,5` →
+//! GE, `13/37` → QE, …). When no rule fires, the nearest example in the
+//! few-shot bank votes with its universe if it is similar enough; otherwise the
+//! query is treated as QE. Proposers use the intent to pick prompts and route
+//! non-fraction queries away from the fraction compiler.
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::fewshot;
+use crate::semtrace::Op;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Intent {
+    Qe,
+    Ge,
+    Boolfun,
+    Lattice,
+    Group,
+    Subsets,
+    Quad,
+    Tetra,
+}
+
+impl Intent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Intent::Qe => "QE",
+            Intent::Ge => "GE",
+            Intent::Boolfun => "BOOLFUN",
+            Intent::Lattice => "LATTICE",
+            Intent::Group => "GROUP",
+            Intent::Subsets => "SUBSETS",
+            Intent::Quad => "QUAD",
+            Intent::Tetra => "TETRA",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Intent> {
+        Some(match s.to_ascii_uppercase().as_str() {
+            "QE" => Intent::Qe,
+            "GE" => Intent::Ge,
+            "BOOLFUN" => Intent::Boolfun,
+            "LATTICE" => Intent::Lattice,
+            "GROUP" => Intent::Group,
+            "SUBSETS" => Intent::Subsets,
+            "QUAD" => Intent::Quad,
+            "TETRA" => Intent::Tetra,
+            _ => return None,
+        })
+    }
+
+    /// Universe an op script runs in, from its first op.
+    pub fn of_ops(ops: &[String]) -> Option<Intent> {
+        match ops.first()?.parse::<Op>().ok()? {
+            Op::SelectUniverse { universe, .. } => Intent::parse(&universe),
+            Op::StartElem { elem } => match elem.matches(',').count() {
+                0 if elem.contains('/') => Some(Intent::Qe),
+                2 => Some(Intent::Ge),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Classification {
+    pub intent: Intent,
+    /// "rules", "examples" (nearest few-shot example), or "default".
+    pub source: &'static str,
+    /// The matched keyword, or the nearest example's query.
+    pub evidence: String,
+}
+
+/// Minimum trigram similarity for the nearest example to decide the intent.
+pub const EXAMPLE_THRESHOLD: f64 = 0.35;
+
+const RULES: [(Intent, &str); 8] = [
+    (Intent::Boolfun, r"\b0x[0-9a-f]+\b|boolean|boolfun|truth[ -]table|\bnpn\b|nonlinearity"),
+    (Intent::Tetra, r"tetrahedr"),
+    (Intent::Quad, r"quadrilateral|\bquads?\b"),
+    (Intent::Ge, r"triangle|\b\d+\s*,\s*\d+\s*,\s*\d+\b"),
+    (Intent::Group, r"permutation|\bgroup\b|\bs_?\d\b"),
+    (Intent::Subsets, r"subsets?\b"),
+    (Intent::Lattice, r"lattice|grid point|\(\s*-?\d+\s*,\s*-?\d+\s*\)"),
+    (Intent::Qe, r"fraction|rational|denominator|-?\d+/\d+"),
+];
+
+pub fn classify(query: &str) -> Classification {
+    let q = query.to_lowercase();
+    for (intent, re) in RULES {
+        if let Some(m) = Regex::new(re).expect("intent rule compiles").find(&q) {
+            return Classification { intent, source: "rules", evidence: m.as_str().to_string() };
+        }
+    }
+    let bank = fewshot::bank();
+    if let Some(e) = fewshot::retrieve(&bank, query, 1).first() {
+        if fewshot::similarity(query, &e.query) >= EXAMPLE_THRESHOLD {
+            if let Some(intent) = Intent::of_ops(&e.ops) {
+                return Classification { intent, source: "examples", evidence: e.query.clone() };
+            }
+        }
+    }
+    Classification { intent: Intent::Qe, source: "default", evidence: String::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_then_examples_then_default() {
+        let c = classify("boolean functions on 4 inputs with weight 8");
+        assert_eq!((c.intent, c.source), (Intent::Boolfun, "rules"));
+        assert_eq!(classify("triangles similar to 3,4,5").intent, Intent::Ge);
+        assert_eq!(classify("fractions near 13/37").intent, Intent::Qe);
+        assert_eq!(classify("permutations of order 2 in S4").intent, Intent::Group);
+
+        let c = classify("one representative per class of 3-input functions");
+        assert_eq!((c.intent, c.source), (Intent::Boolfun, "examples"));
+        assert_eq!(classify("hello world").source, "default");
+        assert_eq!(serde_json::to_value(Intent::Boolfun).unwrap(), serde_json::json!("BOOLFUN"));
+    }
+}
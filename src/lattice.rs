@@ -0,0 +1,214 @@
+//! Lattice universe (L) — integer points (x,y) in the closed disk x²+y² ≤ R².
+//!
+//! Element syntax: "(x,y)" or "x,y"; selected with
+//! `SELECT_UNIVERSE universe=LATTICE n=R` (R defaults to 10 when n=0).
+//!
+//! Signature bits:
+//!   bit0  quadrant_1   x > 0, y > 0
+//!   bit1  quadrant_2   x < 0, y > 0
+//!   bit2  quadrant_3   x < 0, y < 0
+//!   bit3  quadrant_4   x > 0, y < 0
+//!   bit4  visible      gcd(|x|,|y|) = 1 (visible from the origin)
+//!   bit5  on_axis      x = 0 or y = 0
+//!   bit6  even         x + y even
+//!
+//! Witness metric: EUCLID_SQ, the exact squared Euclidean distance.
+
+use std::cmp::Ordering;
+
+pub const DEFAULT_RADIUS: i32 = 10;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Pt {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Pt {
+    /// Canonical bytes for hashing/merkle: fixed 8 bytes (i32 x, i32 y) big-endian.
+    pub fn canonical_bytes(&self) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        out[0..4].copy_from_slice(&self.x.to_be_bytes());
+        out[4..8].copy_from_slice(&self.y.to_be_bytes());
+        out
+    }
+
+    pub fn norm_sq(&self) -> i64 {
+        let x = self.x as i64;
+        let y = self.y as i64;
+        x * x + y * y
+    }
+
+    /// Quadrant 1..=4 (counter-clockwise from +x,+y); 0 for points on an axis.
+    pub fn quadrant(&self) -> u8 {
+        match (self.x.cmp(&0), self.y.cmp(&0)) {
+            (Ordering::Greater, Ordering::Greater) => 1,
+            (Ordering::Less, Ordering::Greater) => 2,
+            (Ordering::Less, Ordering::Less) => 3,
+            (Ordering::Greater, Ordering::Less) => 4,
+            _ => 0,
+        }
+    }
+
+    pub fn is_on_axis(&self) -> bool {
+        self.x == 0 || self.y == 0
+    }
+
+    /// Visible from the origin: no other lattice point lies strictly between.
+    pub fn is_visible(&self) -> bool {
+        gcd(self.x, self.y) == 1
+    }
+
+    pub fn is_even(&self) -> bool {
+        (self.x + self.y) % 2 == 0
+    }
+}
+
+/// Exact squared Euclidean distance.
+pub fn dist_sq(a: &Pt, b: &Pt) -> i64 {
+    let dx = (a.x - b.x) as i64;
+    let dy = (a.y - b.y) as i64;
+    dx * dx + dy * dy
+}
+
+/// Canonical total order: norm² ascending, then x ascending, then y ascending.
+pub fn canonical_cmp(a: &Pt, b: &Pt) -> Ordering {
+    a.norm_sq()
+        .cmp(&b.norm_sq())
+        .then_with(|| a.x.cmp(&b.x))
+        .then_with(|| a.y.cmp(&b.y))
+}
+
+/// Build the lattice disk of radius r (inclusive), canonically sorted.
+pub fn build_lattice(r: i32) -> Vec<Pt> {
+    let r2 = (r as i64) * (r as i64);
+    let mut v = Vec::new();
+    for x in -r..=r {
+        for y in -r..=r {
+            let p = Pt { x, y };
+            if p.norm_sq() <= r2 {
+                v.push(p);
+            }
+        }
+    }
+    v.sort_by(canonical_cmp);
+    v
+}
+
+/// Nearest point to target under EUCLID_SQ; ties broken by canonical order.
+pub fn witness_nearest(set: &[Pt], target: &Pt) -> Option<Pt> {
+    set.iter()
+        .copied()
+        .min_by(|a, b| dist_sq(a, target).cmp(&dist_sq(b, target)).then_with(|| canonical_cmp(a, b)))
+}
+
+/// Compute signature bits for lattice predicates.
+pub fn sig7(p: &Pt) -> u8 {
+    let q = p.quadrant();
+    let preds = [
+        q == 1,
+        q == 2,
+        q == 3,
+        q == 4,
+        p.is_visible(),
+        p.is_on_axis(),
+        p.is_even(),
+    ];
+    let mut bits: u8 = 0;
+    for (i, b) in preds.iter().enumerate() {
+        if *b {
+            bits |= 1u8 << i;
+        }
+    }
+    bits
+}
+
+pub fn bit_legend() -> [&'static str; 7] {
+    [
+        "quadrant_1",
+        "quadrant_2",
+        "quadrant_3",
+        "quadrant_4",
+        "visible",
+        "on_axis",
+        "even",
+    ]
+}
+
+/// Parse "(x,y)" or "x,y".
+pub fn parse_elem(s: &str) -> Option<Pt> {
+    let t = s.trim();
+    let t = t.strip_prefix('(').unwrap_or(t);
+    let t = t.strip_suffix(')').unwrap_or(t);
+    let parts: Vec<&str> = t.split(',').map(|p| p.trim()).collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    Some(Pt {
+        x: parts[0].parse().ok()?,
+        y: parts[1].parse().ok()?,
+    })
+}
+
+pub fn pt_to_string(p: &Pt) -> String {
+    format!("({},{})", p.x, p.y)
+}
+
+pub fn is_lattice_universe(u: &str) -> bool {
+    matches!(
+        u.to_ascii_uppercase().as_str(),
+        "LATTICE" | "LATTICE2" | "Z2" | "DISK"
+    )
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lattice_disk_size() {
+        // Gauss circle problem: N(1)=5, N(2)=13, N(10)=317
+        assert_eq!(build_lattice(1).len(), 5);
+        assert_eq!(build_lattice(2).len(), 13);
+        assert_eq!(build_lattice(10).len(), 317);
+        assert_eq!(build_lattice(10)[0], Pt { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn lattice_predicates() {
+        let p = Pt { x: 3, y: -4 };
+        assert_eq!(p.quadrant(), 4);
+        assert!(p.is_visible());
+        assert!(!p.is_on_axis());
+        assert!(!p.is_even());
+        // origin and multiples are not visible
+        assert!(!Pt { x: 0, y: 0 }.is_visible());
+        assert!(!Pt { x: 2, y: 4 }.is_visible());
+        assert!(Pt { x: 0, y: 1 }.is_visible());
+        assert_eq!(sig7(&Pt { x: 0, y: 1 }), 0b0110000);
+    }
+
+    #[test]
+    fn lattice_parse_and_witness() {
+        assert_eq!(parse_elem("(3,-4)"), Some(Pt { x: 3, y: -4 }));
+        assert_eq!(parse_elem(" 1, 2 "), Some(Pt { x: 1, y: 2 }));
+        assert!(parse_elem("1,2,3").is_none());
+        let set = build_lattice(2);
+        // (10,0) is outside the disk; nearest in-disk point is (2,0)
+        let w = witness_nearest(&set, &Pt { x: 10, y: 0 }).unwrap();
+        assert_eq!(w, Pt { x: 2, y: 0 });
+        // exact tie between (1,0) and (0,1) broken canonically: x ascending
+        let w = witness_nearest(&[Pt { x: 1, y: 0 }, Pt { x: 0, y: 1 }], &Pt { x: 1, y: 1 }).unwrap();
+        assert_eq!(w, Pt { x: 0, y: 1 });
+    }
+}
@@ -1,11 +1,41 @@
+pub mod api_proposer;
+pub mod attest;
+pub mod bench;
 pub mod boolfun;
+pub mod bundle;
+pub mod cancel;
+pub mod commitments;
+pub mod catalog;
 pub mod compiler;
+pub mod config;
 pub mod digest;
 pub mod exec;
+pub mod explain;
+pub mod ffi;
+pub mod fewshot;
+pub mod fraud;
+pub mod gc;
+pub mod grpc;
 pub mod geom;
+pub mod intent;
+pub mod group;
+pub mod lattice;
+pub mod pred;
 pub mod qe;
+pub mod query_proposer;
+pub mod schema;
 pub mod semtrace;
+pub mod server;
+pub mod setops;
+pub mod signing;
+pub mod subsets;
+pub mod succinct;
+pub mod trace_builder;
+pub mod tui;
 pub mod verify;
+pub mod wasm;
+pub use verifier_core::{canonical, trace_format};
+pub mod watch;
 pub mod word;
 pub mod phoneme;
 pub mod syllable;
@@ -1,56 +1,745 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
-use llm_nature_semantic_transformer::exec;
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use llm_nature_semantic_transformer::api_proposer::{ApiProposer, Sampling};
+use llm_nature_semantic_transformer::{attest, bench, bundle, catalog, config, exec, explain, fraud, gc, geom, grpc, intent, lattice, query_proposer, schema, server, signing, succinct, tui, verify, watch};
+use llm_nature_semantic_transformer::digest::HashAlg;
 use serde_json::Value;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Exit status when the replay verifier rejects the run.
+const EXIT_VERIFIER_MISMATCH: i32 = 2;
+/// Exit status for an empty answer set under --fail-on-empty.
+const EXIT_EMPTY_SET: i32 = 3;
+/// Exit status when the proposer cannot produce an op script.
+const EXIT_PROPOSER_FAILURE: i32 = 4;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit status: 0 valid, 1 error, 2 verifier mismatch, 3 empty set (with --fail-on-empty), 4 proposer failure"
+)]
 struct Cli {
-    /// Query string or JSON trace
-    query: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Verbose output with debug details
-    #[arg(short, long)]
+    /// Query string or JSON trace; `-` (or omitted, with piped stdin) reads it from stdin
+    query: Option<String>,
+
+    /// Read a semtrace JSON trace, or a JSON array of op lines, from this file instead of argv
+    #[arg(long, conflicts_with = "query")]
+    trace_file: Option<PathBuf>,
+
+    /// Log progress (proposer, execute, verify, artifacts) to stderr down to debug level;
+    /// RUST_LOG, when set, filters instead
+    #[arg(short, long, env = "LNST_VERBOSE")]
     verbose: bool,
 
+    /// Log line format on stderr: text, or one JSON object per event
+    #[arg(long, global = true, env = "LNST_LOG_FORMAT", default_value = "text", value_parser = ["text", "json"])]
+    log_format: String,
+
+    /// Output format: the human narrative, or the run's result.json alone (implies no --verbose)
+    #[arg(long, global = true, env = "LNST_FORMAT", default_value = "text", value_parser = ["text", "json"])]
+    format: String,
+
+    /// Print only the answer line (or the JSON document), without the banner block
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Colorize the verifier verdict: auto (terminal and no NO_COLOR), always, never
+    #[arg(long, global = true, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Config file (default ./lnst.toml when present); flags and LNST_* variables override it
+    #[arg(long, global = true, env = "LNST_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Replay traces on up to N threads, one universe segment each; traces using
+    /// predicates, the state stack or saved sets still replay sequentially
+    #[arg(long, global = true, env = "LNST_VERIFY_THREADS", default_value_t = 1)]
+    verify_threads: usize,
+
+    /// Print the op grammar with an example per op, then exit
+    #[arg(long)]
+    help_ops: bool,
+
     /// Show all ranked candidates instead of executing the top one
     #[arg(short, long)]
     candidates: bool,
+
+    /// Proposer backend for natural-language queries (compiler, rules, api, local, fixture)
+    #[arg(long, env = "LNST_PROPOSER", default_value = "compiler")]
+    proposer: String,
+
+    /// Model for --proposer api (claude-* uses Anthropic, others OpenAI) or local (GGUF path)
+    #[arg(long, env = "LNST_MODEL")]
+    model: Option<String>,
+
+    /// Chat endpoint override (default: provider URL, or llama-server on 127.0.0.1:8080 for local)
+    #[arg(long)]
+    endpoint: Option<String>,
+
+    /// Sampling temperature for api/local proposers
+    #[arg(long, default_value_t = 0.0)]
+    temperature: f64,
+
+    /// Nucleus sampling threshold for api/local proposers
+    #[arg(long, default_value_t = 1.0)]
+    top_p: f64,
+
+    /// Reply token limit for api/local proposers
+    #[arg(long, default_value_t = 512)]
+    max_tokens: u32,
+
+    /// Sampling seed for api/local proposers (recorded in proof.json)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Re-prompts with parse errors before an api/local proposer falls back
+    #[arg(long, default_value_t = 2)]
+    max_repairs: usize,
+
+    /// System prompt file for api/local proposers, replacing the per-universe template
+    #[arg(long)]
+    prompt_file: Option<PathBuf>,
+
+    /// Fail instead of falling back to the rules proposer when an api/local model gives no usable ops
+    #[arg(long)]
+    strict_proposer: bool,
+
+    /// Solved examples retrieved into the api/local proposer prompt (0 disables)
+    #[arg(long, default_value_t = 3)]
+    few_shots: usize,
+
+    /// Re-propose up to N times with per-step counts when a proposed trace yields an empty set
+    #[arg(long, default_value_t = 0)]
+    max_refinements: usize,
+
+    /// Canned {query, ops} pairs for --proposer fixture
+    #[arg(long)]
+    fixture_file: Option<PathBuf>,
+
+    /// Re-prompt, then fall back, when an api/local script's token confidence is below this
+    #[arg(long)]
+    min_confidence: Option<f64>,
+
+    /// Root directory for run artifacts (default ./runs)
+    #[arg(long, env = "LNST_OUTPUT_DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Run directory name under the artifacts root (default <utc timestamp>_<thread>; env LNST_RUN_ID)
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Execute up to K proposed traces and keep the best (valid > nonempty > closest witness)
+    #[arg(long, default_value_t = 1)]
+    beam: usize,
+
+    /// Exit with status 3 when the verified answer set is empty
+    #[arg(long)]
+    fail_on_empty: bool,
+
+    /// Also write deltas.ndjson, the per-step element deltas `verify --succinct` checks
+    #[arg(long)]
+    deltas: bool,
+
+    /// Sign each run with this Ed25519 PKCS#8 PEM private key, writing proof.sig
+    #[arg(long, env = "LNST_SIGN_KEY")]
+    sign_key: Option<PathBuf>,
+
+    /// Reproducible artifacts: run id derived from the ops, no timestamps, paths
+    /// relative to the run directory; `replay` then checks them byte for byte
+    #[arg(long, env = "LNST_DETERMINISTIC")]
+    deterministic: bool,
+
+    /// Hash backend for set digests, unless the trace picks one (recorded in the trace)
+    #[arg(long, env = "LNST_HASH", default_value = "sha256", value_parser = ["sha256", "blake3"])]
+    hash: String,
+}
+
+/// Report a proposer error and exit with its dedicated status.
+fn proposer_failed(e: anyhow::Error) -> ! {
+    eprintln!("Error: proposer failed: {:#}", e);
+    std::process::exit(EXIT_PROPOSER_FAILURE)
+}
+
+/// Exit status for a completed run. The verifier rejects steps that empty the
+/// set, so --fail-on-empty is what tells an empty answer apart from a mismatch.
+fn outcome_status(result: &exec::ExecutionResult, fail_on_empty: bool) -> i32 {
+    if fail_on_empty && result.final_count == 0 {
+        EXIT_EMPTY_SET
+    } else if !result.valid {
+        EXIT_VERIFIER_MISMATCH
+    } else {
+        0
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay an existing trace.ndjson (or run directory) and report the first mismatch; exits 1 if invalid
+    Verify {
+        /// trace.ndjson, or a run directory containing one
+        trace: PathBuf,
+        /// Check the digests against deltas.ndjson (from a --deltas run) instead of replaying every op
+        #[arg(long)]
+        succinct: bool,
+        /// Also check the run's proof.sig against this Ed25519 PEM public key
+        #[arg(long)]
+        pubkey: Option<PathBuf>,
+        /// On failure, replay ever shorter prefixes to find the first record that does not reproduce
+        #[arg(long, conflicts_with = "succinct")]
+        bisect: bool,
+        /// On failure, write a fraud proof for the first record that does not replay here
+        #[arg(long, conflicts_with = "succinct")]
+        fraud_proof: Option<PathBuf>,
+    },
+    /// Re-derive the one transition a fraud proof disputes; exits 0 if the proof stands, 1 if not
+    CheckFraudProof {
+        /// Fraud proof written by `verify --fraud-proof`
+        proof: PathBuf,
+    },
+    /// Re-execute a saved run directory's ops and compare chain hash and result.json (every artifact, byte for byte, for a --deterministic run); exits 1 on divergence
+    Replay {
+        /// Run directory, e.g. runs/<timestamp>
+        run: PathBuf,
+    },
+    /// Build a trace interactively: toggle signature bits and watch the count and witness (needs the `tui` feature)
+    Tui,
+    /// Print a shell completion script (bash and zsh also complete op and universe names for the query)
+    Completions {
+        shell: clap_complete::Shell,
+        /// Command name to complete, when installed under another name (e.g. lnst)
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Re-execute and re-verify trace JSON files whenever they change, printing one line per run
+    Watch {
+        /// Directory of *.json traces, or a single trace file
+        #[arg(default_value = "traces")]
+        path: PathBuf,
+        /// Polling interval in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval_ms: u64,
+    },
+    /// Pack a run directory's trace, proof, result and proposer metadata into a .tar.zst proof bundle
+    Export {
+        /// Run directory, e.g. runs/<id>
+        run: PathBuf,
+        /// Bundle to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Check a proof bundle's manifest digests and replay its trace, using nothing but the bundle; exits 1 if invalid
+    VerifyBundle {
+        /// Bundle written by `export`
+        bundle: PathBuf,
+    },
+    /// Replay many runs and commit to their chain hashes with one Merkle root, with an inclusion proof per run
+    Attest {
+        /// Run directories, or directories of runs such as runs/
+        #[arg(required = true)]
+        runs: Vec<PathBuf>,
+        /// Take each chain hash from result.json instead of replaying the run
+        #[arg(long)]
+        no_replay: bool,
+        /// Write the attestation here instead of printing it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Narrate a trace.ndjson (or run directory) step by step, with bit meanings and counts
+    Explain {
+        /// trace.ndjson, or a run directory containing one
+        trace: PathBuf,
+        /// Render Markdown instead of plain text
+        #[arg(long)]
+        markdown: bool,
+    },
+    /// List every op with its arguments, defaults and universes (--format json for a table)
+    Ops,
+    /// Print the JSON Schema of a trace JSON, a trace.ndjson step record, proof.json or result.json (all four when none is named)
+    Schema {
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(schema::NAMES))]
+        name: Option<String>,
+    },
+    /// List every universe with its size, bit legend and element syntax (--format json for a table)
+    Universes,
+    /// Time universe construction, filtering, digesting, witness search and verification; prints a JSON report
+    Bench {
+        /// Comma-separated workloads: qe, boolfun, geom
+        #[arg(long, default_value = "qe,boolfun,geom")]
+        suite: String,
+        /// Timed repetitions of each phase
+        #[arg(long, default_value_t = 10)]
+        iters: usize,
+    },
+    /// Prune old run directories and stale input traces under the artifacts root
+    Gc {
+        /// Keep this many of the newest runs
+        #[arg(long, default_value_t = 20)]
+        keep_last: usize,
+        /// Also keep every run whose verifier passed
+        #[arg(long)]
+        keep_valid: bool,
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove every run directory and input trace (gc --keep-last 0)
+    Clean {
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Inspect the layered configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Serve POST /query, /execute and /verify over HTTP (needs the `serve` feature)
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+    },
+    /// Serve Propose, Execute, ExecuteStream and Verify over gRPC, per proto/lnst.proto (needs the `grpc` feature)
+    Grpc {
+        #[arg(long, default_value_t = 50051)]
+        port: u16,
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective settings and whether each came from a flag, env, the file or a default
+    Show,
+}
+
+/// Fill settings that neither a flag nor the environment set from the config
+/// file. Returns each setting as (key, value, source) for `config show`.
+fn apply_config(cli: &mut Cli, cfg: &config::Config, m: &ArgMatches) -> Result<Vec<(&'static str, String, &'static str)>> {
+    let given = |id: &str| match m.value_source(id) {
+        Some(ValueSource::CommandLine) => Some("flag"),
+        Some(ValueSource::EnvVariable) => Some("env"),
+        _ => None,
+    };
+    if let Some(f) = cfg.format.as_deref().filter(|f| !matches!(*f, "text" | "json")) {
+        return Err(anyhow!("config format must be \"text\" or \"json\", got {:?}", f));
+    }
+    if given("proposer").is_none() {
+        if let Some(p) = &cfg.proposer {
+            cli.proposer = p.clone();
+        }
+    }
+    if given("model").is_none() && cfg.model.is_some() {
+        cli.model = cfg.model.clone();
+    }
+    if given("output_dir").is_none() && cfg.output_dir.is_some() {
+        cli.output_dir = cfg.output_dir.clone();
+    }
+    if given("verbose").is_none() {
+        if let Some(v) = cfg.verbose {
+            cli.verbose = v;
+        }
+    }
+    if given("format").is_none() {
+        if let Some(f) = &cfg.format {
+            cli.format = f.clone();
+        }
+    }
+    let source = |id: &str, in_file: bool| given(id).unwrap_or(if in_file { "file" } else { "default" });
+    let output_dir = cli.output_dir.clone().unwrap_or_else(|| PathBuf::from("runs"));
+    Ok(vec![
+        ("proposer", format!("{:?}", cli.proposer), source("proposer", cfg.proposer.is_some())),
+        ("model", format!("{:?}", cli.model.as_deref().unwrap_or("")), source("model", cfg.model.is_some())),
+        ("output_dir", format!("{:?}", output_dir.display().to_string()), source("output_dir", cfg.output_dir.is_some())),
+        ("verbose", cli.verbose.to_string(), source("verbose", cfg.verbose.is_some())),
+        ("format", format!("{:?}", cli.format), source("format", cfg.format.is_some())),
+    ])
+}
+
+const GREEN: &str = "32";
+const RED: &str = "31";
+
+/// ANSI styling for the narrative: `--color always|never`, or under `auto` only
+/// when stdout is a terminal and NO_COLOR is unset or empty.
+struct Paint {
+    on: bool,
+}
+
+impl Paint {
+    fn new(mode: &str) -> Paint {
+        use std::io::IsTerminal;
+        let on = match mode {
+            "always" => true,
+            "never" => false,
+            _ => std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal(),
+        };
+        Paint { on }
+    }
+
+    fn bold(&self, color: &str, text: &str) -> String {
+        if self.on {
+            format!("\u{1b}[1m\u{1b}[{}m{}\u{1b}[0m", color, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// `gc` / `clean`: plan, delete unless dry-run, and report.
+fn prune(policy: gc::Policy, dry_run: bool, json: bool) -> Result<()> {
+    let plan = gc::plan(&exec::artifacts_root()?, &exec::traces_dir()?, policy)?;
+    if !dry_run {
+        gc::apply(&plan)?;
+    }
+    if json {
+        let mut doc = serde_json::to_value(&plan)?;
+        doc["dry_run"] = serde_json::json!(dry_run);
+        println!("{}", serde_json::to_string_pretty(&doc)?);
+        return Ok(());
+    }
+    let verb = if dry_run { "would remove" } else { "removed" };
+    for p in plan.remove_runs.iter().chain(&plan.remove_traces) {
+        println!("{} {}", verb, p.display());
+    }
+    println!(
+        "kept {} run(s); {} {} run(s) and {} trace file(s)",
+        plan.kept_runs,
+        verb,
+        plan.remove_runs.len(),
+        plan.remove_traces.len()
+    );
+    Ok(())
+}
+
+/// Write a completion script to stdout. The query positional is given the op
+/// and universe names as candidate values here only, so completion can offer
+/// them while parsing still accepts any query.
+fn print_completions(shell: clap_complete::Shell, name: Option<&str>) {
+    use clap::builder::PossibleValue;
+    let words: Vec<PossibleValue> = catalog::OPS
+        .iter()
+        .map(|o| PossibleValue::new(o.name).help(o.summary))
+        .chain(catalog::UNIVERSE_NAMES.iter().map(|u| PossibleValue::new(*u).help("universe")))
+        .collect();
+    let mut cmd = Cli::command().mut_arg("query", |a| a.value_parser(words));
+    let name = name.map_or_else(|| cmd.get_name().to_string(), str::to_string);
+    clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+}
+
+/// Execute and replay-verify one trace file, as a summary record for `watch`.
+fn watch_run(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path)?;
+    let json_value: Value = serde_json::from_str(&text)?;
+    let ops = schema::trace_ops(&json_value)?;
+    let previous = schema::trace_hash(&json_value)?.map(exec::set_default_hash);
+    let r = exec::run_trace_and_write(&ops, Some(path), false);
+    if let Some(h) = previous {
+        exec::set_default_hash(h);
+    }
+    let r = r?;
+    let dir = r.artifacts_path.clone().ok_or_else(|| anyhow!("run wrote no artifacts"))?;
+    let report = verify::verify_trace_report(&dir.join("trace.ndjson"));
+    Ok(serde_json::json!({
+        "ops": ops.len(),
+        "count": r.final_count,
+        "witness": r.witness,
+        "valid": report.valid,
+        "chain_hash": report.chain_hash,
+        "reason": report.reason,
+        "run_id": r.run_id,
+    }))
+}
+
+/// Re-run every trace under `path` whenever it changes, one line per run
+/// (one JSON object per line with `json`). Runs until interrupted.
+fn watch_traces(path: &Path, interval: std::time::Duration, json: bool, paint: &Paint) -> Result<()> {
+    let mut watcher = watch::Watcher::new(path)?;
+    if !json {
+        println!("Watching {} for trace JSON changes (Ctrl-C to stop)", path.display());
+    }
+    loop {
+        for file in watcher.changed()? {
+            let at = chrono::Local::now().format("%H:%M:%S").to_string();
+            let outcome = watch_run(&file);
+            if json {
+                let mut doc = outcome.unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() }));
+                doc["file"] = Value::from(file.display().to_string());
+                doc["at"] = Value::from(at);
+                println!("{}", doc);
+                continue;
+            }
+            match outcome {
+                Ok(s) => {
+                    let verdict = match (s["valid"].as_bool(), s["chain_hash"].as_str()) {
+                        (Some(true), Some(h)) => format!("{} (chain {})", paint.bold(GREEN, "VERIFIED"), &h[..12]),
+                        _ => format!("{} ({})", paint.bold(RED, "FAILED"), s["reason"].as_str().unwrap_or("replay rejected")),
+                    };
+                    let witness = s["witness"].as_str().unwrap_or("none");
+                    println!(
+                        "[{}] {}: {} ops, count {}, witness {} — {} run {}",
+                        at, file.display(), s["ops"], s["count"], witness, verdict, s["run_id"].as_str().unwrap_or("")
+                    );
+                }
+                Err(e) => println!("[{}] {}: {} {}", at, file.display(), paint.bold(RED, "error:"), e),
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Send `tracing` events to stderr. RUST_LOG filters them when set;
+/// otherwise warnings only, or this crate's events down to debug with `--verbose`.
+fn init_logging(format: &str, verbose: bool) {
+    use tracing_subscriber::EnvFilter;
+    let default = if verbose { "warn,llm_nature_semantic_transformer=debug" } else { "warn" };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default));
+    let ansi = std::io::IsTerminal::is_terminal(&std::io::stderr());
+    let logs = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).with_ansi(ansi);
+    // a second init (e.g. from a test harness) keeps the first subscriber
+    let _ = if format == "json" { logs.json().try_init() } else { logs.try_init() };
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    let (config_path, config) = config::Config::load(cli.config.as_deref())?;
+    let settings = apply_config(&mut cli, &config, &matches)?;
+    exec::set_universe_bounds(config.universe);
+    verify::set_verify_threads(cli.verify_threads);
+    // JSON output owns stdout
+    if cli.format == "json" {
+        cli.verbose = false;
+    }
+    init_logging(&cli.log_format, cli.verbose);
+
+    exec::set_output_config(exec::OutputConfig {
+        root: cli.output_dir.clone(),
+        run_id: cli.run_id.clone(),
+        deltas: cli.deltas,
+        sign_key: cli.sign_key.clone(),
+        hash: HashAlg::parse(&cli.hash).unwrap_or_default(),
+        deterministic: cli.deterministic,
+    });
+    if cli.help_ops {
+        println!("{}", catalog::render_grammar_help());
+        return Ok(());
+    }
+    match cli.command.as_ref() {
+        Some(Command::Verify { trace, succinct, pubkey, bisect, fraud_proof }) => {
+            let path = if trace.is_dir() { trace.join("trace.ndjson") } else { trace.clone() };
+            let mut report = if *succinct {
+                succinct::verify_succinct(&path, &path.with_file_name("deltas.ndjson"))
+            } else {
+                verify::verify_trace_report(&path)
+            };
+            if let Some(pubkey) = pubkey {
+                let run_dir = path.parent().unwrap_or(Path::new("."));
+                match signing::verify_run_signature(run_dir, pubkey) {
+                    Ok(()) => report.signature_verified = Some(true),
+                    Err(e) => {
+                        report.signature_verified = Some(false);
+                        report.valid = false;
+                        report.reason = Some(format!("signature: {}", e));
+                    }
+                }
+            }
+            if *bisect && !report.valid {
+                report.bisect = verify::bisect_trace(&path)?;
+            }
+            if let (Some(out), false) = (fraud_proof, report.valid) {
+                if let Some(proof) = fraud::fraud_proof(&path)? {
+                    fs::write(out, serde_json::to_string_pretty(&proof)? + "\n")?;
+                    eprintln!("verify: wrote fraud proof for step {} to {}", proof.step, out.display());
+                }
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if let Some(d) = report.diagnosis() {
+                eprintln!("verify: {}", d);
+            }
+            if let Some(b) = report.bisect.as_ref() {
+                eprint!("bisect: {}", b);
+            }
+            std::process::exit(if report.valid { 0 } else { 1 });
+        }
+        Some(Command::CheckFraudProof { proof }) => {
+            let proof: fraud::FraudProof = serde_json::from_str(&fs::read_to_string(proof)?)?;
+            match fraud::check_fraud_proof(&proof) {
+                Ok(()) => {
+                    println!("fraud proof stands: step {} ({}) does not reproduce", proof.step, proof.op);
+                    return Ok(());
+                }
+                Err(e) => {
+                    println!("fraud proof does not stand: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Replay { run }) => {
+            let report = exec::replay_run(run)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            std::process::exit(if report.reproduced { 0 } else { 1 });
+        }
+        Some(Command::Tui) => {
+            return tui::run(config.universe);
+        }
+        Some(Command::Completions { shell, name }) => {
+            print_completions(*shell, name.as_deref());
+            return Ok(());
+        }
+        Some(Command::Watch { path, interval_ms }) => {
+            let interval = std::time::Duration::from_millis(*interval_ms);
+            return watch_traces(path, interval, cli.format == "json", &Paint::new(&cli.color));
+        }
+        Some(Command::Export { run, out }) => {
+            let manifest = bundle::export(run, out)?;
+            if cli.format == "json" {
+                println!("{}", serde_json::to_string_pretty(&manifest)?);
+            } else {
+                println!("Wrote {} ({} files, run {})", out.display(), manifest.files.len(), manifest.run_id);
+            }
+            return Ok(());
+        }
+        Some(Command::VerifyBundle { bundle }) => {
+            let report = bundle::verify_bundle(bundle);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            std::process::exit(if report.valid { 0 } else { 1 });
+        }
+        Some(Command::Attest { runs, no_replay, out }) => {
+            let att = attest::attest(runs, !no_replay)?;
+            let json = serde_json::to_string_pretty(&att)?;
+            match out {
+                Some(path) => {
+                    fs::write(path, json + "\n")?;
+                    println!("Wrote {} ({} runs, root {})", path.display(), att.runs.len(), att.root);
+                }
+                None => println!("{}", json),
+            }
+            return Ok(());
+        }
+        Some(Command::Explain { trace, markdown }) => {
+            println!("{}", explain::explain_trace(trace, *markdown)?);
+            return Ok(());
+        }
+        Some(Command::Config { action: ConfigAction::Show }) => {
+            match config_path.as_ref() {
+                Some(p) => println!("# config file: {}", p.display()),
+                None => println!("# config file: none"),
+            }
+            for (key, value, source) in &settings {
+                println!("{:<32} # {}", format!("{} = {}", key, value), source);
+            }
+            println!("\n[universe]");
+            let bounds = [
+                ("lattice_radius", config.universe.lattice_radius, lattice::DEFAULT_RADIUS as u64),
+                ("quad_perimeter", config.universe.quad_perimeter, geom::DEFAULT_QUAD_PERIMETER as u64),
+                ("tetra_edge", config.universe.tetra_edge, geom::DEFAULT_TETRA_EDGE as u64),
+            ];
+            for (key, set, default) in bounds {
+                let source = if set.is_some() { "file" } else { "default" };
+                println!("{:<32} # {}", format!("{} = {}", key, set.unwrap_or(default)), source);
+            }
+            return Ok(());
+        }
+        Some(Command::Gc { keep_last, keep_valid, dry_run }) => {
+            return prune(gc::Policy { keep_last: *keep_last, keep_valid: *keep_valid }, *dry_run, cli.format == "json");
+        }
+        Some(Command::Clean { dry_run }) => {
+            return prune(gc::Policy { keep_last: 0, keep_valid: false }, *dry_run, cli.format == "json");
+        }
+        Some(Command::Ops) => {
+            if cli.format == "json" {
+                println!("{}", serde_json::to_string_pretty(catalog::OPS)?);
+            } else {
+                println!("{}", catalog::render_ops());
+            }
+            return Ok(());
+        }
+        Some(Command::Schema { name }) => {
+            let out = match name {
+                Some(n) => schema::schema(n).map(|s| s.to_value()),
+                None => Some(Value::Object(schema::NAMES.iter().filter_map(|n| Some((n.to_string(), schema::schema(n)?.to_value()))).collect())),
+            };
+            println!("{}", serde_json::to_string_pretty(&out.ok_or_else(|| anyhow!("no schema named {:?}", name))?)?);
+            return Ok(());
+        }
+        Some(Command::Universes) => {
+            let universes = catalog::universes();
+            if cli.format == "json" {
+                println!("{}", serde_json::to_string_pretty(&universes)?);
+            } else {
+                println!("{}", catalog::render_universes(&universes));
+            }
+            return Ok(());
+        }
+        Some(Command::Bench { suite, iters }) => {
+            let report = bench::run(&bench::parse_suites(suite)?, *iters)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(());
+        }
+        Some(Command::Serve { port, host }) => {
+            let addr = format!("{}:{}", host, port);
+            println!("Listening on http://{}", addr);
+            return server::serve(&addr);
+        }
+        Some(Command::Grpc { port, host }) => {
+            let addr = format!("{}:{}", host, port);
+            println!("gRPC listening on {}", addr);
+            return grpc::serve(&addr);
+        }
+        None => {}
+    }
+    let query = match cli.trace_file.as_ref() {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+            if !text.trim_start().starts_with(['{', '[']) {
+                return Err(anyhow!("{}: expected a JSON trace object or an array of op lines", path.display()));
+            }
+            text
+        }
+        None => {
+            use std::io::{IsTerminal, Read};
+            let piped = cli.query.is_none() && !std::io::stdin().is_terminal();
+            if cli.query.as_deref() == Some("-") || piped {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text)?;
+                if text.trim().is_empty() {
+                    return Err(anyhow!("a query is required (stdin was empty)"));
+                }
+                text.trim().to_string()
+            } else {
+                cli.query.clone().ok_or_else(|| anyhow!("a query is required (or use a subcommand; see --help)"))?
+            }
+        }
+    };
 
     // Candidates mode: compile and rank all candidate traces, print and exit
     if cli.candidates {
-        use llm_nature_semantic_transformer::semtrace::{Constraint, Op, sig7};
         use llm_nature_semantic_transformer::qe::build_qe;
         let qe = build_qe();
         let universe_size = qe.len() as f64;
 
-        let mut cands = llm_nature_semantic_transformer::compiler::compile_query_to_candidates(&cli.query)?;
+        let mut cands = llm_nature_semantic_transformer::compiler::compile_query_to_candidates(&query)?;
         if cands.is_empty() {
             println!("No candidates generated for query.");
             return Ok(());
         }
 
-        // Score each candidate by selectivity: 1.0 - (matching / total)
+        // Score each candidate by selectivity (matching / total).
         // Tighter constraint = fewer matches = lower score = preferred
-        for c in cands.iter_mut() {
-            let mut cst = Constraint::empty();
-            for op in &c.trace.ops {
-                if let Op::SetBit { i, b } = op {
-                    cst = cst.set_bit(*i, *b);
-                }
-            }
-            let matching = qe.iter().filter(|f| cst.matches(sig7(f))).count() as f64;
-            c.score = matching / universe_size;
-        }
-        cands.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+        query_proposer::rank_by_selectivity(&mut cands);
 
-        println!("\n{} candidate(s) for: {}\n", cands.len(), cli.query);
+        println!("\n{} candidate(s) for: {}\n", cands.len(), query);
         println!("{:<4} {:<10} {:<12} {}", "Rank", "Score", "Selectivity", "Rationale");
         println!("{}", "-".repeat(70));
         for (i, c) in cands.iter().enumerate() {
@@ -58,26 +747,20 @@ fn main() -> Result<()> {
             println!("{:<4} {:<10.4} {:<12} {}", i, c.score, format!("{}/{}", matching, qe.len()), c.rationale);
         }
         println!();
-        if cli.verbose {
-            for (i, c) in cands.iter().enumerate() {
-                println!("--- Candidate {} ops ---", i);
-                for op in &c.trace.ops {
-                    println!("  {:?}", op);
-                }
-                println!();
-            }
+        for (i, c) in cands.iter().enumerate() {
+            tracing::debug!(candidate = i, ops = ?c.trace.ops, "candidate ops");
         }
         return Ok(());
     }
 
     // Check if input is JSON (starts with { or [)
-    let is_json = cli.query.trim().starts_with('{') || cli.query.trim().starts_with('[');
+    let is_json = query.trim().starts_with('{') || query.trim().starts_with('[');
 
     // For JSON input, bypass proposer
 
     // Explicit-ops mode: allow passing a space-separated op script directly (no NL compiler).
     // Example: JOIN_NEAREST ... RETURN_SET ...
-    let qtrim = cli.query.trim();
+    let qtrim = query.trim();
     let qfirst = qtrim.split_whitespace().next().unwrap_or("");
     let _is_explicit_ops = !is_json
         && matches!(
@@ -86,8 +769,36 @@ fn main() -> Result<()> {
                 | "MASK_BIT"
                 | "SELECT_UNIVERSE"
                 | "FILTER_WEIGHT"
+                | "FILTER_CONJ"
+                | "FILTER_SUM"
+                | "FILTER_AREA"
+                | "FILTER_MAX_ANGLE"
+                | "FILTER_RANGE"
                 | "TOPK"
                 | "WITNESS_NEAREST"
+                | "WITNESS_ALL_TIES"
+                | "SAVE_SET"
+                | "COMPLEMENT"
+                | "SAMPLE"
+                | "AGGREGATE"
+                | "GROUP_BY"
+                | "ASSERT_COUNT"
+                | "ASSERT_WITNESS"
+                | "NEGATE"
+                | "RECIPROCAL"
+                | "MEDIANT_WITH"
+                | "PUSH_STATE"
+                | "POP_STATE"
+                | "FILTER_PRED"
+                | "DEFINE_PRED"
+                | "FILTER_CLASS"
+                | "FILTER_NONLINEARITY"
+                | "NPN_CLASS"
+                | "DUAL"
+                | "PERMUTE_VARS"
+                | "RESTRICT"
+                | "INTERSECT"
+                | "UNION"
                 | "RETURN_SET"
                 | "JOIN_NEAREST"
                 | "PROJECT_SIGNATURE"
@@ -99,8 +810,36 @@ fn main() -> Result<()> {
             "MASK_BIT",
             "SELECT_UNIVERSE",
             "FILTER_WEIGHT",
+            "FILTER_CONJ",
+            "FILTER_SUM",
+            "FILTER_AREA",
+            "FILTER_MAX_ANGLE",
+            "FILTER_RANGE",
             "TOPK",
             "WITNESS_NEAREST",
+            "WITNESS_ALL_TIES",
+            "SAVE_SET",
+            "COMPLEMENT",
+            "SAMPLE",
+            "AGGREGATE",
+            "GROUP_BY",
+            "ASSERT_COUNT",
+            "ASSERT_WITNESS",
+            "NEGATE",
+            "RECIPROCAL",
+            "MEDIANT_WITH",
+            "PUSH_STATE",
+            "POP_STATE",
+            "FILTER_PRED",
+            "DEFINE_PRED",
+            "FILTER_CLASS",
+            "FILTER_NONLINEARITY",
+            "NPN_CLASS",
+            "DUAL",
+            "PERMUTE_VARS",
+            "RESTRICT",
+            "INTERSECT",
+            "UNION",
             "RETURN_SET",
             "JOIN_NEAREST",
             "PROJECT_SIGNATURE",
@@ -122,224 +861,173 @@ fn main() -> Result<()> {
         out
     }
 
+    let mut proposer_log: Option<Value> = None;
+    let mut executed: Option<exec::ExecutionResult> = None;
+    let mut proposer_provenance: Option<Value> = None;
     let (trace_ops, trace_path) = if is_json {
         // Parse and validate JSON
-        let json_value: Value = serde_json::from_str(&cli.query)?;
-
-        // Extract ops if present (lossless: include required args)
-        let ops = if let Some(ops_array) = json_value.get("ops").and_then(|v| v.as_array()) {
-            let mut out: Vec<String> = Vec::with_capacity(ops_array.len());
-            for opv in ops_array {
-                let op = opv
-                    .get("op")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("op missing op"))?;
-                match op {
-                    "SELECT_UNIVERSE" => {
-                        let u = opv
-                            .get("universe")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("SELECT_UNIVERSE missing universe"))?;
-                        let n = opv
-                            .get("n")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("SELECT_UNIVERSE missing n"))?;
-                        out.push(format!("SELECT_UNIVERSE universe={} n={}", u, n));
-                    }
-                    "FILTER_WEIGHT" => {
-                        let min = opv
-                            .get("min")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("FILTER_WEIGHT missing min"))?;
-                        let max = opv
-                            .get("max")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("FILTER_WEIGHT missing max"))?;
-                        out.push(format!("FILTER_WEIGHT min={} max={}", min, max));
-                    }
-                    "TOPK" => {
-                        let target = opv
-                            .get("target_elem")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| opv.get("target").and_then(|v| v.as_str()))
-                            .ok_or_else(|| anyhow!("TOPK missing target_elem"))?;
-                        let k = opv
-                            .get("k")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("TOPK missing k"))?;
-                        out.push(format!("TOPK target_elem={} k={}", target, k));
-                    }
-                    "RETURN_SET" => {
-                        let max_items = opv.get("max_items").and_then(|v| v.as_u64()).unwrap_or(20);
-                        let include_witness = opv
-                            .get("include_witness")
-                            .and_then(|v| v.as_bool())
-                            .unwrap_or(false);
-                        out.push(format!(
-                            "RETURN_SET max_items={} include_witness={}",
-                            max_items,
-                            if include_witness { 1 } else { 0 }
-                        ));
-                    }
-                    "START_ELEM" => {
-                        let elem = opv
-                            .get("elem")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("START_ELEM missing elem"))?;
-                        out.push(format!("LOAD {}", elem));
-                    }
-                    "SET_BIT" => {
-                        let i = opv
-                            .get("i")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("SET_BIT missing i"))?;
-                        let b = opv
-                            .get("b")
-                            .and_then(|v| v.as_u64())
-                            .ok_or_else(|| anyhow!("SET_BIT missing b"))?;
-                        out.push(format!("MASK_BIT bit={} val={}", i, b));
-                    }
-                    "WITNESS_NEAREST" => {
-                        let target = opv
-                            .get("target_elem")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| opv.get("target").and_then(|v| v.as_str()))
-                            .ok_or_else(|| anyhow!("WITNESS_NEAREST missing target_elem"))?;
-                        let metric = opv
-                            .get("metric")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("ABS_DIFF");
-                        out.push(format!(
-                            "WITNESS_NEAREST target_elem={} metric={}",
-                            target, metric
-                        ));
-                    }
-                    "PROJECT_SIGNATURE" => {
-                        let elem = opv
-                            .get("elem")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("PROJECT_SIGNATURE missing elem"))?;
-                        out.push(format!("PROJECT_SIGNATURE elem={}", elem));
-                    }
-                    "JOIN_NEAREST" => {
-                        let lu = opv
-                            .get("left_universe")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("JOIN_NEAREST missing left_universe"))?;
-                        let ru = opv
-                            .get("right_universe")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("JOIN_NEAREST missing right_universe"))?;
-                        let le = opv
-                            .get("left_elem")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("JOIN_NEAREST missing left_elem"))?;
-                        let re = opv
-                            .get("right_elem")
-                            .and_then(|v| v.as_str())
-                            .ok_or_else(|| anyhow!("JOIN_NEAREST missing right_elem"))?;
-                        let metric = opv
-                            .get("metric")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("ABS_DIFF");
-                        out.push(format!(
-                                "JOIN_NEAREST left_universe={} right_universe={} left_elem={} right_elem={} metric={}",
-                                lu, ru, le, re, metric
-                            ));
-                    }
-                    other => return Err(anyhow!("unsupported op in JSON: {}", other)),
-                }
+        let json_value: Value = serde_json::from_str(&query)?;
+
+        let ops = schema::trace_ops(&json_value)?;
+        if let Some(h) = schema::trace_hash(&json_value)? {
+            exec::set_default_hash(h);
+        }
+
+        let trace_path = match cli.trace_file.clone() {
+            Some(path) => path,
+            None => {
+                // Create a temporary trace file
+                let trace_dir = exec::traces_dir()?;
+                fs::create_dir_all(&trace_dir)?;
+                let trace_path = trace_dir.join("direct_input.json");
+                fs::write(&trace_path, &query)?;
+                trace_path
             }
-            out
-        } else {
-            vec![cli.query.clone()]
         };
 
-        // Create a temporary trace file
-        let trace_dir = PathBuf::from("traces");
-        fs::create_dir_all(&trace_dir)?;
-        let trace_path = trace_dir.join("direct_input.json");
-        fs::write(&trace_path, &cli.query)?;
-
         (ops, Some(trace_path))
     } else if _is_explicit_ops {
         // Treat the input as a space-separated op script (already explicit, no NL compiler).
         (split_explicit_ops(qtrim), None)
     } else {
-        // Multi-candidate compiler: generate ranked candidates, execute top by selectivity
-        use llm_nature_semantic_transformer::semtrace::{Constraint, Op, sig7};
-        use llm_nature_semantic_transformer::qe::build_qe;
-
-        let mut cands = llm_nature_semantic_transformer::compiler::compile_query_to_candidates(&cli.query)?;
-        if cands.is_empty() {
-            return Err(anyhow!("unable to compile query; provide explicit JSON ops"));
-        }
-
-        // Score by selectivity: 1.0 - (matching / universe_size)
-        let qe_score = build_qe();
-        let universe_size = qe_score.len() as f64;
-        for c in cands.iter_mut() {
-            let mut cst = Constraint::empty();
-            for op in &c.trace.ops {
-                if let Op::SetBit { i, b } = op {
-                    cst = cst.set_bit(*i, *b);
-                }
-            }
-            let matching = qe_score.iter().filter(|f| cst.matches(sig7(f))).count() as f64;
-            c.score = matching / universe_size;
+        // Natural language: ask the selected proposer backend for an op script
+        let mut registry = query_proposer::Registry::with_defaults();
+        // Run settings recorded next to each proposal's own provenance
+        let intent = intent::classify(&query);
+        tracing::info!(intent = intent.intent.as_str(), source = %intent.source, evidence = %intent.evidence, "intent");
+        let mut run_meta = serde_json::json!({ "intent": intent });
+        if cli.proposer == "fixture" {
+            let path = cli
+                .fixture_file
+                .as_ref()
+                .ok_or_else(|| anyhow!("--proposer fixture requires --fixture-file"))?;
+            registry.register(Box::new(query_proposer::FixtureProposer::from_file(path)?));
         }
-        cands.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
-
-        // Execute top candidate
-        let top = &cands[0];
-        if cli.verbose {
-            let matching = ((1.0 - top.score) * universe_size) as usize;
-            println!("Selected candidate: {} (score={:.4}, {}/{}, {})",
-                top.rationale, top.score, matching, qe_score.len(), top.rationale);
+        if cli.proposer == "api" || cli.proposer == "local" {
+            let model = cli
+                .model
+                .as_deref()
+                .ok_or_else(|| anyhow!("--proposer {} requires --model", cli.proposer))?;
+            let base = if cli.proposer == "local" { ApiProposer::local(model) } else { ApiProposer::new(model) };
+            let sampling = Sampling {
+                temperature: cli.temperature,
+                top_p: cli.top_p,
+                max_tokens: cli.max_tokens,
+                seed: cli.seed,
+            };
+            let prompt = match cli.prompt_file.as_ref() {
+                Some(p) => Some(fs::read_to_string(p).with_context(|| format!("reading {}", p.display()))?),
+                None => None,
+            };
+            let api = ApiProposer {
+                sampling,
+                endpoint: cli.endpoint.clone(),
+                max_repairs: cli.max_repairs,
+                prompt,
+                strict: cli.strict_proposer,
+                few_shots: cli.few_shots,
+                min_confidence: cli.min_confidence,
+                ..base
+            };
+            run_meta["sampling"] = serde_json::to_value(sampling)?;
+            run_meta["prompt_source"] = serde_json::json!(cli
+                .prompt_file
+                .as_ref()
+                .map_or("template".to_string(), |p| p.display().to_string()));
+            registry.register(Box::new(api));
         }
-
-        let mut out: Vec<String> = Vec::with_capacity(top.trace.ops.len());
-        for op in top.trace.ops.iter() {
-            match op {
-                Op::StartElem { elem } => {
-                    out.push(format!("LOAD {}", elem));
-                }
-                Op::SetBit { i, b } => {
-                    out.push(format!("MASK_BIT bit={} val={}", i, b));
-                }
-                Op::SelectUniverse { universe, n } => {
-                    out.push(format!("SELECT_UNIVERSE universe={} n={}", universe, n));
-                }
-                Op::FilterWeight { min, max } => {
-                    out.push(format!("FILTER_WEIGHT min={} max={}", min, max));
-                }
-                Op::TopK { target_elem, k } => {
-                    out.push(format!("TOPK target_elem={} k={}", target_elem, k));
-                }
-                Op::WitnessNearest { target_elem, metric } => {
-                    out.push(format!("WITNESS_NEAREST target_elem={} metric={}", target_elem, metric));
-                }
-                Op::ReturnSet { max_items, include_witness } => {
-                    out.push(format!("RETURN_SET max_items={} include_witness={}",
-                        max_items, if *include_witness { 1 } else { 0 }));
-                }
-                _ => unreachable!("unhandled Op variant in NL compiler path")
+        let proposer = registry.get(&cli.proposer)?;
+        let _span = tracing::info_span!("proposer", proposer = proposer.name()).entered();
+        let proposal = if cli.beam > 1 {
+            let beam = proposer.propose_k(&query, cli.beam).unwrap_or_else(|e| proposer_failed(e));
+            let (selected, entries, r) = query_proposer::run_beam(&beam, cli.verbose, Some(&run_meta))?;
+            for e in &entries {
+                tracing::info!(
+                    beam = e.index,
+                    selected = e.index == selected,
+                    valid = e.valid,
+                    count = e.final_count,
+                    distance = e.witness_distance.as_deref(),
+                    rationale = %e.rationale,
+                    "beam entry"
+                );
             }
-        }
+            executed = Some(r);
+            beam.into_iter().nth(selected).expect("selected beam entry")
+        } else {
+            proposer.propose(&query).unwrap_or_else(|e| proposer_failed(e))
+        };
+        tracing::info!(
+            rationale = %proposal.rationale,
+            confidence = proposal.provenance.confidence,
+            op_confidence = ?proposal.provenance.op_confidence,
+            "proposal"
+        );
+        let proposal = if cli.max_refinements > 0 {
+            let r = match executed.take() {
+                Some(r) => r,
+                None => exec::run_trace_and_write_with(
+                    &proposal.ops,
+                    None,
+                    cli.verbose,
+                    Some(&proposal.provenance_json(Some(&run_meta))),
+                )?,
+            };
+            let (refined, r, rounds) = query_proposer::refine_until_nonempty(
+                proposer,
+                &query,
+                proposal,
+                r,
+                cli.max_refinements,
+                cli.verbose,
+                Some(&run_meta),
+            )?;
+            for round in &rounds[1..] {
+                tracing::info!(round = round.round, count = round.final_count, rationale = %round.rationale, "refinement");
+            }
+            executed = Some(r);
+            refined
+        } else {
+            proposal
+        };
 
         // Write compiled semtrace JSON for auditability
-        let trace_dir = PathBuf::from("traces");
-        fs::create_dir_all(&trace_dir)?;
-        let trace_path = trace_dir.join("compiled_input.json");
-        fs::write(&trace_path, serde_json::to_string_pretty(&top.trace)?)?;
+        let trace_path = match proposal.trace.as_ref() {
+            Some(t) => {
+                let trace_dir = exec::traces_dir()?;
+                fs::create_dir_all(&trace_dir)?;
+                let trace_path = trace_dir.join("compiled_input.json");
+                fs::write(&trace_path, serde_json::to_string_pretty(t)?)?;
+                Some(trace_path)
+            }
+            None => None,
+        };
 
-        (out, Some(trace_path))
+        proposer_provenance = Some(proposal.provenance_json(Some(&run_meta)));
+        proposer_log = proposal.log;
+        (proposal.ops, trace_path)
     };
 
     // Run the trace through the verifier
-    let result = exec::run_trace_and_write(&trace_ops, trace_path.as_deref(), cli.verbose)?;
+    let result = match executed {
+        Some(r) => r,
+        None => exec::run_trace_and_write_with(
+            &trace_ops,
+            trace_path.as_deref(),
+            cli.verbose,
+            proposer_provenance.as_ref(),
+        )?,
+    };
+    if let (Some(log), Some(dir)) = (proposer_log.as_ref(), result.artifacts_path.as_ref()) {
+        fs::write(dir.join("proposer.json"), serde_json::to_string_pretty(log)?)?;
+    }
+    if cli.format == "json" {
+        let doc = result.document.as_ref().ok_or_else(|| anyhow!("run wrote no artifacts"))?;
+        println!("{}", serde_json::to_string_pretty(&serde_json::to_value(doc)?)?);
+        std::process::exit(outcome_status(&result, cli.fail_on_empty));
+    }
     // Extract reference (prefer LOAD; else PROJECT_SIGNATURE elem=; else WITNESS_NEAREST target_elem=; else JOIN_NEAREST left_elem=)
-    fn describe_constraint_qe(mask: u8, value: u8) -> String {
+    fn describe_constraint_qe(mask: u64, value: u64) -> String {
     let legend = ["positive", "rat_int", "den<=6", "num_even", "den_mod3", "proper", "num_abs<=5"];
     let parts: Vec<String> = (0..7u8)
         .filter(|&i| (mask >> i) & 1 == 1)
@@ -417,30 +1105,21 @@ fn extract_kv(op: &str, key: &str) -> Option<String> {
     } else {
         0.0
     };
-    // Print narrative block
-    println!("\n───────────────────────────────────────────────────────────────────────────────");
-    if cli.verbose {
-        println!(
-            "Semantic Transformer • {}",
-            chrono::Utc::now().format("%Y-%m-%d %H:%M:%SZ")
-        );
-    }
-
-    println!("\nQuery: {}", cli.query);
-
     let is_join = trace_ops.iter().any(|op| op.starts_with("JOIN_NEAREST"));
     let join_right_elem = trace_ops.iter()
         .find_map(|op| if op.starts_with("JOIN_NEAREST") { extract_kv(op, "right_elem") } else { None })
         .unwrap_or_default();
-    if is_join {
-        println!(
-            "Answer: Nearest QE fraction to {} matching BOOLFUN signature {} is {} (diff ≈ {:.4}). Total: {}. Verified.",
-            reference, join_right_elem, witness, diff, result.final_count
-        );
-        println!();
-        println!("Reference: {} ≈ {:.5}", reference, ref_value);
-        println!("Witness: {} ≈ {:.5}", witness, witness_value);
-        println!("Total matching: {}", result.final_count);
+    let (answer, details) = if is_join {
+        (
+            format!(
+                "Answer: Nearest QE fraction to {} matching BOOLFUN signature {} is {} (diff ≈ {:.4}). Total: {}. Verified.",
+                reference, join_right_elem, witness, diff, result.final_count
+            ),
+            vec![
+                format!("Reference: {} ≈ {:.5}", reference, ref_value),
+                format!("Witness: {} ≈ {:.5}", witness, witness_value),
+            ],
+        )
     } else if reference_is_frac && witness_is_frac {
         // Fraction/QE narrative
         let constraint_desc = if result.universe == "QE" {
@@ -448,39 +1127,57 @@ fn extract_kv(op: &str, key: &str) -> Option<String> {
         } else {
             format!("universe={}", result.universe)
         };
-        println!(
-              "Answer: Closest fraction to {} ({}) is {} (diff ≈ {:.4}). Total: {}. Verified.",
-              reference,
-              constraint_desc,
-              witness,
-              diff,
-              result.final_count
-          );
-        println!();
-        println!("Reference: {} ≈ {:.5}", reference, ref_value);
-        println!("Witness: {} ≈ {:.5}", witness, witness_value);
-        println!("Total matching: {}", result.final_count);
+        (
+            format!(
+                "Answer: Closest fraction to {} ({}) is {} (diff ≈ {:.4}). Total: {}. Verified.",
+                reference, constraint_desc, witness, diff, result.final_count
+            ),
+            vec![
+                format!("Reference: {} ≈ {:.5}", reference, ref_value),
+                format!("Witness: {} ≈ {:.5}", witness, witness_value),
+            ],
+        )
     } else {
         // Non-fraction narrative (e.g. BOOLFUN)
+        (
+            format!("Answer: Witness is {}. Total: {}. Verified.", witness, result.final_count),
+            vec![format!("Witness: {}", witness)],
+        )
+    };
+    if cli.quiet {
+        println!("{}", answer);
+        std::process::exit(outcome_status(&result, cli.fail_on_empty));
+    }
+
+    // Print narrative block
+    let paint = Paint::new(&cli.color);
+    println!("\n───────────────────────────────────────────────────────────────────────────────");
+    if cli.verbose {
         println!(
-            "Answer: Witness is {}. Total: {}. Verified.",
-            witness, result.final_count
+            "Semantic Transformer • {}",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M:%SZ")
         );
-        println!();
-        println!("Witness: {}", witness);
-        println!("Total matching: {}", result.final_count);
     }
+    println!("\nQuery: {}", query);
+    println!("{}", answer);
+    println!();
+    for line in &details {
+        println!("{}", line);
+    }
+    println!("Total matching: {}", result.final_count);
 
     if result.valid {
         if cli.verbose {
-            println!("\n\u{1b}[1m\u{1b}[32mVERIFIER:\u{1b}[0m \u{1b}[1m\u{1b}[32mVALID (replay matched)\u{1b}[0m");
+            println!("\n{} {}", paint.bold(GREEN, "VERIFIER:"), paint.bold(GREEN, "VALID (replay matched)"));
         } else {
             println!("Execution verified: VALID");
         }
+    } else if let Some(msg) = result.failed_assertion.as_ref() {
+        println!("\n{} {}", paint.bold(RED, "VERIFIER:"), paint.bold(RED, &format!("FAILED ({})", msg)));
     } else {
         println!("\nExecution verification: FAILED");
     }
     println!("───────────────────────────────────────────────────────────────────────────────");
 
-    Ok(())
+    std::process::exit(outcome_status(&result, cli.fail_on_empty))
 }
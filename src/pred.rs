@@ -0,0 +1,281 @@
+//! User-defined predicates (DEFINE_PRED) — a small boolean DSL over QE elements.
+//!
+//! `DEFINE_PRED name=small expr="den<=10 && num>0"` registers `small` as the next
+//! signature bit after the fixed seven (bit 7, 8, …). The expression is parsed into
+//! an AST and its canonical rendering is what the trace records, so the verifier
+//! re-parses the same text and recomputes identical signatures.
+//!
+//! Grammar:
+//!   expr  := and ("||" and)*
+//!   and   := unary ("&&" unary)*
+//!   unary := "!" unary | "(" expr ")" | cmp
+//!   cmp   := term ("<=" | "<" | ">=" | ">" | "==" | "!=") term
+//!   term  := atom ("%" atom)*
+//!   atom  := var | integer | "-" integer
+//!
+//! Variables: num, den, abs_num.
+
+use crate::qe::Frac;
+
+/// Bits 0..7 are the fixed QE predicates; user predicates take the rest of a u64.
+pub const FIRST_USER_BIT: u8 = 7;
+pub const MAX_USER_PREDS: usize = 64 - FIRST_USER_BIT as usize;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Term {
+    Var(String),
+    Int(i64),
+    Mod(Box<Term>, Box<Term>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Le,
+    Lt,
+    Ge,
+    Gt,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Term, CmpOp, Term),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tok {
+    Ident(String),
+    Int(i64),
+    Op(&'static str),
+}
+
+const OPS: [&str; 13] = ["<=", ">=", "==", "!=", "&&", "||", "<", ">", "!", "(", ")", "%", "-"];
+
+fn lex(s: &str) -> Result<Vec<Tok>, String> {
+    let mut out = Vec::new();
+    let b = s.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i] as char;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let j = i + b[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            out.push(Tok::Int(s[i..j].parse().map_err(|_| format!("bad integer {}", &s[i..j]))?));
+            i = j;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let j = i + b[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_')
+                .count();
+            out.push(Tok::Ident(s[i..j].to_string()));
+            i = j;
+        } else if let Some(op) = OPS.iter().find(|op| s[i..].starts_with(**op)) {
+            out.push(Tok::Op(op));
+            i += op.len();
+        } else {
+            return Err(format!("unexpected '{}' at {}", c, i));
+        }
+    }
+    Ok(out)
+}
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn eat(&mut self, op: &str) -> bool {
+        if self.toks.get(self.pos) == Some(&Tok::Op(OPS.iter().find(|o| **o == op).unwrap())) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut e = self.and()?;
+        while self.eat("||") {
+            e = Expr::Or(Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut e = self.unary()?;
+        while self.eat("&&") {
+            e = Expr::And(Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let e = self.expr()?;
+            if !self.eat(")") {
+                return Err("expected ')'".to_string());
+            }
+            return Ok(e);
+        }
+        let l = self.term()?;
+        let op = match self.toks.get(self.pos) {
+            Some(Tok::Op("<=")) => CmpOp::Le,
+            Some(Tok::Op("<")) => CmpOp::Lt,
+            Some(Tok::Op(">=")) => CmpOp::Ge,
+            Some(Tok::Op(">")) => CmpOp::Gt,
+            Some(Tok::Op("==")) => CmpOp::Eq,
+            Some(Tok::Op("!=")) => CmpOp::Ne,
+            other => return Err(format!("expected comparison, got {:?}", other)),
+        };
+        self.pos += 1;
+        Ok(Expr::Cmp(l, op, self.term()?))
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        let mut t = self.atom()?;
+        while self.eat("%") {
+            t = Term::Mod(Box::new(t), Box::new(self.atom()?));
+        }
+        Ok(t)
+    }
+
+    fn atom(&mut self) -> Result<Term, String> {
+        let neg = self.eat("-");
+        let tok = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        match tok {
+            Some(Tok::Int(n)) => Ok(Term::Int(if neg { -n } else { n })),
+            Some(Tok::Ident(v)) if !neg => match v.as_str() {
+                "num" | "den" | "abs_num" => Ok(Term::Var(v)),
+                _ => Err(format!("unknown variable {}", v)),
+            },
+            other => Err(format!("expected variable or integer, got {:?}", other)),
+        }
+    }
+}
+
+/// Parse a predicate expression.
+pub fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut p = Parser { toks: lex(s)?, pos: 0 };
+    let e = p.expr()?;
+    if p.pos != p.toks.len() {
+        return Err(format!("trailing input at token {}", p.pos));
+    }
+    Ok(e)
+}
+
+fn render_term(t: &Term) -> String {
+    match t {
+        Term::Var(v) => v.clone(),
+        Term::Int(n) => n.to_string(),
+        Term::Mod(a, b) => format!("{}%{}", render_term(a), render_term(b)),
+    }
+}
+
+/// Canonical, fully parenthesized text of an expression (what the trace records).
+pub fn render(e: &Expr) -> String {
+    match e {
+        Expr::Or(a, b) => format!("({} || {})", render(a), render(b)),
+        Expr::And(a, b) => format!("({} && {})", render(a), render(b)),
+        Expr::Not(a) => format!("!{}", render(a)),
+        Expr::Cmp(l, op, r) => {
+            let op = match op {
+                CmpOp::Le => "<=",
+                CmpOp::Lt => "<",
+                CmpOp::Ge => ">=",
+                CmpOp::Gt => ">",
+                CmpOp::Eq => "==",
+                CmpOp::Ne => "!=",
+            };
+            format!("{}{}{}", render_term(l), op, render_term(r))
+        }
+    }
+}
+
+fn eval_term(t: &Term, f: &Frac) -> i64 {
+    match t {
+        Term::Var(v) => match v.as_str() {
+            "num" => f.num as i64,
+            "den" => f.den as i64,
+            _ => f.num.abs() as i64,
+        },
+        Term::Int(n) => *n,
+        // x % 0 is taken as x, so every expression is total
+        Term::Mod(a, b) => {
+            let (x, m) = (eval_term(a, f), eval_term(b, f));
+            if m == 0 {
+                x
+            } else {
+                x.rem_euclid(m)
+            }
+        }
+    }
+}
+
+pub fn eval(e: &Expr, f: &Frac) -> bool {
+    match e {
+        Expr::Or(a, b) => eval(a, f) || eval(b, f),
+        Expr::And(a, b) => eval(a, f) && eval(b, f),
+        Expr::Not(a) => !eval(a, f),
+        Expr::Cmp(l, op, r) => {
+            let (x, y) = (eval_term(l, f), eval_term(r, f));
+            match op {
+                CmpOp::Le => x <= y,
+                CmpOp::Lt => x < y,
+                CmpOp::Ge => x >= y,
+                CmpOp::Gt => x > y,
+                CmpOp::Eq => x == y,
+                CmpOp::Ne => x != y,
+            }
+        }
+    }
+}
+
+/// Extended signature: the fixed 7 QE bits, then one bit per user predicate in definition order.
+pub fn ext_sig(f: &Frac, preds: &[(String, Expr)]) -> u64 {
+    let mut bits = crate::semtrace::sig7(f) as u64;
+    for (k, (_, e)) in preds.iter().enumerate() {
+        if eval(e, f) {
+            bits |= 1u64 << (FIRST_USER_BIT as usize + k);
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_render_eval() {
+        let e = parse_expr("den<=10 && num>0 || !(num%2==0)").unwrap();
+        assert_eq!(render(&e), "((den<=10 && num>0) || !num%2==0)");
+        // canonical text re-parses to the same AST
+        assert_eq!(parse_expr(&render(&e)).unwrap(), e);
+        assert!(eval(&e, &Frac { num: 2, den: 3 }));
+        assert!(eval(&e, &Frac { num: -3, den: 50 }));
+        assert!(!eval(&e, &Frac { num: -2, den: 3 }));
+        assert!(parse_expr("den <= ").is_err());
+        assert!(parse_expr("value > 1").is_err());
+    }
+
+    #[test]
+    fn user_bits_follow_fixed_seven() {
+        let preds = vec![
+            ("small".to_string(), parse_expr("den<=10").unwrap()),
+            ("neg".to_string(), parse_expr("num<0").unwrap()),
+        ];
+        let s = ext_sig(&Frac { num: 1, den: 3 }, &preds);
+        assert_eq!(s & 0x7F, crate::semtrace::sig7(&Frac { num: 1, den: 3 }) as u64);
+        assert_eq!(s >> 7, 0b01);
+    }
+}
@@ -1,3 +1,5 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
@@ -115,6 +117,160 @@ pub fn parse_frac(s: &str) -> Option<Frac> {
     Some(Frac::new_reduced(num, den))
 }
 
+/// k elements nearest to target by exact |f − target|; ties by (|num|, den), then canonical.
+pub fn topk_nearest(set: &[Frac], target: &Frac, k: usize) -> Vec<Frac> {
+    let dist = |f: &Frac| -> (i64, i64) {
+        let (a, b) = (target.num as i64, target.den as i64);
+        let (c, d) = (f.num as i64, f.den as i64);
+        ((a * d - b * c).abs(), b * d)
+    };
+    let mut v: Vec<Frac> = set.to_vec();
+    v.sort_by(|x, y| {
+        let (dx, dy) = (dist(x), dist(y));
+        (dx.0 * dy.1)
+            .cmp(&(dy.0 * dx.1))
+            .then_with(|| (x.abs_num(), x.den).cmp(&(y.abs_num(), y.den)))
+            .then_with(|| canonical_cmp(x, y))
+    });
+    v.truncate(k);
+    v
+}
+
+/// Element-wise map over a fraction set; the image is re-sorted canonically and
+/// deduplicated, and elements mapped to None are dropped.
+pub fn map_set(set: &[Frac], f: impl Fn(&Frac) -> Option<Frac>) -> Vec<Frac> {
+    let mut out: Vec<Frac> = set.iter().filter_map(f).collect();
+    out.sort_by(canonical_cmp);
+    out.dedup();
+    out
+}
+
+pub fn negate(f: &Frac) -> Option<Frac> {
+    Some(Frac { num: -f.num, den: f.den })
+}
+
+/// 1/f; 0 has no reciprocal and is dropped.
+pub fn reciprocal(f: &Frac) -> Option<Frac> {
+    (f.num != 0).then(|| Frac::new_reduced(f.den, f.num))
+}
+
+/// Mediant (a+c)/(b+d) of reduced a/b and c/d, reduced.
+pub fn mediant(f: &Frac, g: &Frac) -> Frac {
+    Frac::new_reduced(f.num + g.num, f.den + g.den)
+}
+
+/// Parse a range bound: "a/b" or a bare integer "a" (= a/1).
+pub fn parse_bound(s: &str) -> Option<Frac> {
+    parse_frac(s).or_else(|| s.trim().parse::<i32>().ok().map(|n| Frac { num: n, den: 1 }))
+}
+
+/// Exact closed-interval test min ≤ f ≤ max.
+pub fn in_range(f: &Frac, min: &Frac, max: &Frac) -> bool {
+    f.cmp_value(min) != Ordering::Less && f.cmp_value(max) != Ordering::Greater
+}
+
+/// Extra WITNESS_NEAREST metrics over QE (ABS_DIFF lives with the executor).
+pub const EXTRA_METRICS: [&str; 3] = ["DEN_DIFF", "MEDIANT_DEPTH", "LOG_RATIO"];
+
+/// Stern–Brocot path of f: a sign step, then the L/R mediant moves from 1/1 for |f|.
+/// 0/1 is the root, so the tree distance between any two rationals is well defined.
+fn sb_path(f: &Frac) -> Vec<u8> {
+    if f.num == 0 {
+        return Vec::new();
+    }
+    let mut path = vec![if f.num > 0 { b'+' } else { b'-' }];
+    let (mut p, mut q) = (f.num.abs(), f.den);
+    while p != q {
+        if p > q {
+            path.push(b'R');
+            p -= q;
+        } else {
+            path.push(b'L');
+            q -= p;
+        }
+    }
+    path
+}
+
+/// Distance under an extra metric as an exact ratio (num, den); None means unbounded.
+///
+/// - DEN_DIFF: |den − den_t|
+/// - MEDIANT_DEPTH: tree distance between the two Stern–Brocot paths
+/// - LOG_RATIO: |log(f / t)| ordered exactly via max(f/t, t/f); only same-sign, nonzero pairs
+pub fn metric_distance(metric: &str, target: &Frac, f: &Frac) -> Option<(i128, i128)> {
+    match metric {
+        "DEN_DIFF" => Some(((f.den - target.den).abs() as i128, 1)),
+        "MEDIANT_DEPTH" => {
+            let (a, b) = (sb_path(target), sb_path(f));
+            let common = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+            Some(((a.len() + b.len() - 2 * common) as i128, 1))
+        }
+        "LOG_RATIO" => {
+            if target.num == 0 || f.num == 0 || (target.num > 0) != (f.num > 0) {
+                return None;
+            }
+            let x = (f.num as i128 * target.den as i128).abs();
+            let y = (f.den as i128 * target.num as i128).abs();
+            Some((x.max(y), x.min(y)))
+        }
+        _ => None,
+    }
+}
+
+/// Nearest element under an extra metric; ties by (|num|, den), then canonical order.
+/// None when the set has no element at bounded distance.
+pub fn witness_nearest_by(set: &[Frac], target: &Frac, metric: &str) -> Option<Frac> {
+    set.iter()
+        .filter_map(|f| metric_distance(metric, target, f).map(|d| (d, *f)))
+        .min_by(|(dx, x), (dy, y)| {
+            (dx.0 * dy.1)
+                .cmp(&(dy.0 * dx.1))
+                .then_with(|| (x.abs_num(), x.den).cmp(&(y.abs_num(), y.den)))
+                .then_with(|| canonical_cmp(x, y))
+        })
+        .map(|(_, f)| f)
+}
+
+/// Nearest element to several targets under ABS_DIFF, exactly: minimax minimizes the
+/// largest |f − tᵢ|, otherwise the sum Σ|f − tᵢ| is minimized.
+/// Ties by (|num|, den), then canonical order.
+pub fn witness_nearest_multi(set: &[Frac], targets: &[Frac], minimax: bool) -> Option<Frac> {
+    let ratio = |f: &Frac| BigRational::new(BigInt::from(f.num), BigInt::from(f.den));
+    let ts: Vec<BigRational> = targets.iter().map(ratio).collect();
+    set.iter()
+        .map(|f| {
+            let x = ratio(f);
+            let ds = ts.iter().map(|t| if x > *t { &x - t } else { t - &x });
+            let key = if minimax {
+                ds.max().unwrap_or_else(|| BigRational::from_integer(BigInt::from(0)))
+            } else {
+                ds.fold(BigRational::from_integer(BigInt::from(0)), |acc, d| acc + d)
+            };
+            (key, *f)
+        })
+        .min_by(|(dx, x), (dy, y)| {
+            dx.cmp(dy)
+                .then_with(|| (x.abs_num(), x.den).cmp(&(y.abs_num(), y.den)))
+                .then_with(|| canonical_cmp(x, y))
+        })
+        .map(|(_, f)| f)
+}
+
+/// Exact arithmetic mean of the set as a reduced "a/b" (None when empty).
+///
+/// Denominators up to 200 overflow any fixed-width lcm, so the sum is a BigRational.
+pub fn exact_mean(set: &[Frac]) -> Option<String> {
+    if set.is_empty() {
+        return None;
+    }
+    let mut sum = BigRational::from_integer(BigInt::from(0));
+    for f in set {
+        sum += BigRational::new(BigInt::from(f.num), BigInt::from(f.den));
+    }
+    let mean = sum / BigRational::from_integer(BigInt::from(set.len()));
+    Some(format!("{}/{}", mean.numer(), mean.denom()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -124,4 +280,65 @@ mod tests {
         assert_eq!(qe.len(), 48927);
         assert_eq!(qe.last().unwrap(), &Frac { num: 200, den: 1 });
     }
+
+    #[test]
+    fn exact_mean_is_reduced() {
+        let v = [Frac { num: 1, den: 2 }, Frac { num: 1, den: 3 }, Frac { num: 2, den: 1 }];
+        assert_eq!(exact_mean(&v).as_deref(), Some("17/18"));
+        assert_eq!(exact_mean(&[Frac { num: -4, den: 1 }]).as_deref(), Some("-4/1"));
+        assert_eq!(exact_mean(&[]), None);
+        // the full universe is symmetric about zero
+        assert_eq!(exact_mean(&build_qe()).as_deref(), Some("0/1"));
+    }
+
+    #[test]
+    fn extra_metrics() {
+        let f = |s: &str| parse_frac(s).unwrap();
+        // 1/2 = L, 2/3 = LR, 1/3 = LL: siblings are two steps apart
+        assert_eq!(metric_distance("MEDIANT_DEPTH", &f("2/3"), &f("1/3")), Some((2, 1)));
+        assert_eq!(metric_distance("MEDIANT_DEPTH", &f("1/2"), &f("-1/2")), Some((4, 1)));
+        assert_eq!(metric_distance("DEN_DIFF", &f("1/7"), &f("3/4")), Some((3, 1)));
+        // log(2) < log(3): 4 is nearer 2 than 2/3 is, by ratio
+        assert_eq!(metric_distance("LOG_RATIO", &f("2/1"), &f("4/1")), Some((4, 2)));
+        assert_eq!(metric_distance("LOG_RATIO", &f("2/1"), &f("-4/1")), None);
+        let set = [f("-1/1"), f("2/3"), f("4/1")];
+        assert_eq!(witness_nearest_by(&set, &f("2/1"), "LOG_RATIO"), Some(f("4/1")));
+        assert_eq!(witness_nearest_by(&set, &f("1/2"), "MEDIANT_DEPTH"), Some(f("2/3")));
+        assert_eq!(witness_nearest_by(&set[..1], &f("2/1"), "LOG_RATIO"), None);
+    }
+
+    #[test]
+    fn multi_target_witness() {
+        let f = |s: &str| parse_frac(s).unwrap();
+        let set = [f("0/1"), f("1/2"), f("3/4"), f("1/1")];
+        let ts = [f("0/1"), f("1/1")];
+        // minimax: 1/2 is 1/2 from both ends
+        assert_eq!(witness_nearest_multi(&set, &ts, true), Some(f("1/2")));
+        // sum: every point in [0,1] sums to 1; (|num|, den) prefers 0/1
+        assert_eq!(witness_nearest_multi(&set, &ts, false), Some(f("0/1")));
+        let ts = [f("0/1"), f("1/1"), f("1/1")];
+        assert_eq!(witness_nearest_multi(&set, &ts, false), Some(f("1/1")));
+        assert_eq!(witness_nearest_multi(&[], &ts, true), None);
+    }
+
+    #[test]
+    fn element_maps() {
+        let f = |s: &str| parse_frac(s).unwrap();
+        let set = [f("-1/2"), f("0/1"), f("1/2"), f("2/1")];
+        assert_eq!(map_set(&set, negate), vec![f("-2/1"), f("-1/2"), f("0/1"), f("1/2")]);
+        assert_eq!(map_set(&set, reciprocal), vec![f("-2/1"), f("1/2"), f("2/1")]);
+        // 0/1 ⊕ 1/1 = 1/2 and 1/2 ⊕ 1/1 = 2/3; -1/2 ⊕ 1/1 = 0/3 = 0
+        let m = map_set(&set, |x| Some(mediant(x, &f("1/1"))));
+        assert_eq!(m, vec![f("0/1"), f("1/2"), f("2/3"), f("3/2")]);
+    }
+
+    #[test]
+    fn range_bounds_are_exact() {
+        let lo = parse_bound("1/4").unwrap();
+        let hi = parse_bound("1/2").unwrap();
+        assert!(in_range(&Frac { num: 1, den: 4 }, &lo, &hi));
+        assert!(in_range(&Frac { num: 1, den: 2 }, &lo, &hi));
+        assert!(!in_range(&Frac { num: 49, den: 199 }, &lo, &hi));
+        assert_eq!(parse_bound("3"), Some(Frac { num: 3, den: 1 }));
+    }
 }
@@ -0,0 +1,746 @@
+//! Query proposers — natural-language query → explicit op script.
+//!
+//! A `Proposer` turns a query into the space-separated op lines that
+//! `exec::run_trace_and_write` executes and the verifier replays. Proposals are
+//! never trusted: whatever a backend emits is checked by replay, so backends can
+//! be swapped without touching the executor.
+//!
+//! Backends are looked up by name in a `Registry`. The built-in `compiler`
+//! backend wraps the deterministic candidate compiler (src/compiler.rs) and
+//! picks the most selective candidate; `rules` pattern-matches the common query
+//! shapes straight to op lines; `fixture` replays canned scripts from a file.
+//! Library users can register their own backends (remote models, fixed scripts
+//! for tests) next to them.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+
+use crate::compiler::{compile_query_to_candidates, Candidate};
+use crate::exec::{run_trace_and_write_with, ExecutionResult};
+use crate::fewshot::Example;
+use crate::intent::{classify, Intent};
+use crate::qe::{build_qe, parse_frac, Frac};
+use crate::semtrace::{sig7, Constraint, Op, Trace};
+
+/// One proposal: the op script to execute plus how it was chosen.
+#[derive(Clone, Debug)]
+pub struct ProposedTrace {
+    pub ops: Vec<String>,
+    pub rationale: String,
+    /// Structured semtrace, when the backend produced one (written next to the run for audit).
+    pub trace: Option<Trace>,
+    /// Backend exchange log (e.g. API request/response), stored with the run artifacts.
+    pub log: Option<JsonValue>,
+    pub provenance: Provenance,
+}
+
+/// Where a proposal came from, persisted under `proposer` in proof.json so a run
+/// can be audited back to the model output.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Provenance {
+    pub backend: String,
+    pub model: Option<String>,
+    /// System prompt sent to the model.
+    pub prompt: Option<String>,
+    /// Raw text of the model reply the ops were parsed from.
+    pub raw_text: Option<String>,
+    /// Total tokens reported by the provider, summed over repair attempts.
+    pub tokens: Option<u64>,
+    /// "cpu" for in-process backends, the endpoint URL for served models.
+    pub device: Option<String>,
+    /// exp(mean token logprob) over the op lines, when the model reports logprobs.
+    pub confidence: Option<f64>,
+    /// The same per op line, in script order.
+    pub op_confidence: Option<Vec<f64>>,
+    /// The backend failed and its fallback produced the ops.
+    pub fallback_used: bool,
+}
+
+impl Provenance {
+    /// In-process backend with no model.
+    pub fn offline(backend: &str) -> Self {
+        Provenance { backend: backend.to_string(), device: Some("cpu".to_string()), ..Provenance::default() }
+    }
+}
+
+impl ProposedTrace {
+    /// Provenance as JSON, with the keys of `extra` (e.g. CLI sampling settings) merged in.
+    pub fn provenance_json(&self, extra: Option<&JsonValue>) -> JsonValue {
+        let mut v = serde_json::to_value(&self.provenance).expect("provenance serializes");
+        if let (Some(obj), Some(JsonValue::Object(more))) = (v.as_object_mut(), extra) {
+            obj.extend(more.iter().map(|(k, x)| (k.clone(), x.clone())));
+        }
+        v
+    }
+
+    /// The ops, read through the op grammar.
+    pub fn typed_ops(&self) -> Result<Vec<Op>> {
+        self.ops.iter().map(|o| o.parse()).collect()
+    }
+}
+
+pub trait Proposer {
+    fn name(&self) -> &str;
+    fn propose(&self, query: &str) -> Result<ProposedTrace>;
+
+    /// Up to `k` alternative proposals, best first (for `run_beam`).
+    /// Backends without alternatives return their single proposal.
+    fn propose_k(&self, query: &str, _k: usize) -> Result<Vec<ProposedTrace>> {
+        Ok(vec![self.propose(query)?])
+    }
+
+    /// Revise `previous` after step `emptied` reduced the set to nothing; `feedback`
+    /// lists the per-step counts. The default drops the step that emptied the set.
+    fn refine(&self, _query: &str, previous: &ProposedTrace, emptied: usize, _feedback: &str) -> Result<ProposedTrace> {
+        relax(previous, emptied)
+    }
+}
+
+/// `previous` without step `emptied`. The first op sets up the universe and is never dropped.
+pub fn relax(previous: &ProposedTrace, emptied: usize) -> Result<ProposedTrace> {
+    if emptied == 0 || emptied >= previous.ops.len() {
+        return Err(anyhow!("cannot relax step {} of {}", emptied, previous.ops.len()));
+    }
+    let mut ops = previous.ops.clone();
+    let dropped = ops.remove(emptied);
+    Ok(ProposedTrace {
+        ops,
+        rationale: format!("dropped `{}`, which emptied the set", dropped),
+        trace: None,
+        log: None,
+        provenance: previous.provenance.clone(),
+    })
+}
+
+/// Score candidates by QE selectivity (fraction of the universe their SET_BITs admit)
+/// and sort ascending, so the tightest candidate comes first.
+pub fn rank_by_selectivity(cands: &mut [Candidate]) {
+    let qe = build_qe();
+    let universe_size = qe.len() as f64;
+    for c in cands.iter_mut() {
+        let mut cst = Constraint::empty();
+        for op in &c.trace.ops {
+            if let Op::SetBit { i, b } = op {
+                cst = cst.set_bit(*i, *b);
+            }
+        }
+        let matching = qe.iter().filter(|f| cst.matches(sig7(f))).count() as f64;
+        c.score = matching / universe_size;
+    }
+    cands.sort_by(|a, b| a.score.total_cmp(&b.score));
+}
+
+/// The deterministic candidate compiler; proposes its most selective candidate.
+pub struct CompilerProposer;
+
+impl Proposer for CompilerProposer {
+    fn name(&self) -> &str {
+        "compiler"
+    }
+
+    fn propose(&self, query: &str) -> Result<ProposedTrace> {
+        Ok(self.propose_k(query, 1)?.remove(0))
+    }
+
+    fn propose_k(&self, query: &str, k: usize) -> Result<Vec<ProposedTrace>> {
+        let mut cands = match compile_query_to_candidates(query) {
+            Ok(c) => c,
+            // The compiler is fraction-first; other universes go to the rules backend.
+            Err(e) => {
+                let intent = classify(query).intent;
+                if intent == Intent::Qe {
+                    return Err(e);
+                }
+                let mut p = RulesProposer.propose(query)?;
+                p.rationale = format!("{} query routed to {}", intent.as_str(), p.rationale);
+                return Ok(vec![p]);
+            }
+        };
+        if cands.is_empty() {
+            return Err(anyhow!("unable to compile query; provide explicit JSON ops"));
+        }
+        rank_by_selectivity(&mut cands);
+        Ok(cands
+            .into_iter()
+            .take(k.max(1))
+            .map(|c| ProposedTrace {
+                ops: c.trace.ops.iter().map(Op::to_string).collect(),
+                rationale: format!("{} (score={:.4})", c.rationale, c.score),
+                trace: Some(c.trace),
+                log: None,
+                provenance: Provenance::offline("compiler"),
+            })
+            .collect())
+    }
+}
+
+/// One executed beam candidate.
+#[derive(Clone, Debug, Serialize)]
+pub struct BeamEntry {
+    pub index: usize,
+    pub ops: Vec<String>,
+    pub rationale: String,
+    pub valid: bool,
+    pub final_count: usize,
+    pub witness: Option<String>,
+    /// |witness − target| as an exact "num/den" when both are fractions.
+    pub witness_distance: Option<String>,
+    pub artifacts: Option<String>,
+    pub error: Option<String>,
+}
+
+pub const BEAM_POLICY: &str = "valid > nonempty > smallest witness distance > proposal order";
+
+/// Fraction target of a script: WITNESS_NEAREST target_elem, else the LOAD element.
+fn frac_target(ops: &[Op]) -> Option<Frac> {
+    let nearest = ops.iter().find_map(|o| match o {
+        Op::WitnessNearest { target_elem: Some(t), .. } => Some(t.as_str()),
+        _ => None,
+    });
+    let load = ops.iter().find_map(|o| match o {
+        Op::StartElem { elem } => Some(elem.as_str()),
+        _ => None,
+    });
+    nearest.or(load).and_then(parse_frac)
+}
+
+fn witness_distance(ops: &[Op], witness: Option<&str>) -> Option<(i128, i128)> {
+    let (t, w) = (frac_target(ops)?, parse_frac(witness?)?);
+    let num = (t.num as i128 * w.den as i128 - w.num as i128 * t.den as i128).abs();
+    Some((num, t.den as i128 * w.den as i128))
+}
+
+/// Execute every proposal, pick the best by `BEAM_POLICY`, and write `beam.json`
+/// (all candidates plus the selection rationale) into the selected run's directory.
+/// Each run's proof.json records its proposal's provenance, merged with `extra`.
+/// Returns the selected index, every entry, and the selected run's result.
+pub fn run_beam(
+    proposals: &[ProposedTrace],
+    verbose: bool,
+    extra: Option<&JsonValue>,
+) -> Result<(usize, Vec<BeamEntry>, ExecutionResult)> {
+    let _span = tracing::info_span!("proposer", beam = proposals.len()).entered();
+    let mut entries = Vec::with_capacity(proposals.len());
+    let mut results: Vec<Option<ExecutionResult>> = Vec::with_capacity(proposals.len());
+    let mut dists = Vec::with_capacity(proposals.len());
+    for (index, p) in proposals.iter().enumerate() {
+        let run = run_trace_and_write_with(&p.ops, None, verbose, Some(&p.provenance_json(extra)));
+        let (valid, final_count, witness, artifacts, error) = match &run {
+            Ok(r) => (
+                r.valid,
+                r.final_count,
+                r.witness.clone(),
+                r.artifacts_path.as_ref().map(|d| d.display().to_string()),
+                None,
+            ),
+            Err(e) => (false, 0, None, None, Some(e.to_string())),
+        };
+        let dist = witness_distance(&p.typed_ops().unwrap_or_default(), witness.as_deref());
+        dists.push(dist);
+        entries.push(BeamEntry {
+            index,
+            ops: p.ops.clone(),
+            rationale: p.rationale.clone(),
+            valid,
+            final_count,
+            witness,
+            witness_distance: dist.map(|(n, d)| format!("{}/{}", n, d)),
+            artifacts,
+            error,
+        });
+        results.push(run.ok());
+    }
+
+    // best first: valid, nonempty, known distance, smaller distance, earlier index
+    let better = |a: usize, b: usize| -> Ordering {
+        let (ea, eb) = (&entries[a], &entries[b]);
+        eb.valid
+            .cmp(&ea.valid)
+            .then((eb.final_count > 0).cmp(&(ea.final_count > 0)))
+            .then_with(|| match (dists[a], dists[b]) {
+                (Some(x), Some(y)) => (x.0 * y.1).cmp(&(y.0 * x.1)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then(a.cmp(&b))
+    };
+    let selected = (0..entries.len())
+        .min_by(|&a, &b| better(a, b))
+        .ok_or_else(|| anyhow!("beam is empty"))?;
+    let result = results[selected]
+        .take()
+        .ok_or_else(|| anyhow!("no beam candidate executed: {}", entries[selected].error.clone().unwrap_or_default()))?;
+
+    let e = &entries[selected];
+    let rationale = format!(
+        "candidate {} of {}: valid={} count={} distance={}",
+        selected,
+        entries.len(),
+        e.valid,
+        e.final_count,
+        e.witness_distance.as_deref().unwrap_or("n/a")
+    );
+    if let Some(dir) = result.artifacts_path.as_ref() {
+        let beam = json!({
+            "policy": BEAM_POLICY,
+            "selected": selected,
+            "rationale": rationale,
+            "candidates": entries,
+        });
+        fs::write(dir.join("beam.json"), serde_json::to_string_pretty(&beam)?)?;
+    }
+    Ok((selected, entries, result))
+}
+
+/// The first step that took a nonempty set to empty, with feedback for the proposer
+/// listing the count after every step. None if no step emptied the set.
+pub fn empty_set_feedback(ops: &[String], run_dir: &Path) -> Result<Option<(usize, String)>> {
+    let mut lines = vec!["The op script returned an empty set. Count after each step:".to_string()];
+    let mut emptied = None;
+    for line in fs::read_to_string(run_dir.join("trace.ndjson"))?.lines() {
+        let rec: JsonValue = serde_json::from_str(line)?;
+        let step = rec["step"].as_u64().unwrap_or(0) as usize;
+        let (pre, post) = (rec["pre"]["count"].as_u64().unwrap_or(0), rec["post"]["count"].as_u64().unwrap_or(0));
+        let op = ops.get(step).map_or_else(|| rec["op"].to_string(), String::clone);
+        lines.push(format!("  {}: {} -> {}", step, op, post));
+        if emptied.is_none() && pre > 0 && post == 0 {
+            emptied = Some((step, op));
+        }
+    }
+    Ok(emptied.map(|(step, op)| {
+        lines.push(format!("Step {} (`{}`) emptied the set. Reply with a revised op script only.", step, op));
+        (step, lines.join("\n"))
+    }))
+}
+
+/// One round of `refine_until_nonempty`; round 0 is the original proposal.
+#[derive(Clone, Debug, Serialize)]
+pub struct RefineRound {
+    pub round: usize,
+    pub ops: Vec<String>,
+    pub rationale: String,
+    /// Feedback that produced this round's ops.
+    pub feedback: Option<String>,
+    pub valid: bool,
+    pub final_count: usize,
+}
+
+/// While `result` is empty, feed the per-step counts back to `proposer` for a revised
+/// script and execute it, at most `max_refinements` times. Every round's trace,
+/// result and proof are copied into the first run's directory as
+/// `round_<i>.{trace.ndjson,result.json,proof.json}`, next to `refinements.json`.
+/// The returned result is the last round's own run; it gets a copy of `refinements.json`.
+pub fn refine_until_nonempty(
+    proposer: &dyn Proposer,
+    query: &str,
+    proposal: ProposedTrace,
+    result: ExecutionResult,
+    max_refinements: usize,
+    verbose: bool,
+    extra: Option<&JsonValue>,
+) -> Result<(ProposedTrace, ExecutionResult, Vec<RefineRound>)> {
+    let _span = tracing::info_span!("proposer", proposer = proposer.name(), max_refinements).entered();
+    let round_of = |round: usize, p: &ProposedTrace, r: &ExecutionResult, feedback: Option<String>| RefineRound {
+        round,
+        ops: p.ops.clone(),
+        rationale: p.rationale.clone(),
+        feedback,
+        valid: r.valid,
+        final_count: r.final_count,
+    };
+    let mut rounds = vec![round_of(0, &proposal, &result, None)];
+    let Some(run_dir) = result.artifacts_path.clone() else {
+        return Ok((proposal, result, rounds));
+    };
+    let (mut proposal, mut result) = (proposal, result);
+    while result.final_count == 0 && rounds.len() <= max_refinements {
+        let Some(dir) = result.artifacts_path.clone() else { break };
+        let Some((emptied, feedback)) = empty_set_feedback(&proposal.ops, &dir)? else { break };
+        let next = match proposer.refine(query, &proposal, emptied, &feedback) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::info!(error = %e, "refinement stopped");
+                break;
+            }
+        };
+        let r = run_trace_and_write_with(&next.ops, None, verbose, Some(&next.provenance_json(extra)))?;
+        rounds.push(round_of(rounds.len(), &next, &r, Some(feedback)));
+        if let Some(round_dir) = r.artifacts_path.as_ref() {
+            let i = rounds.len() - 1;
+            for name in ["trace.ndjson", "result.json", "proof.json"] {
+                fs::copy(round_dir.join(name), run_dir.join(format!("round_{}.{}", i, name)))?;
+            }
+        }
+        proposal = next;
+        result = r;
+    }
+    if rounds.len() > 1 {
+        let summary = json!({ "max_refinements": max_refinements, "first_run": run_dir, "rounds": rounds });
+        let summary = serde_json::to_string_pretty(&summary)?;
+        fs::write(run_dir.join("refinements.json"), &summary)?;
+        if let Some(last) = result.artifacts_path.as_ref() {
+            fs::write(last.join("refinements.json"), &summary)?;
+        }
+    }
+    Ok((proposal, result, rounds))
+}
+
+/// Offline pattern rules for the common query shapes, no model required:
+///   - "fractions similar to 13/37 with denominator ≤ 10"  (QE; positive/proper hints, top N)
+///   - "boolean functions of weight 8 closest to 0xBEEF"    (BOOLFUN n=4)
+///   - "triangles similar to 3,4,5 with area ≥ 10"          (GE; max angle bound)
+pub struct RulesProposer;
+
+fn capture_u64(re: &str, q: &str) -> Option<u64> {
+    Regex::new(re).ok()?.captures(q)?.get(1)?.as_str().parse().ok()
+}
+
+fn capture_str(re: &str, q: &str) -> Option<String> {
+    Some(Regex::new(re).ok()?.captures(q)?.get(1)?.as_str().to_string())
+}
+
+const LE: &str = r"(?:≤|<=|<|at most|up to|below)";
+const GE: &str = r"(?:≥|>=|>|at least|above)";
+
+impl RulesProposer {
+    fn triangle(q: &str, tri: String) -> ProposedTrace {
+        let mut ops = vec![format!("LOAD {}", tri)];
+        let mut why = vec!["triangle".to_string()];
+        if let Some(a) = capture_u64(&format!(r"area\s*{}\s*(\d+)", GE), q) {
+            ops.push(format!("FILTER_AREA min={} max={}", a, i32::MAX));
+            why.push(format!("area>={}", a));
+        }
+        if let Some(d) = capture_u64(&format!(r"angles?\s*{}\s*(\d+)", LE), q) {
+            ops.push(format!("FILTER_MAX_ANGLE max={}", d));
+            why.push(format!("max angle<={}", d));
+        } else if q.contains("nearly equilateral") {
+            ops.push("FILTER_MAX_ANGLE max=70".to_string());
+            why.push("nearly equilateral: max angle<=70".to_string());
+        }
+        let metric = if q.contains("similar") || q.contains("shape") { "SIMILARITY" } else { "L1" };
+        ops.push(format!("WITNESS_NEAREST target_elem={} metric={}", tri, metric));
+        why.push(format!("metric={}", metric));
+        ops.push(format!("RETURN_SET max_items={} include_witness=1", Self::max_items(q)));
+        ProposedTrace {
+            ops,
+            rationale: format!("rules: {}", why.join(", ")),
+            trace: None,
+            log: None,
+            provenance: Provenance::offline("rules"),
+        }
+    }
+
+    fn boolfun(q: &str, target: String) -> ProposedTrace {
+        let mut ops = vec!["SELECT_UNIVERSE universe=BOOLFUN n=4".to_string()];
+        let mut why = vec!["boolfun n=4".to_string()];
+        if let Some(w) = capture_u64(r"weight\s*(?:=|of)?\s*(\d+)", q) {
+            ops.push(format!("FILTER_WEIGHT min={} max={}", w, w));
+            why.push(format!("weight={}", w));
+        }
+        let k = Self::max_items(q);
+        ops.push(format!("TOPK target_elem={} k={}", target, k));
+        ops.push(format!("RETURN_SET max_items={} include_witness=1", k));
+        why.push(format!("nearest to {}", target));
+        ProposedTrace {
+            ops,
+            rationale: format!("rules: {}", why.join(", ")),
+            trace: None,
+            log: None,
+            provenance: Provenance::offline("rules"),
+        }
+    }
+
+    fn fraction(q: &str, target: String) -> ProposedTrace {
+        let mut ops = vec![format!("LOAD {}", target)];
+        let mut why = vec!["fraction".to_string()];
+        if let Some(k) = capture_u64(&format!(r"(?:denominators?|den)\s*{}\s*(\d+)", LE), q) {
+            if k == 6 {
+                ops.push("MASK_BIT bit=2 val=1".to_string());
+            } else {
+                ops.push(format!("DEFINE_PRED name=den_le_{} expr=den<={}", k, k));
+                ops.push(format!("FILTER_PRED name=den_le_{} val=1", k));
+            }
+            why.push(format!("den<={}", k));
+        }
+        if q.contains("positive") {
+            ops.push("MASK_BIT bit=0 val=1".to_string());
+            why.push("positive".to_string());
+        }
+        if q.contains("proper") {
+            ops.push("MASK_BIT bit=5 val=1".to_string());
+            why.push("proper".to_string());
+        }
+        ops.push(format!("WITNESS_NEAREST target_elem={} metric=ABS_DIFF", target));
+        ops.push(format!("RETURN_SET max_items={} include_witness=1", Self::max_items(q)));
+        ProposedTrace {
+            ops,
+            rationale: format!("rules: {}", why.join(", ")),
+            trace: None,
+            log: None,
+            provenance: Provenance::offline("rules"),
+        }
+    }
+
+    /// "top 5", "first 5", "limit 5"; default 20.
+    fn max_items(q: &str) -> usize {
+        capture_u64(r"(?:top|first|limit)\s*(\d+)", q).unwrap_or(20) as usize
+    }
+}
+
+impl Proposer for RulesProposer {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    fn propose(&self, query: &str) -> Result<ProposedTrace> {
+        let q = query.trim().to_lowercase();
+        if let Some(tri) = capture_str(r"(\d+\s*,\s*\d+\s*,\s*\d+)", &q) {
+            if q.contains("triangle") {
+                let tri: String = tri.chars().filter(|c| !c.is_whitespace()).collect();
+                return Ok(Self::triangle(&q, tri));
+            }
+        }
+        if let Some(target) = capture_str(r"\b(0x[0-9a-f]{1,4})\b", &q) {
+            return Ok(Self::boolfun(&q, format!("0x{}", target[2..].to_uppercase())));
+        }
+        if let Some(target) = capture_str(r"(-?\d+/\d+)", &q) {
+            return Ok(Self::fraction(&q, target));
+        }
+        Err(anyhow!("rules proposer: no rule matches query '{}'", query.trim()))
+    }
+}
+
+/// Always proposes the same script; for tests and scripted pipelines.
+pub struct FixedProposer {
+    pub ops: Vec<String>,
+}
+
+impl Proposer for FixedProposer {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    fn propose(&self, _query: &str) -> Result<ProposedTrace> {
+        Ok(ProposedTrace {
+            ops: self.ops.clone(),
+            rationale: "fixed script".to_string(),
+            trace: None,
+            log: None,
+            provenance: Provenance::offline("fixed"),
+        })
+    }
+}
+
+/// Replays canned scripts per query from a fixture file, so end-to-end runs need
+/// no model. The file is a JSON array of `{"query": ..., "ops": [...]}`, or the same
+/// objects one per line (so examples/fewshot.jsonl works as a fixture); queries
+/// match exactly after trimming, ignoring case.
+pub struct FixtureProposer {
+    pub source: String,
+    pub entries: Vec<Example>,
+}
+
+impl FixtureProposer {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| anyhow!("reading fixture {}: {}", path.display(), e))?;
+        let entries = if text.trim_start().starts_with('[') {
+            serde_json::from_str(&text).map_err(|e| anyhow!("parsing fixture {}: {}", path.display(), e))?
+        } else {
+            crate::fewshot::load_bank(&text).map_err(|e| anyhow!("parsing fixture {}: {}", path.display(), e))?
+        };
+        Ok(FixtureProposer { source: path.display().to_string(), entries })
+    }
+}
+
+impl Proposer for FixtureProposer {
+    fn name(&self) -> &str {
+        "fixture"
+    }
+
+    fn propose(&self, query: &str) -> Result<ProposedTrace> {
+        let q = query.trim();
+        let e = self
+            .entries
+            .iter()
+            .find(|e| e.query.trim().eq_ignore_ascii_case(q))
+            .ok_or_else(|| anyhow!("no fixture for query {:?} in {} ({} entries)", q, self.source, self.entries.len()))?;
+        Ok(ProposedTrace {
+            ops: e.ops.clone(),
+            rationale: format!("fixture: {}", self.source),
+            trace: None,
+            log: None,
+            provenance: Provenance { model: Some(self.source.clone()), ..Provenance::offline("fixture") },
+        })
+    }
+}
+
+/// Named proposer backends, in registration order.
+pub struct Registry {
+    backends: Vec<Box<dyn Proposer>>,
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Registry { backends: Vec::new() }
+    }
+
+    /// The built-in backends.
+    pub fn with_defaults() -> Self {
+        let mut r = Registry::empty();
+        r.register(Box::new(CompilerProposer));
+        r.register(Box::new(RulesProposer));
+        r
+    }
+
+    /// Add a backend; a later registration under the same name replaces the earlier one.
+    pub fn register(&mut self, p: Box<dyn Proposer>) {
+        self.backends.retain(|b| b.name() != p.name());
+        self.backends.push(p);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Proposer> {
+        self.backends
+            .iter()
+            .find(|b| b.name() == name)
+            .map(|b| b.as_ref())
+            .ok_or_else(|| anyhow!("unknown proposer '{}' (available: {})", name, self.names().join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiler_backend_proposes_executable_ops() {
+        let reg = Registry::with_defaults();
+        let p = reg.get("compiler").unwrap().propose("fractions near 13/37 with den<=6").unwrap();
+        assert_eq!(p.ops.first().map(String::as_str), Some("LOAD 13/37"));
+        assert!(p.ops.iter().any(|o| o == "MASK_BIT bit=2 val=1"));
+        assert!(p.trace.is_some());
+        assert!(reg.get("nope").is_err());
+    }
+
+    #[test]
+    fn rules_backend_covers_common_shapes() {
+        let run = |q: &str| {
+            let p = RulesProposer.propose(q).unwrap();
+            let r = crate::exec::run_trace_and_write(&p.ops, None, false).unwrap();
+            assert!(r.valid, "{}: {:?}", q, p.ops);
+            (p, r)
+        };
+
+        let (p, r) = run("fractions similar to 13/37 with denominator ≤ 10, top 5");
+        assert!(p.ops.contains(&"FILTER_PRED name=den_le_10 val=1".to_string()));
+        assert_eq!(r.witness.as_deref(), Some("1/3"));
+
+        let (p, r) = run("boolean functions of weight 8 closest to 0xBEEF");
+        assert!(p.ops.contains(&"FILTER_WEIGHT min=8 max=8".to_string()));
+        assert_eq!(r.final_count, 20);
+
+        let (p, r) = run("nearly equilateral triangles similar to 5,5,6 with area >= 10");
+        assert!(p.ops.iter().any(|o| o.starts_with("FILTER_MAX_ANGLE max=70")));
+        assert!(r.witness.is_some());
+
+        assert!(RulesProposer.propose("hello world").is_err());
+    }
+
+    #[test]
+    fn beam_prefers_valid_then_closest() {
+        let bad = FixedProposer { ops: vec!["LOAD 13/37".to_string(), "MASK_BIT bit=99 val=1".to_string()] };
+        let far = FixedProposer {
+            ops: vec![
+                "LOAD 13/37".to_string(),
+                "MASK_BIT bit=1 val=1".to_string(),
+                "WITNESS_NEAREST target_elem=13/37 metric=ABS_DIFF".to_string(),
+            ],
+        };
+        let mut beam = CompilerProposer.propose_k("fractions near 13/37 with den<=6", 3).unwrap();
+        assert!(beam.len() > 1);
+        beam.insert(0, far.propose("").unwrap());
+        beam.insert(0, bad.propose("").unwrap());
+        assert!(!beam.iter().any(|p| p.provenance.fallback_used));
+        let (selected, entries, result) = run_beam(&beam, false, Some(&json!({ "beam": 5 }))).unwrap();
+        assert!(entries[0].error.is_some());
+        // integer-only witness 0/1 is farther than the den<=6 candidates' 1/3
+        assert_eq!(entries[1].witness.as_deref(), Some("0/1"));
+        assert!(selected >= 2);
+        assert_eq!(result.witness.as_deref(), Some("1/3"));
+        let dir = result.artifacts_path.unwrap();
+        let beam_json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("beam.json")).unwrap()).unwrap();
+        assert_eq!(beam_json["selected"], json!(selected));
+        assert_eq!(beam_json["candidates"].as_array().unwrap().len(), entries.len());
+        let out: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("result.json")).unwrap()).unwrap();
+        assert_eq!(out["fallback_used"], json!(false));
+        let proof: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("proof.json")).unwrap()).unwrap();
+        assert_eq!(proof["proposer"]["backend"], json!("compiler"));
+        assert_eq!(proof["proposer"]["device"], json!("cpu"));
+        assert_eq!(proof["proposer"]["beam"], json!(5));
+    }
+
+    #[test]
+    fn refinement_relaxes_the_step_that_emptied_the_set() {
+        // no positive integer is proper
+        let fixed = FixedProposer {
+            ops: vec![
+                "LOAD 13/37".to_string(),
+                "MASK_BIT bit=0 val=1".to_string(),
+                "MASK_BIT bit=1 val=1".to_string(),
+                "MASK_BIT bit=5 val=1".to_string(),
+                "RETURN_SET max_items=5 include_witness=0".to_string(),
+            ],
+        };
+        let first = fixed.propose("").unwrap();
+        let r = run_trace_and_write_with(&first.ops, None, false, None).unwrap();
+        assert_eq!(r.final_count, 0);
+        let dir = r.artifacts_path.clone().unwrap();
+        let (step, feedback) = empty_set_feedback(&first.ops, &dir).unwrap().unwrap();
+        assert_eq!(step, 3);
+        assert!(feedback.contains("Step 3 (`MASK_BIT bit=5 val=1`) emptied the set"));
+
+        let (last, result, rounds) = refine_until_nonempty(&fixed, "", first, r, 2, false, None).unwrap();
+        assert_eq!(rounds.len(), 2);
+        assert!(result.final_count > 0);
+        assert!(!last.ops.contains(&"MASK_BIT bit=5 val=1".to_string()));
+        assert!(result.artifacts_path.as_ref().unwrap().join("refinements.json").exists());
+        assert!(dir.join("round_1.result.json").exists());
+        assert!(dir.join("refinements.json").exists());
+    }
+
+    #[test]
+    fn fixture_replays_canned_ops() {
+        let path = std::env::temp_dir().join(format!("fixture_{}.json", std::process::id()));
+        fs::write(&path, r#"[{"query": "Halves", "ops": ["LOAD 1/2", "RETURN_SET max_items=3 include_witness=1"]}]"#)
+            .unwrap();
+        let p = FixtureProposer::from_file(&path).unwrap();
+        let t = p.propose("  halves ").unwrap();
+        assert_eq!(t.ops[0], "LOAD 1/2");
+        assert_eq!(t.provenance.backend, "fixture");
+        assert!(p.propose("thirds").unwrap_err().to_string().contains("no fixture"));
+        fs::remove_file(&path).unwrap();
+
+        let bank = FixtureProposer::from_file(Path::new("examples/fewshot.jsonl")).unwrap();
+        assert_eq!(bank.propose("integers nearest to 7/2").unwrap().ops[0], "LOAD 7/2");
+    }
+
+    #[test]
+    fn registered_backend_replaces_by_name() {
+        let mut reg = Registry::with_defaults();
+        reg.register(Box::new(FixedProposer { ops: vec!["LOAD 1/2".to_string()] }));
+        assert_eq!(reg.names(), vec!["compiler", "rules", "fixed"]);
+        assert_eq!(reg.get("fixed").unwrap().propose("anything").unwrap().ops, vec!["LOAD 1/2"]);
+    }
+}
@@ -0,0 +1,183 @@
+//! JSON Schemas for the files a run reads and writes, derived from their
+//! serde types: the JSON trace it starts from, the trace.ndjson step records,
+//! proof.json and result.json. `lnst schema` prints them.
+//!
+//! `validate_trace` checks an incoming JSON trace against its schema before
+//! anything executes and names each problem by JSON pointer, e.g.
+//! `/ops/1: "i" is a required property`. The trace schema dispatches on each
+//! op's `op` with `if`/`then` rather than `oneOf`, so a bad op is reported
+//! against its own fields instead of as matching none of the alternatives.
+
+use anyhow::{anyhow, Result};
+use schemars::{schema_for, JsonSchema, Schema};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::digest::HashAlg;
+use crate::exec::{RunProof, RunResult, StepRecord};
+use crate::semtrace::Op;
+
+/// Names `schema` accepts.
+pub const NAMES: &[&str] = &["trace", "step", "proof", "result"];
+
+/// A JSON trace: a bare array of op lines, or a semtrace object.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(transform = array_or_object)]
+pub enum TraceJson {
+    /// Op lines as the grammar writes them, e.g. `"MASK_BIT bit=2 val=1"`.
+    Lines(Vec<String>),
+    Semtrace(Semtrace),
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+pub struct Semtrace {
+    #[serde(default)]
+    pub semtrace_version: Option<String>,
+    #[serde(default)]
+    pub universe: Option<String>,
+    #[serde(default)]
+    pub bits: Option<u32>,
+    /// Set-digest hash backend for the run.
+    #[serde(default)]
+    pub hash: Option<TraceHash>,
+    #[serde(default)]
+    pub query: Option<String>,
+    pub ops: Vec<Op>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceHash {
+    Sha256,
+    Blake3,
+}
+
+/// `TraceJson` is an array or an object; say which branch applies so errors
+/// come from that branch alone.
+fn array_or_object(schema: &mut Schema) {
+    let Some(JsonValue::Array(mut branches)) = schema.remove("anyOf") else { return };
+    if branches.len() != 2 {
+        return;
+    }
+    let (lines, object) = (branches.remove(0), branches.remove(0));
+    schema.insert("if".into(), json!({ "type": "array" }));
+    schema.insert("then".into(), lines);
+    schema.insert("else".into(), object);
+}
+
+/// Turn the `oneOf` of op variants into one `if`/`then` per op name.
+pub(crate) fn dispatch_on_op(schema: &mut Schema) {
+    let Some(JsonValue::Array(variants)) = schema.remove("oneOf") else { return };
+    let names: Vec<JsonValue> = variants.iter().filter_map(|v| v.pointer("/properties/op/const").cloned()).collect();
+    let cases: Vec<JsonValue> = variants
+        .into_iter()
+        .map(|v| {
+            let name = v.pointer("/properties/op/const").cloned().unwrap_or(JsonValue::Null);
+            json!({ "if": { "properties": { "op": { "const": name } }, "required": ["op"] }, "then": v })
+        })
+        .collect();
+    schema.insert("type".into(), json!("object"));
+    schema.insert("properties".into(), json!({ "op": { "enum": names } }));
+    schema.insert("required".into(), json!(["op"]));
+    schema.insert("allOf".into(), JsonValue::Array(cases));
+}
+
+/// The schema `name` (one of `NAMES`) names.
+pub fn schema(name: &str) -> Option<Schema> {
+    Some(match name {
+        "trace" => schema_for!(TraceJson),
+        "step" => schema_for!(StepRecord),
+        "proof" => schema_for!(RunProof),
+        "result" => schema_for!(RunResult),
+        _ => return None,
+    })
+}
+
+/// Check a JSON trace against the `trace` schema, listing every problem with
+/// its JSON pointer.
+pub fn validate_trace(trace: &JsonValue) -> Result<()> {
+    let schema = schema_for!(TraceJson);
+    let validator = jsonschema::validator_for(schema.as_value()).map_err(|e| anyhow!("trace schema: {}", e))?;
+    let problems: Vec<String> = validator
+        .iter_errors(trace)
+        .map(|e| {
+            let at = e.instance_path().as_str();
+            format!("{}: {}", if at.is_empty() { "/" } else { at }, e.masked())
+        })
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!("trace does not match its schema:\n  {}", problems.join("\n  ")))
+}
+
+/// Op lines of a JSON trace: a bare array of op strings, or a semtrace object
+/// whose `ops` read as typed ops. The trace is checked against its schema and
+/// every op against the grammar before it is returned.
+pub fn trace_ops(trace: &JsonValue) -> Result<Vec<String>> {
+    validate_trace(trace)?;
+    let ops: Vec<String> = match serde_json::from_value(trace.clone())? {
+        TraceJson::Lines(lines) => lines,
+        TraceJson::Semtrace(trace) => trace.ops.iter().map(ToString::to_string).collect(),
+    };
+
+    // Check every op against the grammar before anything executes
+    for (i, op) in ops.iter().enumerate() {
+        crate::exec::validate_op(op).map_err(|e| anyhow!("trace op {} ({}): {}", i, op, e))?;
+    }
+    Ok(ops)
+}
+
+/// The `hash` header of a JSON trace object, if it names one.
+pub fn trace_hash(trace: &JsonValue) -> Result<Option<HashAlg>> {
+    match trace.get("hash") {
+        None => Ok(None),
+        Some(h) => h
+            .as_str()
+            .and_then(HashAlg::parse)
+            .map(Some)
+            .ok_or_else(|| anyhow!("trace header: unknown hash {} (sha256, blake3)", h)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_are_checked_against_their_schema() {
+        let demo: JsonValue = serde_json::from_str(include_str!("../trace_demo.json")).unwrap();
+        validate_trace(&demo).unwrap();
+        validate_trace(&json!(["LOAD 13/37", "MASK_BIT bit=2 val=1"])).unwrap();
+
+        let err = |t: JsonValue| validate_trace(&t).unwrap_err().to_string();
+        let missing = err(json!({ "ops": [{ "op": "START_ELEM", "elem": "1/3" }, { "op": "SET_BIT", "i": 2 }] }));
+        assert!(missing.contains("/ops/1: \"b\" is a required property"), "{}", missing);
+        let typed = err(json!({ "ops": [{ "op": "SAMPLE", "seed": "x", "k": 3 }] }));
+        assert!(typed.contains("/ops/0/seed:"), "{}", typed);
+        assert!(err(json!({ "ops": [{ "op": "NO_SUCH_OP" }] })).contains("/ops/0/op:"));
+        assert!(err(json!({ "hash": "md5", "ops": [] })).contains("/hash:"));
+        assert!(err(json!(["LOAD 13/37", 7])).contains("/1:"));
+        assert!(err(json!({ "universe": "QE" })).contains("\"ops\" is a required property"));
+    }
+
+    #[test]
+    fn written_artifacts_match_their_schemas() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "RETURN_SET max_items=3"].iter().map(|s| s.to_string()).collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let check = |name: &str, value: &JsonValue| {
+            let validator = jsonschema::validator_for(schema(name).unwrap().as_value()).unwrap();
+            let problems: Vec<String> = validator.iter_errors(value).map(|e| e.to_string()).collect();
+            assert!(problems.is_empty(), "{}: {:?}", name, problems);
+        };
+        let read = |f: &str| std::fs::read_to_string(dir.join(f)).unwrap();
+        for line in read("trace.ndjson").lines() {
+            check("step", &serde_json::from_str(line).unwrap());
+        }
+        check("proof", &serde_json::from_str(&read("proof.json")).unwrap());
+        check("result", &serde_json::from_str(&read("result.json")).unwrap());
+        assert!(schema("nope").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,13 @@
 use crate::qe::Frac;
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value as JsonValue;
+use std::fmt;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Trace {
@@ -11,34 +17,303 @@ pub struct Trace {
     pub ops: Vec<Op>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(tag = "op")]
+/// One op, typed. Its fields are the trace record's `args`: `args()` is what
+/// a step records and `from_record` reads a record back. Op lines
+/// (`"MASK_BIT bit=2 val=1"`) are a front-end: `str::parse` reads one through
+/// the executor's grammar and `Display` writes the canonical line.
+///
+/// In a semtrace object an op is `{"op": ..., <args>}`; there `target` is
+/// accepted for `target_elem`, and a `FILTER_RANGE` bound may be a number.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "SCREAMING_SNAKE_CASE")]
+#[schemars(transform = crate::schema::dispatch_on_op)]
 pub enum Op {
-    #[serde(rename = "START_ELEM")]
+    /// Written `LOAD <elem>`.
     StartElem { elem: String },
-    #[serde(rename = "SET_BIT")]
+    /// Written `MASK_BIT bit=<i> val=<b>`.
     SetBit { i: u8, b: u8 },
-    #[serde(rename = "SELECT_UNIVERSE")]
-    SelectUniverse { universe: String, n: u8 },
-    #[serde(rename = "FILTER_WEIGHT")]
+    SelectUniverse {
+        universe: String,
+        n: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        items: Option<Vec<i64>>,
+        /// Set-digest hash backend; only the first op may choose it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
     FilterWeight { min: u32, max: u32 },
+    FilterSum { min: i64, max: i64 },
+    FilterArea { min: u64, max: u64 },
+    FilterMaxAngle { max: u64 },
+    FilterRange {
+        #[serde(deserialize_with = "string_or_number")]
+        #[schemars(with = "Bound")]
+        min: String,
+        #[serde(deserialize_with = "string_or_number")]
+        #[schemars(with = "Bound")]
+        max: String,
+    },
+    FilterNonlinearity { min: u64, max: u64 },
+    FilterClass { class: String },
+    FilterConj { elem: String },
+    DefinePred { name: String, expr: String },
+    FilterPred { name: String, val: u64 },
+    Sample { seed: u64, k: u64 },
+    Complement,
+    Aggregate,
+    Negate,
+    Reciprocal,
+    PushState,
+    PopState,
+    Dual,
+    NpnClass {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        elem: Option<String>,
+    },
+    PermuteVars { perm: Vec<u64> },
+    Restrict { var: u64, value: u64 },
+    AssertCount { eq: u64 },
+    AssertWitness { elem: String },
+    MediantWith { elem: String },
+    GroupBy {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        by: Option<String>,
+    },
+    SaveSet { name: String },
+    Intersect { name: String },
+    Union { name: String },
+    /// Nearest to `target_elem`, or ranked by `metric` (BOOLFUN).
     #[serde(rename = "TOPK")]
-    TopK { target_elem: String, k: usize },
-    #[serde(rename = "WITNESS_NEAREST")]
-    WitnessNearest { target_elem: String, metric: String },
-    #[serde(rename = "RETURN_SET")]
-    ReturnSet {
-        max_items: usize,
-        include_witness: bool,
+    TopK {
+        k: usize,
+        #[serde(default, alias = "target", skip_serializing_if = "Option::is_none")]
+        target_elem: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metric: Option<String>,
+    },
+    /// Nearest to `target_elem`, or to all of `targets` by `mode`.
+    WitnessNearest {
+        #[serde(default, alias = "target", skip_serializing_if = "Option::is_none")]
+        target_elem: Option<String>,
+        #[serde(default = "abs_diff")]
+        metric: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        targets: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<String>,
     },
-    #[serde(rename = "JOIN_NEAREST")]
+    WitnessAllTies {
+        #[serde(alias = "target")]
+        target_elem: String,
+        #[serde(default = "abs_diff")]
+        metric: String,
+    },
+    ProjectSignature { elem: String },
     JoinNearest {
         left_universe: String,
         right_universe: String,
         left_elem: String,
         right_elem: String,
+        #[serde(default = "abs_diff")]
         metric: String,
     },
+    ReturnSet {
+        #[serde(default = "default_max_items")]
+        max_items: usize,
+        #[serde(default)]
+        include_witness: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sort_by: Option<String>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        include_proofs: bool,
+    },
+}
+
+/// A `FILTER_RANGE` bound as a semtrace object may give it.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum Bound {
+    Text(String),
+    Number(f64),
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    match JsonValue::deserialize(d)? {
+        JsonValue::String(s) => Ok(s),
+        n @ JsonValue::Number(_) => Ok(n.to_string()),
+        other => Err(D::Error::custom(format!("expected a string or a number, got {}", other))),
+    }
+}
+
+fn abs_diff() -> String {
+    "ABS_DIFF".to_string()
+}
+
+fn default_max_items() -> usize {
+    20
+}
+
+impl Op {
+    /// The op name a trace record carries.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::StartElem { .. } => "START_ELEM",
+            Op::SetBit { .. } => "SET_BIT",
+            Op::SelectUniverse { .. } => "SELECT_UNIVERSE",
+            Op::FilterWeight { .. } => "FILTER_WEIGHT",
+            Op::FilterSum { .. } => "FILTER_SUM",
+            Op::FilterArea { .. } => "FILTER_AREA",
+            Op::FilterMaxAngle { .. } => "FILTER_MAX_ANGLE",
+            Op::FilterRange { .. } => "FILTER_RANGE",
+            Op::FilterNonlinearity { .. } => "FILTER_NONLINEARITY",
+            Op::FilterClass { .. } => "FILTER_CLASS",
+            Op::FilterConj { .. } => "FILTER_CONJ",
+            Op::DefinePred { .. } => "DEFINE_PRED",
+            Op::FilterPred { .. } => "FILTER_PRED",
+            Op::Sample { .. } => "SAMPLE",
+            Op::Complement => "COMPLEMENT",
+            Op::Aggregate => "AGGREGATE",
+            Op::Negate => "NEGATE",
+            Op::Reciprocal => "RECIPROCAL",
+            Op::PushState => "PUSH_STATE",
+            Op::PopState => "POP_STATE",
+            Op::Dual => "DUAL",
+            Op::NpnClass { .. } => "NPN_CLASS",
+            Op::PermuteVars { .. } => "PERMUTE_VARS",
+            Op::Restrict { .. } => "RESTRICT",
+            Op::AssertCount { .. } => "ASSERT_COUNT",
+            Op::AssertWitness { .. } => "ASSERT_WITNESS",
+            Op::MediantWith { .. } => "MEDIANT_WITH",
+            Op::GroupBy { .. } => "GROUP_BY",
+            Op::SaveSet { .. } => "SAVE_SET",
+            Op::Intersect { .. } => "INTERSECT",
+            Op::Union { .. } => "UNION",
+            Op::TopK { .. } => "TOPK",
+            Op::WitnessNearest { .. } => "WITNESS_NEAREST",
+            Op::WitnessAllTies { .. } => "WITNESS_ALL_TIES",
+            Op::ProjectSignature { .. } => "PROJECT_SIGNATURE",
+            Op::JoinNearest { .. } => "JOIN_NEAREST",
+            Op::ReturnSet { .. } => "RETURN_SET",
+        }
+    }
+
+    /// The `args` a trace record holds for this op.
+    pub fn args(&self) -> JsonValue {
+        let mut v = serde_json::to_value(self).unwrap_or_default();
+        if let Some(m) = v.as_object_mut() {
+            m.remove("op");
+        }
+        v
+    }
+
+    /// Read a trace record's `op` and `args` back into an op.
+    pub fn from_record(op: &str, args: &JsonValue) -> Result<Op> {
+        let mut obj = match args {
+            JsonValue::Object(m) => m.clone(),
+            JsonValue::Null => serde_json::Map::new(),
+            other => return Err(anyhow!("{} args must be an object, got {}", op, other)),
+        };
+        obj.insert("op".to_string(), JsonValue::String(op.to_string()));
+        serde_json::from_value(JsonValue::Object(obj)).map_err(|e| anyhow!("bad args for {}: {}", op, e))
+    }
+}
+
+impl FromStr for Op {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Op> {
+        let (op, args) = crate::exec::parse_op(line)?;
+        Op::from_record(&op, &args)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.name();
+        match self {
+            Op::StartElem { elem } => write!(f, "LOAD {}", elem),
+            Op::SetBit { i, b } => write!(f, "MASK_BIT bit={} val={}", i, b),
+            Op::SelectUniverse { universe, n, group, items, hash } => {
+                write!(f, "SELECT_UNIVERSE universe={} n={}", universe, n)?;
+                if let Some(g) = group {
+                    write!(f, " group={}", g)?;
+                }
+                if let Some(items) = items {
+                    let items: Vec<String> = items.iter().map(i64::to_string).collect();
+                    write!(f, " items={}", items.join(","))?;
+                }
+                match hash {
+                    Some(h) => write!(f, " hash={}", h),
+                    None => Ok(()),
+                }
+            }
+            Op::FilterWeight { min, max } => write!(f, "{} min={} max={}", name, min, max),
+            Op::FilterSum { min, max } => write!(f, "{} min={} max={}", name, min, max),
+            Op::FilterArea { min, max } | Op::FilterNonlinearity { min, max } => write!(f, "{} min={} max={}", name, min, max),
+            Op::FilterRange { min, max } => write!(f, "{} min={} max={}", name, min, max),
+            Op::FilterMaxAngle { max } => write!(f, "{} max={}", name, max),
+            Op::FilterClass { class } => write!(f, "{} class={}", name, class),
+            Op::FilterConj { elem }
+            | Op::AssertWitness { elem }
+            | Op::MediantWith { elem }
+            | Op::ProjectSignature { elem }
+            | Op::NpnClass { elem: Some(elem) } => write!(f, "{} elem={}", name, elem),
+            Op::DefinePred { name: pred, expr } => write!(f, "{} name={} expr=\"{}\"", name, pred, expr),
+            Op::FilterPred { name: pred, val } => write!(f, "{} name={} val={}", name, pred, val),
+            Op::Sample { seed, k } => write!(f, "{} seed={} k={}", name, seed, k),
+            Op::Complement
+            | Op::Aggregate
+            | Op::Negate
+            | Op::Reciprocal
+            | Op::PushState
+            | Op::PopState
+            | Op::Dual
+            | Op::NpnClass { elem: None }
+            | Op::GroupBy { by: None } => f.write_str(name),
+            Op::GroupBy { by: Some(by) } => write!(f, "{} by={}", name, by),
+            Op::PermuteVars { perm } => {
+                let perm: Vec<String> = perm.iter().map(u64::to_string).collect();
+                write!(f, "{} perm={}", name, perm.join(","))
+            }
+            Op::Restrict { var, value } => write!(f, "{} var={} value={}", name, var, value),
+            Op::AssertCount { eq } => write!(f, "{} eq={}", name, eq),
+            Op::SaveSet { name: set } | Op::Intersect { name: set } | Op::Union { name: set } => write!(f, "{} name={}", name, set),
+            Op::TopK { k, target_elem, metric } => match (metric, target_elem) {
+                (Some(m), _) => write!(f, "{} metric={} k={}", name, m, k),
+                (None, t) => write!(f, "{} target_elem={} k={}", name, t.as_deref().unwrap_or(""), k),
+            },
+            Op::WitnessNearest { targets: Some(targets), mode, metric, .. } => {
+                write!(f, "{} targets={} mode={} metric={}", name, targets.join(","), mode.as_deref().unwrap_or("minimax"), metric)
+            }
+            Op::WitnessNearest { target_elem, metric, .. } => {
+                write!(f, "{} target_elem={} metric={}", name, target_elem.as_deref().unwrap_or(""), metric)
+            }
+            Op::WitnessAllTies { target_elem, metric } => write!(f, "{} target_elem={} metric={}", name, target_elem, metric),
+            Op::JoinNearest { left_universe, right_universe, left_elem, right_elem, metric } => write!(
+                f,
+                "{} left_universe={} right_universe={} left_elem={} right_elem={} metric={}",
+                name, left_universe, right_universe, left_elem, right_elem, metric
+            ),
+            Op::ReturnSet { max_items, include_witness, offset, sort_by, include_proofs } => {
+                write!(f, "{} max_items={} include_witness={}", name, max_items, *include_witness as u8)?;
+                if let Some(o) = offset {
+                    write!(f, " offset={}", o)?;
+                }
+                if let Some(b) = sort_by {
+                    write!(f, " sort_by={}", b)?;
+                }
+                if *include_proofs {
+                    f.write_str(" include_proofs=1")?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -63,12 +338,17 @@ pub fn demo_trace() -> Trace {
             },
             Op::SetBit { i: 2, b: 1 },
             Op::WitnessNearest {
-                target_elem: "7/200".to_string(),
+                target_elem: Some("7/200".to_string()),
                 metric: "ABS_DIFF".to_string(),
+                targets: None,
+                mode: None,
             },
             Op::ReturnSet {
                 max_items: 20,
                 include_witness: true,
+                offset: None,
+                sort_by: None,
+                include_proofs: false,
             },
         ],
     }
@@ -99,6 +379,43 @@ pub fn bit_legend_geom() -> [&'static str; 7] {
     ]
 }
 
+pub fn bit_legend_quad() -> [&'static str; 7] {
+    [
+        "perim<=20",
+        "square",
+        "rectangle_like",
+        "primitive",
+        "heronian",
+        "equal_pair",
+        "rational_area",
+    ]
+}
+
+pub fn bit_legend_tetra() -> [&'static str; 7] {
+    [
+        "regular",
+        "faces_isosceles",
+        "face_equilateral",
+        "primitive",
+        "disphenoid",
+        "cm_valid",
+        "edge_sum_even",
+    ]
+}
+
+/// Identifier form of a legend label for FILTER_PRED: "den<=6" → "den_le_6".
+pub fn pred_ident(label: &str) -> String {
+    label.replace("<=", "_le_").replace(">=", "_ge_").replace('<', "_lt_").replace('>', "_gt_")
+}
+
+/// Predicate registry lookup: the bit whose legend label (or its identifier form) is `name`.
+pub fn resolve_pred(legend: &[&str], name: &str) -> Option<u8> {
+    legend
+        .iter()
+        .position(|l| *l == name || pred_ident(l) == name)
+        .map(|i| i as u8)
+}
+
 /// Compute signature bits for QE predicates.
 pub fn sig7(f: &Frac) -> u8 {
     let mut bits: u8 = 0;
@@ -128,7 +445,7 @@ pub fn sig7(f: &Frac) -> u8 {
 }
 
 // ---------------- GEOMETRY SIGNATURE ----------------
-use crate::geom::Tri;
+use crate::geom::{Quad, Tetra, Tri};
 
 pub fn sig7_geom(t: &Tri) -> u8 {
     let mut bits: u8 = 0;
@@ -160,11 +477,59 @@ pub fn sig7_geom(t: &Tri) -> u8 {
     bits
 }
 
+pub fn sig7_quad(q: &Quad) -> u8 {
+    let mut bits: u8 = 0;
+
+    let preds = [
+        q.perimeter() <= 20,    // bit 0
+        q.is_square(),          // bit 1
+        q.is_rectangle_like(),  // bit 2
+        q.is_primitive(),       // bit 3
+        q.is_heronian(),        // bit 4
+        q.has_equal_pair(),     // bit 5
+        q.has_rational_area(),  // bit 6
+    ];
+
+    for (i, p) in preds.iter().enumerate() {
+        if *p {
+            bits |= 1u8 << i;
+        }
+    }
+
+    bits
+}
+
+pub fn sig7_tetra(t: &Tetra) -> u8 {
+    let mut bits: u8 = 0;
+
+    let preds = [
+        t.is_regular(),              // bit 0
+        t.all_faces_isosceles(),     // bit 1
+        t.any_face_equilateral(),    // bit 2
+        t.is_primitive(),            // bit 3
+        t.is_disphenoid(),           // bit 4
+        t.is_cm_valid(),             // bit 5
+        t.edge_sum() % 2 == 0,       // bit 6
+    ];
+
+    for (i, p) in preds.iter().enumerate() {
+        if *p {
+            bits |= 1u8 << i;
+        }
+    }
+
+    bits
+}
+
 /// Constraint (mask,value) for partial signature filtering.
+///
+/// 64 bits wide: the fixed 7-bit signatures use the low bits and DEFINE_PRED
+/// predicates the bits above them. Traces written with the old u8 fields carry the
+/// same JSON numbers, so they read back unchanged.
 #[derive(Clone, Copy, Debug)]
 pub struct Constraint {
-    pub mask: u8,
-    pub value: u8,
+    pub mask: u64,
+    pub value: u64,
 }
 
 impl Constraint {
@@ -172,7 +537,7 @@ impl Constraint {
         Constraint { mask: 0, value: 0 }
     }
     pub fn set_bit(mut self, i: u8, b: u8) -> Self {
-        let bit = 1u8 << i;
+        let bit = 1u64 << i;
         self.mask |= bit;
         if b == 1 {
             self.value |= bit;
@@ -181,8 +546,8 @@ impl Constraint {
         }
         self
     }
-    pub fn matches(&self, sig: u8) -> bool {
-        (sig & self.mask) == (self.value & self.mask)
+    pub fn matches(&self, sig: impl Into<u64>) -> bool {
+        (sig.into() & self.mask) == (self.value & self.mask)
     }
 }
 
@@ -214,6 +579,45 @@ mod tests {
         assert_eq!(s, 0b1000111, "stable signature for Frac {{ num:3, den:1 }}");
     }
 
+    #[test]
+    fn pred_registry_names() {
+        assert_eq!(pred_ident("den<=6"), "den_le_6");
+        assert_eq!(resolve_pred(&bit_legend(), "den_le_6"), Some(2));
+        assert_eq!(resolve_pred(&bit_legend(), "den<=6"), Some(2));
+        assert_eq!(resolve_pred(&bit_legend_geom(), "perim_le_20"), Some(0));
+        assert_eq!(resolve_pred(&bit_legend_geom(), "den_le_6"), None);
+    }
+
+    #[test]
+    fn constraint_high_bits() {
+        let c = Constraint::empty().set_bit(2, 1).set_bit(40, 0);
+        assert!(c.matches(0b100u8));
+        assert!(!c.matches(0b100u64 | (1 << 40)));
+        assert!(!c.matches(0u8));
+    }
+
+    #[test]
+    fn op_lines_round_trip_through_the_typed_op() {
+        let extra = [
+            "RETURN_SET",
+            "RETURN_SET max_items=5 include_witness=1 offset=2 sort_by=distance include_proofs=1",
+            "SELECT_UNIVERSE universe=QE n=10 items=1,3,5",
+            "SELECT_UNIVERSE universe=QE n=0 hash=blake3",
+            "WITNESS_NEAREST target_elem=1/3",
+            "FILTER_RANGE min=1/4 max=3",
+            "TOPK k=3 target_elem=1/2",
+        ];
+        let lines = crate::catalog::OPS.iter().map(|o| o.example).chain(extra);
+        for line in lines {
+            let op: Op = line.parse().unwrap_or_else(|e| panic!("{}: {}", line, e));
+            let (name, args) = crate::exec::parse_op(line).unwrap();
+            assert_eq!((op.name(), op.args()), (name.as_str(), args), "{}", line);
+            assert_eq!(op.to_string().parse::<Op>().unwrap(), op, "{}", line);
+        }
+        let missing = Op::from_record("SET_BIT", &serde_json::json!({ "i": 2 })).unwrap_err();
+        assert!(missing.to_string().contains("bad args for SET_BIT"), "{}", missing);
+    }
+
     #[test]
     fn sig7_negative_integer() {
         let f = Frac { num: -2, den: 1 };
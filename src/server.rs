@@ -0,0 +1,102 @@
+//! HTTP server mode — propose, execute and verify over JSON.
+//!
+//!   POST /query    {"query": "...", "proposer": "rules"}  → result.json of the run
+//!   POST /execute  {"ops": ["LOAD 13/37", ...]}           → result.json of the run
+//!   POST /verify   <trace.ndjson body>                     → verify::VerifyReport
+//!
+//! Routing is the plain function `handle`, so it is testable without a socket;
+//! `serve` (cargo feature `serve`) binds it to a tiny_http listener. Requests are
+//! handled one at a time, like the CLI.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value as JsonValue};
+
+use crate::exec::{run_trace_and_write_with, ExecutionResult};
+use crate::query_proposer::Registry;
+
+/// The result.json document a run wrote.
+fn result_json(r: &ExecutionResult) -> Result<JsonValue> {
+    let doc = r.document.as_ref().ok_or_else(|| anyhow!("run wrote no artifacts"))?;
+    Ok(serde_json::to_value(doc)?)
+}
+
+fn query(body: &str) -> Result<JsonValue> {
+    let req: JsonValue = serde_json::from_str(body)?;
+    let q = req["query"].as_str().ok_or_else(|| anyhow!("body needs a \"query\" string"))?;
+    let registry = Registry::with_defaults();
+    let proposer = registry.get(req["proposer"].as_str().unwrap_or("compiler"))?;
+    let proposal = proposer.propose(q)?;
+    let provenance = proposal.provenance_json(None);
+    result_json(&run_trace_and_write_with(&proposal.ops, None, false, Some(&provenance))?)
+}
+
+fn execute(body: &str) -> Result<JsonValue> {
+    let req: JsonValue = serde_json::from_str(body)?;
+    let ops: Vec<String> = serde_json::from_value(req["ops"].clone())
+        .map_err(|_| anyhow!("body needs an \"ops\" array of op strings"))?;
+    result_json(&run_trace_and_write_with(&ops, None, false, None)?)
+}
+
+fn verify(body: &str) -> Result<JsonValue> {
+    Ok(serde_json::to_value(crate::verify::verify_trace_text(body)?)?)
+}
+
+/// Status code and JSON body for one request.
+pub fn handle(method: &str, path: &str, body: &str) -> (u16, JsonValue) {
+    let out = match (method, path) {
+        ("POST", "/query") => query(body),
+        ("POST", "/execute") => execute(body),
+        ("POST", "/verify") => verify(body),
+        _ => return (404, json!({ "error": format!("no route for {} {}", method, path) })),
+    };
+    match out {
+        Ok(v) => (200, v),
+        Err(e) => (400, json!({ "error": e.to_string() })),
+    }
+}
+
+/// Serve `handle` on `addr` (e.g. "127.0.0.1:8080") until the process exits.
+#[cfg(feature = "serve")]
+pub fn serve(addr: &str) -> Result<()> {
+    let server = tiny_http::Server::http(addr).map_err(|e| anyhow!("binding {}: {}", addr, e))?;
+    let content_type = tiny_http::Header::from_bytes("content-type", "application/json").expect("static header");
+    for mut req in server.incoming_requests() {
+        let mut body = String::new();
+        let (status, out) = match req.as_reader().read_to_string(&mut body) {
+            Ok(_) => handle(req.method().as_str(), req.url(), &body),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        };
+        let resp = tiny_http::Response::from_string(out.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        let _ = req.respond(resp);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "serve"))]
+pub fn serve(_addr: &str) -> Result<()> {
+    Err(anyhow!("built without the `serve` feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn routes_execute_and_verify() {
+        let (status, out) = handle("POST", "/execute", r#"{"ops": ["LOAD 13/37", "MASK_BIT bit=2 val=1"]}"#);
+        assert_eq!(status, 200);
+        assert_eq!(out["verifier"]["valid"], json!(true));
+        let trace = fs::read_to_string(out["artifacts"]["trace_ndjson"].as_str().unwrap()).unwrap();
+
+        let (status, report) = handle("POST", "/verify", &trace);
+        assert_eq!(status, 200);
+        assert_eq!(report["valid"], json!(true));
+        assert_eq!(report["chain_hash"], out["chain_hash"]);
+
+        assert_eq!(handle("POST", "/execute", "{}").0, 400);
+        assert_eq!(handle("GET", "/nope", "").0, 404);
+    }
+}
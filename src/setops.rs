@@ -0,0 +1,99 @@
+//! Set algebra over canonically sorted element vectors.
+//!
+//! Every universe keeps its state set sorted by its own canonical_cmp, so the
+//! combinators here take that comparator and return a sorted, duplicate-free
+//! result that can be digested directly.
+
+use rand_chacha::ChaCha20Rng;
+use rand_core::{RngCore, SeedableRng};
+use std::cmp::Ordering;
+
+/// a ∪ b, canonically sorted, duplicates removed.
+pub fn union_by<T: Clone>(a: &[T], b: &[T], cmp: impl Fn(&T, &T) -> Ordering) -> Vec<T> {
+    let mut out: Vec<T> = a.iter().chain(b.iter()).cloned().collect();
+    out.sort_by(&cmp);
+    out.dedup_by(|x, y| cmp(x, y) == Ordering::Equal);
+    out
+}
+
+/// a ∩ b, canonically sorted.
+pub fn intersect_by<T: Clone>(a: &[T], b: &[T], cmp: impl Fn(&T, &T) -> Ordering) -> Vec<T> {
+    let mut bs: Vec<T> = b.to_vec();
+    bs.sort_by(&cmp);
+    let mut out: Vec<T> = a
+        .iter()
+        .filter(|x| bs.binary_search_by(|y| cmp(y, x)).is_ok())
+        .cloned()
+        .collect();
+    out.sort_by(&cmp);
+    out.dedup_by(|x, y| cmp(x, y) == Ordering::Equal);
+    out
+}
+
+/// a \ b, canonically sorted.
+pub fn difference_by<T: Clone>(a: &[T], b: &[T], cmp: impl Fn(&T, &T) -> Ordering) -> Vec<T> {
+    let mut bs: Vec<T> = b.to_vec();
+    bs.sort_by(&cmp);
+    let mut out: Vec<T> = a
+        .iter()
+        .filter(|x| bs.binary_search_by(|y| cmp(y, x)).is_err())
+        .cloned()
+        .collect();
+    out.sort_by(&cmp);
+    out.dedup_by(|x, y| cmp(x, y) == Ordering::Equal);
+    out
+}
+
+/// k elements drawn without replacement by a ChaCha20 stream seeded with `seed`.
+///
+/// Partial Fisher–Yates over positions, so the same (set, seed, k) always yields the
+/// same subset; the result keeps the input (canonical) order.
+pub fn sample_seeded<T: Clone>(set: &[T], seed: u64, k: usize) -> Vec<T> {
+    let n = set.len();
+    let k = k.min(n);
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let mut idx: Vec<usize> = (0..n).collect();
+    for i in 0..k {
+        let j = i + below(&mut rng, (n - i) as u64) as usize;
+        idx.swap(i, j);
+    }
+    let mut picked: Vec<usize> = idx[..k].to_vec();
+    picked.sort_unstable();
+    picked.into_iter().map(|i| set[i].clone()).collect()
+}
+
+/// Uniform integer in [0, n) by rejection, so no modulo bias.
+fn below(rng: &mut ChaCha20Rng, n: u64) -> u64 {
+    let zone = u64::MAX - (u64::MAX % n);
+    loop {
+        let x = rng.next_u64();
+        if x < zone {
+            return x % n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_algebra_is_sorted_and_deduped() {
+        let a = [5, 1, 3, 3];
+        let b = [4, 3, 2];
+        assert_eq!(union_by(&a, &b, i32::cmp), vec![1, 2, 3, 4, 5]);
+        assert_eq!(intersect_by(&a, &b, i32::cmp), vec![3]);
+        assert_eq!(difference_by(&a, &b, i32::cmp), vec![1, 5]);
+    }
+
+    #[test]
+    fn seeded_sample_is_reproducible() {
+        let v: Vec<u32> = (0..1000).collect();
+        let s1 = sample_seeded(&v, 42, 10);
+        assert_eq!(s1, sample_seeded(&v, 42, 10));
+        assert_ne!(s1, sample_seeded(&v, 43, 10));
+        assert_eq!(s1.len(), 10);
+        assert!(s1.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sample_seeded(&v[..3], 7, 10), vec![0, 1, 2]);
+    }
+}
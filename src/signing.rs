@@ -0,0 +1,129 @@
+//! Ed25519 run signatures: `proof.sig` next to proof.json.
+//!
+//! The signed message is a line-oriented canonical form of the run —
+//!
+//! ```text
+//! lnst-sig/1
+//! chain_hash <hex>
+//! result_sha256 <hex of SHA-256 over result.json as written>
+//! op <ops_in[0]>
+//! op <ops_in[1]>
+//! ...
+//! ```
+//!
+//! so the signature covers the ops that were asked for, the chain they
+//! produced and the reported result, and does not depend on how any JSON
+//! serializer orders keys. Keys are PKCS#8 PEM files, e.g. from
+//! `openssl genpkey -algorithm ed25519 -out key.pem` and
+//! `openssl pkey -in key.pem -pubout -out pub.pem`.
+
+use anyhow::{anyhow, Result};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+use crate::digest::sha256_bytes;
+
+/// `format` of proof.sig and first line of the signed message.
+pub const FORMAT: &str = "lnst-sig/1";
+pub const SIG_FILE: &str = "proof.sig";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RunSignature {
+    pub format: String,
+    /// Hex of the 32-byte Ed25519 public key, to tell which key signed.
+    pub public_key: String,
+    pub signature: String,
+}
+
+fn read_json(path: &Path) -> Result<JsonValue> {
+    let txt = fs::read_to_string(path).map_err(|e| anyhow!("reading {}: {}", path.display(), e))?;
+    serde_json::from_str(&txt).map_err(|e| anyhow!("parsing {}: {}", path.display(), e))
+}
+
+/// The message a run's signature covers, rebuilt from its proof.json and result.json.
+pub fn signed_message(run_dir: &Path) -> Result<Vec<u8>> {
+    let proof = read_json(&run_dir.join("proof.json"))?;
+    let result_path = run_dir.join("result.json");
+    let result_bytes = fs::read(&result_path).map_err(|e| anyhow!("reading {}: {}", result_path.display(), e))?;
+    let result: JsonValue = serde_json::from_slice(&result_bytes)?;
+    let chain_hash = result["chain_hash"].as_str().ok_or_else(|| anyhow!("result.json has no chain_hash"))?;
+    let ops = proof["ops_in"].as_array().ok_or_else(|| anyhow!("proof.json has no ops_in"))?;
+
+    let mut msg = format!("{}\nchain_hash {}\nresult_sha256 {}\n", FORMAT, chain_hash, hex::encode(sha256_bytes(&result_bytes)));
+    for op in ops {
+        let op = op.as_str().ok_or_else(|| anyhow!("proof.json ops_in holds a non-string"))?;
+        if op.contains('\n') {
+            return Err(anyhow!("op {:?} spans lines", op));
+        }
+        msg.push_str("op ");
+        msg.push_str(op);
+        msg.push('\n');
+    }
+    Ok(msg.into_bytes())
+}
+
+/// Sign the run in `run_dir` with the PKCS#8 PEM private key at `key_path`,
+/// writing proof.sig.
+pub fn sign_run(run_dir: &Path, key_path: &Path) -> Result<RunSignature> {
+    let pem = fs::read_to_string(key_path).map_err(|e| anyhow!("reading {}: {}", key_path.display(), e))?;
+    let key = SigningKey::from_pkcs8_pem(&pem).map_err(|e| anyhow!("{}: not an Ed25519 PKCS#8 key: {}", key_path.display(), e))?;
+    let sig = RunSignature {
+        format: FORMAT.to_string(),
+        public_key: hex::encode(key.verifying_key().as_bytes()),
+        signature: hex::encode(key.sign(&signed_message(run_dir)?).to_bytes()),
+    };
+    fs::write(run_dir.join(SIG_FILE), serde_json::to_string_pretty(&sig)? + "\n")?;
+    Ok(sig)
+}
+
+/// Check the run's proof.sig against the PEM public key at `pubkey_path`.
+pub fn verify_run_signature(run_dir: &Path, pubkey_path: &Path) -> Result<()> {
+    let pem = fs::read_to_string(pubkey_path).map_err(|e| anyhow!("reading {}: {}", pubkey_path.display(), e))?;
+    let key = VerifyingKey::from_public_key_pem(&pem)
+        .map_err(|e| anyhow!("{}: not an Ed25519 public key: {}", pubkey_path.display(), e))?;
+    let sig: RunSignature = serde_json::from_value(read_json(&run_dir.join(SIG_FILE))?)?;
+    if sig.format != FORMAT {
+        return Err(anyhow!("unsupported signature format {:?}", sig.format));
+    }
+    if sig.public_key != hex::encode(key.as_bytes()) {
+        return Err(anyhow!("signed by key {}, not the given public key", sig.public_key));
+    }
+    let bytes: [u8; 64] = hex::decode(&sig.signature)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow!("malformed signature"))?;
+    key.verify(&signed_message(run_dir)?, &Signature::from_bytes(&bytes))
+        .map_err(|_| anyhow!("signature does not match the run"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+
+    #[test]
+    fn signed_runs_verify_and_edits_break_the_signature() {
+        let ops: Vec<String> = ["LOAD 1/4", "MASK_BIT bit=2 val=1"].iter().map(|s| s.to_string()).collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        let (key_pem, pub_pem, other_pub) = (dir.join("key.pem"), dir.join("pub.pem"), dir.join("other.pem"));
+        let lf = ed25519_dalek::pkcs8::spki::der::pem::LineEnding::LF;
+        fs::write(&key_pem, key.to_pkcs8_pem(lf).unwrap().as_bytes()).unwrap();
+        fs::write(&pub_pem, key.verifying_key().to_public_key_pem(lf).unwrap()).unwrap();
+        fs::write(&other_pub, other.verifying_key().to_public_key_pem(lf).unwrap()).unwrap();
+
+        sign_run(&dir, &key_pem).unwrap();
+        verify_run_signature(&dir, &pub_pem).unwrap();
+        assert!(verify_run_signature(&dir, &other_pub).unwrap_err().to_string().contains("not the given public key"));
+
+        let result = fs::read_to_string(dir.join("result.json")).unwrap();
+        fs::write(dir.join("result.json"), result.replacen("\"count\"", "\"count\" ", 1)).unwrap();
+        assert!(verify_run_signature(&dir, &pub_pem).unwrap_err().to_string().contains("does not match"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
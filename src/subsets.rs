@@ -0,0 +1,175 @@
+//! Subset universe (SS) — all subsets of a fixed multiset of integers.
+//!
+//! Selected with `SELECT_UNIVERSE universe=SUBSETS items=3,5,7,11`; the items
+//! travel in the step args so the verifier rebuilds the same universe.
+//! A subset is identified by the bitmask of item positions it takes, so
+//! repeated values in the multiset are distinct elements.
+//!
+//! Element syntax: "{3,7}" (chosen values in item order); "{}" is the empty set.
+//!
+//! Signature bits:
+//!   bit0  empty         no items chosen
+//!   bit1  singleton     exactly one item
+//!   bit2  full          every item chosen
+//!   bit3  size_even     even number of items
+//!   bit4  sum_even      sum is even
+//!   bit5  sum_positive  sum > 0
+//!   bit6  sum_zero      sum = 0
+//!
+//! Witness metric: ABS_DIFF on the sum, |sum − T| for an integer target T.
+
+use std::cmp::Ordering;
+
+/// Upper bound on the multiset size (2^16 subsets).
+pub const MAX_ITEMS: usize = 16;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Subset {
+    pub mask: u32,
+    pub sum: i64,
+}
+
+impl Subset {
+    /// Canonical bytes for hashing/merkle: fixed 12 bytes (u32 mask, i64 sum) big-endian.
+    pub fn canonical_bytes(&self) -> [u8; 12] {
+        let mut out = [0u8; 12];
+        out[0..4].copy_from_slice(&self.mask.to_be_bytes());
+        out[4..12].copy_from_slice(&self.sum.to_be_bytes());
+        out
+    }
+
+    pub fn size(&self) -> u32 {
+        self.mask.count_ones()
+    }
+}
+
+/// Canonical total order: sum ascending, then size ascending, then mask ascending.
+pub fn canonical_cmp(a: &Subset, b: &Subset) -> Ordering {
+    a.sum
+        .cmp(&b.sum)
+        .then_with(|| a.size().cmp(&b.size()))
+        .then_with(|| a.mask.cmp(&b.mask))
+}
+
+/// Enumerate all 2^n subsets of items, canonically sorted.
+pub fn build_subsets(items: &[i64]) -> Vec<Subset> {
+    let n = items.len();
+    let mut v: Vec<Subset> = (0..(1u32 << n))
+        .map(|mask| Subset {
+            mask,
+            sum: (0..n).filter(|i| mask & (1 << i) != 0).map(|i| items[i]).sum(),
+        })
+        .collect();
+    v.sort_by(canonical_cmp);
+    v
+}
+
+/// Nearest subset sum to target under ABS_DIFF; ties broken by canonical order.
+pub fn witness_nearest(set: &[Subset], target: i64) -> Option<Subset> {
+    set.iter().copied().min_by(|a, b| {
+        (a.sum - target)
+            .abs()
+            .cmp(&(b.sum - target).abs())
+            .then_with(|| canonical_cmp(a, b))
+    })
+}
+
+/// Compute signature bits for subset predicates.
+pub fn sig7(s: &Subset, n_items: usize) -> u8 {
+    let size = s.size();
+    let preds = [
+        size == 0,
+        size == 1,
+        size as usize == n_items,
+        size.is_multiple_of(2),
+        s.sum % 2 == 0,
+        s.sum > 0,
+        s.sum == 0,
+    ];
+    let mut bits: u8 = 0;
+    for (i, b) in preds.iter().enumerate() {
+        if *b {
+            bits |= 1u8 << i;
+        }
+    }
+    bits
+}
+
+pub fn bit_legend() -> [&'static str; 7] {
+    [
+        "empty",
+        "singleton",
+        "full",
+        "size_even",
+        "sum_even",
+        "sum_positive",
+        "sum_zero",
+    ]
+}
+
+/// Parse the `items=` list "3,5,7,11" (at least one, at most MAX_ITEMS).
+pub fn parse_items(s: &str) -> Option<Vec<i64>> {
+    let v: Vec<i64> = s
+        .split(',')
+        .map(|p| p.trim().parse::<i64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if v.is_empty() || v.len() > MAX_ITEMS {
+        return None;
+    }
+    Some(v)
+}
+
+pub fn subset_to_string(items: &[i64], s: &Subset) -> String {
+    let parts: Vec<String> = (0..items.len())
+        .filter(|i| s.mask & (1 << i) != 0)
+        .map(|i| items[i].to_string())
+        .collect();
+    format!("{{{}}}", parts.join(","))
+}
+
+pub fn is_subsets_universe(u: &str) -> bool {
+    matches!(
+        u.to_ascii_uppercase().as_str(),
+        "SUBSETS" | "SUBSET_SUM" | "POWERSET"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsets_enumeration() {
+        let items = [3, 5, 7, 11];
+        let all = build_subsets(&items);
+        assert_eq!(all.len(), 16);
+        assert_eq!(all[0], Subset { mask: 0, sum: 0 });
+        assert_eq!(all[15].sum, 26);
+        assert_eq!(subset_to_string(&items, &all[15]), "{3,5,7,11}");
+        assert_eq!(subset_to_string(&items, &all[0]), "{}");
+        // repeated values are distinct positions
+        assert_eq!(build_subsets(&[2, 2]).len(), 4);
+    }
+
+    #[test]
+    fn subsets_predicates() {
+        let s = Subset { mask: 0b0101, sum: 10 };
+        assert_eq!(sig7(&s, 4), 0b0111000);
+        assert_eq!(sig7(&Subset { mask: 0, sum: 0 }, 4), 0b1011001);
+        assert!(parse_items("1,-2, 3").is_some());
+        assert!(parse_items("").is_none());
+        assert!(parse_items(&vec!["1"; MAX_ITEMS + 1].join(",")).is_none());
+    }
+
+    #[test]
+    fn subsets_nearest_sum() {
+        let items = [3, 5, 7, 11];
+        let all = build_subsets(&items);
+        // 17 is unreachable; 16 = {5,11} and 18 = {7,11} tie, the smaller sum wins
+        let w = witness_nearest(&all, 17).unwrap();
+        assert_eq!(w.sum, 16);
+        assert_eq!(subset_to_string(&items, &w), "{5,11}");
+        assert_eq!(witness_nearest(&all, 15).unwrap().sum, 15);
+    }
+}
@@ -0,0 +1,225 @@
+//! Succinct verification from per-step element deltas.
+//!
+//! A full replay (`verify::verify_trace_report`) rebuilds every universe and
+//! re-applies every op. When a run is executed with `--deltas` the executor
+//! also writes `deltas.ndjson`: for each step, the leaf hashes that left the
+//! set and the ones that entered it with their positions. `verify_succinct`
+//! replays only those lists, so it checks that
+//!
+//! - step 0 opens a universe whose root is pinned (see `commitments`),
+//! - each step's recorded set digest and count follow from the deltas, and
+//! - the step digests chain to the recorded chain hash,
+//!
+//! without knowing how an op decides which elements to keep. That makes it
+//! cheap enough for third parties, and weaker than a full replay: it vouches
+//! for the integrity of the record, not for the semantics of each op.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::digest::{set_root, sha256_bytes};
+use crate::trace_format::TraceFormat;
+use crate::verify::{record_hash, VerifyReport};
+use verifier_core::{group_by_digest, step_digest, Mismatch, MismatchKind};
+
+/// One line of deltas.ndjson. Leaves are hex SHA-256 hashes as in the set
+/// digest; `added` pairs each new leaf with its index in the step's post set.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Delta {
+    pub step: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<(usize, String)>,
+}
+
+/// Apply a delta: drop `removed`, keep the rest in order, then insert `added`
+/// by ascending index.
+pub fn apply_delta(leaves: &[[u8; 32]], removed: &[[u8; 32]], added: &[(usize, [u8; 32])]) -> Result<Vec<[u8; 32]>> {
+    let gone: HashSet<&[u8; 32]> = removed.iter().collect();
+    let present: HashSet<&[u8; 32]> = leaves.iter().collect();
+    if let Some(h) = removed.iter().find(|h| !present.contains(h)) {
+        return Err(anyhow!("removed leaf {} is not in the set", hex::encode(h)));
+    }
+    let mut out: Vec<[u8; 32]> = leaves.iter().filter(|h| !gone.contains(h)).copied().collect();
+    let mut last: Option<usize> = None;
+    for (pos, h) in added {
+        if last.is_some_and(|l| *pos <= l) || *pos > out.len() {
+            return Err(anyhow!("added index {} is out of order or past the end", pos));
+        }
+        out.insert(*pos, *h);
+        last = Some(*pos);
+    }
+    Ok(out)
+}
+
+/// The delta taking `prev` to `cur`. When `cur` reorders leaves both sides
+/// keep, the delta replaces the whole set instead.
+pub fn delta(step: usize, prev: &[[u8; 32]], cur: &[[u8; 32]]) -> Delta {
+    let before: HashSet<&[u8; 32]> = prev.iter().collect();
+    let after: HashSet<&[u8; 32]> = cur.iter().collect();
+    let mut removed: Vec<[u8; 32]> = prev.iter().filter(|h| !after.contains(h)).copied().collect();
+    let mut added: Vec<(usize, [u8; 32])> =
+        cur.iter().enumerate().filter(|(_, h)| !before.contains(h)).map(|(i, h)| (i, *h)).collect();
+    if apply_delta(prev, &removed, &added).ok().as_deref() != Some(cur) {
+        removed = prev.to_vec();
+        added = cur.iter().copied().enumerate().collect();
+    }
+    Delta {
+        step,
+        removed: removed.into_iter().map(hex::encode).collect(),
+        added: added.into_iter().map(|(i, h)| (i, hex::encode(h))).collect(),
+    }
+}
+
+fn parse_hex32(s: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s).map_err(|e| anyhow!("bad hash {:?}: {}", s, e))?;
+    bytes.try_into().map_err(|_| anyhow!("bad hash {:?}: not 32 bytes", s))
+}
+
+fn check(trace_path: &Path, deltas_path: &Path, report: &mut VerifyReport) -> Result<()> {
+    let trace = fs::read_to_string(trace_path).map_err(|e| anyhow!("reading {}: {}", trace_path.display(), e))?;
+    let deltas = fs::read_to_string(deltas_path).map_err(|e| anyhow!("reading {}: {}", deltas_path.display(), e))?;
+    let mut deltas = deltas.lines().filter(|l| !l.trim().is_empty());
+
+    let mut leaves: Vec<[u8; 32]> = Vec::new();
+    let mut chain = sha256_bytes(b"");
+    let first: Option<JsonValue> = trace.lines().find(|l| !l.trim().is_empty()).and_then(|l| serde_json::from_str(l).ok());
+    let hash = record_hash(first.as_ref().and_then(|r| r["hash"].as_str()))?;
+    let _hash = crate::digest::use_hash(hash);
+    for line in trace.lines().filter(|l| !l.trim().is_empty()) {
+        let rec: JsonValue = serde_json::from_str(line)?;
+        if record_hash(rec["hash"].as_str())? != hash {
+            return Err(anyhow!("hash backend changes mid-trace"));
+        }
+        let step = rec["step"].as_u64().ok_or_else(|| anyhow!("record without step"))? as usize;
+        let op = rec["op"].as_str().ok_or_else(|| anyhow!("record without op"))?;
+        report.steps_checked += 1;
+        report.failed_step = Some(step);
+        report.failed_op = Some(op.to_string());
+        let format = TraceFormat::for_version(rec["semtrace_version"].as_str())?;
+
+        let delta: Delta = serde_json::from_str(deltas.next().ok_or_else(|| anyhow!("no delta for step {}", step))?)?;
+        if delta.step != step {
+            return Err(anyhow!("delta is for step {}", delta.step));
+        }
+        if let Some(pre) = rec["pre"]["set_digest"].as_str() {
+            if pre != hex::encode(set_root(&leaves)) {
+                return Err(anyhow!("pre set digest does not match the previous step"));
+            }
+        }
+        let removed = delta.removed.iter().map(|h| parse_hex32(h)).collect::<Result<Vec<_>>>()?;
+        let added = delta.added.iter().map(|(i, h)| Ok((*i, parse_hex32(h)?))).collect::<Result<Vec<_>>>()?;
+        leaves = apply_delta(&leaves, &removed, &added)?;
+
+        let set_digest = set_root(&leaves);
+        if rec["post"]["set_digest"].as_str() != Some(hex::encode(set_digest).as_str()) {
+            return Err(Mismatch::new(step, MismatchKind::Digest, "post.set_digest", hex::encode(set_digest), rec["post"]["set_digest"].as_str().unwrap_or("null")).into());
+        }
+        if rec["post"]["count"].as_u64() != Some(leaves.len() as u64) {
+            return Err(Mismatch::new(step, MismatchKind::Count, "post.count", leaves.len(), &rec["post"]["count"]).into());
+        }
+        if report.steps_checked == 1 {
+            let pinned = crate::commitments::check_step_zero(op, &rec["args"], &set_digest)?
+                .ok_or_else(|| anyhow!("step 0 opens no pinned universe; succinct verification needs one"))?;
+            if rec["universe_root"].as_str().is_some_and(|r| r != pinned) {
+                return Err(anyhow!("universe_root is not the pinned root for this universe"));
+            }
+        }
+        let post_digest = match rec["post"].get("group_by").filter(|h| !h.is_null()) {
+            Some(h) => group_by_digest(format, &set_digest, &serde_json::from_value::<BTreeMap<String, usize>>(h.clone())?),
+            None => set_digest,
+        };
+        chain = step_digest(format, &chain, op, &rec["args"], &post_digest);
+        if rec["step_digest"].as_str() != Some(hex::encode(chain).as_str()) {
+            return Err(Mismatch::new(step, MismatchKind::Chain, "step_digest", hex::encode(chain), rec["step_digest"].as_str().unwrap_or("null")).into());
+        }
+    }
+    if deltas.next().is_some() {
+        return Err(anyhow!("more deltas than trace records"));
+    }
+    report.failed_step = None;
+    report.failed_op = None;
+    report.chain_hash = Some(hex::encode(chain));
+    Ok(())
+}
+
+/// Verify `trace_path` from its deltas alone, without rebuilding any universe.
+pub fn verify_succinct(trace_path: &Path, deltas_path: &Path) -> VerifyReport {
+    let mut report = VerifyReport {
+        trace: trace_path.display().to_string(),
+        valid: false,
+        steps_checked: 0,
+        chain_hash: None,
+        failed_step: None,
+        failed_op: None,
+        reason: None,
+        mismatch: None,
+        steps: Vec::new(),
+        proofs_checked: None,
+        signature_verified: None,
+        artifacts_checked: Vec::new(),
+        artifact_mismatch: None,
+        bisect: None,
+    };
+    match check(trace_path, deltas_path, &mut report) {
+        Ok(()) => report.valid = true,
+        Err(e) => {
+            report.reason = Some(e.to_string());
+            report.mismatch = e.downcast::<Mismatch>().ok();
+        }
+    }
+    let trace = fs::read_to_string(trace_path).unwrap_or_default();
+    report.steps = crate::verify::step_reports(&trace, report.steps_checked, report.valid);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(i: u8) -> [u8; 32] {
+        sha256_bytes(&[i])
+    }
+
+    #[test]
+    fn deltas_rebuild_the_next_set() {
+        let prev: Vec<[u8; 32]> = (0..6).map(h).collect();
+        for cur in [vec![h(1), h(3), h(4)], vec![h(0), h(7), h(2), h(9)], vec![h(5), h(4)], vec![]] {
+            let d = delta(1, &prev, &cur);
+            let removed: Vec<[u8; 32]> = d.removed.iter().map(|x| parse_hex32(x).unwrap()).collect();
+            let added: Vec<(usize, [u8; 32])> = d.added.iter().map(|(i, x)| (*i, parse_hex32(x).unwrap())).collect();
+            assert_eq!(apply_delta(&prev, &removed, &added).unwrap(), cur);
+        }
+        // a reorder of kept leaves falls back to replacing the set
+        assert_eq!(delta(1, &prev, &[h(5), h(4)]).removed.len(), 6);
+        assert!(apply_delta(&prev, &[h(8)], &[]).is_err());
+    }
+
+    #[test]
+    fn succinct_verification_agrees_with_replay() {
+        crate::exec::set_output_config(crate::exec::OutputConfig { deltas: true, ..Default::default() });
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "MASK_BIT bit=0 val=0"].iter().map(|s| s.to_string()).collect();
+        let r = crate::exec::run_trace_and_write(&ops, None, false);
+        crate::exec::set_output_config(crate::exec::OutputConfig::default());
+        let dir = r.unwrap().artifacts_path.unwrap();
+        let (trace, deltas) = (dir.join("trace.ndjson"), dir.join("deltas.ndjson"));
+
+        let report = verify_succinct(&trace, &deltas);
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.chain_hash, crate::verify::verify_trace_report(&trace).chain_hash);
+
+        // drop one removal from the last step
+        let mut lines: Vec<String> = fs::read_to_string(&deltas).unwrap().lines().map(str::to_string).collect();
+        let mut last: Delta = serde_json::from_str(&lines[2]).unwrap();
+        last.removed.pop();
+        lines[2] = serde_json::to_string(&last).unwrap();
+        fs::write(&deltas, lines.join("\n")).unwrap();
+        let report = verify_succinct(&trace, &deltas);
+        assert!(!report.valid);
+        assert_eq!(report.failed_step, Some(2));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
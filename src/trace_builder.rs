@@ -0,0 +1,237 @@
+//! Interactive trace construction, independent of any terminal front-end.
+//!
+//! A `TraceBuilder` holds one universe, a tri-state choice per signature bit
+//! (required true, required false, or free) and a witness target. `preview`
+//! recomputes the filtered count and nearest witness in memory with the same
+//! signatures, canonical orders and tie-breaks the executor uses, so toggling a
+//! bit is instant; `ops` renders the equivalent op script for the executor.
+
+use anyhow::{anyhow, Result};
+
+use crate::exec::UniverseBounds;
+use crate::geom::{Quad, Tetra, Tri};
+use crate::qe::Frac;
+use crate::semtrace::Constraint;
+
+/// Universes the builder can drive, in the order `next_universe` cycles them.
+pub const UNIVERSES: &[&str] = &["QE", "GE", "QUAD", "TETRA"];
+
+enum Elems {
+    Qe(Vec<Frac>),
+    Ge(Vec<Tri>),
+    Quad(Vec<Quad>),
+    Tetra(Vec<Tetra>),
+}
+
+/// Live count and witness for the current choices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub total: usize,
+    pub count: usize,
+    pub witness: Option<String>,
+    /// Why there is no witness (bad target, empty set).
+    pub error: Option<String>,
+}
+
+pub struct TraceBuilder {
+    bounds: UniverseBounds,
+    universe: usize,
+    bits: [Option<bool>; 7],
+    target: String,
+    all: Elems,
+}
+
+fn default_target(universe: &str) -> &'static str {
+    match universe {
+        "GE" => "3,4,5",
+        "QUAD" => "2,3,3,4",
+        "TETRA" => "2,2,2,2,2,2",
+        _ => "13/37",
+    }
+}
+
+impl TraceBuilder {
+    /// Start on QE; QUAD and TETRA use `bounds` where set, else the executor defaults.
+    pub fn new(bounds: UniverseBounds) -> TraceBuilder {
+        let mut b = TraceBuilder { bounds, universe: 0, bits: [None; 7], target: String::new(), all: Elems::Qe(Vec::new()) };
+        b.select(0);
+        b
+    }
+
+    fn quad_perimeter(&self) -> i32 {
+        self.bounds.quad_perimeter.map_or(crate::geom::DEFAULT_QUAD_PERIMETER, |n| n as i32)
+    }
+
+    fn tetra_edge(&self) -> i32 {
+        self.bounds.tetra_edge.map_or(crate::geom::DEFAULT_TETRA_EDGE, |n| n as i32)
+    }
+
+    fn select(&mut self, index: usize) {
+        self.universe = index % UNIVERSES.len();
+        self.bits = [None; 7];
+        self.target = default_target(self.universe()).to_string();
+        self.all = match self.universe() {
+            "GE" => Elems::Ge(crate::geom::build_ge(20)),
+            "QUAD" => Elems::Quad(crate::geom::build_quad(self.quad_perimeter())),
+            "TETRA" => Elems::Tetra(crate::geom::build_tetra(self.tetra_edge())),
+            _ => Elems::Qe(crate::qe::build_qe()),
+        };
+    }
+
+    pub fn universe(&self) -> &'static str {
+        UNIVERSES[self.universe]
+    }
+
+    /// Switch to the next universe, clearing the bit choices and target.
+    pub fn next_universe(&mut self) {
+        self.select(self.universe + 1);
+    }
+
+    pub fn legend(&self) -> [&'static str; 7] {
+        match self.universe() {
+            "GE" => crate::semtrace::bit_legend_geom(),
+            "QUAD" => crate::semtrace::bit_legend_quad(),
+            "TETRA" => crate::semtrace::bit_legend_tetra(),
+            _ => crate::semtrace::bit_legend(),
+        }
+    }
+
+    pub fn bits(&self) -> [Option<bool>; 7] {
+        self.bits
+    }
+
+    /// Cycle bit `i` through free → required true → required false → free.
+    pub fn toggle_bit(&mut self, i: usize) {
+        if let Some(b) = self.bits.get_mut(i) {
+            *b = match *b {
+                None => Some(true),
+                Some(true) => Some(false),
+                Some(false) => None,
+            };
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn set_target(&mut self, target: &str) {
+        self.target = target.trim().to_string();
+    }
+
+    fn constraint(&self) -> Constraint {
+        self.bits
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.map(|b| (i as u8, b)))
+            .fold(Constraint::empty(), |c, (i, b)| c.set_bit(i, u8::from(b)))
+    }
+
+    /// The op script the current choices stand for.
+    pub fn ops(&self) -> Vec<String> {
+        let mut ops = vec![match self.universe() {
+            "QUAD" => format!("SELECT_UNIVERSE universe=QUAD n={}", self.quad_perimeter()),
+            "TETRA" => format!("SELECT_UNIVERSE universe=TETRA n={}", self.tetra_edge()),
+            _ => format!("LOAD {}", self.target),
+        }];
+        for (i, b) in self.bits.iter().enumerate() {
+            if let Some(b) = b {
+                ops.push(format!("MASK_BIT bit={} val={}", i, u8::from(*b)));
+            }
+        }
+        let metric = match self.universe() {
+            "GE" => "SIMILARITY",
+            "QUAD" | "TETRA" => "L1",
+            _ => "ABS_DIFF",
+        };
+        ops.push(format!("WITNESS_NEAREST target_elem={} metric={}", self.target, metric));
+        ops
+    }
+
+    /// Count and witness under the current choices.
+    pub fn preview(&self) -> Preview {
+        let cst = self.constraint();
+        let (total, count, witness) = match &self.all {
+            Elems::Qe(all) => {
+                let mut set: Vec<Frac> = all.iter().copied().filter(|f| cst.matches(crate::semtrace::sig7(f))).collect();
+                set.sort_by(crate::qe::canonical_cmp);
+                let w = crate::qe::parse_frac(&self.target)
+                    .ok_or_else(|| anyhow!("bad frac target {:?}", self.target))
+                    .map(|t| crate::exec::witness_nearest(&set, &t).map(|f| format!("{}/{}", f.num, f.den)));
+                (all.len(), set.len(), w)
+            }
+            Elems::Ge(all) => {
+                let mut set: Vec<Tri> = all.iter().copied().filter(|t| cst.matches(crate::semtrace::sig7_geom(t))).collect();
+                set.sort_by(crate::geom::canonical_cmp);
+                let w = crate::geom::parse_tri(&self.target)
+                    .ok_or_else(|| anyhow!("bad triangle target {:?}", self.target))
+                    .map(|t| crate::geom::similarity_ties(&set, &t).first().map(|w| format!("{},{},{}", w.a, w.b, w.c)));
+                (all.len(), set.len(), w)
+            }
+            Elems::Quad(all) => {
+                let set: Vec<Quad> = all.iter().copied().filter(|q| cst.matches(crate::semtrace::sig7_quad(q))).collect();
+                let w = crate::geom::parse_quad(&self.target).ok_or_else(|| anyhow!("bad quad target {:?}", self.target)).map(|t| {
+                    set.iter()
+                        .min_by(|x, y| {
+                            crate::geom::quad_distance(x, &t)
+                                .cmp(&crate::geom::quad_distance(y, &t))
+                                .then_with(|| crate::geom::canonical_cmp_quad(x, y))
+                        })
+                        .map(crate::geom::quad_to_string)
+                });
+                (all.len(), set.len(), w)
+            }
+            Elems::Tetra(all) => {
+                let set: Vec<Tetra> = all.iter().copied().filter(|t| cst.matches(crate::semtrace::sig7_tetra(t))).collect();
+                let w = crate::geom::parse_tetra(&self.target).ok_or_else(|| anyhow!("bad tetra target {:?}", self.target)).map(|t| {
+                    set.iter()
+                        .min_by(|x, y| {
+                            crate::geom::tetra_distance(x, &t)
+                                .cmp(&crate::geom::tetra_distance(y, &t))
+                                .then_with(|| crate::geom::canonical_cmp_tetra(x, y))
+                        })
+                        .map(crate::geom::tetra_to_string)
+                });
+                (all.len(), set.len(), w)
+            }
+        };
+        let (witness, error) = match witness {
+            Ok(Some(w)) => (Some(w), None),
+            Ok(None) => (None, Some("empty set".to_string())),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Preview { total, count, witness, error }
+    }
+
+    /// Write the op script as a JSON array of lines, the form `--trace-file` reads.
+    pub fn export(&self, path: &std::path::Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.ops())? + "\n")
+            .map_err(|e| anyhow!("writing {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_matches_the_executor() {
+        let mut b = TraceBuilder::new(UniverseBounds::default());
+        for _ in UNIVERSES {
+            b.toggle_bit(1);
+            b.toggle_bit(1);
+            b.toggle_bit(3);
+            assert_eq!(b.bits()[1], Some(false));
+            let p = b.preview();
+            assert!(p.error.is_none(), "{}: {:?}", b.universe(), p);
+            let r = crate::exec::run_trace_and_write(&b.ops(), None, false).unwrap();
+            assert_eq!(p.count, r.final_count, "{}", b.universe());
+            if b.universe() != "GE" {
+                assert_eq!(p.witness, r.witness, "{}", b.universe());
+            }
+            std::fs::remove_dir_all(r.artifacts_path.unwrap()).unwrap();
+            b.next_universe();
+        }
+        assert_eq!(b.universe(), "QE");
+    }
+}
@@ -0,0 +1,129 @@
+//! `lnst tui` — a terminal front-end over `TraceBuilder` (needs the `tui` feature).
+//!
+//! Keys: 0–6 cycle a signature bit (free → 1 → 0), Tab switches universe,
+//! t edits the witness target, e exports the trace JSON (to tui_trace.json in
+//! the traces directory) and runs it through the executor and verifier, q quits.
+
+use anyhow::Result;
+
+use crate::exec::UniverseBounds;
+
+#[cfg(feature = "tui")]
+pub fn run(bounds: UniverseBounds) -> Result<()> {
+    use ratatui::backend::TermionBackend;
+    use ratatui::layout::{Constraint, Direction, Layout};
+    use ratatui::style::{Modifier, Style};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, Borders, Paragraph};
+    use ratatui::termion::event::Key;
+    use ratatui::termion::input::TermRead;
+    use ratatui::termion::raw::IntoRawMode;
+    use ratatui::termion::screen::IntoAlternateScreen;
+    use ratatui::Terminal;
+
+    use crate::trace_builder::TraceBuilder;
+
+    let mut builder = TraceBuilder::new(bounds);
+    let mut editing: Option<String> = None;
+    let mut status = "0-6 toggle bit · Tab universe · t target · e export + verify · q quit".to_string();
+
+    let out = std::io::stdout().into_raw_mode()?.into_alternate_screen()?;
+    let mut terminal = Terminal::new(TermionBackend::new(out))?;
+    let mut keys = std::io::stdin().keys();
+    loop {
+        let preview = builder.preview();
+        terminal.draw(|f| {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Length(9), Constraint::Min(4), Constraint::Length(3)])
+                .split(f.area());
+
+            let target = match &editing {
+                Some(t) => format!("{}▏", t),
+                None => builder.target().to_string(),
+            };
+            let header = format!(
+                "universe {}   count {} / {}   target {}   witness {}",
+                builder.universe(),
+                preview.count,
+                preview.total,
+                target,
+                preview.witness.as_deref().or(preview.error.as_deref()).unwrap_or("-")
+            );
+            f.render_widget(Paragraph::new(header).block(Block::default().borders(Borders::ALL).title("lnst")), rows[0]);
+
+            let bits: Vec<Line> = builder
+                .legend()
+                .iter()
+                .zip(builder.bits())
+                .enumerate()
+                .map(|(i, (label, b))| {
+                    let mark = match b {
+                        Some(true) => "[1]",
+                        Some(false) => "[0]",
+                        None => "[ ]",
+                    };
+                    let line = Line::from(format!("{} {} {}", i, mark, label));
+                    if b.is_some() {
+                        line.style(Style::default().add_modifier(Modifier::BOLD))
+                    } else {
+                        line
+                    }
+                })
+                .collect();
+            f.render_widget(Paragraph::new(bits).block(Block::default().borders(Borders::ALL).title("signature bits")), rows[1]);
+
+            let ops: Vec<Line> = builder.ops().into_iter().map(Line::from).collect();
+            f.render_widget(Paragraph::new(ops).block(Block::default().borders(Borders::ALL).title("trace")), rows[2]);
+            f.render_widget(Paragraph::new(status.as_str()).block(Block::default().borders(Borders::ALL)), rows[3]);
+        })?;
+
+        let Some(key) = keys.next() else { break };
+        let key = key?;
+        if let Some(text) = editing.as_mut() {
+            match key {
+                Key::Char('\n') => {
+                    builder.set_target(text);
+                    editing = None;
+                }
+                Key::Esc => editing = None,
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Char(c) => text.push(c),
+                _ => {}
+            }
+            continue;
+        }
+        match key {
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => break,
+            Key::Char('\t') => builder.next_universe(),
+            Key::Char(c @ '0'..='6') => builder.toggle_bit(c as usize - '0' as usize),
+            Key::Char('t') => editing = Some(builder.target().to_string()),
+            Key::Char('e') => status = export_and_verify(&builder),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Write the trace, execute it and replay-verify the run, as one status line.
+#[cfg(feature = "tui")]
+fn export_and_verify(builder: &crate::trace_builder::TraceBuilder) -> String {
+    let attempt = || -> Result<String> {
+        let path = crate::exec::traces_dir()?.join("tui_trace.json");
+        std::fs::create_dir_all(path.parent().unwrap_or(std::path::Path::new(".")))?;
+        builder.export(&path)?;
+        let r = crate::exec::run_trace_and_write(&builder.ops(), Some(&path), false)?;
+        let dir = r.artifacts_path.unwrap_or_default();
+        let report = crate::verify::verify_trace_report(&dir.join("trace.ndjson"));
+        let verdict = if report.valid { "VERIFIED" } else { "FAILED" };
+        Ok(format!("wrote {} · run {} · {} · {}", path.display(), r.run_id, r.final_count, verdict))
+    };
+    attempt().unwrap_or_else(|e| format!("export failed: {}", e))
+}
+
+#[cfg(not(feature = "tui"))]
+pub fn run(_bounds: UniverseBounds) -> Result<()> {
+    Err(anyhow::anyhow!("built without the `tui` feature"))
+}
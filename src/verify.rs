@@ -8,76 +8,149 @@ use crate::boolfun::{
     build_boolfun, canonical_cmp as boolfun_canonical_cmp, is_boolfun_universe,
     parse_elem as parse_boolfun, BoolFun,
 };
-use crate::digest::{merkle_root, sha256_bytes};
-use crate::qe::{build_qe, canonical_cmp, parse_frac, Frac};
-use crate::semtrace::{sig7, Constraint};
+use crate::digest::{leaf_hash, merkle_root_from_path, set_root, sha256_bytes, HashAlg};
+use crate::geom::{
+    build_quad, build_tetra, canonical_cmp_quad, canonical_cmp_tetra, is_quad_universe,
+    is_tetra_universe, parse_quad, parse_tetra, quad_distance, quad_to_string, tetra_distance,
+    tetra_to_string, Quad, Tetra,
+};
+use crate::group::{
+    canonical_cmp as group_canonical_cmp, is_group_universe, parse_elem as parse_perm,
+    parse_group_name, perm_to_string, GroupUniverse, Perm,
+};
+use crate::lattice::{
+    build_lattice, canonical_cmp as lattice_canonical_cmp, is_lattice_universe,
+    parse_elem as parse_pt, pt_to_string, Pt,
+};
+use crate::setops::{difference_by, intersect_by, sample_seeded, union_by};
+use crate::qe::{build_qe, canonical_cmp, in_range, parse_bound, parse_frac, Frac};
+use crate::subsets::{
+    build_subsets, canonical_cmp as subset_canonical_cmp, is_subsets_universe,
+    subset_to_string, Subset,
+};
+use crate::cancel::CancelToken;
+use crate::semtrace::{resolve_pred, Constraint, Op};
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
+use verifier_core::{Chain, StepRec};
 
-#[derive(Clone, Debug, Deserialize)]
-#[allow(dead_code)]
-struct StepPre {
-    set_digest: Option<String>,
-    count: usize,
-    constraint_mask: u8,
-    constraint_value: u8,
+pub use verifier_core::{group_by_digest, step_digest, Mismatch, MismatchKind};
+
+fn canonical_set_digest(set: &[Frac]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for f in set {
+        leaves.push(leaf_hash(&f.canonical_bytes()));
+    }
+    set_root(&leaves)
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct StepPost {
-    set_digest: Option<String>,
-    count: usize,
-    witness: Option<String>,
+fn canonical_set_digest_boolfun(set: &[BoolFun]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for f in set {
+        leaves.push(leaf_hash(&f.canonical_bytes()));
+    }
+    set_root(&leaves)
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[allow(dead_code)]
-struct StepRec {
-    step: usize,
-    op: String,
-    args: serde_json::Value,
-    pre: StepPre,
-    post: StepPost,
-    step_digest: String,
+fn canonical_set_digest_lattice(set: &[Pt]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for p in set {
+        leaves.push(leaf_hash(&p.canonical_bytes()));
+    }
+    set_root(&leaves)
 }
 
-fn canonical_set_digest(set: &[Frac]) -> [u8; 32] {
+fn canonical_set_digest_group(set: &[Perm]) -> [u8; 32] {
     let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
-    for f in set {
-        leaves.push(sha256_bytes(&f.canonical_bytes()));
+    for g in set {
+        leaves.push(leaf_hash(&g.canonical_bytes()));
     }
-    merkle_root(&leaves)
+    set_root(&leaves)
 }
 
-fn canonical_set_digest_boolfun(set: &[BoolFun]) -> [u8; 32] {
+fn canonical_set_digest_subsets(set: &[Subset]) -> [u8; 32] {
     let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
-    for f in set {
-        leaves.push(sha256_bytes(&f.canonical_bytes()));
+    for x in set {
+        leaves.push(leaf_hash(&x.canonical_bytes()));
     }
-    merkle_root(&leaves)
+    set_root(&leaves)
 }
 
-fn hex32(b: [u8; 32]) -> String {
-    hex::encode(b)
+fn canonical_set_digest_quad(set: &[Quad]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for q in set {
+        leaves.push(leaf_hash(&q.to_bytes()));
+    }
+    set_root(&leaves)
 }
 
-fn step_digest(pre: &[u8], op: &str, args: &serde_json::Value, post: &[u8]) -> [u8; 32] {
-    let obj = serde_json::json!({
-        "pre": hex::encode(pre),
-        "op": op,
-        "args": args,
-        "post": hex::encode(post),
-    });
-    let bytes = serde_json::to_vec(&obj).expect("json encode");
-    sha256_bytes(&bytes)
+fn canonical_set_digest_tetra(set: &[Tetra]) -> [u8; 32] {
+    let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(set.len());
+    for t in set {
+        leaves.push(leaf_hash(&t.to_bytes()));
+    }
+    set_root(&leaves)
+}
+
+/// Named snapshot of a state set (SAVE_SET), tagged by universe.
+#[derive(Clone, Debug)]
+enum SavedSet {
+    Qe(Vec<Frac>),
+    Ge(Vec<crate::geom::Tri>),
+    BoolFun(Vec<BoolFun>),
+}
+
+/// One PUSH_STATE entry: everything POP_STATE restores for the active universe.
+#[derive(Clone, Debug)]
+struct Frame {
+    key: String,
+    set_digest: [u8; 32],
+    cst: Constraint,
+    state_set: Vec<Frac>,
+    ge_set: Vec<crate::geom::Tri>,
+    boolfun_set: Vec<BoolFun>,
+    lattice_set: Vec<Pt>,
+    group_set: Vec<Perm>,
+    subset_set: Vec<Subset>,
+    quad_set: Vec<Quad>,
+    tetra_set: Vec<Tetra>,
+    witness: Option<Frac>,
+    witness_bf: Option<BoolFun>,
+    witness_pt: Option<Pt>,
+    witness_perm: Option<Perm>,
+    witness_subset: Option<Subset>,
+    witness_quad: Option<Quad>,
+    witness_tetra: Option<Tetra>,
 }
 
-fn filter_qe(qe: &[Frac], cst: Constraint) -> Vec<Frac> {
+/// Identity of the selected universe, so a frame is only popped back into the universe it came from.
+fn frame_key(flags: [bool; 7], group: Option<&GroupUniverse>, items: &[i64], boolfun_n: u8) -> String {
+    format!(
+        "{:?}|{}|{:?}|{}",
+        flags,
+        group.map(|g| g.kind.name()).unwrap_or_default(),
+        items,
+        boolfun_n
+    )
+}
+
+fn project_tris(tris: &[crate::geom::Tri]) -> Vec<Frac> {
+    let mut v: Vec<Frac> = tris.iter().map(|t| Frac { num: t.a, den: t.c }).collect();
+    v.sort_by(crate::qe::canonical_cmp);
+    v
+}
+
+fn hex32(b: [u8; 32]) -> String {
+    hex::encode(b)
+}
+
+fn filter_qe(qe: &[Frac], cst: Constraint, user_preds: &[(String, crate::pred::Expr)]) -> Vec<Frac> {
     let mut out = Vec::new();
     for f in qe {
-        if cst.matches(sig7(f)) {
+        if cst.matches(crate::pred::ext_sig(f, user_preds)) {
             out.push(*f);
         }
     }
@@ -85,10 +158,51 @@ fn filter_qe(qe: &[Frac], cst: Constraint) -> Vec<Frac> {
     out
 }
 
+/// Evaluate an ASSERT_COUNT / ASSERT_WITNESS step against the post-state.
+/// Returns the asserted argument against the replayed value, or None when the
+/// assertion holds.
+fn failed_assertion(step: usize, op: &str, args: &serde_json::Value, count: usize, witness: Option<&str>) -> Option<Mismatch> {
+    match op {
+        "ASSERT_COUNT" => {
+            let want = args.get("eq").and_then(|v| v.as_u64())?;
+            (count as u64 != want).then(|| Mismatch::new(step, MismatchKind::Assertion, "args.eq", count, want))
+        }
+        "ASSERT_WITNESS" => {
+            let want = args.get("elem").and_then(|v| v.as_str())?;
+            // fractions compare in reduced form, everything else by its rendering
+            let norm = |s: &str| parse_frac(s).map(|f| frac_to_string(&f)).unwrap_or_else(|| s.to_string());
+            (witness.map(norm) != Some(norm(want)))
+                .then(|| Mismatch::new(step, MismatchKind::Assertion, "args.elem", witness.unwrap_or("none"), want))
+        }
+        _ => None,
+    }
+}
+
+/// The 7-bit predicate legend of the selected universe; flags are
+/// (boolfun, ge, lattice, group, subsets, quad, tetra, linguistic).
+/// None where SET_BIT has no 7-bit signature to filter on.
+fn pred_legend(flags: &[bool; 8]) -> Option<[&'static str; 7]> {
+    match flags {
+        [true, ..] | [.., true] => None,
+        [_, true, ..] => Some(crate::semtrace::bit_legend_geom()),
+        [_, _, true, ..] => Some(crate::lattice::bit_legend()),
+        [_, _, _, true, ..] => Some(crate::group::bit_legend()),
+        [_, _, _, _, true, ..] => Some(crate::subsets::bit_legend()),
+        [_, _, _, _, _, true, ..] => Some(crate::semtrace::bit_legend_quad()),
+        [_, _, _, _, _, _, true, _] => Some(crate::semtrace::bit_legend_tetra()),
+        _ => Some(crate::semtrace::bit_legend()),
+    }
+}
+
 fn frac_to_string(f: &Frac) -> String {
     format!("{}/{}", f.num, f.den)
 }
 
+/// First and last element of a canonically sorted set, rendered (null when empty).
+fn set_ends<T>(set: &[T], render: impl Fn(&T) -> String) -> (Option<String>, Option<String>) {
+    (set.first().map(&render), set.last().map(&render))
+}
+
 fn boolfun_to_string(f: &BoolFun) -> String {
     if f.n == 4 {
         format!("0x{:04X}", (f.bits & 0xFFFF) as u16)
@@ -129,11 +243,668 @@ fn witness_nearest(set: &[Frac], target: &Frac) -> Option<Frac> {
     Some(best)
 }
 
-#[allow(unused_assignments)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Ok,
+    Failed,
+    /// After the failing step; never replayed.
+    NotReplayed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StepReport {
+    pub step: usize,
+    pub op: String,
+    pub status: StepStatus,
+}
+
+/// Per-step statuses for `trace_text`: `ok` up to `checked` records, `failed`
+/// for the last of them unless `valid`, `not_replayed` after.
+pub(crate) fn step_reports(trace_text: &str, checked: usize, valid: bool) -> Vec<StepReport> {
+    trace_text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let rec: serde_json::Value = serde_json::from_str(line).unwrap_or_default();
+            let status = if i + 1 < checked || (i + 1 == checked && valid) {
+                StepStatus::Ok
+            } else if i + 1 == checked {
+                StepStatus::Failed
+            } else {
+                StepStatus::NotReplayed
+            };
+            StepReport {
+                step: rec["step"].as_u64().map_or(i, |s| s as usize),
+                op: rec["op"].as_str().unwrap_or("").to_string(),
+                status,
+            }
+        })
+        .collect()
+}
+
+/// Outcome of replaying a trace.ndjson, for reporting to third parties.
+#[derive(Clone, Debug, Serialize)]
+pub struct VerifyReport {
+    pub trace: String,
+    pub valid: bool,
+    /// Records replayed, including the failing one.
+    pub steps_checked: usize,
+    /// Final chain hash recomputed by the replay (only when valid).
+    pub chain_hash: Option<String>,
+    pub failed_step: Option<usize>,
+    pub failed_op: Option<String>,
+    /// Mismatch or error at the failing step.
+    pub reason: Option<String>,
+    /// The failing step's first disagreeing field, when the failure is one
+    /// (rather than an op the replay rejects or an unreadable record).
+    pub mismatch: Option<Mismatch>,
+    /// Every record in the trace with how far the replay got.
+    pub steps: Vec<StepReport>,
+    /// Inclusion proofs checked from the run's result.json, when it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proofs_checked: Option<usize>,
+    /// Whether proof.sig verified, when a public key was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_verified: Option<bool>,
+    /// Run-directory files cross-checked against the replay (result.json, proof.json).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts_checked: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_mismatch: Option<ArtifactMismatch>,
+    /// Filled by `verify --bisect` for a trace that fails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bisect: Option<Bisection>,
+}
+
+/// A run-directory file that disagrees with the replayed trace.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ArtifactMismatch {
+    pub file: String,
+    /// JSON field, e.g. `count` or `ops_in[2]`.
+    pub field: String,
+    /// What the replayed trace implies.
+    pub expected: String,
+    /// What the file says.
+    pub got: String,
+}
+
+impl std::fmt::Display for ArtifactMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} is {}, expected {}", self.file, self.field, self.got, self.expected)
+    }
+}
+
+/// Where a replay has got to, kept up to date so a failure can be located.
+#[derive(Default)]
+struct Progress {
+    steps: usize,
+    current: Option<(usize, String)>,
+    chain: Option<[u8; 32]>,
+    /// Set digest recomputed for the last record.
+    set_digest: Option<[u8; 32]>,
+    /// Set-digest hash backend the trace names.
+    hash: HashAlg,
+    /// Why the replay returned false at the current step, when it is a mismatch.
+    mismatch: Option<Mismatch>,
+    fin: FinalState,
+    /// Replayed count and set digest per record, for every record whose op replayed.
+    trail: Vec<(usize, [u8; 32])>,
+    /// Stop before the next record once this fires.
+    cancel: Option<CancelToken>,
+}
+
+/// What the replay ends on, for cross-checking result.json.
+#[derive(Default)]
+struct FinalState {
+    count: usize,
+    witness: Option<String>,
+    constraint: (u64, u64),
+    /// Last WITNESS_ALL_TIES ties and root, AGGREGATE stats and GROUP_BY
+    /// histogram, as result.json reports them.
+    ties: Option<(Vec<String>, String)>,
+    aggregate: Option<serde_json::Value>,
+    group_by: Option<BTreeMap<String, usize>>,
+    /// Final set leaves with their `proof_leaf` encoding, where there is one.
+    leaves: Option<(String, Vec<[u8; 32]>)>,
+    /// Linguistic universes record no count or witness in the trace.
+    linguistic: bool,
+}
+
+impl FinalState {
+    /// The state after `self`'s segment and then `later`'s.
+    fn then(self, later: FinalState) -> FinalState {
+        FinalState {
+            ties: later.ties.or(self.ties),
+            aggregate: later.aggregate.or(self.aggregate),
+            group_by: later.group_by.or(self.group_by),
+            ..later
+        }
+    }
+}
+
 pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
+    replay_trace(trace_path, &mut Progress::default())
+}
+
+/// Where `verify --bisect` found a trace to stop reproducing.
+#[derive(Clone, Debug, Serialize)]
+pub struct Bisection {
+    /// The earliest record whose prefix no longer replays.
+    pub step: usize,
+    pub op: String,
+    pub args: serde_json::Value,
+    pub recorded_set_digest: Option<String>,
+    /// None when the op itself did not replay.
+    pub replayed_set_digest: Option<String>,
+    /// Prefix replays the search took.
+    pub replays: usize,
+    /// Recorded and replayed counts for the failing record and up to three before it.
+    pub counts: Vec<CountDiff>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CountDiff {
+    pub step: usize,
+    pub op: String,
+    pub recorded: usize,
+    pub replayed: Option<usize>,
+}
+
+/// A sequential replay of trace lines from the start of a trace.
+pub(crate) struct LinesReplay {
+    pub valid: bool,
+    /// Records replayed, including a failing one.
+    pub steps: usize,
+    /// Replayed count and set digest per record whose op replayed.
+    pub trail: Vec<(usize, [u8; 32])>,
+    /// Why the last record did not replay.
+    pub reason: Option<String>,
+}
+
+pub(crate) fn replay_lines(lines: &[&str]) -> LinesReplay {
+    let mut progress = Progress::default();
+    let outcome = replay_records(&lines.join("\n"), sha256_bytes(b""), &mut progress);
+    let reason = match outcome {
+        Ok(true) => None,
+        Ok(false) => Some(progress.mismatch.map_or("op arguments or preconditions rejected".to_string(), |m| m.to_string())),
+        Err(e) => Some(e.to_string()),
+    };
+    LinesReplay { valid: reason.is_none(), steps: progress.steps, trail: progress.trail, reason }
+}
+
+/// Binary-search the prefixes of `trace_path` for the shortest one that does
+/// not replay. None when the whole trace replays.
+pub fn bisect_trace(trace_path: &Path) -> Result<Option<Bisection>> {
+    let txt = fs::read_to_string(trace_path)?;
+    let lines: Vec<&str> = txt.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut replays = 0;
+    let mut replay = |k: usize| {
+        replays += 1;
+        replay_lines(&lines[..k])
+    };
+    if replay(lines.len()).valid {
+        return Ok(None);
+    }
+    // prefix lo replays, prefix hi does not
+    let (mut lo, mut hi) = (0, lines.len());
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if replay(mid).valid {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let progress = replay(hi);
+    let recs: Vec<serde_json::Value> = lines[..hi].iter().map(|l| serde_json::from_str(l)).collect::<Result<_, _>>()?;
+    let failing = &recs[hi - 1];
+    let counts = recs
+        .iter()
+        .enumerate()
+        .skip(hi.saturating_sub(4))
+        .map(|(i, r)| CountDiff {
+            step: r["step"].as_u64().unwrap_or(i as u64) as usize,
+            op: r["op"].as_str().unwrap_or("").to_string(),
+            recorded: r["post"]["count"].as_u64().unwrap_or(0) as usize,
+            replayed: progress.trail.get(i).map(|(c, _)| *c),
+        })
+        .collect();
+    Ok(Some(Bisection {
+        step: failing["step"].as_u64().unwrap_or(hi as u64 - 1) as usize,
+        op: failing["op"].as_str().unwrap_or("").to_string(),
+        args: failing["args"].clone(),
+        recorded_set_digest: failing["post"]["set_digest"].as_str().map(str::to_string),
+        replayed_set_digest: progress.trail.get(hi - 1).map(|(_, d)| hex32(*d)),
+        replays,
+        counts,
+    }))
+}
+
+impl std::fmt::Display for Bisection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |d: &Option<String>| d.clone().unwrap_or_else(|| "(none)".to_string());
+        writeln!(f, "step {} ({} {}) is the first that does not reproduce ({} prefix replays)", self.step, self.op, self.args, self.replays)?;
+        writeln!(f, "  recorded post.set_digest {}", or_none(&self.recorded_set_digest))?;
+        writeln!(f, "  replayed post.set_digest {}", or_none(&self.replayed_set_digest))?;
+        writeln!(f, "  {:>5}  {:<20} {:>10} {:>10}", "step", "op", "recorded", "replayed")?;
+        for c in &self.counts {
+            let replayed = c.replayed.map_or("-".to_string(), |n| n.to_string());
+            let mark = if c.replayed != Some(c.recorded) { "  <" } else { "" };
+            writeln!(f, "  {:>5}  {:<20} {:>10} {:>10}{}", c.step, c.op, c.recorded, replayed, mark)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replay `trace_path` and report the first step that fails, if any.
+pub fn verify_trace_report(trace_path: &Path) -> VerifyReport {
+    let _span = tracing::info_span!("verify", trace = %trace_path.display()).entered();
+    text_report(trace_path.display().to_string(), fs::read_to_string(trace_path), Some(trace_path), None)
+}
+
+/// The report for trace.ndjson `text`. The result.json and proof.json next
+/// to `trace_path`, when there is one, are checked against the replay too.
+fn text_report(trace: String, text: std::io::Result<String>, trace_path: Option<&Path>, cancel: Option<&CancelToken>) -> VerifyReport {
+    let mut progress = Progress { cancel: cancel.cloned(), ..Default::default() };
+    let outcome = match text.as_deref() {
+        Ok(txt) => replay_text(txt, &mut progress),
+        Err(e) => Err(anyhow!("{}", e)),
+    };
+    let valid = matches!(outcome, Ok(true));
+    let (reason, mismatch) = match outcome {
+        Ok(true) => (None, None),
+        Ok(false) => match progress.mismatch.take() {
+            Some(m) => (Some(m.to_string()), Some(m)),
+            None => (Some("step does not replay: op arguments or preconditions rejected".to_string()), None),
+        },
+        Err(e) => (Some(e.to_string()), e.downcast::<Mismatch>().ok()),
+    };
+    let (failed_step, failed_op) = match (&progress.current, valid) {
+        (Some((step, op)), false) => (Some(*step), Some(op.clone())),
+        _ => (None, None),
+    };
+    let mut report = VerifyReport {
+        trace,
+        valid,
+        steps_checked: progress.steps,
+        chain_hash: progress.chain.filter(|_| valid).map(hex32),
+        failed_step,
+        failed_op,
+        reason,
+        mismatch,
+        steps: step_reports(text.as_deref().unwrap_or_default(), progress.steps, valid),
+        proofs_checked: None,
+        signature_verified: None,
+        artifacts_checked: Vec::new(),
+        artifact_mismatch: None,
+        bisect: None,
+    };
+    // A run directory's result.json may carry inclusion proofs for its elements.
+    if let (true, Some(digest), Some(trace_path)) = (valid, progress.set_digest, trace_path) {
+        let proofs = fs::read_to_string(trace_path.with_file_name("result.json"))
+            .ok()
+            .and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok())
+            .and_then(|r| r.get("proofs").cloned());
+        if let Some(proofs) = proofs {
+            let _hash = crate::digest::use_hash(progress.hash);
+            match check_inclusion_proofs(&proofs, &digest) {
+                Ok(n) => report.proofs_checked = Some(n),
+                Err(e) => {
+                    report.valid = false;
+                    report.chain_hash = None;
+                    report.reason = Some(format!("inclusion proof: {}", e));
+                }
+            }
+        }
+    }
+    if let (true, Some(trace_path)) = (report.valid, trace_path) {
+        let chain = progress.chain.map(hex32).unwrap_or_default();
+        let _hash = crate::digest::use_hash(progress.hash);
+        match check_artifacts(trace_path, &chain, &progress.fin) {
+            Ok(files) => report.artifacts_checked = files,
+            Err(m) => {
+                report.valid = false;
+                report.chain_hash = None;
+                report.reason = Some(m.to_string());
+                report.artifact_mismatch = Some(*m);
+            }
+        }
+    }
+    match report.reason.as_deref() {
+        None => tracing::info!(steps = report.steps_checked, chain_hash = report.chain_hash.as_deref(), "trace verified"),
+        Some(reason) => tracing::info!(steps = report.steps_checked, failed_step = report.failed_step, reason, "trace does not verify"),
+    }
+    report
+}
+
+/// Cross-check the result.json and proof.json next to `trace_path`, when
+/// present, against the replay's final state and the trace's records.
+/// Returns the files checked.
+fn check_artifacts(trace_path: &Path, chain_hash: &str, fin: &FinalState) -> Result<Vec<String>, Box<ArtifactMismatch>> {
+    let read = |name: &str| -> Option<serde_json::Value> {
+        serde_json::from_str(&fs::read_to_string(trace_path.with_file_name(name)).ok()?).ok()
+    };
+    let differ = |file: &str, field: &str, expected: String, got: &serde_json::Value| {
+        Box::new(ArtifactMismatch { file: file.to_string(), field: field.to_string(), expected, got: got.to_string() })
+    };
+    let mut checked = Vec::new();
+
+    if let Some(result) = read("result.json") {
+        let f = "result.json";
+        let expect: [(&str, serde_json::Value); 8] = [
+            ("chain_hash", serde_json::json!(chain_hash)),
+            ("count", serde_json::json!(fin.count)),
+            ("verdict", serde_json::json!(if fin.count > 0 { "OK" } else { "EMPTY_SET" })),
+            ("witness", serde_json::json!(fin.witness)),
+            ("constraint", serde_json::json!({ "mask": fin.constraint.0, "value": fin.constraint.1 })),
+            ("witness_ties_root", serde_json::json!(fin.ties.as_ref().map(|(_, r)| r))),
+            ("aggregate", serde_json::json!(fin.aggregate)),
+            ("group_by", serde_json::json!(fin.group_by)),
+        ];
+        for (field, want) in expect {
+            if fin.linguistic && matches!(field, "count" | "verdict" | "witness") {
+                continue;
+            }
+            let got = result.get(field).cloned().unwrap_or(serde_json::Value::Null);
+            if got != want {
+                return Err(differ(f, field, want.to_string(), &got));
+            }
+        }
+        let sample = result["sample"].as_array().cloned().unwrap_or_default();
+        let max_items = result["return_set"]["max_items"].as_u64().unwrap_or(u64::MAX);
+        if sample.len() as u64 > max_items {
+            return Err(differ(f, "sample", format!("at most {} items", max_items), &serde_json::json!(sample.len())));
+        }
+        // every sampled element is in the final set (the witness may lead the page)
+        if let Some((encoding, leaves)) = fin.leaves.as_ref() {
+            let members: std::collections::HashSet<&[u8; 32]> = leaves.iter().collect();
+            let skip = usize::from(result["return_set"]["include_witness"] == serde_json::json!(true) && fin.witness.is_some());
+            for (i, elem) in sample.iter().enumerate().skip(skip) {
+                let leaf = elem.as_str().and_then(|e| proof_leaf(encoding, e));
+                if !leaf.is_some_and(|l| members.contains(&l)) {
+                    return Err(differ(f, &format!("sample[{}]", i), "an element of the final set".to_string(), elem));
+                }
+            }
+        }
+        checked.push(f.to_string());
+    }
+
+    if let Some(proof) = read("proof.json") {
+        let f = "proof.json";
+        let records: Vec<serde_json::Value> = fs::read_to_string(trace_path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        let ops = proof["ops_in"].as_array().cloned().unwrap_or_default();
+        if ops.len() != records.len() {
+            return Err(differ(f, "ops_in", format!("{} ops", records.len()), &serde_json::json!(ops.len())));
+        }
+        for (i, (op, rec)) in ops.iter().zip(&records).enumerate() {
+            let recorded = rec["op"].as_str().and_then(|name| Op::from_record(name, &rec["args"]).ok());
+            let matches = match (op.as_str().and_then(|o| o.parse::<Op>().ok()), recorded) {
+                (Some(mut ours), Some(theirs)) => {
+                    // SELECT_UNIVERSE n=0 is recorded with the configured bound
+                    if let (Op::SelectUniverse { n, .. }, Op::SelectUniverse { n: bound, .. }) = (&mut ours, &theirs) {
+                        if *n == 0 {
+                            *n = *bound;
+                        }
+                    }
+                    ours == theirs
+                }
+                _ => false,
+            };
+            if !matches {
+                let want = format!("{} {}", rec["op"].as_str().unwrap_or(""), rec["args"]);
+                return Err(differ(f, &format!("ops_in[{}]", i), want, op));
+            }
+        }
+        checked.push(f.to_string());
+    }
+    Ok(checked)
+}
+
+impl VerifyReport {
+    /// One line locating the failure for people, e.g. `step 2 (SET_BIT): post.count
+    /// mismatch (count): replay has 12, trace records 13`. None when valid.
+    pub fn diagnosis(&self) -> Option<String> {
+        if self.valid {
+            return None;
+        }
+        let at = match (self.failed_step, self.failed_op.as_deref()) {
+            (Some(step), Some(op)) => format!("step {} ({}): ", step, op),
+            _ => String::new(),
+        };
+        Some(match &self.mismatch {
+            Some(m) => format!(
+                "{}{} mismatch ({}): replay has {}, trace records {}",
+                at,
+                m.field,
+                m.kind.as_str(),
+                m.expected,
+                m.got
+            ),
+            None => format!("{}{}", at, self.reason.as_deref().unwrap_or("invalid")),
+        })
+    }
+}
+
+/// Leaf hash of a rendered element under an inclusion-proof `encoding`:
+/// "frac" (QE), "boolfun<n>" (e.g. "boolfun3"), "pt", "quad" or "tetra".
+pub(crate) fn proof_leaf(encoding: &str, elem: &str) -> Option<[u8; 32]> {
+    if let Some(n) = encoding.strip_prefix("boolfun") {
+        // rendered as 0xHHHH for n = 4 and u64:<bits> otherwise
+        let bits = match elem.strip_prefix("0x") {
+            Some(h) => u64::from_str_radix(h, 16).ok()?,
+            None => elem.strip_prefix("u64:")?.parse().ok()?,
+        };
+        return Some(leaf_hash(&BoolFun { n: n.parse().ok()?, bits }.canonical_bytes()));
+    }
+    match encoding {
+        "frac" => parse_frac(elem).map(|f| leaf_hash(&f.canonical_bytes())),
+        "pt" => parse_pt(elem).map(|p| leaf_hash(&p.canonical_bytes())),
+        "quad" => parse_quad(elem).map(|q| leaf_hash(&q.to_bytes())),
+        "tetra" => parse_tetra(elem).map(|t| leaf_hash(&t.to_bytes())),
+        _ => None,
+    }
+}
+
+/// The set-digest backend a record names in `hash` (SHA-256 when absent).
+pub(crate) fn record_hash(hash: Option<&str>) -> Result<HashAlg> {
+    match hash {
+        None => Ok(HashAlg::Sha256),
+        Some(h) => HashAlg::parse(h).ok_or_else(|| anyhow!("unknown hash backend {}", h)),
+    }
+}
+
+fn parse_hex32(s: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s).map_err(|e| anyhow!("bad hash {:?}: {}", s, e))?;
+    bytes.try_into().map_err(|_| anyhow!("bad hash {:?}: not 32 bytes", s))
+}
+
+/// Check a result.json `proofs` object against the replayed final set digest,
+/// returning how many elements it proves.
+fn check_inclusion_proofs(proofs: &serde_json::Value, set_digest: &[u8; 32]) -> Result<usize> {
+    if proofs["set_digest"].as_str() != Some(hex32(*set_digest).as_str()) {
+        return Err(anyhow!("set digest differs from the replayed trace"));
+    }
+    let encoding = proofs["encoding"].as_str().ok_or_else(|| anyhow!("missing encoding"))?;
+    let elements = proofs["elements"].as_array().ok_or_else(|| anyhow!("missing elements"))?;
+    for item in elements {
+        let elem = item["elem"].as_str().ok_or_else(|| anyhow!("element without elem"))?;
+        let leaf = proof_leaf(encoding, elem).ok_or_else(|| anyhow!("cannot encode {} as {}", elem, encoding))?;
+        if item["leaf"].as_str() != Some(hex32(leaf).as_str()) {
+            return Err(anyhow!("{}: leaf does not match the element", elem));
+        }
+        let index = item["index"].as_u64().ok_or_else(|| anyhow!("{}: missing index", elem))? as usize;
+        let path = item["path"]
+            .as_array()
+            .ok_or_else(|| anyhow!("{}: missing path", elem))?
+            .iter()
+            .map(|h| parse_hex32(h.as_str().unwrap_or("")))
+            .collect::<Result<Vec<_>>>()?;
+        if merkle_root_from_path(&HashAlg::active(), &leaf, index, &path) != *set_digest {
+            return Err(anyhow!("{}: path does not lead to the set digest", elem));
+        }
+    }
+    Ok(elements.len())
+}
+
+/// As `verify_trace_report`, for trace.ndjson contents held in memory (a
+/// request body), without touching the filesystem; the report names the
+/// trace `<request body>`.
+pub fn verify_trace_text(text: &str) -> Result<VerifyReport> {
+    let _span = tracing::info_span!("verify", trace = "<request body>").entered();
+    Ok(text_report("<request body>".to_string(), Ok(text.to_string()), None, None))
+}
+
+/// `verify_trace_text` for async servers: the trace replays on a thread of
+/// its own rather than the caller's, and stops before the next record with
+/// `cancel::Cancelled` once `cancel` fires.
+pub async fn verify_async(text: String, cancel: &CancelToken) -> Result<VerifyReport> {
+    let token = cancel.clone();
+    let report = crate::cancel::on_thread(move || text_report("<request body>".to_string(), Ok(text), None, Some(&token))).await;
+    cancel.check()?;
+    Ok(report)
+}
+
+fn replay_trace(trace_path: &Path, progress: &mut Progress) -> Result<bool> {
+    replay_text(&fs::read_to_string(trace_path)?, progress)
+}
+
+fn replay_text(txt: &str, progress: &mut Progress) -> Result<bool> {
+    match verify_threads() {
+        0 | 1 => replay_records(txt, sha256_bytes(b""), progress),
+        n => replay_segments(txt, n, progress),
+    }
+}
+
+static VERIFY_THREADS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(1);
+
+/// Replay traces on up to `n` threads from now on (see `replay_segments`).
+pub fn set_verify_threads(n: usize) {
+    VERIFY_THREADS.store(n, std::sync::atomic::Ordering::Relaxed);
+}
+
+fn verify_threads() -> usize {
+    VERIFY_THREADS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Ops that read state kept across universe switches (predicates, the state
+/// stack, saved sets); a trace using any of them replays sequentially.
+const CROSS_SEGMENT_OPS: &[&str] = &["DEFINE_PRED", "PUSH_STATE", "POP_STATE", "SAVE_SET", "INTERSECT", "UNION"];
+
+/// Record indices where a replay from fresh state is equivalent to the
+/// sequential one: 0 and every SELECT_UNIVERSE of a universe whose selection
+/// resets all replay state. None when the trace must replay sequentially.
+fn segment_starts(records: &[serde_json::Value]) -> Option<Vec<usize>> {
+    let mut starts = vec![0];
+    for (i, rec) in records.iter().enumerate() {
+        let op = rec["op"].as_str()?;
+        if CROSS_SEGMENT_OPS.contains(&op) {
+            return None;
+        }
+        if op == "SELECT_UNIVERSE" {
+            let u = rec["args"]["universe"].as_str()?.to_ascii_uppercase();
+            // the linguistic universes leave their flags set on a later switch
+            if is_word_universe(&u) || is_syllable_universe(&u) || is_morpheme_universe(&u) || is_phrase_universe(&u)
+                || is_semantic_universe(&u) || is_discourse_universe(&u)
+            {
+                return None;
+            }
+            if i > 0 {
+                starts.push(i);
+            }
+        }
+    }
+    Some(starts)
+}
+
+/// Replay `txt` as independent segments on `threads` threads, then check the
+/// chain across segment boundaries. Each segment starts at a universe switch
+/// and seeds its chain with the step digest recorded just before it (trusted
+/// on read, confirmed by the final pass). Falls back to `replay_records` when
+/// the trace has no independent segments.
+fn replay_segments(txt: &str, threads: usize, progress: &mut Progress) -> Result<bool> {
+    let lines: Vec<&str> = txt.lines().filter(|l| !l.trim().is_empty()).collect();
+    let records: Vec<serde_json::Value> = lines.iter().map(|l| serde_json::from_str(l).unwrap_or_default()).collect();
+    let starts = match segment_starts(&records) {
+        Some(s) if s.len() > 1 => s,
+        _ => return replay_records(txt, sha256_bytes(b""), progress),
+    };
+    // group segments into about `threads` chunks of similar record counts
+    let target = lines.len().div_ceil(threads);
+    let mut chunks: Vec<std::ops::Range<usize>> = Vec::new();
+    let mut begin = 0;
+    for &s in &starts[1..] {
+        if s - begin >= target {
+            chunks.push(begin..s);
+            begin = s;
+        }
+    }
+    chunks.push(begin..lines.len());
+
+    let seed = |start: usize| -> Result<[u8; 32]> {
+        if start == 0 {
+            return Ok(sha256_bytes(b""));
+        }
+        let hex = records[start - 1]["step_digest"].as_str().unwrap_or("");
+        parse_hex32(hex).map_err(|e| anyhow!("record {}: step_digest: {}", start - 1, e))
+    };
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+    let results: Vec<(Result<bool>, Progress)> = pool.install(|| {
+        use rayon::prelude::*;
+        chunks
+            .par_iter()
+            .map(|r| {
+                let mut p = Progress { cancel: progress.cancel.clone(), ..Default::default() };
+                let out = seed(r.start).and_then(|s| replay_records(&lines[r.clone()].join("\n"), s, &mut p));
+                (out, p)
+            })
+            .collect()
+    });
+
+    let mut done = 0;
+    let mut prev_chain: Option<[u8; 32]> = None;
+    for ((out, p), r) in results.into_iter().zip(&chunks) {
+        progress.steps = done + p.steps;
+        progress.current = p.current;
+        progress.mismatch = p.mismatch;
+        if r.start == 0 {
+            progress.hash = p.hash;
+        } else if p.hash != progress.hash {
+            return Err(anyhow!("hash backend changes mid-trace"));
+        }
+        match out {
+            Ok(true) => {}
+            other => return other,
+        }
+        // final pass: each segment's seed is the chain its predecessor verified
+        if prev_chain.is_some() && prev_chain != Some(seed(r.start)?) {
+            return Err(anyhow!("chain breaks between records {} and {}", r.start - 1, r.start));
+        }
+        prev_chain = p.chain;
+        progress.set_digest = p.set_digest;
+        progress.fin = std::mem::take(&mut progress.fin).then(p.fin);
+        done += p.steps;
+    }
+    progress.chain = prev_chain;
+    Ok(true)
+}
+
+/// Replay trace records `txt` with the step-digest chain starting at `seed`.
+#[allow(unused_assignments)]
+fn replay_records(txt: &str, seed: [u8; 32], progress: &mut Progress) -> Result<bool> {
     let qe = build_qe();
     let ge_state = crate::geom::build_ge(20);
-    let txt = fs::read_to_string(trace_path)?;
+    // one backend for the whole trace, named by every record
+    let first: Option<serde_json::Value> =
+        txt.lines().find(|l| !l.trim().is_empty()).and_then(|l| serde_json::from_str(l).ok());
+    progress.hash = record_hash(first.as_ref().and_then(|r| r["hash"].as_str()))?;
+    let _hash = crate::digest::use_hash(progress.hash);
 
     let mut boolfun_all: Vec<BoolFun> = Vec::new();
     let mut boolfun_set: Vec<BoolFun> = Vec::new();
@@ -142,10 +913,37 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
 
     let mut state_set: Vec<Frac> = Vec::new();
     let mut cst = Constraint::empty();
-    let mut set_digest = sha256_bytes(b"");
+    let mut set_digest = set_root(&[]);
     let mut witness: Option<Frac> = None;
     let mut witness_bf: Option<BoolFun> = None;
     let mut is_ge: bool = false;
+    let mut ge_set: Vec<crate::geom::Tri> = Vec::new();
+    let mut saved_sets: BTreeMap<String, SavedSet> = BTreeMap::new();
+    // PUSH_STATE / POP_STATE stack, replayed identically by the verifier
+    let mut state_stack: Vec<Frame> = Vec::new();
+    // DEFINE_PRED registry: user signature bits 7, 8, … in definition order
+    let mut user_preds: Vec<(String, crate::pred::Expr)> = Vec::new();
+    let mut lattice_all: Vec<Pt> = Vec::new();
+    let mut lattice_set: Vec<Pt> = Vec::new();
+    let mut witness_pt: Option<Pt> = None;
+    let mut is_lattice: bool = false;
+    let mut group_univ: Option<GroupUniverse> = None;
+    let mut group_set: Vec<Perm> = Vec::new();
+    let mut witness_perm: Option<Perm> = None;
+    let mut is_group: bool = false;
+    let mut subset_items: Vec<i64> = Vec::new();
+    let mut subset_all: Vec<Subset> = Vec::new();
+    let mut subset_set: Vec<Subset> = Vec::new();
+    let mut witness_subset: Option<Subset> = None;
+    let mut is_subsets: bool = false;
+    let mut quad_all: Vec<Quad> = Vec::new();
+    let mut quad_set: Vec<Quad> = Vec::new();
+    let mut witness_quad: Option<Quad> = None;
+    let mut is_quad: bool = false;
+    let mut tetra_all: Vec<Tetra> = Vec::new();
+    let mut tetra_set: Vec<Tetra> = Vec::new();
+    let mut witness_tetra: Option<Tetra> = None;
+    let mut is_tetra: bool = false;
     let mut word_all: Vec<Word> = Vec::new();
     let mut word_set: Vec<Word> = Vec::new();
     let mut is_word: bool = false;
@@ -168,13 +966,32 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
     let mut is_discourse = false;
     let _ = &witness_word; // read via is_word branches
 
-    let mut chain: [u8; 32] = sha256_bytes(b"");
+    let mut chain = Chain::resume(seed);
 
     for line in txt.lines() {
         if line.trim().is_empty() {
             continue;
         }
+        if let Some(cancel) = progress.cancel.as_ref() {
+            cancel.check()?;
+        }
+        progress.current = None;
         let rec: StepRec = serde_json::from_str(line)?;
+        progress.steps += 1;
+        progress.current = Some((rec.step, rec.op.clone()));
+        crate::trace_format::TraceFormat::for_version(rec.semtrace_version.as_deref())?;
+        if record_hash(rec.hash.as_deref())? != progress.hash {
+            return Err(anyhow!("hash backend changes mid-trace"));
+        }
+        let op = Op::from_record(&rec.op, &rec.args)?;
+        if let Op::SelectUniverse { hash: Some(h), .. } = &op {
+            if h != progress.hash.name() {
+                return Err(anyhow!("SELECT_UNIVERSE hash= differs from the trace's {} backend", progress.hash.name()));
+            }
+        }
+        let mut step_ties: Option<(Vec<String>, [u8; 32])> = None;
+        let mut step_hist: Option<BTreeMap<String, usize>> = None;
+        let mut step_agg: Option<serde_json::Value> = None;
 
         // recompute transition based on rec.op/args
         match rec.op.as_str() {
@@ -187,6 +1004,11 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                 let n = rec.args.get("n").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
 
                 let u_norm = u.to_ascii_uppercase();
+                is_lattice = false;
+                is_group = false;
+                is_subsets = false;
+                is_quad = false;
+                is_tetra = false;
 
                 if is_boolfun_universe(u_norm.as_str()) {
                     is_boolfun = true;
@@ -223,9 +1045,9 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     word_set = word_all.clone();
                     set_digest = {
                         let leaves: Vec<[u8; 32]> = word_set.iter()
-                            .map(|w| crate::digest::sha256_bytes(&w.canonical_bytes()))
+                            .map(|w| leaf_hash(&w.canonical_bytes()))
                             .collect();
-                        crate::digest::merkle_root(&leaves)
+                        set_root(&leaves)
                     };
                     witness = None;
                     witness_bf = None;
@@ -236,7 +1058,7 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     cst=Constraint::empty(); state_set.clear();
                     if syllable_all.is_empty() { syllable_all=build_syllable_universe(); }
                     syllable_set=syllable_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=syllable_set.iter().map(|s|sha256_bytes(&s.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
+                    set_digest={let mut l:Vec<[u8;32]>=syllable_set.iter().map(|s|leaf_hash(&s.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
                     witness=None; witness_bf=None;
                 } else if is_morpheme_universe(u_norm.as_str()) {
                     is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
@@ -244,7 +1066,7 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     cst=Constraint::empty(); state_set.clear();
                     if morpheme_all.is_empty() { morpheme_all=build_morpheme_universe(); }
                     morpheme_set=morpheme_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=morpheme_set.iter().map(|m|sha256_bytes(&m.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
+                    set_digest={let mut l:Vec<[u8;32]>=morpheme_set.iter().map(|m|leaf_hash(&m.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
                     witness=None; witness_bf=None;
                 } else if is_phrase_universe(u_norm.as_str()) {
                     is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
@@ -252,7 +1074,7 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     cst=Constraint::empty(); state_set.clear();
                     if phrase_all.is_empty() { phrase_all=build_phrase_inventory(); }
                     phrase_set=phrase_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=phrase_set.iter().map(|p|sha256_bytes(&p.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
+                    set_digest={let mut l:Vec<[u8;32]>=phrase_set.iter().map(|p|leaf_hash(&p.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
                     witness=None; witness_bf=None;
                 } else if is_semantic_universe(u_norm.as_str()) {
                     is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
@@ -260,7 +1082,7 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     cst=Constraint::empty(); state_set.clear();
                     if semantic_all.is_empty() { semantic_all=build_semantic_inventory(); }
                     semantic_set=semantic_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=semantic_set.iter().map(|g|sha256_bytes(&g.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
+                    set_digest={let mut l:Vec<[u8;32]>=semantic_set.iter().map(|g|leaf_hash(&g.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
                     witness=None; witness_bf=None;
                 } else if is_discourse_universe(u_norm.as_str()) {
                     is_boolfun=false; is_ge=false; is_word=false; is_syllable=false;
@@ -268,11 +1090,603 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     cst=Constraint::empty(); state_set.clear();
                     if discourse_all.is_empty() { discourse_all=build_discourse_inventory(); }
                     discourse_set=discourse_all.clone();
-                    set_digest={let mut l:Vec<[u8;32]>=discourse_set.iter().map(|g|sha256_bytes(&g.canonical_bytes())).collect();l.sort_unstable();merkle_root(&l)};
+                    set_digest={let mut l:Vec<[u8;32]>=discourse_set.iter().map(|g|leaf_hash(&g.canonical_bytes())).collect();l.sort_unstable();set_root(&l)};
                     witness=None; witness_bf=None;
+                } else if is_lattice_universe(u_norm.as_str()) {
+                    is_boolfun = false;
+                    is_ge = false;
+                    is_lattice = true;
+                    cst = Constraint::empty();
+                    state_set.clear();
+                    let r = if n == 0 { crate::lattice::DEFAULT_RADIUS } else { n as i32 };
+                    lattice_all = build_lattice(r);
+                    lattice_set = lattice_all.clone();
+                    set_digest = canonical_set_digest_lattice(&lattice_set);
+                    witness = None;
+                    witness_bf = None;
+                    witness_pt = None;
+                } else if is_group_universe(u_norm.as_str()) {
+                    let kind = match rec.args.get("group").and_then(|v| v.as_str()).and_then(parse_group_name) {
+                        Some(k) => k,
+                        None => return Ok(false),
+                    };
+                    is_boolfun = false;
+                    is_ge = false;
+                    is_group = true;
+                    cst = Constraint::empty();
+                    state_set.clear();
+                    let g = GroupUniverse::build(kind);
+                    group_set = g.elems.clone();
+                    group_univ = Some(g);
+                    set_digest = canonical_set_digest_group(&group_set);
+                    witness = None;
+                    witness_bf = None;
+                    witness_perm = None;
+                } else if is_subsets_universe(u_norm.as_str()) {
+                    let items: Vec<i64> = match rec
+                        .args
+                        .get("items")
+                        .and_then(|v| serde_json::from_value(v.clone()).ok())
+                    {
+                        Some(v) => v,
+                        None => return Ok(false),
+                    };
+                    if items.is_empty() || items.len() > crate::subsets::MAX_ITEMS {
+                        return Ok(false);
+                    }
+                    is_boolfun = false;
+                    is_ge = false;
+                    is_subsets = true;
+                    cst = Constraint::empty();
+                    state_set.clear();
+                    subset_all = build_subsets(&items);
+                    subset_items = items;
+                    subset_set = subset_all.clone();
+                    set_digest = canonical_set_digest_subsets(&subset_set);
+                    witness = None;
+                    witness_bf = None;
+                    witness_subset = None;
+                } else if is_quad_universe(u_norm.as_str()) {
+                    is_boolfun = false;
+                    is_ge = false;
+                    is_quad = true;
+                    cst = Constraint::empty();
+                    state_set.clear();
+                    let max_p = if n == 0 { crate::geom::DEFAULT_QUAD_PERIMETER } else { n as i32 };
+                    quad_all = build_quad(max_p);
+                    quad_set = quad_all.clone();
+                    set_digest = canonical_set_digest_quad(&quad_set);
+                    witness = None;
+                    witness_bf = None;
+                    witness_quad = None;
+                } else if is_tetra_universe(u_norm.as_str()) {
+                    let max_e = if n == 0 { crate::geom::DEFAULT_TETRA_EDGE } else { n as i32 };
+                    if max_e > crate::geom::MAX_TETRA_EDGE {
+                        return Ok(false);
+                    }
+                    is_boolfun = false;
+                    is_ge = false;
+                    is_tetra = true;
+                    cst = Constraint::empty();
+                    state_set.clear();
+                    tetra_all = build_tetra(max_e);
+                    tetra_set = tetra_all.clone();
+                    set_digest = canonical_set_digest_tetra(&tetra_set);
+                    witness = None;
+                    witness_bf = None;
+                    witness_tetra = None;
+                } else {
+                    return Ok(false);
+                }
+            }
+            "FILTER_SUM" => {
+                if !is_subsets {
+                    return Ok(false);
+                }
+                let min = rec
+                    .args
+                    .get("min")
+                    .and_then(|v| v.as_i64())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let max = rec
+                    .args
+                    .get("max")
+                    .and_then(|v| v.as_i64())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                subset_set.retain(|x| x.sum >= min && x.sum <= max);
+                set_digest = canonical_set_digest_subsets(&subset_set);
+            }
+            "FILTER_MAX_ANGLE" => {
+                let max = match rec.args.get("max").and_then(|v| v.as_u64()) {
+                    Some(d) if is_ge => d as u32,
+                    _ => return Ok(false),
+                };
+                ge_set.retain(|t| t.max_angle_at_most(max));
+                state_set = project_tris(&ge_set);
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "FILTER_AREA" => {
+                if !is_quad && !is_ge {
+                    return Ok(false);
+                }
+                let min = rec
+                    .args
+                    .get("min")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as i64;
+                let max = rec
+                    .args
+                    .get("max")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as i64;
+                let in_range = |k16: i64| {
+                    let k16 = k16 as i128;
+                    k16 >= 16 * (min as i128).pow(2) && k16 <= 16 * (max as i128).pow(2)
+                };
+                if is_ge {
+                    ge_set.retain(|t| in_range(t.area_sq16()));
+                    state_set = project_tris(&ge_set);
+                    set_digest = canonical_set_digest(&state_set);
+                } else {
+                    quad_set.retain(|q| in_range(q.area_sq16()));
+                    set_digest = canonical_set_digest_quad(&quad_set);
+                }
+            }
+            "SAMPLE" => {
+                let seed = rec
+                    .args
+                    .get("seed")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let k = rec
+                    .args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as usize;
+                if is_boolfun {
+                    boolfun_set = sample_seeded(&boolfun_set, seed, k);
+                    set_digest = canonical_set_digest_boolfun(&boolfun_set);
+                } else if is_ge {
+                    ge_set = sample_seeded(&ge_set, seed, k);
+                    state_set = project_tris(&ge_set);
+                    set_digest = canonical_set_digest(&state_set);
+                } else if is_lattice {
+                    lattice_set = sample_seeded(&lattice_set, seed, k);
+                    set_digest = canonical_set_digest_lattice(&lattice_set);
+                } else if is_group {
+                    group_set = sample_seeded(&group_set, seed, k);
+                    set_digest = canonical_set_digest_group(&group_set);
+                } else if is_subsets {
+                    subset_set = sample_seeded(&subset_set, seed, k);
+                    set_digest = canonical_set_digest_subsets(&subset_set);
+                } else if is_quad {
+                    quad_set = sample_seeded(&quad_set, seed, k);
+                    set_digest = canonical_set_digest_quad(&quad_set);
+                } else if is_tetra {
+                    tetra_set = sample_seeded(&tetra_set, seed, k);
+                    set_digest = canonical_set_digest_tetra(&tetra_set);
+                } else if is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+                    return Ok(false);
+                } else {
+                    state_set = sample_seeded(&state_set, seed, k);
+                    set_digest = canonical_set_digest(&state_set);
+                }
+            }
+            "AGGREGATE" => {
+                // min/max follow each universe's canonical order (exact value order for QE)
+                let mut stats = if is_boolfun {
+                    let mut hist = vec![0usize; (1usize << boolfun_n) + 1];
+                    for f in &boolfun_set {
+                        hist[f.weight() as usize] += 1;
+                    }
+                    let (min, max) = set_ends(&boolfun_set, boolfun_to_string);
+                    serde_json::json!({ "min": min, "max": max, "weight_hist": hist })
+                } else if is_ge {
+                    let per_sum: i64 = ge_set.iter().map(|t| t.perimeter() as i64).sum();
+                    let (min, max) = set_ends(&ge_set, |t| format!("{},{},{}", t.a, t.b, t.c));
+                    serde_json::json!({ "min": min, "max": max, "perimeter_sum": per_sum })
+                } else if is_lattice {
+                    let (min, max) = set_ends(&lattice_set, pt_to_string);
+                    serde_json::json!({ "min": min, "max": max })
+                } else if is_group {
+                    let (min, max) = set_ends(&group_set, perm_to_string);
+                    serde_json::json!({ "min": min, "max": max })
+                } else if is_subsets {
+                    let (min, max) = set_ends(&subset_set, |x| subset_to_string(&subset_items, x));
+                    serde_json::json!({ "min": min, "max": max })
+                } else if is_quad {
+                    let (min, max) = set_ends(&quad_set, quad_to_string);
+                    serde_json::json!({ "min": min, "max": max })
+                } else if is_tetra {
+                    let (min, max) = set_ends(&tetra_set, tetra_to_string);
+                    serde_json::json!({ "min": min, "max": max })
+                } else if is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+                    return Ok(false);
+                } else {
+                    let (min, max) = set_ends(&state_set, frac_to_string);
+                    serde_json::json!({ "min": min, "max": max, "mean": crate::qe::exact_mean(&state_set) })
+                };
+                stats["count"] = serde_json::json!(if is_boolfun {
+                    boolfun_set.len()
+                } else if is_ge {
+                    ge_set.len()
+                } else if is_lattice {
+                    lattice_set.len()
+                } else if is_group {
+                    group_set.len()
+                } else if is_subsets {
+                    subset_set.len()
+                } else if is_quad {
+                    quad_set.len()
+                } else if is_tetra {
+                    tetra_set.len()
+                } else {
+                    state_set.len()
+                });
+                step_agg = Some(stats);
+            }
+            "GROUP_BY" => {
+                let by = rec.args.get("by").and_then(|v| v.as_str()).unwrap_or("sig");
+                let (sigs, legend): (Vec<u8>, [&str; 7]) = if is_ge {
+                    (
+                        ge_set.iter().map(crate::semtrace::sig7_geom).collect(),
+                        crate::semtrace::bit_legend_geom(),
+                    )
+                } else if is_lattice {
+                    (
+                        lattice_set.iter().map(crate::lattice::sig7).collect(),
+                        crate::lattice::bit_legend(),
+                    )
+                } else if is_group {
+                    let g = group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                    (group_set.iter().map(|h| g.sig7(h)).collect(), crate::group::bit_legend())
+                } else if is_subsets {
+                    let n_items = subset_items.len();
+                    (
+                        subset_set.iter().map(|x| crate::subsets::sig7(x, n_items)).collect(),
+                        crate::subsets::bit_legend(),
+                    )
+                } else if is_quad {
+                    (
+                        quad_set.iter().map(crate::semtrace::sig7_quad).collect(),
+                        crate::semtrace::bit_legend_quad(),
+                    )
+                } else if is_tetra {
+                    (
+                        tetra_set.iter().map(crate::semtrace::sig7_tetra).collect(),
+                        crate::semtrace::bit_legend_tetra(),
+                    )
+                } else if is_boolfun || is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+                    return Ok(false);
+                } else {
+                    (
+                        state_set.iter().map(crate::semtrace::sig7).collect(),
+                        crate::semtrace::bit_legend(),
+                    )
+                };
+                // by=sig buckets on the full signature (bit6..bit0); a legend name buckets on one bit
+                let bit = if by == "sig" {
+                    None
+                } else {
+                    match legend.iter().position(|p| *p == by) {
+                        Some(i) => Some(i),
+                        None => return Ok(false),
+                    }
+                };
+                let mut hist: BTreeMap<String, usize> = BTreeMap::new();
+                for s in sigs {
+                    let key = match bit {
+                        Some(i) => ((s >> i) & 1).to_string(),
+                        None => format!("{:07b}", s),
+                    };
+                    *hist.entry(key).or_insert(0) += 1;
+                }
+                step_hist = Some(hist);
+            }
+            "ASSERT_COUNT" | "ASSERT_WITNESS" => {
+                // checked against the post-state below; no state change
+            }
+            // NEGATE on BOOLFUN complements outputs; on QE it is handled by the element-map arm below
+            "NEGATE" | "DUAL" | "PERMUTE_VARS" | "RESTRICT" if is_boolfun || rec.op != "NEGATE" => {
+                if !is_boolfun || boolfun_n > 6 {
+                    return Ok(false);
+                }
+                let perm: Vec<u8> = match rec.op.as_str() {
+                    "PERMUTE_VARS" => {
+                        let perm: Option<Vec<u8>> = rec.args
+                            .get("perm")
+                            .and_then(|v| v.as_array())
+                            .and_then(|a| a.iter().map(|x| x.as_u64().map(|x| x as u8)).collect());
+                        let mut sorted = perm.clone().unwrap_or_default();
+                        sorted.sort_unstable();
+                        if sorted != (0..boolfun_n).collect::<Vec<u8>>() {
+                            return Ok(false);
+                        }
+                        perm.unwrap_or_default()
+                    }
+                    _ => Vec::new(),
+                };
+                let (var, value) = match rec.op.as_str() {
+                    "RESTRICT" => match (rec.args.get("var").and_then(|v| v.as_u64()), rec.args.get("value").and_then(|v| v.as_u64())) {
+                        (Some(i), Some(b)) if i < boolfun_n as u64 && b <= 1 => (i as u8, b == 1),
+                        _ => return Ok(false),
+                    },
+                    _ => (0, false),
+                };
+                let t = |f: &BoolFun| match rec.op.as_str() {
+                    "NEGATE" => f.negate(),
+                    "DUAL" => f.dual(),
+                    "PERMUTE_VARS" => f.permute_vars(&perm),
+                    _ => f.restrict(var, value),
+                };
+                boolfun_set = boolfun_set.iter().map(t).collect();
+                boolfun_set.sort_by(boolfun_canonical_cmp);
+                boolfun_set.dedup();
+                witness_bf = witness_bf.as_ref().map(t);
+                if rec.op == "RESTRICT" {
+                    boolfun_n -= 1;
+                    boolfun_all = build_boolfun(boolfun_n);
+                }
+                set_digest = canonical_set_digest_boolfun(&boolfun_set);
+            }
+            "NEGATE" | "RECIPROCAL" | "MEDIANT_WITH" => {
+                if is_boolfun || is_ge || is_lattice || is_group || is_subsets || is_quad || is_tetra
+                    || is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse
+                {
+                    return Ok(false);
+                }
+                state_set = match rec.op.as_str() {
+                    "NEGATE" => crate::qe::map_set(&state_set, crate::qe::negate),
+                    "RECIPROCAL" => crate::qe::map_set(&state_set, crate::qe::reciprocal),
+                    _ => {
+                        let g = match rec.args.get("elem").and_then(|v| v.as_str()).and_then(parse_frac) {
+                            Some(g) => g,
+                            None => return Ok(false),
+                        };
+                        crate::qe::map_set(&state_set, |f| Some(crate::qe::mediant(f, &g)))
+                    }
+                };
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "PUSH_STATE" | "POP_STATE" => {
+                if is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+                    return Ok(false);
+                }
+                let key = frame_key(
+                    [is_boolfun, is_ge, is_lattice, is_group, is_subsets, is_quad, is_tetra],
+                    group_univ.as_ref(),
+                    &subset_items,
+                    boolfun_n,
+                );
+                if rec.op == "PUSH_STATE" {
+                    state_stack.push(Frame {
+                        key,
+                        set_digest,
+                        cst,
+                        state_set: state_set.clone(),
+                        ge_set: ge_set.clone(),
+                        boolfun_set: boolfun_set.clone(),
+                        lattice_set: lattice_set.clone(),
+                        group_set: group_set.clone(),
+                        subset_set: subset_set.clone(),
+                        quad_set: quad_set.clone(),
+                        tetra_set: tetra_set.clone(),
+                        witness,
+                        witness_bf,
+                        witness_pt,
+                        witness_perm: witness_perm.clone(),
+                        witness_subset,
+                        witness_quad,
+                        witness_tetra,
+                    });
+                } else {
+                    let fr = match state_stack.pop() {
+                        Some(fr) => fr,
+                        None => return Ok(false),
+                    };
+                    if fr.key != key {
+                        return Ok(false);
+                    }
+                    set_digest = fr.set_digest;
+                    cst = fr.cst;
+                    state_set = fr.state_set;
+                    ge_set = fr.ge_set;
+                    boolfun_set = fr.boolfun_set;
+                    lattice_set = fr.lattice_set;
+                    group_set = fr.group_set;
+                    subset_set = fr.subset_set;
+                    quad_set = fr.quad_set;
+                    tetra_set = fr.tetra_set;
+                    witness = fr.witness;
+                    witness_bf = fr.witness_bf;
+                    witness_pt = fr.witness_pt;
+                    witness_perm = fr.witness_perm;
+                    witness_subset = fr.witness_subset;
+                    witness_quad = fr.witness_quad;
+                    witness_tetra = fr.witness_tetra;
+                }
+            }
+            "DEFINE_PRED" => {
+                let name = rec.args.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                let expr = rec.args.get("expr").and_then(|v| v.as_str()).unwrap_or("");
+                let e = match crate::pred::parse_expr(expr) {
+                    Ok(e) => e,
+                    Err(_) => return Ok(false),
+                };
+                if name.is_empty()
+                    || user_preds.iter().any(|(n, _)| n == name)
+                    || resolve_pred(&crate::semtrace::bit_legend(), name).is_some()
+                    || user_preds.len() >= crate::pred::MAX_USER_PREDS
+                {
+                    return Ok(false);
+                }
+                user_preds.push((name.to_string(), e));
+            }
+            "COMPLEMENT" => {
+                if is_boolfun {
+                    boolfun_set = difference_by(&boolfun_all, &boolfun_set, boolfun_canonical_cmp);
+                    set_digest = canonical_set_digest_boolfun(&boolfun_set);
+                } else if is_ge {
+                    ge_set = difference_by(&ge_state, &ge_set, crate::geom::canonical_cmp);
+                    state_set = project_tris(&ge_set);
+                    set_digest = canonical_set_digest(&state_set);
+                } else if is_lattice {
+                    lattice_set = difference_by(&lattice_all, &lattice_set, lattice_canonical_cmp);
+                    set_digest = canonical_set_digest_lattice(&lattice_set);
+                } else if is_group {
+                    let g = match group_univ.as_ref() {
+                        Some(g) => g,
+                        None => return Ok(false),
+                    };
+                    group_set = difference_by(&g.elems, &group_set, group_canonical_cmp);
+                    set_digest = canonical_set_digest_group(&group_set);
+                } else if is_subsets {
+                    subset_set = difference_by(&subset_all, &subset_set, subset_canonical_cmp);
+                    set_digest = canonical_set_digest_subsets(&subset_set);
+                } else if is_quad {
+                    quad_set = difference_by(&quad_all, &quad_set, canonical_cmp_quad);
+                    set_digest = canonical_set_digest_quad(&quad_set);
+                } else if is_tetra {
+                    tetra_set = difference_by(&tetra_all, &tetra_set, canonical_cmp_tetra);
+                    set_digest = canonical_set_digest_tetra(&tetra_set);
+                } else if is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+                    return Ok(false);
+                } else {
+                    state_set = difference_by(&qe, &state_set, canonical_cmp);
+                    set_digest = canonical_set_digest(&state_set);
+                }
+            }
+            "SAVE_SET" => {
+                let name = rec
+                    .args
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let snap = if is_boolfun {
+                    SavedSet::BoolFun(boolfun_set.clone())
+                } else if is_ge {
+                    SavedSet::Ge(ge_set.clone())
+                } else if is_lattice || is_group || is_subsets || is_quad || is_tetra {
+                    return Ok(false);
+                } else {
+                    SavedSet::Qe(state_set.clone())
+                };
+                saved_sets.insert(name.to_string(), snap);
+            }
+            "INTERSECT" | "UNION" => {
+                let name = rec
+                    .args
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let union = rec.op.as_str() == "UNION";
+                let is_qe = !is_boolfun && !is_ge && !is_lattice && !is_group && !is_subsets && !is_quad && !is_tetra;
+                match match saved_sets.get(name) {
+                    Some(v) => v,
+                    None => return Ok(false),
+                } {
+                    SavedSet::BoolFun(v) if is_boolfun => {
+                        boolfun_set = if union {
+                            union_by(&boolfun_set, v, boolfun_canonical_cmp)
+                        } else {
+                            intersect_by(&boolfun_set, v, boolfun_canonical_cmp)
+                        };
+                        set_digest = canonical_set_digest_boolfun(&boolfun_set);
+                    }
+                    SavedSet::Ge(v) if is_ge => {
+                        ge_set = if union {
+                            union_by(&ge_set, v, crate::geom::canonical_cmp)
+                        } else {
+                            intersect_by(&ge_set, v, crate::geom::canonical_cmp)
+                        };
+                        state_set = project_tris(&ge_set);
+                        set_digest = canonical_set_digest(&state_set);
+                    }
+                    SavedSet::Qe(v) if is_qe => {
+                        state_set = if union {
+                            union_by(&state_set, v, canonical_cmp)
+                        } else {
+                            intersect_by(&state_set, v, canonical_cmp)
+                        };
+                        set_digest = canonical_set_digest(&state_set);
+                    }
+                    _ => {
+                        return Ok(false);
+                    }
+                }
+            }
+            "FILTER_RANGE" => {
+                let min_s = rec
+                    .args
+                    .get("min")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let max_s = rec
+                    .args
+                    .get("max")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                if is_ge {
+                    let (min, max): (i32, i32) = match (min_s.parse(), max_s.parse()) {
+                        (Ok(a), Ok(b)) => (a, b),
+                        _ => return Ok(false),
+                    };
+                    ge_set.retain(|t| t.perimeter() >= min && t.perimeter() <= max);
+                    state_set = project_tris(&ge_set);
+                } else if is_boolfun || is_lattice || is_group || is_subsets || is_quad || is_tetra {
+                    return Ok(false);
                 } else {
+                    let (min, max) = match (parse_bound(min_s), parse_bound(max_s)) {
+                        (Some(a), Some(b)) => (a, b),
+                        _ => return Ok(false),
+                    };
+                    state_set.retain(|f| in_range(f, &min, &max));
+                }
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "NPN_CLASS" => {
+                if !is_boolfun || boolfun_n > 6 {
+                    return Ok(false);
+                }
+                match rec.args.get("elem").and_then(|v| v.as_str()) {
+                    // filter to the class of elem
+                    Some(e) => {
+                        let target = match parse_boolfun(e) {
+                            Some(t) if t.n == boolfun_n => t.npn_canonical(),
+                            _ => return Ok(false),
+                        };
+                        boolfun_set.retain(|f| f.npn_canonical() == target);
+                    }
+                    // dedup to class representatives
+                    None => {
+                        boolfun_set = boolfun_set.iter().map(|f| f.npn_canonical()).collect();
+                        boolfun_set.sort_by(boolfun_canonical_cmp);
+                        boolfun_set.dedup();
+                        witness_bf = witness_bf.map(|f| f.npn_canonical());
+                    }
+                }
+                set_digest = canonical_set_digest_boolfun(&boolfun_set);
+            }
+            "FILTER_CLASS" => {
+                let class = rec.args.get("class").and_then(|v| v.as_str()).unwrap_or("");
+                if !is_boolfun || boolfun_n > 6 {
                     return Ok(false);
                 }
+                if !crate::boolfun::CLASSES.contains(&class) {
+                    return Ok(false);
+                }
+                boolfun_set.retain(|f| f.in_class(class) == Some(true));
+                set_digest = canonical_set_digest_boolfun(&boolfun_set);
+            }
+            "FILTER_NONLINEARITY" => {
+                let min = rec.args.get("min").and_then(|v| v.as_u64());
+                let max = rec.args.get("max").and_then(|v| v.as_u64());
+                let (min, max) = match (min, max) {
+                    (Some(a), Some(b)) if is_boolfun && boolfun_n <= 6 => (a as u32, b as u32),
+                    _ => return Ok(false),
+                };
+                boolfun_set.retain(|f| (min..=max).contains(&f.nonlinearity()));
+                set_digest = canonical_set_digest_boolfun(&boolfun_set);
             }
             "FILTER_WEIGHT" => {
                 if !is_boolfun {
@@ -300,10 +1714,107 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                 boolfun_set = out;
                 set_digest = canonical_set_digest_boolfun(&boolfun_set);
             }
-            "TOPK" => {
-                if !is_boolfun {
+            "TOPK" if rec.args.get("metric").and_then(|v| v.as_str()) == Some("NONLINEARITY") => {
+                let k = match rec.args.get("k").and_then(|v| v.as_u64()) {
+                    Some(k) if is_boolfun && boolfun_n <= 6 => k as usize,
+                    _ => return Ok(false),
+                };
+                let mut scored: Vec<(u32, BoolFun)> =
+                    boolfun_set.iter().map(|f| (f.nonlinearity(), *f)).collect();
+                scored.sort_by(|(da, fa), (db, fb)| db.cmp(da).then_with(|| boolfun_canonical_cmp(fa, fb)));
+                scored.truncate(k);
+                witness_bf = scored.first().map(|(_, f)| *f);
+                boolfun_set = scored.into_iter().map(|(_, f)| f).collect();
+                boolfun_set.sort_by(boolfun_canonical_cmp);
+                set_digest = canonical_set_digest_boolfun(&boolfun_set);
+            }
+            "TOPK" if is_group => {
+                let g = group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                let target_s = rec
+                    .args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let k = rec
+                    .args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as usize;
+                let target = match parse_perm(target_s).filter(|t| g.contains(t)) {
+                    Some(t) => t,
+                    None => return Ok(false),
+                };
+                let top = g.topk(&group_set, &target, k);
+                witness_perm = top.first().cloned();
+                group_set = top;
+                group_set.sort_by(group_canonical_cmp);
+                set_digest = canonical_set_digest_group(&group_set);
+            }
+            "FILTER_CONJ" => {
+                if !is_group {
+                    return Ok(false);
+                }
+                let g = group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                let elem = rec
+                    .args
+                    .get("elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let x = match parse_perm(elem).filter(|x| g.contains(x)) {
+                    Some(x) => x,
+                    None => return Ok(false),
+                };
+                let rep = g.class_rep(&x);
+                group_set.retain(|h| g.class_rep(h) == rep);
+                set_digest = canonical_set_digest_group(&group_set);
+            }
+            "TOPK" if is_ge => {
+                let target_s = rec
+                    .args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let k = rec
+                    .args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as usize;
+                let t = match crate::geom::parse_tri(target_s) {
+                    Some(t) => t,
+                    None => return Ok(false),
+                };
+                let top = crate::geom::topk_tri(&ge_set, &t, k);
+                witness = top.first().map(|w| Frac { num: w.a, den: w.c });
+                ge_set = top;
+                ge_set.sort_by(crate::geom::canonical_cmp);
+                state_set = project_tris(&ge_set);
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "TOPK" if !is_boolfun => {
+                if is_lattice || is_group || is_subsets || is_quad || is_tetra {
                     return Ok(false);
                 }
+                let target_s = rec
+                    .args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let k = rec
+                    .args
+                    .get("k")
+                    .and_then(|v| v.as_u64())
+                    .ok_or_else(|| anyhow!("bad args"))? as usize;
+                let t = match parse_frac(target_s) {
+                    Some(t) => t,
+                    None => return Ok(false),
+                };
+                let top = crate::qe::topk_nearest(&state_set, &t, k);
+                witness = top.first().copied();
+                state_set = top;
+                state_set.sort_by(canonical_cmp);
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "TOPK" => {
                 let target_s = rec
                     .args
                     .get("target_elem")
@@ -349,6 +1860,11 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("bad args"))?;
                 is_ge = elem.contains(",");
+                is_lattice = false;
+                is_group = false;
+                is_subsets = false;
+                is_quad = false;
+                is_tetra = false;
                 let f = if is_ge {
                     let parts: Vec<&str> = elem
                         .split(",")
@@ -368,55 +1884,147 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                 };
                 cst = Constraint::empty();
                 state_set = if is_ge {
-                    let mut tris: Vec<crate::geom::Tri> = ge_state.clone();
-                    tris.sort_by(crate::geom::canonical_cmp);
-                    let mut v: Vec<Frac> = tris
-                        .into_iter()
-                        .map(|t| Frac { num: t.a, den: t.c })
-                        .collect();
-                    v.sort_by(crate::qe::canonical_cmp);
-                    v
+                    ge_set = ge_state.clone();
+                    ge_set.sort_by(crate::geom::canonical_cmp);
+                    project_tris(&ge_set)
                 } else {
                     qe.clone()
                 };
                 set_digest = canonical_set_digest(&state_set);
                 witness = Some(f);
             }
-            "SET_BIT" => {
-                let i = rec
-                    .args
-                    .get("i")
-                    .and_then(|v| v.as_u64())
-                    .ok_or_else(|| anyhow!("bad args"))? as u8;
-                let b = rec
-                    .args
-                    .get("b")
-                    .and_then(|v| v.as_u64())
-                    .ok_or_else(|| anyhow!("bad args"))? as u8;
+            "FILTER_PRED"
+                if user_preds
+                    .iter()
+                    .any(|(n, _)| Some(n.as_str()) == rec.args.get("name").and_then(|v| v.as_str())) =>
+            {
+                let name = rec.args.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                let val = rec.args.get("val").and_then(|v| v.as_u64()).unwrap_or(2);
+                if val > 1
+                    || is_boolfun || is_ge || is_lattice || is_group || is_subsets || is_quad || is_tetra
+                    || is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse
+                {
+                    return Ok(false);
+                }
+                let k = user_preds.iter().position(|(n, _)| n == name).unwrap_or(0);
+                let i = crate::pred::FIRST_USER_BIT + k as u8;
+                cst = cst.set_bit(i, val as u8);
+                let bit = 1u64 << i;
+                state_set.retain(|f| (crate::pred::ext_sig(f, &user_preds) & bit != 0) == (val == 1));
+                set_digest = canonical_set_digest(&state_set);
+            }
+            "SET_BIT" | "FILTER_PRED" => {
+                let (i, b) = if rec.op == "FILTER_PRED" {
+                    let linguistic = is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse;
+                    let flags = [is_boolfun, is_ge, is_lattice, is_group, is_subsets, is_quad, is_tetra, linguistic];
+                    let name = rec.args.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                    let val = rec.args.get("val").and_then(|v| v.as_u64()).unwrap_or(2);
+                    match (pred_legend(&flags), val) {
+                        (Some(legend), 0 | 1) => match resolve_pred(&legend, name) {
+                            Some(i) => (i, val as u8),
+                            None => return Ok(false),
+                        },
+                        _ => return Ok(false),
+                    }
+                } else {
+                    let i = rec
+                        .args
+                        .get("i")
+                        .and_then(|v| v.as_u64())
+                        .ok_or_else(|| anyhow!("bad args"))? as u8;
+                    let b = rec
+                        .args
+                        .get("b")
+                        .and_then(|v| v.as_u64())
+                        .ok_or_else(|| anyhow!("bad args"))? as u8;
+                    (i, b)
+                };
+                if i >= 64 {
+                    return Ok(false);
+                }
                 cst = cst.set_bit(i, b);
-                if is_ge {
-                    let mut tris: Vec<crate::geom::Tri> = ge_state
+                if is_tetra {
+                    tetra_set = tetra_all
                         .iter()
                         .copied()
-                        .filter(|t| cst.matches(crate::semtrace::sig7_geom(t)))
+                        .filter(|t| cst.matches(crate::semtrace::sig7_tetra(t)))
                         .collect();
-                    tris.sort_by(crate::geom::canonical_cmp);
-                    {
-                        let mut v: Vec<Frac> = tris
-                            .into_iter()
-                            .map(|t| Frac { num: t.a, den: t.c })
-                            .collect();
-                        v.sort_by(crate::qe::canonical_cmp);
-                        state_set = v;
+                    tetra_set.sort_by(canonical_cmp_tetra);
+                    set_digest = canonical_set_digest_tetra(&tetra_set);
+                } else if is_quad {
+                    quad_set = quad_all
+                        .iter()
+                        .copied()
+                        .filter(|q| cst.matches(crate::semtrace::sig7_quad(q)))
+                        .collect();
+                    quad_set.sort_by(canonical_cmp_quad);
+                    set_digest = canonical_set_digest_quad(&quad_set);
+                } else if is_subsets {
+                    let n_items = subset_items.len();
+                    subset_set = subset_all
+                        .iter()
+                        .copied()
+                        .filter(|x| cst.matches(crate::subsets::sig7(x, n_items)))
+                        .collect();
+                    subset_set.sort_by(subset_canonical_cmp);
+                    set_digest = canonical_set_digest_subsets(&subset_set);
+                } else if is_group {
+                    let g = group_univ.as_ref().ok_or_else(|| anyhow!("GROUP not selected"))?;
+                    group_set = g
+                        .elems
+                        .iter()
+                        .filter(|h| cst.matches(g.sig7(h)))
+                        .cloned()
+                        .collect();
+                    set_digest = canonical_set_digest_group(&group_set);
+                } else if is_lattice {
+                    lattice_set = lattice_all
+                        .iter()
+                        .copied()
+                        .filter(|p| cst.matches(crate::lattice::sig7(p)))
+                        .collect();
+                    lattice_set.sort_by(lattice_canonical_cmp);
+                    set_digest = canonical_set_digest_lattice(&lattice_set);
+                    if lattice_set.is_empty() {
+                        return Ok(false);
                     }
+                } else if is_ge {
+                    ge_set = ge_state
+                        .iter()
+                        .copied()
+                        .filter(|t| cst.matches(crate::semtrace::sig7_geom(t)))
+                        .collect();
+                    ge_set.sort_by(crate::geom::canonical_cmp);
+                    state_set = project_tris(&ge_set);
                 } else {
-                    state_set = filter_qe(&qe, cst);
+                    state_set = filter_qe(&qe, cst, &user_preds);
                     set_digest = canonical_set_digest(&state_set);
                 }
-                if state_set.is_empty() {
+                if !is_lattice && !is_group && !is_subsets && !is_quad && !is_tetra && state_set.is_empty() {
                     return Ok(false);
                 }
             }
+            "WITNESS_NEAREST" if rec.args.get("targets").is_some() => {
+                let targets: Option<Vec<Frac>> = rec.args["targets"]
+                    .as_array()
+                    .and_then(|v| v.iter().map(|t| t.as_str().and_then(parse_frac)).collect());
+                let mode = rec.args.get("mode").and_then(|v| v.as_str()).unwrap_or("minimax");
+                let metric = rec.args.get("metric").and_then(|v| v.as_str()).unwrap_or("ABS_DIFF");
+                let targets = match targets {
+                    Some(t) if !t.is_empty() => t,
+                    _ => return Ok(false),
+                };
+                if is_boolfun || is_ge || is_lattice || is_group || is_subsets || is_quad || is_tetra
+                    || metric != "ABS_DIFF"
+                    || (mode != "minimax" && mode != "sum")
+                {
+                    return Ok(false);
+                }
+                match crate::qe::witness_nearest_multi(&state_set, &targets, mode == "minimax") {
+                    Some(w) => witness = Some(w),
+                    None => return Ok(false),
+                }
+            }
             "WITNESS_NEAREST" => {
                 let target = rec
                     .args
@@ -428,7 +2036,62 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     .get("metric")
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| anyhow!("bad args"))?;
-                if is_word && metric == "HAMMING_SIG" {
+                if is_tetra {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t = match parse_tetra(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let w = tetra_set
+                        .iter()
+                        .copied()
+                        .min_by(|x, y| {
+                            tetra_distance(x, &t)
+                                .cmp(&tetra_distance(y, &t))
+                                .then_with(|| canonical_cmp_tetra(x, y))
+                        })
+                        .ok_or_else(|| anyhow!("empty"))?;
+                    witness_tetra = Some(w);
+                } else if is_quad {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t = match parse_quad(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let w = quad_set
+                        .iter()
+                        .copied()
+                        .min_by(|x, y| {
+                            quad_distance(x, &t)
+                                .cmp(&quad_distance(y, &t))
+                                .then_with(|| canonical_cmp_quad(x, y))
+                        })
+                        .ok_or_else(|| anyhow!("empty"))?;
+                    witness_quad = Some(w);
+                } else if is_subsets {
+                    if metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t: i64 = match target.trim().parse() {
+                        Ok(t) => t,
+                        Err(_) => return Ok(false),
+                    };
+                    let w = crate::subsets::witness_nearest(&subset_set, t)
+                        .ok_or_else(|| anyhow!("empty"))?;
+                    witness_subset = Some(w);
+                } else if is_lattice {
+                    if metric != "EUCLID_SQ" {
+                        return Ok(false);
+                    }
+                    let t = parse_pt(target).ok_or_else(|| anyhow!("bad target"))?;
+                    let w = crate::lattice::witness_nearest(&lattice_set, &t)
+                        .ok_or_else(|| anyhow!("empty"))?;
+                    witness_pt = Some(w);
+                } else if is_word && metric == "HAMMING_SIG" {
                     let t_text = target.trim().to_ascii_lowercase();
                     let t_word = word_all.iter().find(|w| w.text == t_text)
                         .cloned()
@@ -469,6 +2132,31 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                     if let Some(tg) = discourse_all.iter().find(|g| g.discourse_id == t_id).cloned() {
                         let _ = discourse_set.iter().min_by_key(|g| discourse_sig_distance(g, &tg));
                     }
+                } else if is_ge && metric == "SIMILARITY" {
+                    let t = match crate::geom::parse_tri(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let ties = crate::geom::similarity_ties(&ge_set, &t);
+                    if ties.is_empty() {
+                        return Ok(false);
+                    }
+                    witness = ties.first().map(|w| Frac { num: w.a, den: w.c });
+                    // equally similar shapes go into the trace so the canonical pick is auditable
+                    let leaves: Vec<[u8; 32]> = ties.iter().map(|x| leaf_hash(&x.to_bytes())).collect();
+                    step_ties = Some((
+                        ties.iter().map(|x| format!("{},{},{}", x.a, x.b, x.c)).collect(),
+                        set_root(&leaves),
+                    ));
+                } else if !is_ge && crate::qe::EXTRA_METRICS.contains(&metric) {
+                    let t = match parse_frac(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    match crate::qe::witness_nearest_by(&state_set, &t, metric) {
+                        Some(w) => witness = Some(w),
+                        None => return Ok(false),
+                    }
                 } else if metric != "ABS_DIFF" {
                     return Ok(false);
                 } else {
@@ -495,6 +2183,134 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                 witness = Some(w);
                 } // end ABS_DIFF branch
             }
+            "WITNESS_ALL_TIES" => {
+                let target = rec
+                    .args
+                    .get("target_elem")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                let metric = rec
+                    .args
+                    .get("metric")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("bad args"))?;
+                // (display string, canonical bytes) of every co-minimal element, canonical order
+                let ties: Vec<(String, Vec<u8>)> = if is_lattice {
+                    if metric != "EUCLID_SQ" {
+                        return Ok(false);
+                    }
+                    let t = match parse_pt(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let d = lattice_set.iter().map(|p| crate::lattice::dist_sq(p, &t)).min();
+                    let ties: Vec<Pt> = lattice_set
+                        .iter()
+                        .copied()
+                        .filter(|p| Some(crate::lattice::dist_sq(p, &t)) == d)
+                        .collect();
+                    witness_pt = ties.first().copied();
+                    ties.iter().map(|p| (pt_to_string(p), p.canonical_bytes().to_vec())).collect()
+                } else if is_subsets {
+                    if metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t: i64 = match target.trim().parse::<i64>().ok() {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let d = subset_set.iter().map(|x| (x.sum - t).abs()).min();
+                    let ties: Vec<Subset> = subset_set
+                        .iter()
+                        .copied()
+                        .filter(|x| Some((x.sum - t).abs()) == d)
+                        .collect();
+                    witness_subset = ties.first().copied();
+                    ties.iter()
+                        .map(|x| (subset_to_string(&subset_items, x), x.canonical_bytes().to_vec()))
+                        .collect()
+                } else if is_quad {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t = match parse_quad(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let d = quad_set.iter().map(|q| quad_distance(q, &t)).min();
+                    let ties: Vec<Quad> = quad_set
+                        .iter()
+                        .copied()
+                        .filter(|q| Some(quad_distance(q, &t)) == d)
+                        .collect();
+                    witness_quad = ties.first().copied();
+                    ties.iter().map(|q| (quad_to_string(q), q.to_bytes().to_vec())).collect()
+                } else if is_tetra {
+                    if metric != "L1" && metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t = match parse_tetra(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let d = tetra_set.iter().map(|x| tetra_distance(x, &t)).min();
+                    let ties: Vec<Tetra> = tetra_set
+                        .iter()
+                        .copied()
+                        .filter(|x| Some(tetra_distance(x, &t)) == d)
+                        .collect();
+                    witness_tetra = ties.first().copied();
+                    ties.iter().map(|x| (tetra_to_string(x), x.to_bytes().to_vec())).collect()
+                } else if is_ge {
+                    if metric != "L1" && metric != "ABS_DIFF" && metric != "SIMILARITY" {
+                        return Ok(false);
+                    }
+                    let t = match crate::geom::parse_tri(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let ties: Vec<crate::geom::Tri> = if metric == "SIMILARITY" {
+                        crate::geom::similarity_ties(&ge_set, &t)
+                    } else {
+                        let d = ge_set.iter().map(|x| crate::geom::tri_distance(x, &t)).min();
+                        ge_set
+                            .iter()
+                            .copied()
+                            .filter(|x| Some(crate::geom::tri_distance(x, &t)) == d)
+                            .collect()
+                    };
+                    witness = ties.first().map(|w| Frac { num: w.a, den: w.c });
+                    ties.iter()
+                        .map(|x| (format!("{},{},{}", x.a, x.b, x.c), x.to_bytes().to_vec()))
+                        .collect()
+                } else if is_boolfun || is_group {
+                    return Ok(false);
+                } else {
+                    if metric != "ABS_DIFF" {
+                        return Ok(false);
+                    }
+                    let t = match parse_frac(target) {
+                        Some(t) => t,
+                        None => return Ok(false),
+                    };
+                    let w = witness_nearest(&state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
+                    let dw = distance_num_den(&t, &w);
+                    witness = Some(w);
+                    let mut ties: Vec<Frac> = state_set
+                        .iter()
+                        .copied()
+                        .filter(|f| {
+                            let d = distance_num_den(&t, f);
+                            d.0 * dw.1 == dw.0 * d.1
+                        })
+                        .collect();
+                    ties.sort_by(canonical_cmp);
+                    ties.iter().map(|f| (frac_to_string(f), f.canonical_bytes().to_vec())).collect()
+                };
+                let leaves: Vec<[u8; 32]> = ties.iter().map(|(_, b)| leaf_hash(b)).collect();
+                let root = set_root(&leaves);
+                step_ties = Some((ties.into_iter().map(|(s, _)| s).collect(), root));
+            }
             "PROJECT_SIGNATURE" => {
                 let elem = rec
                     .args
@@ -507,6 +2323,11 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
                 // QE -> 7-bit signature -> BOOLFUN signature universe (n=7, bits in 0..127)
                 is_boolfun = true;
                 is_ge = false;
+                is_lattice = false;
+                is_group = false;
+                is_subsets = false;
+                is_quad = false;
+                is_tetra = false;
                 cst = Constraint::empty();
                 state_set.clear();
                 boolfun_n = 7;
@@ -552,11 +2373,16 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
 
                 if lu_norm == "QE" && is_boolfun_universe(ru_norm.as_str()) {
                     let bf = parse_boolfun(re).ok_or_else(|| anyhow!("bad right_elem"))?;
+                    is_lattice = false;
+                    is_group = false;
+                    is_subsets = false;
+                is_quad = false;
+                is_tetra = false;
                     witness_bf = Some(bf);
                     cst.mask = 0x7f;
-                    cst.value = (bf.bits as u8) & 0x7f;
+                    cst.value = bf.bits & 0x7f;
 
-                    state_set = filter_qe(&qe, cst);
+                    state_set = filter_qe(&qe, cst, &user_preds);
                     set_digest = canonical_set_digest(&state_set);
                     let t = parse_frac(le).ok_or_else(|| anyhow!("bad left_elem"))?;
                     let w = witness_nearest(&state_set, &t).ok_or_else(|| anyhow!("empty set"))?;
@@ -575,73 +2401,292 @@ pub fn verify_trace_ndjson(trace_path: &Path) -> Result<bool> {
             _ => return Ok(false),
         }
 
+        let want_count = if is_boolfun {
+            boolfun_set.len()
+        } else if is_lattice {
+            lattice_set.len()
+        } else if is_group {
+            group_set.len()
+        } else if is_subsets {
+            subset_set.len()
+        } else if is_quad {
+            quad_set.len()
+        } else if is_tetra {
+            tetra_set.len()
+        } else {
+            state_set.len()
+        };
+        progress.trail.push((want_count, set_digest));
+
         // check post fields
         let post_set_hex = rec.post.set_digest.clone().unwrap_or_default();
         if post_set_hex != hex32(set_digest) {
-            return Err(anyhow!(
-                "post.set_digest mismatch step={} got={} want={}",
-                rec.step,
-                post_set_hex,
-                hex32(set_digest)
-            ));
+            return Err(Mismatch::new(rec.step, MismatchKind::Digest, "post.set_digest", hex32(set_digest), post_set_hex).into());
         }
 
-        if rec.post.count
-            != (if is_boolfun {
-                boolfun_set.len()
-            } else {
-                state_set.len()
-            })
+        let want_ties_root = step_ties.as_ref().map(|(_, r)| hex32(*r));
+        if rec.post.witness_ties_root != want_ties_root
+            || rec.post.witness_ties != step_ties.map(|(v, _)| v)
         {
-            return Err(anyhow!(
-                "post.count mismatch step={} got={} want={}",
+            let kind = if rec.post.witness_ties_root != want_ties_root { MismatchKind::Digest } else { MismatchKind::Witness };
+            return Err(Mismatch::new(
                 rec.step,
-                rec.post.count,
-                if is_boolfun {
-                    boolfun_set.len()
-                } else {
-                    state_set.len()
-                }
-            ));
+                kind,
+                "post.witness_ties",
+                want_ties_root.unwrap_or_else(|| "null".to_string()),
+                rec.post.witness_ties_root.as_deref().unwrap_or("null"),
+            )
+            .into());
+        }
+
+        if rec.post.group_by != step_hist {
+            let json = |h: &Option<BTreeMap<String, usize>>| serde_json::to_string(h).unwrap_or_default();
+            return Err(Mismatch::new(rec.step, MismatchKind::GroupBy, "post.group_by", json(&step_hist), json(&rec.post.group_by)).into());
+        }
+
+        if rec.post.aggregate != step_agg {
+            let json = |a: &Option<serde_json::Value>| a.as_ref().map_or("null".to_string(), |v| v.to_string());
+            return Err(Mismatch::new(rec.step, MismatchKind::Aggregate, "post.aggregate", json(&step_agg), json(&rec.post.aggregate)).into());
+        }
+
+        if rec.post.count != want_count {
+            return Err(Mismatch::new(rec.step, MismatchKind::Count, "post.count", want_count, rec.post.count).into());
         }
 
         if is_boolfun {
             if let Some(w) = witness_bf {
                 let want = boolfun_to_string(&w);
                 if rec.post.witness.as_deref() != Some(&want) {
-                    return Err(anyhow!(
-                        "post.witness mismatch step={} got={:?} want={}",
-                        rec.step,
-                        rec.post.witness,
-                        want
-                    ));
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
+                }
+            }
+        } else if is_lattice {
+            if let Some(w) = witness_pt {
+                let want = pt_to_string(&w);
+                if rec.post.witness.as_deref() != Some(&want) {
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
+                }
+            }
+        } else if is_group {
+            if let Some(w) = witness_perm.as_ref() {
+                let want = perm_to_string(w);
+                if rec.post.witness.as_deref() != Some(&want) {
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
+                }
+            }
+        } else if is_subsets {
+            if let Some(w) = witness_subset.as_ref() {
+                let want = subset_to_string(&subset_items, w);
+                if rec.post.witness.as_deref() != Some(&want) {
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
+                }
+            }
+        } else if is_quad {
+            if let Some(w) = witness_quad.as_ref() {
+                let want = (quad_to_string)(w);
+                if rec.post.witness.as_deref() != Some(&want) {
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
+                }
+            }
+        } else if is_tetra {
+            if let Some(w) = witness_tetra.as_ref() {
+                let want = (tetra_to_string)(w);
+                if rec.post.witness.as_deref() != Some(&want) {
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
                 }
             }
         } else {
             if let Some(w) = witness {
                 let want = frac_to_string(&w);
                 if rec.post.witness.as_deref() != Some(&want) {
-                    return Err(anyhow!(
-                        "post.witness mismatch step={} got={:?} want={}",
-                        rec.step,
-                        rec.post.witness,
-                        want
-                    ));
+                    return Err(Mismatch::new(rec.step, MismatchKind::Witness, "post.witness", want, rec.post.witness.as_deref().unwrap_or("null")).into());
                 }
             }
         }
 
-        let sd = step_digest(&chain, &rec.op, &rec.args, &set_digest);
-        chain = sd;
-        if rec.step_digest != hex32(sd) {
-            return Err(anyhow!(
-                "step_digest mismatch step={} got={} want={}",
-                rec.step,
-                rec.step_digest,
-                hex32(sd)
-            ));
+        let want_witness = if is_boolfun {
+            witness_bf.as_ref().map(boolfun_to_string)
+        } else if is_lattice {
+            witness_pt.as_ref().map(pt_to_string)
+        } else if is_group {
+            witness_perm.as_ref().map(perm_to_string)
+        } else if is_subsets {
+            witness_subset.as_ref().map(|x| subset_to_string(&subset_items, x))
+        } else if is_quad {
+            witness_quad.as_ref().map(quad_to_string)
+        } else if is_tetra {
+            witness_tetra.as_ref().map(tetra_to_string)
+        } else {
+            witness.as_ref().map(frac_to_string)
+        };
+        if let Some(m) = failed_assertion(rec.step, &rec.op, &rec.args, want_count, want_witness.as_deref()) {
+            progress.mismatch = Some(m);
+            return Ok(false);
+        }
+
+        if progress.steps == 1 {
+            // the verifier's own universe must match the pinned root, whatever the trace records
+            let pinned = crate::commitments::check_step_zero(&rec.op, &rec.args, &set_digest)?;
+            if rec.universe_root.is_some() && rec.universe_root.as_deref() != pinned {
+                return Err(Mismatch::new(
+                    rec.step,
+                    MismatchKind::Digest,
+                    "universe_root",
+                    pinned.unwrap_or("null"),
+                    rec.universe_root.as_deref().unwrap_or(""),
+                )
+                .into());
+            }
         }
+        // post.group_by equals the replayed histogram by now
+        chain.advance(&rec, &set_digest).map_err(|e| match e {
+            verifier_core::Error::Mismatch(m) => anyhow::Error::from(m),
+            e => e.into(),
+        })?;
+
+        let fin = &mut progress.fin;
+        (fin.count, fin.witness, fin.constraint) = (want_count, rec.post.witness, (cst.mask, cst.value));
+        if let (Some(ties), Some(root)) = (rec.post.witness_ties, rec.post.witness_ties_root) {
+            fin.ties = Some((ties, root));
+        }
+        fin.aggregate = rec.post.aggregate.or(fin.aggregate.take());
+        fin.group_by = rec.post.group_by.or(fin.group_by.take());
     }
 
+    progress.fin.linguistic = is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse;
+    progress.fin.leaves = if is_boolfun {
+        Some((format!("boolfun{}", boolfun_n), boolfun_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect()))
+    } else if is_lattice {
+        Some(("pt".to_string(), lattice_set.iter().map(|p| leaf_hash(&p.canonical_bytes())).collect()))
+    } else if is_quad {
+        Some(("quad".to_string(), quad_set.iter().map(|q| leaf_hash(&q.to_bytes())).collect()))
+    } else if is_tetra {
+        Some(("tetra".to_string(), tetra_set.iter().map(|t| leaf_hash(&t.to_bytes())).collect()))
+    } else if is_ge || is_group || is_subsets || is_word || is_syllable || is_morpheme || is_phrase || is_semantic || is_discourse {
+        // GE elements print as unreduced a/c, which parse back reduced
+        None
+    } else {
+        Some(("frac".to_string(), state_set.iter().map(|f| leaf_hash(&f.canonical_bytes())).collect()))
+    };
+    progress.chain = Some(chain.digest());
+    progress.set_digest = Some(set_digest);
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_format::{TraceFormat, STEP_DOMAIN};
+    use serde_json::{json, Value as JsonValue};
+
+    #[test]
+    fn segments_replay_like_the_whole_trace() {
+        let ops: Vec<String> = [
+            "LOAD 13/37",
+            "MASK_BIT bit=2 val=1",
+            "SELECT_UNIVERSE universe=LATTICE n=3",
+            "MASK_BIT bit=0 val=1",
+            "SELECT_UNIVERSE universe=QE n=0",
+            "MASK_BIT bit=1 val=0",
+            "WITNESS_NEAREST target=1/2",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let txt = fs::read_to_string(dir.join("trace.ndjson")).unwrap();
+        let records: Vec<serde_json::Value> = txt.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(segment_starts(&records), Some(vec![0, 2, 4]));
+
+        let replay = |txt: &str, threads: usize| {
+            let mut p = Progress::default();
+            let out = if threads == 1 {
+                replay_records(txt, sha256_bytes(b""), &mut p)
+            } else {
+                replay_segments(txt, threads, &mut p)
+            };
+            (out.map_err(|e| e.to_string()), p.steps, p.chain, p.current)
+        };
+        assert_eq!(replay(&txt, 3), replay(&txt, 1));
+        assert!(replay(&txt, 3).0.unwrap());
+
+        // a forged count in the last segment fails at the same step either way
+        let forged = txt.replacen(&format!("\"count\":{}", records[5]["post"]["count"]), "\"count\":1", 2);
+        let (seq, par) = (replay(&forged, 1), replay(&forged, 3));
+        assert_eq!(par, seq);
+        assert_eq!(par.1, 6);
+
+        // a broken link between segments is caught by the seed of the next one
+        let mut relinked = records.clone();
+        relinked[3]["step_digest"] = serde_json::json!("00".repeat(32));
+        let relinked: Vec<String> = relinked.iter().map(|r| r.to_string()).collect();
+        assert!(replay(&relinked.join("\n"), 2).0.is_err());
+
+        let mut stateful = records.clone();
+        stateful[1]["op"] = serde_json::json!("PUSH_STATE");
+        assert_eq!(segment_starts(&stateful), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bisection_finds_the_first_record_that_does_not_reproduce() {
+        let ops: Vec<String> = ["LOAD 13/37", "MASK_BIT bit=2 val=1", "MASK_BIT bit=0 val=0", "MASK_BIT bit=3 val=1", "RETURN_SET"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        assert!(bisect_trace(&trace).unwrap().is_none());
+
+        let text = fs::read_to_string(&trace).unwrap();
+        let mut lines: Vec<JsonValue> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        lines[2]["post"]["count"] = json!(lines[2]["post"]["count"].as_u64().unwrap() + 1);
+        fs::write(&trace, lines.iter().map(JsonValue::to_string).collect::<Vec<_>>().join("\n")).unwrap();
+        let b = bisect_trace(&trace).unwrap().unwrap();
+        assert_eq!((b.step, b.op.as_str()), (2, "SET_BIT"));
+        assert_eq!(b.recorded_set_digest, b.replayed_set_digest);
+        let last = b.counts.last().unwrap();
+        assert_eq!((last.step, Some(last.recorded)), (2, last.replayed.map(|n| n + 1)));
+        assert_eq!(b.counts.len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn older_format_traces_still_verify() {
+        let ops: Vec<String> = ["LOAD 1/3", "MASK_BIT bit=2 val=1", "AGGREGATE"].iter().map(|s| s.to_string()).collect();
+        let dir = crate::exec::run_trace_and_write(&ops, None, false).unwrap().artifacts_path.unwrap();
+        let trace = dir.join("trace.ndjson");
+        let current = std::fs::read_to_string(&trace).unwrap();
+        assert!(current.lines().all(|l| l.contains("\"semtrace_version\":\"0.2.0\"")));
+
+        // re-chain the same records as a 0.1.0 trace: v1 digests, version 0.1.0
+        let mut chain = sha256_bytes(b"");
+        let mut lines = Vec::new();
+        for line in current.lines() {
+            let mut rec: JsonValue = serde_json::from_str(line).unwrap();
+            let payload = json!({
+                "pre": hex::encode(chain),
+                "op": rec["op"],
+                "args": rec["args"],
+                "post": rec["post"]["set_digest"],
+            });
+            chain = TraceFormat::V1.digest(STEP_DOMAIN, &payload);
+            rec["semtrace_version"] = json!("0.1.0");
+            rec["step_digest"] = json!(hex::encode(chain));
+            lines.push(rec.to_string());
+        }
+        std::fs::write(&trace, lines.join("\n") + "\n").unwrap();
+        // result.json reports the chain it was written with
+        let last: JsonValue = serde_json::from_str(current.lines().last().unwrap()).unwrap();
+        let result = std::fs::read_to_string(dir.join("result.json")).unwrap();
+        std::fs::write(dir.join("result.json"), result.replace(last["step_digest"].as_str().unwrap(), &hex::encode(chain))).unwrap();
+        let report = verify_trace_report(&trace);
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.chain_hash, Some(hex::encode(chain)));
+
+        // a 0.1.0 chain does not verify under the current version
+        std::fs::write(&trace, lines.join("\n").replace("\"0.1.0\"", "\"0.2.0\"")).unwrap();
+        assert!(!verify_trace_report(&trace).valid);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+//! Browser bindings — execute and verify a trace without a filesystem.
+//!
+//!   execute_ops(json)    JSON trace (op array or semtrace object) → {"records": [...], "result": {...}}
+//!   verify_ndjson(text)  trace.ndjson contents                     → verify::VerifyReport
+//!
+//! Both run entirely in memory, so a web playground can take a pasted trace,
+//! run it and show the verified result. `execute_json` and `verify_json` are
+//! plain functions, testable natively; under cargo feature `wasm` they are
+//! exported through wasm-bindgen, with errors thrown as JS exceptions. Build
+//! the module with
+//! `cargo rustc --lib --crate-type cdylib --features wasm --target wasm32-unknown-unknown`.
+
+use anyhow::Result;
+use serde_json::{json, Value as JsonValue};
+
+use crate::digest::HashAlg;
+use crate::exec::execute_in_memory_with;
+
+/// Execute a JSON trace and return its trace.ndjson records and a summary of
+/// the run, as JSON.
+pub fn execute_json(trace: &str) -> Result<String> {
+    let trace: JsonValue = serde_json::from_str(trace)?;
+    let ops = crate::schema::trace_ops(&trace)?;
+    let hash = crate::schema::trace_hash(&trace)?.unwrap_or(HashAlg::Sha256);
+    let (records, r) = execute_in_memory_with(&ops, hash)?;
+    let steps: Vec<JsonValue> = r
+        .steps
+        .iter()
+        .map(|m| json!({ "step": m.step, "op": m.op, "count_before": m.count_before, "count_after": m.count_after }))
+        .collect();
+    let out = json!({
+        "records": records,
+        "result": {
+            "valid": r.valid,
+            "count": r.final_count,
+            "witness": r.witness,
+            "sample": r.sample,
+            "chain_hash": r.chain_hash,
+            "universe": r.universe,
+            "constraint": { "mask": r.constraint_mask, "value": r.constraint_value },
+            "failed_assertion": r.failed_assertion,
+            "run_id": r.run_id,
+            "steps": steps,
+        },
+    });
+    Ok(out.to_string())
+}
+
+/// Replay trace.ndjson contents and return the `VerifyReport` as JSON.
+pub fn verify_json(ndjson: &str) -> Result<String> {
+    Ok(serde_json::to_string(&crate::verify::verify_trace_text(ndjson)?)?)
+}
+
+#[cfg(feature = "wasm")]
+mod bindings {
+    use wasm_bindgen::prelude::*;
+
+    fn thrown(e: anyhow::Error) -> JsValue {
+        JsValue::from_str(&e.to_string())
+    }
+
+    #[wasm_bindgen]
+    pub fn execute_ops(json: &str) -> Result<String, JsValue> {
+        super::execute_json(json).map_err(thrown)
+    }
+
+    #[wasm_bindgen]
+    pub fn verify_ndjson(text: &str) -> Result<String, JsValue> {
+        super::verify_json(text).map_err(thrown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn executes_and_verifies_without_artifacts() {
+        let out: JsonValue = serde_json::from_str(&execute_json(include_str!("../trace_demo.json")).unwrap()).unwrap();
+        assert_eq!(out["result"]["valid"], json!(true));
+        let records = out["records"].as_array().unwrap();
+        assert_eq!(records.len(), out["result"]["steps"].as_array().unwrap().len());
+        assert!(crate::exec::artifacts_root().map_or(true, |root| !root.join(out["result"]["run_id"].as_str().unwrap()).exists()));
+
+        let ndjson: Vec<String> = records.iter().map(JsonValue::to_string).collect();
+        let report: JsonValue = serde_json::from_str(&verify_json(&ndjson.join("\n")).unwrap()).unwrap();
+        assert_eq!((&report["valid"], &report["chain_hash"]), (&json!(true), &out["result"]["chain_hash"]));
+
+        let mut bad = records.to_vec();
+        bad[0]["post"]["count"] = json!(999);
+        let bad: Vec<String> = bad.iter().map(JsonValue::to_string).collect();
+        let report: JsonValue = serde_json::from_str(&verify_json(&bad.join("\n")).unwrap()).unwrap();
+        assert_eq!(report["valid"], json!(false));
+        assert!(execute_json(r#"["MASK_BIT bit=99"]"#).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Change detection for `lnst watch`: poll a directory (or one file) for
+//! trace JSON whose size or modification time moved since the last look.
+//!
+//! Polling keeps this dependency-free and behaves the same on every platform;
+//! at the default interval the cost is one `read_dir` and a stat per file.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub struct Watcher {
+    root: PathBuf,
+    seen: BTreeMap<PathBuf, (SystemTime, u64)>,
+}
+
+impl Watcher {
+    /// Watch `root`, a directory of `*.json` traces or a single trace file.
+    /// Nothing is considered seen yet, so the first `changed` reports every file.
+    pub fn new(root: &Path) -> Result<Watcher> {
+        if !root.exists() {
+            return Err(anyhow!("{} does not exist", root.display()));
+        }
+        Ok(Watcher { root: root.to_path_buf(), seen: BTreeMap::new() })
+    }
+
+    fn scan(&self) -> Result<Vec<PathBuf>> {
+        if self.root.is_file() {
+            return Ok(vec![self.root.clone()]);
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e == "json") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Files created or modified since the previous call, in name order.
+    pub fn changed(&mut self) -> Result<Vec<PathBuf>> {
+        let mut out = Vec::new();
+        let files = self.scan()?;
+        self.seen.retain(|p, _| files.contains(p));
+        for path in files {
+            let Ok(meta) = fs::metadata(&path) else { continue };
+            let stamp = (meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len());
+            if self.seen.insert(path.clone(), stamp) != Some(stamp) {
+                out.push(path);
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_new_and_edited_traces_once() {
+        let dir = std::env::temp_dir().join(format!("lnst_watch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.json"), "[]").unwrap();
+        fs::write(dir.join("notes.txt"), "x").unwrap();
+
+        let mut w = Watcher::new(&dir).unwrap();
+        assert_eq!(w.changed().unwrap(), vec![dir.join("a.json")]);
+        assert!(w.changed().unwrap().is_empty());
+
+        fs::write(dir.join("a.json"), "[\"LOAD 1/4\"]").unwrap();
+        fs::write(dir.join("b.json"), "[]").unwrap();
+        assert_eq!(w.changed().unwrap(), vec![dir.join("a.json"), dir.join("b.json")]);
+        assert!(w.changed().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
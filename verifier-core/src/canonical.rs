@@ -0,0 +1,157 @@
+//! Canonical JSON (RFC 8785, JCS) for every payload that gets hashed.
+//!
+//! Step and GROUP_BY digests hash a small JSON object. Hashing
+//! `serde_json::to_vec` ties the digest to serde_json's map order and float
+//! formatting; JCS fixes both: no whitespace, object members sorted by their
+//! UTF-16 code units, strings escaped as `JSON.stringify` does, and numbers
+//! written the way ECMAScript prints a double.
+//!
+//! One deliberate departure: numbers serde_json holds as `i64`/`u64` are
+//! written exactly. RFC 8785 would route them through a double, which rounds
+//! constraint masks above 2^53.
+//!
+//! Which records are hashed this way is decided by their format version; see
+//! `trace_format`.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde_json::Value as JsonValue;
+
+/// Serialize `v` as RFC 8785 canonical JSON.
+pub fn to_jcs(v: &JsonValue) -> Vec<u8> {
+    let mut out = String::new();
+    write_value(&mut out, v);
+    out.into_bytes()
+}
+
+fn write_value(out: &mut String, v: &JsonValue) {
+    match v {
+        JsonValue::Null => out.push_str("null"),
+        JsonValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        JsonValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => out.push_str(&i.to_string()),
+            (_, Some(u)) => out.push_str(&u.to_string()),
+            _ => out.push_str(&es_number(n.as_f64().unwrap_or(0.0))),
+        },
+        JsonValue::String(s) => write_string(out, s),
+        JsonValue::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        JsonValue::Object(map) => {
+            let mut members: Vec<(&String, &JsonValue)> = map.iter().collect();
+            members.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+            out.push('{');
+            for (i, (k, item)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, k);
+                out.push(':');
+                write_value(out, item);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// ECMAScript Number::toString for a finite double.
+fn es_number(x: f64) -> String {
+    if x == 0.0 {
+        return "0".to_string();
+    }
+    // shortest round-trip digits d₁d₂…d_k with x = 0.d₁…d_k × 10^n
+    let sci = format!("{:e}", x.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exp.parse::<i32>().unwrap_or(0) + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat((-n) as usize), digits)
+    } else {
+        let e = n - 1;
+        let sign = if e < 0 { '-' } else { '+' };
+        match digits.split_at(1) {
+            (first, "") => format!("{}e{}{}", first, sign, e.abs()),
+            (first, rest) => format!("{}.{}e{}{}", first, rest, sign, e.abs()),
+        }
+    };
+    if x < 0.0 {
+        format!("-{}", body)
+    } else {
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn numbers_print_as_ecmascript_does() {
+        let cases = [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (4.5, "4.5"),
+            (0.002, "0.002"),
+            (1e-7, "1e-7"),
+            (-1.5e-7, "-1.5e-7"),
+            (1e20, "100000000000000000000"),
+            (1e21, "1e+21"),
+            (1e30, "1e+30"),
+            (333333333.3333333, "333333333.3333333"),
+            (9007199254740994.0, "9007199254740994"),
+            (5e-324, "5e-324"),
+        ];
+        for (x, want) in cases {
+            assert_eq!(es_number(x), want, "{:e}", x);
+        }
+    }
+
+    #[test]
+    fn objects_sort_by_utf16_and_strings_escape_minimally() {
+        let v = json!({ "\u{20ac}": 1, "\r": 2, "\u{1f600}": 3, "\u{fb33}": 4, "1": [true, null, "a\u{1}\"/"] });
+        let got = String::from_utf8(to_jcs(&v)).unwrap();
+        assert_eq!(got, "{\"\\r\":2,\"1\":[true,null,\"a\\u0001\\\"/\"],\"\u{20ac}\":1,\"\u{1f600}\":3,\"\u{fb33}\":4}");
+        assert_eq!(to_jcs(&json!({ "mask": u64::MAX })), format!("{{\"mask\":{}}}", u64::MAX).into_bytes());
+    }
+
+    #[test]
+    fn step_payloads_match_the_legacy_bytes() {
+        // v0 digests stay reproducible: for the payloads exec hashes, JCS and
+        // serde_json agree byte for byte
+        let v = json!({ "pre": "ab", "op": "SET_BIT", "args": { "i": 2, "b": 1, "name": "x y" }, "post": "cd" });
+        assert_eq!(to_jcs(&v), serde_json::to_vec(&v).unwrap());
+    }
+}
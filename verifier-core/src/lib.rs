@@ -0,0 +1,292 @@
+//! Record-level trace verification without a filesystem, clock or threads.
+//!
+//! The full verifier (`verify` in the main crate) rebuilds every universe and
+//! re-applies each op; those builders need std. What a record commits to does
+//! not: the format rules, the GROUP_BY post digest and the step-digest chain.
+//! This crate holds that part, over any iterator of `StepRec`, so a browser or
+//! another constrained host can check a trace's integrity — that its records
+//! chain to the claimed hash — given the records alone.
+//!
+//! It is `no_std` + `alloc` and builds for `wasm32-unknown-unknown`.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod canonical;
+pub mod trace_format;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+
+use trace_format::{TraceFormat, GROUP_BY_DOMAIN, STEP_DOMAIN};
+
+pub fn sha256_bytes(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StepPre {
+    pub set_digest: Option<String>,
+    pub count: usize,
+    pub constraint_mask: u64,
+    pub constraint_value: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StepPost {
+    pub set_digest: Option<String>,
+    pub count: usize,
+    pub witness: Option<String>,
+    #[serde(default)]
+    pub witness_ties: Option<alloc::vec::Vec<String>>,
+    #[serde(default)]
+    pub witness_ties_root: Option<String>,
+    #[serde(default)]
+    pub aggregate: Option<JsonValue>,
+    #[serde(default)]
+    pub group_by: Option<BTreeMap<String, usize>>,
+}
+
+/// One line of trace.ndjson.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StepRec {
+    pub step: usize,
+    /// Absent in v0 traces.
+    #[serde(default)]
+    pub semtrace_version: Option<String>,
+    /// Absent for SHA-256.
+    #[serde(default)]
+    pub hash: Option<String>,
+    pub op: String,
+    pub args: JsonValue,
+    #[serde(default)]
+    pub universe_root: Option<String>,
+    pub pre: StepPre,
+    pub post: StepPost,
+    pub step_digest: String,
+}
+
+/// What a failing step's record disagrees with the replay on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchKind {
+    /// A set digest, WITNESS_ALL_TIES root or pinned universe root.
+    Digest,
+    Count,
+    Witness,
+    /// The step digest, i.e. the hash chain.
+    Chain,
+    GroupBy,
+    Aggregate,
+    /// An ASSERT_* step whose condition does not hold.
+    Assertion,
+}
+
+impl MismatchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MismatchKind::Digest => "digest",
+            MismatchKind::Count => "count",
+            MismatchKind::Witness => "witness",
+            MismatchKind::Chain => "chain",
+            MismatchKind::GroupBy => "group_by",
+            MismatchKind::Aggregate => "aggregate",
+            MismatchKind::Assertion => "assertion",
+        }
+    }
+}
+
+/// The first recorded value a replay disagrees with.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Mismatch {
+    pub step: usize,
+    pub kind: MismatchKind,
+    /// Record field, e.g. `post.count`.
+    pub field: String,
+    /// What the replay computed.
+    pub expected: String,
+    /// What the trace records.
+    pub got: String,
+}
+
+impl Mismatch {
+    pub fn new(step: usize, kind: MismatchKind, field: &str, expected: impl ToString, got: impl ToString) -> Mismatch {
+        Mismatch { step, kind, field: field.to_string(), expected: expected.to_string(), got: got.to_string() }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} mismatch step={} got={} want={}", self.field, self.step, self.got, self.expected)
+    }
+}
+
+impl core::error::Error for Mismatch {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Mismatch(Mismatch),
+    /// A `semtrace_version` this build does not read.
+    UnsupportedVersion(String),
+    /// Records that do not belong to one trace.
+    Malformed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Mismatch(m) => m.fmt(f),
+            Error::UnsupportedVersion(v) => {
+                write!(f, "unsupported semtrace_version {} (this build reads up to {})", v, trace_format::SEMTRACE_VERSION)
+            }
+            Error::Malformed(why) => f.write_str(why),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<Mismatch> for Error {
+    fn from(m: Mismatch) -> Error {
+        Error::Mismatch(m)
+    }
+}
+
+pub fn step_digest(format: TraceFormat, pre: &[u8], op: &str, args: &JsonValue, post: &[u8]) -> [u8; 32] {
+    let obj = serde_json::json!({
+        "pre": hex::encode(pre),
+        "op": op,
+        "args": args,
+        "post": hex::encode(post),
+    });
+    format.digest(STEP_DOMAIN, &obj)
+}
+
+/// Post digest for a GROUP_BY step: binds the histogram to the set digest.
+pub fn group_by_digest(format: TraceFormat, set_digest: &[u8; 32], hist: &BTreeMap<String, usize>) -> [u8; 32] {
+    let obj = serde_json::json!({ "set": hex::encode(set_digest), "group_by": hist });
+    format.digest(GROUP_BY_DOMAIN, &obj)
+}
+
+/// The step-digest chain, advanced one record at a time.
+pub struct Chain {
+    digest: [u8; 32],
+    /// Backend named by the first record, and the previous record's post set digest.
+    hash: Option<Option<String>>,
+    prev_post: Option<Option<String>>,
+}
+
+impl Default for Chain {
+    fn default() -> Chain {
+        Chain::resume(sha256_bytes(b""))
+    }
+}
+
+impl Chain {
+    /// A chain continuing from `digest`, e.g. at the start of a trace segment.
+    pub fn resume(digest: [u8; 32]) -> Chain {
+        Chain { digest, hash: None, prev_post: None }
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        self.digest
+    }
+
+    /// Check that `rec` follows the records before it and that its step
+    /// digest commits to `set_digest`, the post set digest the caller
+    /// trusts: recomputed by a replay, or the recorded one.
+    pub fn advance(&mut self, rec: &StepRec, set_digest: &[u8; 32]) -> Result<[u8; 32], Error> {
+        let format = TraceFormat::for_version(rec.semtrace_version.as_deref())?;
+        if self.hash.get_or_insert_with(|| rec.hash.clone()) != &rec.hash {
+            return Err(Error::Malformed("hash backend changes mid-trace".into()));
+        }
+        if let Some(prev) = self.prev_post.replace(rec.post.set_digest.clone()) {
+            if rec.pre.set_digest.is_some() && rec.pre.set_digest != prev {
+                let want = prev.unwrap_or_else(|| "null".into());
+                return Err(Mismatch::new(rec.step, MismatchKind::Digest, "pre.set_digest", want, rec.pre.set_digest.as_deref().unwrap_or("")).into());
+            }
+        }
+        let post = match rec.post.group_by.as_ref() {
+            Some(h) => group_by_digest(format, set_digest, h),
+            None => *set_digest,
+        };
+        self.digest = step_digest(format, &self.digest, &rec.op, &rec.args, &post);
+        if rec.step_digest != hex::encode(self.digest) {
+            return Err(Mismatch::new(rec.step, MismatchKind::Chain, "step_digest", hex::encode(self.digest), &rec.step_digest).into());
+        }
+        Ok(self.digest)
+    }
+}
+
+/// Check that `records` chain from the empty digest, trusting each record's
+/// post set digest. Returns the chain hash.
+pub fn check_chain(records: impl IntoIterator<Item = StepRec>) -> Result<[u8; 32], Error> {
+    let mut chain = Chain::default();
+    for rec in records {
+        let hex_digest = rec.post.set_digest.as_deref().unwrap_or("");
+        let set_digest: [u8; 32] = hex::decode(hex_digest)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| Error::Malformed(alloc::format!("step {}: bad post.set_digest {:?}", rec.step, hex_digest)))?;
+        chain.advance(&rec, &set_digest)?;
+    }
+    Ok(chain.digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn record(chain: &[u8; 32], step: usize, op: &str, pre: Option<&[u8; 32]>, post: &[u8; 32]) -> StepRec {
+        let digest = step_digest(TraceFormat::CURRENT, chain, op, &JsonValue::Null, post);
+        serde_json::from_value(serde_json::json!({
+            "step": step,
+            "semtrace_version": TraceFormat::CURRENT.version(),
+            "op": op,
+            "args": null,
+            "pre": { "set_digest": pre.map(hex::encode), "count": 0, "constraint_mask": 0, "constraint_value": 0 },
+            "post": { "set_digest": hex::encode(post), "count": 0, "witness": null },
+            "step_digest": hex::encode(digest),
+        }))
+        .unwrap()
+    }
+
+    fn trace() -> (Vec<StepRec>, [u8; 32]) {
+        let sets: Vec<[u8; 32]> = (0..3u8).map(|i| sha256_bytes(&[i])).collect();
+        let mut chain = sha256_bytes(b"");
+        let mut recs = Vec::new();
+        for (i, set) in sets.iter().enumerate() {
+            let rec = record(&chain, i, "STEP", i.checked_sub(1).map(|p| &sets[p]), set);
+            chain = hex::decode(&rec.step_digest).unwrap().try_into().unwrap();
+            recs.push(rec);
+        }
+        (recs, chain)
+    }
+
+    #[test]
+    fn records_chain_and_forgeries_name_their_step() {
+        let (recs, chain) = trace();
+        assert_eq!(check_chain(recs.clone()), Ok(chain));
+
+        let mut forged = recs.clone();
+        forged[1].args = serde_json::json!({ "i": 1 });
+        match check_chain(forged) {
+            Err(Error::Mismatch(m)) => assert_eq!((m.step, m.kind), (1, MismatchKind::Chain)),
+            other => panic!("{:?}", other),
+        }
+
+        let mut unlinked = recs.clone();
+        unlinked[2].pre.set_digest = Some(hex::encode([0u8; 32]));
+        assert!(matches!(check_chain(unlinked), Err(Error::Mismatch(Mismatch { step: 2, kind: MismatchKind::Digest, .. }))));
+
+        let mut switched = recs;
+        switched[2].hash = Some("blake3".into());
+        assert!(matches!(check_chain(switched), Err(Error::Malformed(_))));
+    }
+}
@@ -0,0 +1,95 @@
+//! trace.ndjson format versions and the digest rules each one implies.
+//!
+//! Every record names its format in `semtrace_version`; the verifier picks
+//! the recomputation rules per record, so artifacts written by older builds
+//! keep verifying after the format moves on.
+//!
+//! | version          | payload encoding         | domain tag            |
+//! |------------------|--------------------------|-----------------------|
+//! | absent / "0.0.1" | `serde_json::to_vec`     | none                  |
+//! | "0.1.0"          | RFC 8785 (`canonical`)   | none                  |
+//! | "0.2.0"          | RFC 8785 (`canonical`)   | `<tag> 0x00` prefixed |
+//!
+//! Domain tags keep a step digest from ever colliding with another payload
+//! hashed by this crate (a GROUP_BY digest, a Merkle node) that happens to
+//! share its bytes.
+
+use alloc::vec::Vec;
+use serde_json::Value as JsonValue;
+
+use crate::{sha256_bytes, Error};
+
+/// Format written by this build.
+pub const SEMTRACE_VERSION: &str = "0.2.0";
+
+/// Domain tag for step digests.
+pub const STEP_DOMAIN: &str = "lnst/step/v1";
+/// Domain tag for the post digest of a GROUP_BY step.
+pub const GROUP_BY_DOMAIN: &str = "lnst/group_by/v1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Pre-versioning traces.
+    V0,
+    /// Canonical JSON payloads.
+    V1,
+    /// Canonical JSON payloads under a domain tag.
+    V2,
+}
+
+impl TraceFormat {
+    pub const CURRENT: TraceFormat = TraceFormat::V2;
+
+    /// The format of a record with this `semtrace_version` (absent in v0 traces).
+    pub fn for_version(version: Option<&str>) -> Result<TraceFormat, Error> {
+        match version {
+            None | Some("0.0.1") => Ok(TraceFormat::V0),
+            Some("0.1.0") => Ok(TraceFormat::V1),
+            Some(SEMTRACE_VERSION) => Ok(TraceFormat::V2),
+            Some(v) => Err(Error::UnsupportedVersion(v.into())),
+        }
+    }
+
+    pub fn version(self) -> &'static str {
+        match self {
+            TraceFormat::V0 => "0.0.1",
+            TraceFormat::V1 => "0.1.0",
+            TraceFormat::V2 => SEMTRACE_VERSION,
+        }
+    }
+
+    /// SHA-256 of `payload` as this format hashes it under `domain`.
+    pub fn digest(self, domain: &str, payload: &JsonValue) -> [u8; 32] {
+        match self {
+            TraceFormat::V0 => sha256_bytes(&serde_json::to_vec(payload).expect("json encode")),
+            TraceFormat::V1 => sha256_bytes(&crate::canonical::to_jcs(payload)),
+            TraceFormat::V2 => {
+                let mut bytes = Vec::with_capacity(domain.len() + 1);
+                bytes.extend_from_slice(domain.as_bytes());
+                bytes.push(0);
+                bytes.extend_from_slice(&crate::canonical::to_jcs(payload));
+                sha256_bytes(&bytes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn versions_round_trip_and_domains_separate() {
+        for f in [TraceFormat::V0, TraceFormat::V1, TraceFormat::V2] {
+            assert_eq!(TraceFormat::for_version(Some(f.version())).unwrap(), f);
+        }
+        assert_eq!(TraceFormat::for_version(None).unwrap(), TraceFormat::V0);
+        assert!(TraceFormat::for_version(Some("9.9.9")).is_err());
+
+        let v = json!({ "pre": "ab", "op": "SET_BIT", "args": { "i": 2, "b": 1 }, "post": "cd" });
+        assert_eq!(TraceFormat::V0.digest(STEP_DOMAIN, &v), TraceFormat::V1.digest(STEP_DOMAIN, &v));
+        assert_ne!(TraceFormat::V2.digest(STEP_DOMAIN, &v), TraceFormat::V1.digest(STEP_DOMAIN, &v));
+        assert_ne!(TraceFormat::V2.digest(STEP_DOMAIN, &v), TraceFormat::V2.digest(GROUP_BY_DOMAIN, &v));
+    }
+}